@@ -23,11 +23,12 @@ use crate::{
     generated::{btf_ext_header, btf_header},
     thiserror::{self, Error},
     util::{bytes_of, HashMap},
-    Object,
+    Object, Pod,
 };
 
 pub(crate) const MAX_RESOLVE_DEPTH: u8 = 32;
 pub(crate) const MAX_SPEC_LEN: usize = 64;
+pub(crate) const KSYMS_SECTION: &str = ".ksyms";
 
 /// The error type returned when `BTF` operations fail.
 #[derive(Error, Debug)]
@@ -55,6 +56,13 @@ pub enum BtfError {
     #[error("invalid BTF relocation info segment")]
     InvalidRelocationInfo,
 
+    /// invalid relocation of the .BTF.ext section
+    #[error("invalid .BTF.ext relocation at offset {offset}")]
+    InvalidBtfExtRelocation {
+        /// the offset of the relocated field
+        offset: u64,
+    },
+
     /// invalid BTF type kind
     #[error("invalid BTF type kind `{kind}`")]
     InvalidTypeKind {
@@ -158,6 +166,56 @@ pub enum BtfError {
     /// unable to get symbol name
     #[error("Unable to get symbol name")]
     InvalidSymbolName,
+
+    /// a field path doesn't match the type it's applied to
+    #[error("invalid field path `{path}`")]
+    InvalidFieldPath {
+        /// the field path
+        path: String,
+    },
+
+    /// the size of a field doesn't match the size of the type it's read as
+    #[error("field `{path}` is {size} bytes, expected {expected}")]
+    InvalidFieldSize {
+        /// the field path
+        path: String,
+        /// the size of the field
+        size: usize,
+        /// the size of the type the field is read as
+        expected: usize,
+    },
+
+    /// a field lies outside of the buffer it's read from
+    #[error("field `{path}` is out of bounds of the {len} bytes buffer")]
+    FieldOutOfBounds {
+        /// the field path
+        path: String,
+        /// the length of the buffer
+        len: usize,
+    },
+
+    /// the size of a type doesn't match the size of the Rust type mirroring it
+    #[error("type id {type_id} is {btf_size} bytes, the Rust type is {size}")]
+    LayoutSizeMismatch {
+        /// the type id
+        type_id: u32,
+        /// the size of the BTF type
+        btf_size: usize,
+        /// the size of the Rust type
+        size: usize,
+    },
+
+    /// the offset of a field doesn't match the offset of the Rust field
+    /// mirroring it
+    #[error("field `{path}` is at offset {btf_offset}, the Rust field is at offset {offset}")]
+    LayoutOffsetMismatch {
+        /// the field path
+        path: String,
+        /// the offset of the field in the BTF type
+        btf_offset: usize,
+        /// the offset of the field in the Rust type
+        offset: usize,
+    },
 }
 
 /// Available BTF features
@@ -183,7 +241,7 @@ pub struct BtfFeatures {
 #[derive(Clone, Debug)]
 pub struct Btf {
     header: btf_header,
-    strings: Vec<u8>,
+    strings: Cow<'static, [u8]>,
     types: BtfTypes,
     _endianness: Endianness,
 }
@@ -202,7 +260,7 @@ impl Btf {
                 str_off: 0x00,
                 str_len: 0x00,
             },
-            strings: vec![0],
+            strings: Cow::Owned(vec![0]),
             types: BtfTypes::default(),
             _endianness: Endianness::default(),
         }
@@ -216,7 +274,9 @@ impl Btf {
     pub fn add_string(&mut self, name: String) -> u32 {
         let str = CString::new(name).unwrap();
         let name_offset = self.strings.len();
-        self.strings.extend(str.as_c_str().to_bytes_with_nul());
+        self.strings
+            .to_mut()
+            .extend(str.as_c_str().to_bytes_with_nul());
         self.header.str_len = self.strings.len() as u32;
         name_offset as u32
     }
@@ -238,6 +298,9 @@ impl Btf {
     }
 
     /// Loads BTF metadata from the given `path`.
+    ///
+    /// The contents of the file are read in a single buffer, which is then
+    /// reused to hold the string section, see [`Btf::parse_owned`].
     #[cfg(not(feature = "no_std"))]
     pub fn parse_file<P: AsRef<std::path::Path>>(
         path: P,
@@ -245,8 +308,8 @@ impl Btf {
     ) -> Result<Btf, BtfError> {
         use std::{borrow::ToOwned, fs};
         let path = path.as_ref();
-        Btf::parse(
-            &fs::read(path).map_err(|error| BtfError::FileError {
+        Btf::parse_owned(
+            fs::read(path).map_err(|error| BtfError::FileError {
                 path: path.to_owned(),
                 error,
             })?,
@@ -256,6 +319,71 @@ impl Btf {
 
     /// Parses BTF from binary data of the given endianness
     pub fn parse(data: &[u8], endianness: Endianness) -> Result<Btf, BtfError> {
+        let (header, types, str_off, str_len) = Btf::parse_header_and_types(data, endianness)?;
+        let strings = Cow::Owned(data[str_off..str_off + str_len].to_vec());
+
+        Ok(Btf {
+            header,
+            strings,
+            types,
+            _endianness: endianness,
+        })
+    }
+
+    /// Parses BTF embedded in the binary, in native endianness.
+    ///
+    /// Like [`Btf::parse_owned`] the string section isn't copied, it's
+    /// borrowed from `data` instead. This makes it cheap to ship a known BTF
+    /// blob, for example the BTF of a specific kernel, within a program that
+    /// can't read `/sys/kernel/btf/vmlinux` at runtime:
+    ///
+    /// ```ignore
+    /// use aya_obj::btf::Btf;
+    ///
+    /// // generated with `cp /sys/kernel/btf/vmlinux vmlinux.btf` on the target kernel
+    /// static VMLINUX_BTF: &[u8] = include_bytes!("vmlinux.btf");
+    ///
+    /// let btf = Btf::from_embedded(VMLINUX_BTF)?;
+    /// # Ok::<(), aya_obj::btf::BtfError>(())
+    /// ```
+    pub fn from_embedded(data: &'static [u8]) -> Result<Btf, BtfError> {
+        let endianness = Endianness::default();
+        let (header, types, str_off, str_len) = Btf::parse_header_and_types(data, endianness)?;
+
+        Ok(Btf {
+            header,
+            strings: Cow::Borrowed(&data[str_off..str_off + str_len]),
+            types,
+            _endianness: endianness,
+        })
+    }
+
+    /// Parses BTF from an owned buffer of the given endianness.
+    ///
+    /// Unlike [`Btf::parse`], the string section isn't copied: `data` is
+    /// truncated in place to hold it. This halves the peak memory used to
+    /// parse large BTF blobs like the kernel's `vmlinux` BTF.
+    pub fn parse_owned(mut data: Vec<u8>, endianness: Endianness) -> Result<Btf, BtfError> {
+        let (header, types, str_off, str_len) = Btf::parse_header_and_types(&data, endianness)?;
+        data.truncate(str_off + str_len);
+        data.drain(..str_off);
+        // give back the memory used by the header and the type section
+        data.shrink_to_fit();
+
+        Ok(Btf {
+            header,
+            strings: Cow::Owned(data),
+            types,
+            _endianness: endianness,
+        })
+    }
+
+    // Parses the header and the type section, returning them together with
+    // the offset and length of the string section.
+    fn parse_header_and_types(
+        data: &[u8],
+        endianness: Endianness,
+    ) -> Result<(btf_header, BtfTypes, usize, usize), BtfError> {
         if data.len() < mem::size_of::<btf_header>() {
             return Err(BtfError::InvalidHeader);
         }
@@ -269,15 +397,9 @@ impl Btf {
             return Err(BtfError::InvalidHeader);
         }
 
-        let strings = data[str_off..str_off + str_len].to_vec();
         let types = Btf::read_type_info(&header, data, endianness)?;
 
-        Ok(Btf {
-            header,
-            strings,
-            types,
-            _endianness: endianness,
-        })
+        Ok((header, types, str_off, str_len))
     }
 
     fn read_type_info(
@@ -332,10 +454,57 @@ impl Btf {
         Ok(s.to_string_lossy())
     }
 
+    // renames the DATASEC named `old`, if any
+    pub(crate) fn rename_datasec(&mut self, old: &str, new: &str) -> Result<(), BtfError> {
+        let mut index = None;
+        for (i, ty) in self.types.types.iter().enumerate() {
+            if let BtfType::DataSec(d) = ty {
+                if self.string_at(d.name_offset)? == old {
+                    index = Some(i);
+                    break;
+                }
+            }
+        }
+        if let Some(i) = index {
+            let name_offset = self.add_string(new.to_string());
+            if let BtfType::DataSec(d) = &mut self.types.types[i] {
+                d.name_offset = name_offset;
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn type_by_id(&self, type_id: u32) -> Result<&BtfType, BtfError> {
         self.types.type_by_id(type_id)
     }
 
+    // returns the variables of the `.ksyms` DATASEC, and whether each of them
+    // is typed or declared as `void`
+    pub(crate) fn ksyms(&self) -> Result<Vec<(String, bool)>, BtfError> {
+        for ty in self.types() {
+            let d = match ty {
+                BtfType::DataSec(d) => d,
+                _ => continue,
+            };
+            if self.string_at(d.name_offset)? != KSYMS_SECTION {
+                continue;
+            }
+
+            let mut ksyms = Vec::new();
+            for entry in &d.entries {
+                // extern functions (kfuncs) are FUNC entries and aren't ksyms
+                if let BtfType::Var(var) = self.type_by_id(entry.btf_type)? {
+                    let name = self.string_at(var.name_offset)?.to_string();
+                    let typed = self.resolve_type(var.btf_type)? != 0;
+                    ksyms.push((name, typed));
+                }
+            }
+            return Ok(ksyms);
+        }
+
+        Ok(Vec::new())
+    }
+
     pub(crate) fn resolve_type(&self, root_type_id: u32) -> Result<u32, BtfError> {
         self.types.resolve_type(root_type_id)
     }
@@ -344,10 +513,30 @@ impl Btf {
         self.string_at(ty.name_offset())
     }
 
+    /// Returns true if `type_id` is a struct with a `struct bpf_spin_lock` member.
+    pub(crate) fn has_spin_lock(&self, type_id: u32) -> Result<bool, BtfError> {
+        let s = match self.type_by_id(self.resolve_type(type_id)?)? {
+            BtfType::Struct(s) => s,
+            _ => return Ok(false),
+        };
+        for m in &s.members {
+            let ty = self.type_by_id(self.resolve_type(m.btf_type)?)?;
+            if matches!(ty, BtfType::Struct(_)) && self.type_name(ty)? == "bpf_spin_lock" {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     pub(crate) fn err_type_name(&self, ty: &BtfType) -> Option<String> {
         self.string_at(ty.name_offset()).ok().map(String::from)
     }
 
+    /// Returns the name of the type with the given id
+    pub fn type_name_by_id(&self, type_id: u32) -> Result<Cow<'_, str>, BtfError> {
+        self.type_name(self.type_by_id(type_id)?)
+    }
+
     /// Returns a type id matching the type name and [BtfKind]
     pub fn id_by_type_name_kind(&self, name: &str, kind: BtfKind) -> Result<u32, BtfError> {
         for (type_id, ty) in self.types().enumerate() {
@@ -372,7 +561,8 @@ impl Btf {
             let ty = self.types.type_by_id(type_id)?;
             let size = match ty {
                 BtfType::Array(Array { array, .. }) => {
-                    n_elems = array.len;
+                    // multi-dimensional arrays are arrays of arrays
+                    n_elems *= array.len;
                     type_id = array.element_type;
                     continue;
                 }
@@ -395,13 +585,322 @@ impl Btf {
         })
     }
 
+    /// Reads the field at `field_path` of a value of type `type_id` from
+    /// `bytes`.
+    ///
+    /// `field_path` is a list of member names separated by `.`, where array
+    /// elements can be selected with `[index]`, eg `"foo.bar[2].baz"`. Members
+    /// of anonymous structs and unions are looked up as if they were members of
+    /// the enclosing type. Bitfields can't be read.
+    ///
+    /// This is useful to decode data produced by eBPF programs, eg perf
+    /// events, using the BTF of the program or of the kernel instead of
+    /// mirroring the C structs in Rust.
+    pub fn read_field<T: Pod>(
+        &self,
+        type_id: u32,
+        field_path: &str,
+        bytes: &[u8],
+    ) -> Result<T, BtfError> {
+        let (field_type_id, offset) = self.field_offset(type_id, field_path)?;
+
+        let size = self.type_size(field_type_id)?;
+        let expected = mem::size_of::<T>();
+        if size != expected {
+            return Err(BtfError::InvalidFieldSize {
+                path: field_path.to_string(),
+                size,
+                expected,
+            });
+        }
+        if offset + size > bytes.len() {
+            return Err(BtfError::FieldOutOfBounds {
+                path: field_path.to_string(),
+                len: bytes.len(),
+            });
+        }
+
+        // Safety: T is Pod and the bounds have been checked above
+        Ok(unsafe { ptr::read_unaligned(bytes[offset..].as_ptr() as *const T) })
+    }
+
+    /// Checks that `T` has the same layout as the type `type_id`.
+    ///
+    /// The size of `T` is compared to the size of `type_id`, and each
+    /// `(field_path, offset)` pair in `field_offsets` is compared to the
+    /// offset of the corresponding field in `type_id`. Field paths use the
+    /// same syntax as [`Btf::read_field`]. The offsets are typically
+    /// obtained with `core::mem::offset_of!`.
+    ///
+    /// This is meant to be used in tests, to make sure that Rust structs
+    /// mirroring C types stay in sync with them.
+    pub fn check_pod_layout<T: Pod>(
+        &self,
+        type_id: u32,
+        field_offsets: &[(&str, usize)],
+    ) -> Result<(), BtfError> {
+        let btf_size = self.type_size(type_id)?;
+        let size = mem::size_of::<T>();
+        if btf_size != size {
+            return Err(BtfError::LayoutSizeMismatch {
+                type_id,
+                btf_size,
+                size,
+            });
+        }
+
+        for (path, offset) in field_offsets {
+            let (_, btf_offset) = self.field_offset(type_id, path)?;
+            if btf_offset != *offset {
+                return Err(BtfError::LayoutOffsetMismatch {
+                    path: path.to_string(),
+                    btf_offset,
+                    offset: *offset,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // returns the type id and the byte offset of the field at `field_path`
+    fn field_offset(&self, type_id: u32, field_path: &str) -> Result<(u32, usize), BtfError> {
+        let invalid_path = || BtfError::InvalidFieldPath {
+            path: field_path.to_string(),
+        };
+
+        let mut type_id = type_id;
+        let mut offset = 0;
+        for component in field_path.split('.').filter(|c| !c.is_empty()) {
+            let (name, mut indices) = match component.find('[') {
+                Some(i) => (&component[..i], &component[i..]),
+                None => (component, ""),
+            };
+
+            if !name.is_empty() {
+                let (member_type_id, bit_offset) = self
+                    .find_member(self.resolve_type(type_id)?, name)?
+                    .ok_or_else(invalid_path)?;
+                if bit_offset % 8 != 0 {
+                    return Err(invalid_path());
+                }
+                type_id = member_type_id;
+                offset += bit_offset / 8;
+            }
+
+            while !indices.is_empty() {
+                let end = indices.find(']').ok_or_else(invalid_path)?;
+                let index = indices[1..end]
+                    .parse::<usize>()
+                    .map_err(|_| invalid_path())?;
+                indices = &indices[end + 1..];
+                if !indices.is_empty() && !indices.starts_with('[') {
+                    return Err(invalid_path());
+                }
+
+                let array = match self.type_by_id(self.resolve_type(type_id)?)? {
+                    BtfType::Array(Array { array, .. }) => array,
+                    _ => return Err(invalid_path()),
+                };
+                if index >= array.len as usize {
+                    return Err(invalid_path());
+                }
+                type_id = array.element_type;
+                offset += index * self.type_size(type_id)?;
+            }
+        }
+
+        Ok((type_id, offset))
+    }
+
+    // finds the member `name` of the struct or union `type_id`, looking into
+    // anonymous members too, and returns its type id and bit offset
+    fn find_member(&self, type_id: u32, name: &str) -> Result<Option<(u32, usize)>, BtfError> {
+        let ty = self.type_by_id(type_id)?;
+        let members = match ty.members() {
+            Some(members) => members,
+            None => return Ok(None),
+        };
+        for member in members {
+            // members() returned Some, so these do too
+            let bit_offset = ty.member_bit_offset(member).unwrap();
+            if member.name_offset == 0 {
+                let anon_type_id = self.resolve_type(member.btf_type)?;
+                if let Some((member_type_id, member_offset)) =
+                    self.find_member(anon_type_id, name)?
+                {
+                    return Ok(Some((member_type_id, bit_offset + member_offset)));
+                }
+            } else if self.string_at(member.name_offset)? == name {
+                if ty.member_bit_field_size(member).unwrap() != 0 {
+                    return Ok(None);
+                }
+                return Ok(Some((member.btf_type, bit_offset)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Renders the type with the given id as a string, for debugging.
+    ///
+    /// The output contains the kind, name and size of the type. Struct and
+    /// union members are listed with their bit offset and the name of their
+    /// type, enums with their values. Referenced types are only rendered by
+    /// name, so self referencing types are rendered without recursing.
+    pub fn type_to_string(&self, type_id: u32) -> Result<String, BtfError> {
+        let ty = self.type_by_id(type_id)?;
+        let mut out = format!("{} {} (id {}", ty.kind(), self.display_name(ty)?, type_id);
+        if let Ok(size) = self.type_size(type_id) {
+            out.push_str(&format!(", size {}", size));
+        }
+        out.push(')');
+
+        match ty {
+            BtfType::Struct(_) | BtfType::Union(_) => {
+                for member in ty.members().unwrap() {
+                    let name = self.string_at(member.name_offset)?;
+                    out.push_str(&format!(
+                        "\n    {}: {} (bit offset {}",
+                        if name.is_empty() { "<anon>" } else { &*name },
+                        self.type_ref_name(member.btf_type),
+                        ty.member_bit_offset(member).unwrap()
+                    ));
+                    let bit_size = ty.member_bit_field_size(member).unwrap();
+                    if bit_size != 0 {
+                        out.push_str(&format!(", bit size {}", bit_size));
+                    }
+                    out.push(')');
+                }
+            }
+            BtfType::Enum(e) => {
+                for variant in &e.variants {
+                    let name = self.string_at(variant.name_offset)?;
+                    out.push_str(&format!("\n    {} = {}", name, variant.value));
+                }
+            }
+            BtfType::Array(Array { array, .. }) => {
+                out.push_str(&format!(
+                    " -> {}[{}]",
+                    self.type_ref_name(array.element_type),
+                    array.len
+                ));
+            }
+            other => {
+                if let Some(next) = other.btf_type() {
+                    out.push_str(&format!(" -> {}", self.type_ref_name(next)));
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn display_name(&self, ty: &BtfType) -> Result<Cow<'_, str>, BtfError> {
+        let name = self.type_name(ty)?;
+        if name.is_empty() {
+            Ok(Cow::Borrowed("<anon>"))
+        } else {
+            Ok(name)
+        }
+    }
+
+    // Renders a reference to a type the way it would be written in C, eg
+    // `const struct foo *` or `int (*)[4]`. The declarator is built inside-out
+    // while walking from the root type to the base type: qualifiers apply to
+    // the pointer that follows them, and pointers to arrays are parenthesized.
+    // Named types end the walk so cycles can't recurse.
+    fn type_ref_name(&self, root_type_id: u32) -> String {
+        fn render(qualifiers: &[&str], base: &str, declarator: &str) -> String {
+            let mut out = String::new();
+            for qualifier in qualifiers {
+                out.push_str(qualifier);
+                out.push(' ');
+            }
+            out.push_str(base);
+            if declarator.starts_with(['*', '(']) {
+                out.push(' ');
+            }
+            out.push_str(declarator);
+            out
+        }
+
+        let mut declarator = String::new();
+        let mut qualifiers = Vec::new();
+        let mut type_id = root_type_id;
+        for _ in 0..MAX_RESOLVE_DEPTH {
+            let ty = match self.type_by_id(type_id) {
+                Ok(ty) => ty,
+                Err(_) => {
+                    let base = format!("<invalid type {}>", type_id);
+                    return render(&qualifiers, &base, &declarator);
+                }
+            };
+            let name = match self.display_name(ty) {
+                Ok(name) => name,
+                Err(_) => Cow::Borrowed("<invalid name>"),
+            };
+            let base = match ty {
+                BtfType::Unknown => "void".to_string(),
+                BtfType::Struct(_) => format!("struct {}", name),
+                BtfType::Union(_) => format!("union {}", name),
+                BtfType::Enum(_) => format!("enum {}", name),
+                BtfType::Fwd(_) | BtfType::Typedef(_) | BtfType::Int(_) | BtfType::Float(_) => {
+                    name.into_owned()
+                }
+                BtfType::FuncProto(_) => "fn(..)".to_string(),
+                BtfType::Ptr(t) => {
+                    // the qualifiers seen so far apply to this pointer, eg
+                    // `int *const`
+                    let mut ptr = String::from("*");
+                    ptr.push_str(&qualifiers.join(" "));
+                    if !qualifiers.is_empty() && !declarator.is_empty() {
+                        ptr.push(' ');
+                    }
+                    qualifiers.clear();
+                    declarator.insert_str(0, &ptr);
+                    type_id = t.btf_type;
+                    continue;
+                }
+                BtfType::Array(Array { array, .. }) => {
+                    if declarator.starts_with('*') {
+                        declarator = format!("({})", declarator);
+                    }
+                    declarator.push_str(&format!("[{}]", array.len));
+                    type_id = array.element_type;
+                    continue;
+                }
+                BtfType::Const(t) => {
+                    qualifiers.push("const");
+                    type_id = t.btf_type;
+                    continue;
+                }
+                BtfType::Volatile(t) => {
+                    qualifiers.push("volatile");
+                    type_id = t.btf_type;
+                    continue;
+                }
+                other => match other.btf_type() {
+                    Some(next) => {
+                        type_id = next;
+                        continue;
+                    }
+                    None => other.kind().to_string(),
+                },
+            };
+            return render(&qualifiers, &base, &declarator);
+        }
+
+        render(&qualifiers, "...", &declarator)
+    }
+
     /// Encodes the metadata as BTF format
     pub fn to_bytes(&self) -> Vec<u8> {
         // Safety: btf_header is POD
         let mut buf = unsafe { bytes_of::<btf_header>(&self.header).to_vec() };
         // Skip the first type since it's always BtfType::Unknown for type_by_id to work
         buf.extend(self.types.to_bytes());
-        buf.put(self.strings.as_slice());
+        buf.put(&*self.strings);
         buf
     }
 
@@ -453,6 +952,34 @@ impl Btf {
 
                     let mut fixed_ty = d.clone();
 
+                    // .ksyms has no backing ELF section and its variables are
+                    // externs, which the kernel rejects. Like libbpf does, turn
+                    // them into int globals laid out one after the other.
+                    if name == KSYMS_SECTION {
+                        let int_type_id = types.len() as u32;
+                        let int_name_offset = self.add_string("int".to_string());
+                        types.push(BtfType::Int(Int::new(
+                            int_name_offset,
+                            mem::size_of::<i32>() as u32,
+                            IntEncoding::Signed,
+                            0,
+                        )));
+                        let mut offset = 0;
+                        for entry in fixed_ty.entries.iter_mut() {
+                            if let BtfType::Var(var) = &mut types.types[entry.btf_type as usize] {
+                                var.btf_type = int_type_id;
+                                var.linkage = VarLinkage::Global;
+                            }
+                            entry.offset = offset;
+                            entry.size = mem::size_of::<i32>() as u32;
+                            offset += entry.size;
+                        }
+                        debug!("{} {}: fixup size to {}", kind, name, offset);
+                        fixed_ty.size = offset;
+                        types.types[i] = BtfType::DataSec(fixed_ty);
+                        continue;
+                    }
+
                     // Handle any "/" characters in section names
                     // Example: "maps/hashmap"
                     let fixed_name = name.replace('/', ".");
@@ -770,6 +1297,19 @@ impl BtfExt {
         self.relocations.iter()
     }
 
+    /// Returns the func_info records of every section, keyed by section name.
+    ///
+    /// This includes the records of the subprograms in `.text`, not only
+    /// those of the program sections.
+    pub fn func_info(&self) -> &FuncInfo {
+        &self.func_info
+    }
+
+    /// Returns the line_info records of every section, keyed by section name.
+    pub fn line_info(&self) -> &LineInfo {
+        &self.line_info
+    }
+
     pub(crate) fn func_info_rec_size(&self) -> usize {
         self.func_info_rec_size
     }
@@ -777,6 +1317,10 @@ impl BtfExt {
     pub(crate) fn line_info_rec_size(&self) -> usize {
         self.line_info_rec_size
     }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
 }
 
 pub(crate) struct SecInfoIter<'a> {
@@ -989,6 +1533,32 @@ mod tests {
         assert_eq!(data2.len(), 517);
         assert_eq!(data, data2);
 
+        let owned = Btf::parse_owned(data.to_vec(), Endianness::default()).unwrap();
+        assert_eq!(owned.strings, btf.strings);
+
+        let mut embedded = Btf::from_embedded(data).unwrap();
+        assert!(matches!(embedded.strings, Cow::Borrowed(_)));
+        assert_eq!(embedded.to_bytes(), data);
+        assert!(embedded
+            .id_by_type_name_kind("xdp_md", BtfKind::Struct)
+            .is_ok());
+        // adding strings copies the borrowed section
+        let offset = embedded.add_string("foo".to_string());
+        assert!(matches!(embedded.strings, Cow::Owned(_)));
+        assert_eq!(embedded.string_at(offset).unwrap(), "foo");
+        assert_eq!(owned.types.types.len(), btf.types.types.len());
+        for (name, kind) in [
+            ("xdp_md", BtfKind::Struct),
+            ("__u32", BtfKind::Typedef),
+            ("xdp_pass", BtfKind::Func),
+        ] {
+            assert_eq!(
+                owned.id_by_type_name_kind(name, kind).unwrap(),
+                btf.id_by_type_name_kind(name, kind).unwrap()
+            );
+        }
+        assert_eq!(owned.to_bytes(), data);
+
         let ext_data: &[u8] = &[
             0x9f, 0xeb, 0x01, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x14, 0x00,
             0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x1c, 0x00, 0x00, 0x00, 0x30, 0x00, 0x00, 0x00,
@@ -1031,6 +1601,106 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_type_to_string() {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        let int_type_id = btf.add_type(BtfType::Int(Int::new(
+            name_offset,
+            4,
+            IntEncoding::Signed,
+            0,
+        )));
+        let ptr_type_id = btf.add_type(BtfType::Ptr(Ptr::new(0, int_type_id)));
+
+        let members = vec![
+            BtfMember {
+                name_offset: btf.add_string("count".to_string()),
+                btf_type: int_type_id,
+                offset: 0,
+            },
+            BtfMember {
+                name_offset: btf.add_string("next".to_string()),
+                btf_type: ptr_type_id,
+                offset: 64,
+            },
+        ];
+        let name_offset = btf.add_string("node".to_string());
+        let struct_type_id = btf.add_type(BtfType::Struct(Struct::new(name_offset, members, 16)));
+
+        let rendered = btf.type_to_string(struct_type_id).unwrap();
+        assert!(rendered.starts_with("[STRUCT] node"), "{}", rendered);
+        assert!(rendered.contains("size 16"), "{}", rendered);
+        assert!(
+            rendered.contains("count: int (bit offset 0)"),
+            "{}",
+            rendered
+        );
+        assert!(
+            rendered.contains("next: int * (bit offset 64)"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_type_to_string_cycle() {
+        let mut btf = Btf::new();
+        // struct list { struct list *next; }
+        let name_offset = btf.add_string("list".to_string());
+        let next_offset = btf.add_string("next".to_string());
+        let struct_type_id = btf.types.len() as u32;
+        let ptr_type_id = struct_type_id + 1;
+        btf.add_type(BtfType::Struct(Struct::new(
+            name_offset,
+            vec![BtfMember {
+                name_offset: next_offset,
+                btf_type: ptr_type_id,
+                offset: 0,
+            }],
+            8,
+        )));
+        btf.add_type(BtfType::Ptr(Ptr::new(0, struct_type_id)));
+
+        let rendered = btf.type_to_string(struct_type_id).unwrap();
+        assert!(rendered.contains("next: struct list *"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_type_ref_name_declarators() {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        let int_type_id = btf.add_type(BtfType::Int(Int::new(
+            name_offset,
+            4,
+            IntEncoding::Signed,
+            0,
+        )));
+
+        // const int *
+        let const_int = btf.add_type(BtfType::Const(Const::new(int_type_id)));
+        let ptr_const_int = btf.add_type(BtfType::Ptr(Ptr::new(0, const_int)));
+        assert_eq!(btf.type_ref_name(ptr_const_int), "const int *");
+
+        // int *const
+        let ptr_int = btf.add_type(BtfType::Ptr(Ptr::new(0, int_type_id)));
+        let const_ptr_int = btf.add_type(BtfType::Const(Const::new(ptr_int)));
+        assert_eq!(btf.type_ref_name(const_ptr_int), "int *const");
+
+        // int *const *
+        let ptr_const_ptr_int = btf.add_type(BtfType::Ptr(Ptr::new(0, const_ptr_int)));
+        assert_eq!(btf.type_ref_name(ptr_const_ptr_int), "int *const *");
+
+        // int *[4]
+        let array_ptr_int = btf.add_type(BtfType::Array(Array::new(0, ptr_int, int_type_id, 4)));
+        assert_eq!(btf.type_ref_name(array_ptr_int), "int *[4]");
+
+        // int (*)[4]
+        let array_int = btf.add_type(BtfType::Array(Array::new(0, int_type_id, int_type_id, 4)));
+        let ptr_array_int = btf.add_type(BtfType::Ptr(Ptr::new(0, array_int)));
+        assert_eq!(btf.type_ref_name(ptr_array_int), "int (*)[4]");
+    }
+
     #[test]
     fn test_fixup_ptr() {
         let mut btf = Btf::new();
@@ -1205,6 +1875,255 @@ mod tests {
         Btf::parse(&raw, Endianness::default()).unwrap();
     }
 
+    #[test]
+    fn test_read_field() {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("u16".to_string());
+        let u16_type_id =
+            btf.add_type(BtfType::Int(Int::new(name_offset, 2, IntEncoding::None, 0)));
+        let name_offset = btf.add_string("u32".to_string());
+        let u32_type_id =
+            btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+        let name_offset = btf.add_string("u64".to_string());
+        let u64_type_id =
+            btf.add_type(BtfType::Int(Int::new(name_offset, 8, IntEncoding::None, 0)));
+        let array_type_id =
+            btf.add_type(BtfType::Array(Array::new(0, u64_type_id, u32_type_id, 3)));
+
+        // struct inner { u32 a; u64 b[3]; }
+        let a = btf.add_string("a".to_string());
+        let b = btf.add_string("b".to_string());
+        let name_offset = btf.add_string("inner".to_string());
+        let inner_type_id = btf.add_type(BtfType::Struct(Struct::new(
+            name_offset,
+            vec![
+                BtfMember {
+                    name_offset: a,
+                    btf_type: u32_type_id,
+                    offset: 0,
+                },
+                BtfMember {
+                    name_offset: b,
+                    btf_type: array_type_id,
+                    offset: 64,
+                },
+            ],
+            32,
+        )));
+
+        // struct { u32 z; }
+        let z = btf.add_string("z".to_string());
+        let anon_type_id = btf.add_type(BtfType::Struct(Struct::new(
+            0,
+            vec![BtfMember {
+                name_offset: z,
+                btf_type: u32_type_id,
+                offset: 0,
+            }],
+            4,
+        )));
+
+        // struct outer { u16 x; struct inner foo; struct { u32 z; }; }
+        let x = btf.add_string("x".to_string());
+        let foo = btf.add_string("foo".to_string());
+        let name_offset = btf.add_string("outer".to_string());
+        let outer_type_id = btf.add_type(BtfType::Struct(Struct::new(
+            name_offset,
+            vec![
+                BtfMember {
+                    name_offset: x,
+                    btf_type: u16_type_id,
+                    offset: 0,
+                },
+                BtfMember {
+                    name_offset: foo,
+                    btf_type: inner_type_id,
+                    offset: 64,
+                },
+                BtfMember {
+                    name_offset: 0,
+                    btf_type: anon_type_id,
+                    offset: 320,
+                },
+            ],
+            48,
+        )));
+
+        let mut bytes = [0u8; 48];
+        bytes[0..2].copy_from_slice(&7u16.to_ne_bytes());
+        bytes[8..12].copy_from_slice(&42u32.to_ne_bytes());
+        bytes[32..40].copy_from_slice(&0xdead_beefu64.to_ne_bytes());
+        bytes[40..44].copy_from_slice(&9u32.to_ne_bytes());
+
+        assert_eq!(
+            btf.read_field::<u16>(outer_type_id, "x", &bytes).unwrap(),
+            7
+        );
+        assert_eq!(
+            btf.read_field::<u32>(outer_type_id, "foo.a", &bytes)
+                .unwrap(),
+            42
+        );
+        assert_eq!(
+            btf.read_field::<u64>(outer_type_id, "foo.b[2]", &bytes)
+                .unwrap(),
+            0xdead_beef
+        );
+        assert_eq!(
+            btf.read_field::<u32>(outer_type_id, "z", &bytes).unwrap(),
+            9
+        );
+
+        assert!(matches!(
+            btf.read_field::<u64>(outer_type_id, "foo.a", &bytes),
+            Err(BtfError::InvalidFieldSize {
+                size: 4,
+                expected: 8,
+                ..
+            })
+        ));
+        for path in ["foo.c", "foo.b[3]", "foo.b[1", "x.y"] {
+            assert!(matches!(
+                btf.read_field::<u64>(outer_type_id, path, &bytes),
+                Err(BtfError::InvalidFieldPath { .. })
+            ));
+        }
+        assert!(matches!(
+            btf.read_field::<u64>(outer_type_id, "foo.b[2]", &bytes[..36]),
+            Err(BtfError::FieldOutOfBounds { len: 36, .. })
+        ));
+    }
+
+    #[test]
+    fn test_check_pod_layout() {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("u32".to_string());
+        let u32_type_id =
+            btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+        let name_offset = btf.add_string("u64".to_string());
+        let u64_type_id =
+            btf.add_type(BtfType::Int(Int::new(name_offset, 8, IntEncoding::None, 0)));
+
+        // struct foo { u32 a; u64 b; }
+        let a = btf.add_string("a".to_string());
+        let b = btf.add_string("b".to_string());
+        let name_offset = btf.add_string("foo".to_string());
+        let foo_type_id = btf.add_type(BtfType::Struct(Struct::new(
+            name_offset,
+            vec![
+                BtfMember {
+                    name_offset: a,
+                    btf_type: u32_type_id,
+                    offset: 0,
+                },
+                BtfMember {
+                    name_offset: b,
+                    btf_type: u64_type_id,
+                    offset: 64,
+                },
+            ],
+            16,
+        )));
+
+        #[allow(dead_code)]
+        #[derive(Copy, Clone)]
+        #[repr(C)]
+        struct Foo {
+            a: u32,
+            b: u64,
+        }
+        unsafe impl Pod for Foo {}
+
+        // same size as struct foo, but b isn't aligned
+        #[allow(dead_code)]
+        #[derive(Copy, Clone)]
+        #[repr(C, packed)]
+        struct Misaligned {
+            a: u32,
+            b: u64,
+            _pad: u32,
+        }
+        unsafe impl Pod for Misaligned {}
+
+        #[allow(dead_code)]
+        #[derive(Copy, Clone)]
+        #[repr(C, packed)]
+        struct TooSmall {
+            a: u32,
+            b: u64,
+        }
+        unsafe impl Pod for TooSmall {}
+
+        btf.check_pod_layout::<Foo>(foo_type_id, &[("a", 0), ("b", 8)])
+            .unwrap();
+
+        assert!(matches!(
+            btf.check_pod_layout::<Misaligned>(foo_type_id, &[("a", 0), ("b", 4)]),
+            Err(BtfError::LayoutOffsetMismatch {
+                path,
+                btf_offset: 8,
+                offset: 4,
+            }) if path == "b"
+        ));
+        assert!(matches!(
+            btf.check_pod_layout::<TooSmall>(foo_type_id, &[]),
+            Err(BtfError::LayoutSizeMismatch {
+                btf_size: 16,
+                size: 12,
+                ..
+            })
+        ));
+        assert!(matches!(
+            btf.check_pod_layout::<Foo>(foo_type_id, &[("c", 0)]),
+            Err(BtfError::InvalidFieldPath { .. })
+        ));
+    }
+
+    #[test]
+    fn test_fixup_ksyms_datasec() {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("bpf_prog_active".to_string());
+        let var_type_id = btf.add_type(BtfType::Var(Var::new(name_offset, 0, VarLinkage::Extern)));
+
+        let name_offset = btf.add_string(".ksyms".to_string());
+        let variables = vec![DataSecEntry {
+            btf_type: var_type_id,
+            offset: 0,
+            size: 0,
+        }];
+        let datasec_type_id =
+            btf.add_type(BtfType::DataSec(DataSec::new(name_offset, variables, 0)));
+
+        let features = BtfFeatures {
+            btf_datasec: true,
+            ..Default::default()
+        };
+
+        // .ksyms has no section size or symbol offsets to fix up from
+        btf.fixup_and_sanitize(&HashMap::new(), &HashMap::new(), &features)
+            .unwrap();
+
+        if let BtfType::Var(var) = btf.type_by_id(var_type_id).unwrap() {
+            assert_eq!(var.linkage, VarLinkage::Global);
+            assert!(matches!(
+                btf.type_by_id(var.btf_type).unwrap(),
+                BtfType::Int(int) if int.size == 4
+            ));
+        } else {
+            panic!("not a var")
+        }
+        if let BtfType::DataSec(fixed) = btf.type_by_id(datasec_type_id).unwrap() {
+            assert_eq!(fixed.size, 4);
+            assert_eq!(fixed.entries[0].offset, 0);
+            assert_eq!(fixed.entries[0].size, 4);
+        } else {
+            panic!("not a datasec")
+        }
+        // Ensure we can convert to bytes and back again
+        let raw = btf.to_bytes();
+        Btf::parse(&raw, Endianness::default()).unwrap();
+    }
+
     #[test]
     fn test_sanitize_func_and_proto() {
         let mut btf = Btf::new();
@@ -0,0 +1,541 @@
+//! Caching of BTF relocation results.
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    btf::{Btf, BtfRelocationError, RelocationContext},
+    generated::bpf_insn,
+    thiserror::{self, Error},
+    Object,
+};
+
+const MAGIC: &[u8; 8] = b"AYARELOC";
+const VERSION: u32 = 1;
+
+/// The error type returned when decoding or applying a [`RelocationCache`].
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RelocationCacheError {
+    /// The serialized cache is truncated or malformed.
+    #[error("invalid relocation cache data")]
+    InvalidData,
+
+    /// The serialized cache was written with an unsupported format version.
+    #[error("unsupported relocation cache version {version}")]
+    UnsupportedVersion {
+        /// The version found in the data
+        version: u32,
+    },
+
+    /// The cache was computed for a different object.
+    #[error("relocation cache object digest mismatch: expected {expected:#x}, got {actual:#x}")]
+    ObjectDigestMismatch {
+        /// The digest of the object the cache is applied to
+        expected: u64,
+        /// The digest recorded in the cache
+        actual: u64,
+    },
+
+    /// The cache was computed against a different target BTF.
+    #[error("relocation cache BTF digest mismatch: expected {expected:#x}, got {actual:#x}")]
+    BtfDigestMismatch {
+        /// The digest of the target BTF the cache is applied with
+        expected: u64,
+        /// The digest recorded in the cache
+        actual: u64,
+    },
+
+    /// A patch references a program that doesn't exist in the object.
+    #[error("program `{name}` not found")]
+    ProgramNotFound {
+        /// The name of the program
+        name: String,
+    },
+
+    /// A patch references an instruction that doesn't exist in the program.
+    #[error("invalid instruction index {index} for program `{name}`")]
+    InvalidInstructionIndex {
+        /// The name of the program
+        name: String,
+        /// The index of the instruction
+        index: usize,
+    },
+}
+
+/// The result of relocating an [`Object`] against a target [`Btf`].
+///
+/// CO-RE relocation is deterministic given an object and a target BTF, so the
+/// resulting instruction patches can be computed once with
+/// [`Object::relocate_btf_cached`], serialized with
+/// [`to_bytes`](Self::to_bytes) and later applied to an identical object with
+/// [`Object::apply_relocation_cache`] instead of relocating again.
+///
+/// # Format
+///
+/// All integers are little endian.
+///
+/// | Field            | Size          | Description                              |
+/// |------------------|---------------|------------------------------------------|
+/// | magic            | 8             | `AYARELOC`                               |
+/// | version          | 4             | currently `1`                            |
+/// | object digest    | 8             | digest of the object before relocation   |
+/// | BTF digest       | 8             | digest of the target BTF                 |
+/// | program count    | 4             |                                          |
+///
+/// Then for each program:
+///
+/// | Field            | Size          | Description                              |
+/// |------------------|---------------|------------------------------------------|
+/// | name length      | 4             |                                          |
+/// | name             | name length   | UTF-8 program name                       |
+/// | patch count      | 4             |                                          |
+///
+/// Then for each patch of the program:
+///
+/// | Field            | Size          | Description                              |
+/// |------------------|---------------|------------------------------------------|
+/// | index            | 4             | index of the patched instruction         |
+/// | code             | 1             | instruction opcode                       |
+/// | registers        | 1             | `dst_reg` and `src_reg` in the high bits |
+/// | offset           | 2             | instruction offset                       |
+/// | immediate        | 4             | instruction immediate                    |
+///
+/// Digests are 64-bit FNV-1a hashes. The object digest covers the local BTF,
+/// the `.BTF.ext` section and the instructions of all programs, so a cache
+/// only applies to an object prepared for loading the same way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelocationCache {
+    object_digest: u64,
+    btf_digest: u64,
+    patches: BTreeMap<String, Vec<(u32, [u8; 8])>>,
+}
+
+impl RelocationCache {
+    /// Returns the digest of the object the cache was computed for.
+    pub fn object_digest(&self) -> u64 {
+        self.object_digest
+    }
+
+    /// Returns the digest of the target BTF the cache was computed against.
+    pub fn btf_digest(&self) -> u64 {
+        self.btf_digest
+    }
+
+    /// Returns the number of patched instructions.
+    pub fn len(&self) -> usize {
+        self.patches.values().map(Vec::len).sum()
+    }
+
+    /// Returns `true` if relocation didn't patch any instruction.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Serializes the cache.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+        buf.extend_from_slice(&self.object_digest.to_le_bytes());
+        buf.extend_from_slice(&self.btf_digest.to_le_bytes());
+        buf.extend_from_slice(&(self.patches.len() as u32).to_le_bytes());
+        for (name, patches) in &self.patches {
+            buf.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            buf.extend_from_slice(name.as_bytes());
+            buf.extend_from_slice(&(patches.len() as u32).to_le_bytes());
+            for (index, ins) in patches {
+                buf.extend_from_slice(&index.to_le_bytes());
+                buf.extend_from_slice(ins);
+            }
+        }
+        buf
+    }
+
+    /// Deserializes a cache serialized with [`to_bytes`](Self::to_bytes).
+    pub fn from_bytes(data: &[u8]) -> Result<RelocationCache, RelocationCacheError> {
+        let mut reader = Reader { data };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(RelocationCacheError::InvalidData);
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(RelocationCacheError::UnsupportedVersion { version });
+        }
+        let object_digest = reader.u64()?;
+        let btf_digest = reader.u64()?;
+
+        let mut patches = BTreeMap::new();
+        for _ in 0..reader.u32()? {
+            let len = reader.u32()? as usize;
+            let name = core::str::from_utf8(reader.take(len)?)
+                .map_err(|_| RelocationCacheError::InvalidData)?
+                .to_string();
+            let count = reader.u32()?;
+            let mut program_patches = Vec::new();
+            for _ in 0..count {
+                let index = reader.u32()?;
+                let ins = reader.take(8)?.try_into().unwrap();
+                program_patches.push((index, ins));
+            }
+            patches.insert(name, program_patches);
+        }
+        if !reader.data.is_empty() {
+            return Err(RelocationCacheError::InvalidData);
+        }
+
+        Ok(RelocationCache {
+            object_digest,
+            btf_digest,
+            patches,
+        })
+    }
+}
+
+impl Object {
+    /// Relocates programs like [`relocate_btf_with_context`](Self::relocate_btf_with_context)
+    /// and returns the resulting instruction patches as a [`RelocationCache`].
+    pub fn relocate_btf_cached(
+        &mut self,
+        target_btf: &Btf,
+        ctx: &RelocationContext,
+    ) -> Result<RelocationCache, BtfRelocationError> {
+        let object_digest = self.relocation_digest();
+        let original = self
+            .programs
+            .iter()
+            .map(|(name, program)| (name.clone(), program.function.instructions.clone()))
+            .collect::<Vec<_>>();
+
+        self.relocate_btf_with_context(target_btf, ctx)?;
+
+        let mut patches = BTreeMap::new();
+        for (name, instructions) in original {
+            let relocated = &self.programs[&name].function.instructions;
+            let program_patches = instructions
+                .iter()
+                .map(encode_insn)
+                .zip(relocated.iter().map(encode_insn))
+                .enumerate()
+                .filter(|(_, (old, new))| old != new)
+                .map(|(index, (_, new))| (index as u32, new))
+                .collect::<Vec<_>>();
+            if !program_patches.is_empty() {
+                patches.insert(name, program_patches);
+            }
+        }
+
+        Ok(RelocationCache {
+            object_digest,
+            btf_digest: digest(&target_btf.to_bytes()),
+            patches,
+        })
+    }
+
+    /// Applies relocation results computed with
+    /// [`relocate_btf_cached`](Self::relocate_btf_cached) instead of
+    /// relocating against `target_btf`.
+    ///
+    /// The cache is only applied if it was computed for this object and
+    /// against `target_btf`, otherwise the object is left untouched.
+    pub fn apply_relocation_cache(
+        &mut self,
+        cache: &RelocationCache,
+        target_btf: &Btf,
+    ) -> Result<(), RelocationCacheError> {
+        let object_digest = self.relocation_digest();
+        if cache.object_digest != object_digest {
+            return Err(RelocationCacheError::ObjectDigestMismatch {
+                expected: object_digest,
+                actual: cache.object_digest,
+            });
+        }
+        let btf_digest = digest(&target_btf.to_bytes());
+        if cache.btf_digest != btf_digest {
+            return Err(RelocationCacheError::BtfDigestMismatch {
+                expected: btf_digest,
+                actual: cache.btf_digest,
+            });
+        }
+
+        // validate all the patches first so that a bad cache doesn't leave
+        // the object half relocated
+        for (name, patches) in &cache.patches {
+            let program = self
+                .programs
+                .get(name)
+                .ok_or_else(|| RelocationCacheError::ProgramNotFound { name: name.clone() })?;
+            let len = program.function.instructions.len();
+            if let Some((index, _)) = patches.iter().find(|(index, _)| *index as usize >= len) {
+                return Err(RelocationCacheError::InvalidInstructionIndex {
+                    name: name.clone(),
+                    index: *index as usize,
+                });
+            }
+        }
+
+        for (name, patches) in &cache.patches {
+            let instructions = &mut self.programs.get_mut(name).unwrap().function.instructions;
+            for (index, ins) in patches {
+                instructions[*index as usize] = decode_insn(ins);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn relocation_digest(&self) -> u64 {
+        let mut buf = Vec::new();
+        if let Some(btf) = &self.btf {
+            buf.extend_from_slice(&btf.to_bytes());
+        }
+        if let Some(btf_ext) = &self.btf_ext {
+            buf.extend_from_slice(btf_ext.data());
+        }
+        let mut names = self.programs.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(0);
+            for ins in &self.programs[name].function.instructions {
+                buf.extend_from_slice(&encode_insn(ins));
+            }
+        }
+        digest(&buf)
+    }
+}
+
+// 64-bit FNV-1a
+fn digest(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn encode_insn(ins: &bpf_insn) -> [u8; 8] {
+    let [off0, off1] = ins.off.to_le_bytes();
+    let [imm0, imm1, imm2, imm3] = ins.imm.to_le_bytes();
+    [
+        ins.code,
+        ins.dst_reg() | ins.src_reg() << 4,
+        off0,
+        off1,
+        imm0,
+        imm1,
+        imm2,
+        imm3,
+    ]
+}
+
+fn decode_insn(data: &[u8; 8]) -> bpf_insn {
+    let mut ins = unsafe { core::mem::zeroed::<bpf_insn>() };
+    ins.code = data[0];
+    ins.set_dst_reg(data[1] & 0x0f);
+    ins.set_src_reg(data[1] >> 4);
+    ins.off = i16::from_le_bytes([data[2], data[3]]);
+    ins.imm = i32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    ins
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], RelocationCacheError> {
+        if self.data.len() < len {
+            return Err(RelocationCacheError::InvalidData);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, RelocationCacheError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, RelocationCacheError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{ffi::CString, string::ToString, vec};
+    use core::mem;
+
+    use object::Endianness;
+
+    use super::*;
+    use crate::{
+        btf::{BtfExt, BtfMember, BtfType, Int, IntEncoding, Struct},
+        generated::{
+            bpf_core_relo, bpf_core_relo_kind::BPF_CORE_FIELD_BYTE_OFFSET, btf_ext_header,
+        },
+        obj::{tests::fake_func, KernelVersion},
+        util::bytes_of,
+        Program, ProgramSection,
+    };
+
+    // struct foo { [int x;] int a; int b; }
+    fn struct_btf(with_x: bool) -> (Btf, u32) {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        let int_id = btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+        let mut names = vec!["a", "b"];
+        if with_x {
+            names.insert(0, "x");
+        }
+        let members = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| BtfMember {
+                name_offset: btf.add_string(name.to_string()),
+                btf_type: int_id,
+                offset: i as u32 * 32,
+            })
+            .collect::<Vec<_>>();
+        let size = members.len() as u32 * 4;
+        let name_offset = btf.add_string("foo".to_string());
+        let struct_id = btf.add_type(BtfType::Struct(Struct::new(name_offset, members, size)));
+        (btf, struct_id)
+    }
+
+    // r0 = 0; r0 = *(u32 *)(r1 + 4) with a relocation for foo->b
+    fn fake_obj() -> Object {
+        let (mut btf, struct_id) = struct_btf(false);
+        let sec_name_off = btf.add_string("kprobe/foo".to_string());
+        let access_str_off = btf.add_string("0:1".to_string());
+
+        let rec_size = mem::size_of::<bpf_core_relo>();
+        let header = btf_ext_header {
+            magic: 0xeb9f,
+            version: 1,
+            flags: 0,
+            hdr_len: mem::size_of::<btf_ext_header>() as u32,
+            func_info_off: 0,
+            func_info_len: 0,
+            line_info_off: 0,
+            line_info_len: 0,
+            core_relo_off: 0,
+            core_relo_len: (4 + 8 + rec_size) as u32,
+        };
+        let mut data = unsafe { bytes_of(&header) }.to_vec();
+        data.extend((rec_size as u32).to_ne_bytes());
+        data.extend(sec_name_off.to_ne_bytes());
+        data.extend(1u32.to_ne_bytes());
+        let relo = bpf_core_relo {
+            insn_off: mem::size_of::<bpf_insn>() as u32,
+            type_id: struct_id,
+            access_str_off,
+            kind: BPF_CORE_FIELD_BYTE_OFFSET,
+        };
+        data.extend(unsafe { bytes_of(&relo) });
+        let btf_ext = BtfExt::parse(&data, Endianness::default(), &btf).unwrap();
+
+        let mut mov = unsafe { mem::zeroed::<bpf_insn>() };
+        mov.code = 0xb7;
+        let mut ldx = unsafe { mem::zeroed::<bpf_insn>() };
+        ldx.code = 0x61;
+        ldx.set_src_reg(1);
+        ldx.off = 4;
+
+        let mut obj = Object::new(
+            Endianness::default(),
+            CString::new("GPL").unwrap(),
+            KernelVersion::Any,
+        );
+        obj.btf = Some(btf);
+        obj.btf_ext = Some(btf_ext);
+        obj.programs.insert(
+            "foo".to_string(),
+            Program {
+                license: CString::new("GPL").unwrap(),
+                kernel_version: KernelVersion::Any,
+                section: ProgramSection::KProbe {
+                    name: "foo".to_string(),
+                },
+                function: fake_func("foo", vec![mov, ldx]),
+            },
+        );
+        obj
+    }
+
+    fn instructions(obj: &Object) -> Vec<[u8; 8]> {
+        obj.programs["foo"]
+            .function
+            .instructions
+            .iter()
+            .map(encode_insn)
+            .collect()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let (target_btf, _) = struct_btf(true);
+
+        let mut relocated = fake_obj();
+        let cache = relocated
+            .relocate_btf_cached(&target_btf, &RelocationContext::default())
+            .unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(relocated.programs["foo"].function.instructions[1].off, 8);
+
+        let decoded = RelocationCache::from_bytes(&cache.to_bytes()).unwrap();
+        assert_eq!(decoded, cache);
+
+        let mut obj = fake_obj();
+        obj.apply_relocation_cache(&decoded, &target_btf).unwrap();
+        assert_eq!(instructions(&obj), instructions(&relocated));
+    }
+
+    #[test]
+    fn test_digest_mismatch() {
+        let (target_btf, _) = struct_btf(true);
+        let mut relocated = fake_obj();
+        let cache = relocated
+            .relocate_btf_cached(&target_btf, &RelocationContext::default())
+            .unwrap();
+
+        let mut obj = fake_obj();
+        let (other_btf, _) = struct_btf(false);
+        assert!(matches!(
+            obj.apply_relocation_cache(&cache, &other_btf),
+            Err(RelocationCacheError::BtfDigestMismatch { .. })
+        ));
+        assert_eq!(instructions(&obj), instructions(&fake_obj()));
+
+        // the cache was computed before relocation
+        assert!(matches!(
+            relocated.apply_relocation_cache(&cache, &target_btf),
+            Err(RelocationCacheError::ObjectDigestMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let (target_btf, _) = struct_btf(true);
+        let cache = fake_obj()
+            .relocate_btf_cached(&target_btf, &RelocationContext::default())
+            .unwrap();
+        let data = cache.to_bytes();
+
+        assert_eq!(
+            RelocationCache::from_bytes(&data[..data.len() - 1]),
+            Err(RelocationCacheError::InvalidData)
+        );
+        assert_eq!(
+            RelocationCache::from_bytes(b"NOTACACHE"),
+            Err(RelocationCacheError::InvalidData)
+        );
+        let mut data = data;
+        data[8] = 2;
+        assert_eq!(
+            RelocationCache::from_bytes(&data),
+            Err(RelocationCacheError::UnsupportedVersion { version: 2 })
+        );
+    }
+}
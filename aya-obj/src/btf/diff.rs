@@ -0,0 +1,393 @@
+//! Comparison of BTF blobs.
+
+use core::ptr;
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    btf::{Btf, BtfKind, BtfType, MAX_RESOLVE_DEPTH},
+    util::HashMap,
+};
+
+/// A difference between two BTF blobs, as returned by [`Btf::diff`].
+///
+/// Types are identified by their kind and name, and struct and union fields
+/// by their name. Fields of anonymous structs and unions are reported as
+/// fields of the enclosing type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BtfChange {
+    /// A type only exists in the new BTF.
+    TypeAdded {
+        /// The kind of the type
+        kind: BtfKind,
+        /// The name of the type
+        name: String,
+    },
+    /// A type only exists in the old BTF.
+    TypeRemoved {
+        /// The kind of the type
+        kind: BtfKind,
+        /// The name of the type
+        name: String,
+    },
+    /// The size of a type changed.
+    SizeChanged {
+        /// The kind of the type
+        kind: BtfKind,
+        /// The name of the type
+        name: String,
+        /// The size in the old BTF
+        old_size: u32,
+        /// The size in the new BTF
+        new_size: u32,
+    },
+    /// A field only exists in the new BTF.
+    FieldAdded {
+        /// The name of the struct or union
+        type_name: String,
+        /// The name of the field
+        field: String,
+        /// The bit offset of the field
+        bit_offset: usize,
+    },
+    /// A field only exists in the old BTF.
+    FieldRemoved {
+        /// The name of the struct or union
+        type_name: String,
+        /// The name of the field
+        field: String,
+        /// The bit offset of the field
+        bit_offset: usize,
+    },
+    /// A field is at a different offset in the new BTF.
+    FieldMoved {
+        /// The name of the struct or union
+        type_name: String,
+        /// The name of the field
+        field: String,
+        /// The bit offset in the old BTF
+        old_bit_offset: usize,
+        /// The bit offset in the new BTF
+        new_bit_offset: usize,
+    },
+    /// A field was removed and a field was added at the same offset.
+    FieldRenamed {
+        /// The name of the struct or union
+        type_name: String,
+        /// The name of the field in the old BTF
+        old_name: String,
+        /// The name of the field in the new BTF
+        new_name: String,
+        /// The bit offset of the field
+        bit_offset: usize,
+    },
+}
+
+impl Btf {
+    /// Computes the differences between this BTF and `other`.
+    ///
+    /// Named types are matched by kind and name, anonymous types are
+    /// ignored. If several types have the same kind and name, only the first
+    /// one is compared. For the types present in both, changes of size and of
+    /// struct and union fields are reported.
+    ///
+    /// This can be used to predict which CO-RE relocations are affected
+    /// between two kernel versions.
+    pub fn diff(&self, other: &Btf) -> Vec<BtfChange> {
+        let old_types = self.named_types();
+        let new_types = other.named_types();
+        let old_index = type_index(&old_types);
+        let new_index = type_index(&new_types);
+
+        let mut changes = Vec::new();
+        for (kind, name, ty) in &old_types {
+            if !ptr::eq(old_index[&(*kind, name.as_str())], *ty) {
+                // a duplicate
+                continue;
+            }
+            let new_ty = match new_index.get(&(*kind, name.as_str())) {
+                Some(new_ty) => *new_ty,
+                None => {
+                    changes.push(BtfChange::TypeRemoved {
+                        kind: *kind,
+                        name: name.clone(),
+                    });
+                    continue;
+                }
+            };
+
+            if let (Some(old_size), Some(new_size)) = (ty.size(), new_ty.size()) {
+                if old_size != new_size {
+                    changes.push(BtfChange::SizeChanged {
+                        kind: *kind,
+                        name: name.clone(),
+                        old_size,
+                        new_size,
+                    });
+                }
+            }
+
+            if ty.is_composite() && new_ty.is_composite() {
+                let mut old_fields = Vec::new();
+                self.collect_fields(ty, 0, 0, &mut old_fields);
+                let mut new_fields = Vec::new();
+                other.collect_fields(new_ty, 0, 0, &mut new_fields);
+                diff_fields(name, &old_fields, &new_fields, &mut changes);
+            }
+        }
+
+        for (kind, name, ty) in &new_types {
+            let key = (*kind, name.as_str());
+            if ptr::eq(new_index[&key], *ty) && !old_index.contains_key(&key) {
+                changes.push(BtfChange::TypeAdded {
+                    kind: *kind,
+                    name: name.clone(),
+                });
+            }
+        }
+
+        changes
+    }
+
+    // returns the named types in id order
+    fn named_types(&self) -> Vec<(BtfKind, String, &BtfType)> {
+        self.types()
+            .filter(|ty| ty.name_offset() != 0)
+            .filter_map(|ty| {
+                let name = self.string_at(ty.name_offset()).ok()?;
+                Some((ty.kind(), name.to_string(), ty))
+            })
+            .collect()
+    }
+
+    // collects the named fields of the struct or union `ty` with their bit
+    // offset, looking into anonymous members
+    fn collect_fields(
+        &self,
+        ty: &BtfType,
+        base_offset: usize,
+        depth: u8,
+        fields: &mut Vec<(String, usize)>,
+    ) {
+        let members = match ty.members() {
+            Some(members) => members,
+            None => return,
+        };
+        for member in members {
+            // members() returned Some, so this does too
+            let bit_offset = base_offset + ty.member_bit_offset(member).unwrap();
+            if member.name_offset == 0 {
+                if depth >= MAX_RESOLVE_DEPTH {
+                    continue;
+                }
+                let anon_ty = self
+                    .resolve_type(member.btf_type)
+                    .and_then(|type_id| self.type_by_id(type_id));
+                if let Ok(anon_ty) = anon_ty {
+                    self.collect_fields(anon_ty, bit_offset, depth + 1, fields);
+                }
+            } else if let Ok(name) = self.string_at(member.name_offset) {
+                fields.push((name.to_string(), bit_offset));
+            }
+        }
+    }
+}
+
+// indexes types by kind and name, keeping the first of the duplicates
+fn type_index<'a>(
+    types: &'a [(BtfKind, String, &'a BtfType)],
+) -> HashMap<(BtfKind, &'a str), &'a BtfType> {
+    let mut index = HashMap::new();
+    for (kind, name, ty) in types {
+        index.entry((*kind, name.as_str())).or_insert(*ty);
+    }
+    index
+}
+
+fn diff_fields(
+    type_name: &str,
+    old_fields: &[(String, usize)],
+    new_fields: &[(String, usize)],
+    changes: &mut Vec<BtfChange>,
+) {
+    let find = |fields: &'_ [(String, usize)], name: &str| {
+        fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, offset)| *offset)
+    };
+
+    let removed = old_fields
+        .iter()
+        .filter(|(name, _)| find(new_fields, name).is_none())
+        .collect::<Vec<_>>();
+    let added = new_fields
+        .iter()
+        .filter(|(name, _)| find(old_fields, name).is_none())
+        .collect::<Vec<_>>();
+
+    for (name, old_bit_offset) in old_fields {
+        if let Some(new_bit_offset) = find(new_fields, name) {
+            if new_bit_offset != *old_bit_offset {
+                changes.push(BtfChange::FieldMoved {
+                    type_name: type_name.to_string(),
+                    field: name.clone(),
+                    old_bit_offset: *old_bit_offset,
+                    new_bit_offset,
+                });
+            }
+        }
+    }
+
+    // a field removed at the offset of an added field was renamed
+    let mut renamed = Vec::new();
+    for (name, bit_offset) in &removed {
+        match added.iter().find(|(_, offset)| offset == bit_offset) {
+            Some((new_name, _)) => {
+                renamed.push(new_name.as_str());
+                changes.push(BtfChange::FieldRenamed {
+                    type_name: type_name.to_string(),
+                    old_name: name.clone(),
+                    new_name: new_name.clone(),
+                    bit_offset: *bit_offset,
+                })
+            }
+            None => changes.push(BtfChange::FieldRemoved {
+                type_name: type_name.to_string(),
+                field: name.clone(),
+                bit_offset: *bit_offset,
+            }),
+        }
+    }
+    for (name, bit_offset) in &added {
+        if !renamed.contains(&name.as_str()) {
+            changes.push(BtfChange::FieldAdded {
+                type_name: type_name.to_string(),
+                field: name.clone(),
+                bit_offset: *bit_offset,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+    use crate::btf::{BtfMember, Int, IntEncoding, Struct};
+
+    // builds a BTF with the ints u32 and u64 and the struct `name` with the
+    // given u32 or u64 fields
+    fn btf_with_struct(name: &str, fields: &[(&str, u32, u32)], size: u32) -> Btf {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("u32".to_string());
+        let u32_type_id =
+            btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+        let name_offset = btf.add_string("u64".to_string());
+        let u64_type_id =
+            btf.add_type(BtfType::Int(Int::new(name_offset, 8, IntEncoding::None, 0)));
+
+        let members = fields
+            .iter()
+            .map(|(name, field_size, offset)| BtfMember {
+                name_offset: btf.add_string(name.to_string()),
+                btf_type: if *field_size == 4 {
+                    u32_type_id
+                } else {
+                    u64_type_id
+                },
+                offset: *offset,
+            })
+            .collect();
+        let name_offset = btf.add_string(name.to_string());
+        btf.add_type(BtfType::Struct(Struct::new(name_offset, members, size)));
+        btf
+    }
+
+    #[test]
+    fn test_diff_reordered_field() {
+        // struct foo { u32 a; u32 b; u64 c; }
+        let old = btf_with_struct("foo", &[("a", 4, 0), ("b", 4, 32), ("c", 8, 64)], 16);
+        // struct foo { u32 b; u32 a; u64 c; }
+        let new = btf_with_struct("foo", &[("b", 4, 0), ("a", 4, 32), ("c", 8, 64)], 16);
+
+        assert_eq!(
+            old.diff(&new),
+            [
+                BtfChange::FieldMoved {
+                    type_name: "foo".to_string(),
+                    field: "a".to_string(),
+                    old_bit_offset: 0,
+                    new_bit_offset: 32,
+                },
+                BtfChange::FieldMoved {
+                    type_name: "foo".to_string(),
+                    field: "b".to_string(),
+                    old_bit_offset: 32,
+                    new_bit_offset: 0,
+                },
+            ]
+        );
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn test_diff_types_and_fields() {
+        // struct foo { u32 a; u32 b; u64 c; }
+        let old = btf_with_struct("foo", &[("a", 4, 0), ("b", 4, 32), ("c", 8, 64)], 16);
+        // struct foo { u32 a; u32 renamed; u64 c; u64 d; }
+        let mut new = btf_with_struct(
+            "foo",
+            &[("a", 4, 0), ("renamed", 4, 32), ("c", 8, 64), ("d", 8, 128)],
+            24,
+        );
+        let name_offset = new.add_string("u8".to_string());
+        new.add_type(BtfType::Int(Int::new(name_offset, 1, IntEncoding::None, 0)));
+
+        assert_eq!(
+            old.diff(&new),
+            [
+                BtfChange::SizeChanged {
+                    kind: BtfKind::Struct,
+                    name: "foo".to_string(),
+                    old_size: 16,
+                    new_size: 24,
+                },
+                BtfChange::FieldRenamed {
+                    type_name: "foo".to_string(),
+                    old_name: "b".to_string(),
+                    new_name: "renamed".to_string(),
+                    bit_offset: 32,
+                },
+                BtfChange::FieldAdded {
+                    type_name: "foo".to_string(),
+                    field: "d".to_string(),
+                    bit_offset: 128,
+                },
+                BtfChange::TypeAdded {
+                    kind: BtfKind::Int,
+                    name: "u8".to_string(),
+                },
+            ]
+        );
+
+        let bar = btf_with_struct("bar", &[("a", 4, 0)], 4);
+        assert_eq!(
+            old.diff(&bar),
+            [
+                BtfChange::TypeRemoved {
+                    kind: BtfKind::Struct,
+                    name: "foo".to_string(),
+                },
+                BtfChange::TypeAdded {
+                    kind: BtfKind::Struct,
+                    name: "bar".to_string(),
+                },
+            ]
+        );
+    }
+}
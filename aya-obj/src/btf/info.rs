@@ -192,8 +192,14 @@ impl LineSecInfo {
     }
 }
 
+/// A collection of [LineSecInfo] collected from the `line_info` subsection
+/// in the `.BTF.ext` section.
+///
+/// See [BPF Type Format (BTF) — The Linux Kernel documentation](https://docs.kernel.org/bpf/btf.html)
+/// for more information.
 #[derive(Debug, Clone)]
-pub(crate) struct LineInfo {
+pub struct LineInfo {
+    /// The [LineSecInfo] subsections for some sections, referenced by section names
     pub data: HashMap<String, LineSecInfo>,
 }
 
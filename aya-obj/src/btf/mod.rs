@@ -2,11 +2,15 @@
 
 #[allow(clippy::module_inception)]
 mod btf;
+mod cache;
+mod diff;
 mod info;
 mod relocation;
 mod types;
 
 pub use btf::*;
+pub use cache::{RelocationCache, RelocationCacheError};
+pub use diff::BtfChange;
 pub use info::*;
-pub use relocation::BtfRelocationError;
+pub use relocation::{BtfRelocationError, BtfRelocationOverrides, RelocationContext};
 pub use types::*;
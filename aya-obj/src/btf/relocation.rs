@@ -14,14 +14,19 @@ use crate::{
         IntEncoding, Struct, Union, MAX_SPEC_LEN,
     },
     generated::{
-        bpf_core_relo, bpf_core_relo_kind::*, bpf_insn, BPF_ALU, BPF_ALU64, BPF_B, BPF_DW, BPF_H,
-        BPF_K, BPF_LD, BPF_LDX, BPF_ST, BPF_STX, BPF_W, BTF_INT_SIGNED,
+        bpf_core_relo, bpf_core_relo_kind::*, bpf_insn, BPF_ALU, BPF_ALU64, BPF_B, BPF_CALL,
+        BPF_DW, BPF_H, BPF_JMP, BPF_K, BPF_LD, BPF_LDX, BPF_ST, BPF_STX, BPF_W, BTF_INT_SIGNED,
     },
+    relocation::BPF_IMM,
     thiserror::{self, Error},
     util::HashMap,
     Object, Program, ProgramSection,
 };
 
+// The helper id libbpf calls in place of instructions whose relocation can't
+// be resolved, so that the verifier points at them if they're reachable.
+pub(crate) const BPF_CORE_POISON_IMM: i32 = 0xbad2310;
+
 /// The error type returned by [`Object::relocate_btf`].
 #[derive(Error, Debug)]
 #[error("error relocating `{section}`")]
@@ -182,7 +187,7 @@ impl TryFrom<u32> for RelocationKind {
 #[derive(Debug, Copy, Clone)]
 pub(crate) struct Relocation {
     kind: RelocationKind,
-    ins_offset: usize,
+    pub(crate) ins_offset: usize,
     type_id: u32,
     access_str_offset: u32,
     number: usize,
@@ -207,9 +212,123 @@ impl Relocation {
     }
 }
 
+/// Options that customise how [`Object::relocate_btf_with_context`] resolves
+/// local types to target types.
+#[derive(Debug, Default, Clone)]
+pub struct RelocationContext {
+    overrides: HashMap<u32, u32>,
+    names: BtfRelocationOverrides,
+}
+
+impl RelocationContext {
+    /// Creates a new, empty context.
+    pub fn new() -> RelocationContext {
+        RelocationContext::default()
+    }
+
+    /// Forces the local type `local_id` to be resolved to the target type
+    /// `target_id`.
+    ///
+    /// Overrides are consulted before the automatic name based candidate
+    /// search, which makes them useful when multiple target types share the
+    /// same name or to test relocation robustness.
+    ///
+    /// Note that the override bypasses the name and kind checks normally
+    /// performed on candidate types. It's up to the caller to ensure that the
+    /// target type is a sensible match for the local type.
+    pub fn override_target(&mut self, local_id: u32, target_id: u32) -> &mut RelocationContext {
+        self.overrides.insert(local_id, target_id);
+        self
+    }
+
+    /// Sets the type and field name overrides used when a local type or field
+    /// can't be found by name in the target BTF.
+    pub fn name_overrides(&mut self, overrides: BtfRelocationOverrides) -> &mut RelocationContext {
+        self.names = overrides;
+        self
+    }
+}
+
+/// Maps local type and field names to their names in the target BTF.
+///
+/// CO-RE relocations match local types and fields to target ones by name.
+/// When a kernel renames a type or a field, for example moving a field into
+/// an anonymous union under a new name, the relocation fails. The overrides
+/// provide the target name to try before giving up.
+///
+/// This is advanced usage: the overrides bypass the name matching but not
+/// the compatibility checks, and it's up to the caller to ensure that the
+/// target type or field has the same meaning as the local one.
+///
+/// # Examples
+///
+/// ```
+/// use aya_obj::btf::{BtfRelocationOverrides, RelocationContext};
+///
+/// let mut overrides = BtfRelocationOverrides::new();
+/// // task_struct::state was renamed to __state in 5.14
+/// overrides.rename_field("task_struct", "state", "__state");
+///
+/// let mut ctx = RelocationContext::new();
+/// ctx.name_overrides(overrides);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct BtfRelocationOverrides {
+    types: HashMap<String, String>,
+    fields: HashMap<(String, String), String>,
+}
+
+impl BtfRelocationOverrides {
+    /// Creates a new, empty set of overrides.
+    pub fn new() -> BtfRelocationOverrides {
+        BtfRelocationOverrides::default()
+    }
+
+    /// Resolves the local type `local` to the target type named `target`.
+    pub fn rename_type(&mut self, local: &str, target: &str) -> &mut BtfRelocationOverrides {
+        self.types.insert(local.to_owned(), target.to_owned());
+        self
+    }
+
+    /// Resolves the field `local_field` of the local type `local_type` to the
+    /// field named `target_field` of the target type.
+    pub fn rename_field(
+        &mut self,
+        local_type: &str,
+        local_field: &str,
+        target_field: &str,
+    ) -> &mut BtfRelocationOverrides {
+        self.fields.insert(
+            (local_type.to_owned(), local_field.to_owned()),
+            target_field.to_owned(),
+        );
+        self
+    }
+
+    fn type_name(&self, local: &str) -> Option<&str> {
+        self.types.get(local).map(String::as_str)
+    }
+
+    fn field_name(&self, local_type: &str, local_field: &str) -> Option<&str> {
+        self.fields
+            .get(&(local_type.to_owned(), local_field.to_owned()))
+            .map(String::as_str)
+    }
+}
+
 impl Object {
     /// Relocates programs inside this object file with loaded BTF info.
     pub fn relocate_btf(&mut self, target_btf: &Btf) -> Result<(), BtfRelocationError> {
+        self.relocate_btf_with_context(target_btf, &RelocationContext::default())
+    }
+
+    /// Relocates programs inside this object file with loaded BTF info,
+    /// using the provided [`RelocationContext`].
+    pub fn relocate_btf_with_context(
+        &mut self,
+        target_btf: &Btf,
+        ctx: &RelocationContext,
+    ) -> Result<(), BtfRelocationError> {
         let (local_btf, btf_ext) = match (&self.btf, &self.btf_ext) {
             (Some(btf), Some(btf_ext)) => (btf, btf_ext),
             _ => return Ok(()),
@@ -238,9 +357,22 @@ impl Object {
                     section: section_name.to_owned(),
                     error: RelocationError::ProgramNotFound,
                 })?;
-            match relocate_btf_program(program, relos, local_btf, target_btf, &mut candidates_cache)
-            {
-                Ok(_) => {}
+            match relocate_btf_program(
+                program,
+                relos,
+                local_btf,
+                target_btf,
+                ctx,
+                &mut candidates_cache,
+            ) {
+                Ok(poisoned) => {
+                    if !poisoned.is_empty() {
+                        self.poisoned_relocations
+                            .entry(section_name.to_owned())
+                            .or_default()
+                            .extend(poisoned);
+                    }
+                }
                 Err(error) => {
                     return Err(BtfRelocationError {
                         section: section_name.to_owned(),
@@ -252,6 +384,25 @@ impl Object {
 
         Ok(())
     }
+
+    /// Returns the indices of the instructions of `program` that were
+    /// poisoned because their CO-RE relocation couldn't be resolved against
+    /// the target BTF.
+    ///
+    /// Like libbpf, relocation doesn't fail when a field, type or enum
+    /// variant is missing from the target BTF. The instruction is replaced
+    /// with a call to an invalid helper instead, so programs can guard
+    /// optional accesses with `bpf_core_field_exists()` and friends. Loading
+    /// fails if a poisoned instruction is reachable.
+    pub fn poisoned_relocations(&self, program: &str) -> Vec<usize> {
+        let mut indices = self
+            .poisoned_relocations
+            .get(program)
+            .cloned()
+            .unwrap_or_default();
+        indices.sort_unstable();
+        indices
+    }
 }
 
 fn relocate_btf_program<'target>(
@@ -259,8 +410,10 @@ fn relocate_btf_program<'target>(
     relos: &[Relocation],
     local_btf: &Btf,
     target_btf: &'target Btf,
+    ctx: &RelocationContext,
     candidates_cache: &mut HashMap<u32, Vec<Candidate<'target>>>,
-) -> Result<(), RelocationError> {
+) -> Result<Vec<usize>, RelocationError> {
+    let mut poisoned = Vec::new();
     for rel in relos {
         let instructions = &mut program.function.instructions;
         let ins_index = rel.ins_offset / mem::size_of::<bpf_insn>();
@@ -285,7 +438,7 @@ fn relocate_btf_program<'target>(
                     None => {
                         candidates_cache.insert(
                             rel.type_id,
-                            find_candidates(local_ty, local_name, target_btf)?,
+                            find_candidates(ctx, rel.type_id, local_ty, local_name, target_btf)?,
                         );
                         candidates_cache.get(&rel.type_id).unwrap()
                     }
@@ -293,7 +446,7 @@ fn relocate_btf_program<'target>(
 
                 let mut matches = Vec::new();
                 for candidate in candidates {
-                    if let Some(candidate_spec) = match_candidate(&local_spec, candidate)? {
+                    if let Some(candidate_spec) = match_candidate(ctx, &local_spec, candidate)? {
                         let comp_rel =
                             ComputedRelocation::new(rel, &local_spec, Some(&candidate_spec))?;
                         matches.push((candidate.name.clone(), candidate_spec, comp_rel));
@@ -332,12 +485,47 @@ fn relocate_btf_program<'target>(
             // there are no candidate matches and therefore no target_spec. This might mean
             // that matching failed, or that the relocation can be applied looking at local
             // types only (eg with EnumVariantExists, FieldExists etc)
-            ComputedRelocation::new(rel, &local_spec, None)?
+            match ComputedRelocation::new(rel, &local_spec, None) {
+                Ok(comp_rel) => comp_rel,
+                Err(RelocationError::MissingTargetDefinition { .. }) => {
+                    poison_instruction(program, rel)?;
+                    poisoned.push(ins_index);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
         };
 
         comp_rel.apply(program, rel, local_btf, target_btf)?;
     }
 
+    Ok(poisoned)
+}
+
+fn poison_instruction(program: &mut Program, rel: &Relocation) -> Result<(), RelocationError> {
+    let instructions = &mut program.function.instructions;
+    let num_instructions = instructions.len();
+    let ins_index = rel.ins_offset / mem::size_of::<bpf_insn>();
+    // ldimm64 takes two instruction slots, poison both
+    let len = if instructions[ins_index].code == (BPF_LD | BPF_IMM | BPF_DW) as u8 {
+        2
+    } else {
+        1
+    };
+    let instructions = instructions.get_mut(ins_index..ins_index + len).ok_or(
+        RelocationError::InvalidInstructionIndex {
+            index: ins_index + len - 1,
+            num_instructions,
+            relocation_number: rel.number,
+        },
+    )?;
+    for ins in instructions {
+        ins.code = (BPF_JMP | BPF_CALL) as u8;
+        ins.set_dst_reg(0);
+        ins.set_src_reg(0);
+        ins.off = 0;
+        ins.imm = BPF_CORE_POISON_IMM;
+    }
     Ok(())
 }
 
@@ -346,6 +534,37 @@ fn flavorless_name(name: &str) -> &str {
 }
 
 fn find_candidates<'target>(
+    ctx: &RelocationContext,
+    local_id: u32,
+    local_ty: &BtfType,
+    local_name: &str,
+    target_btf: &'target Btf,
+) -> Result<Vec<Candidate<'target>>, BtfError> {
+    if let Some(&type_id) = ctx.overrides.get(&local_id) {
+        let ty = target_btf.type_by_id(type_id)?;
+        return Ok(vec![Candidate {
+            name: target_btf.type_name(ty)?.to_string(),
+            btf: target_btf,
+            _ty: ty,
+            type_id,
+        }]);
+    }
+
+    let candidates = find_candidates_by_name(local_ty, local_name, target_btf)?;
+    if candidates.is_empty() {
+        let target_name = ctx
+            .names
+            .type_name(local_name)
+            .or_else(|| ctx.names.type_name(flavorless_name(local_name)));
+        if let Some(target_name) = target_name {
+            return find_candidates_by_name(local_ty, target_name, target_btf);
+        }
+    }
+
+    Ok(candidates)
+}
+
+fn find_candidates_by_name<'target>(
     local_ty: &BtfType,
     local_name: &str,
     target_btf: &'target Btf,
@@ -373,6 +592,7 @@ fn find_candidates<'target>(
 }
 
 fn match_candidate<'target>(
+    ctx: &RelocationContext,
     local_spec: &AccessSpec,
     candidate: &'target Candidate,
 ) -> Result<Option<AccessSpec<'target>>, RelocationError> {
@@ -422,6 +642,7 @@ fn match_candidate<'target>(
                             return Ok(Some(target_spec));
                         }
                     }
+                    return Ok(None);
                 }
                 _ => return Ok(None),
             }
@@ -438,6 +659,7 @@ fn match_candidate<'target>(
 
                 if accessor.name.is_some() {
                     if let Some(next_id) = match_member(
+                        ctx,
                         local_spec.btf,
                         local_spec,
                         accessor,
@@ -497,6 +719,7 @@ fn match_candidate<'target>(
 }
 
 fn match_member<'target>(
+    ctx: &RelocationContext,
     local_btf: &Btf,
     local_spec: &AccessSpec<'_>,
     local_accessor: &Accessor,
@@ -513,6 +736,39 @@ fn match_member<'target>(
     };
 
     let local_name = &*local_btf.string_at(local_member.name_offset)?;
+    if let Some(next_id) = match_member_by_name(
+        local_spec,
+        local_member,
+        local_name,
+        target_btf,
+        target_id,
+        target_spec,
+    )? {
+        return Ok(Some(next_id));
+    }
+
+    let local_type_name = &*local_btf.type_name(local_ty)?;
+    match ctx.names.field_name(local_type_name, local_name) {
+        Some(target_name) => match_member_by_name(
+            local_spec,
+            local_member,
+            target_name,
+            target_btf,
+            target_id,
+            target_spec,
+        ),
+        None => Ok(None),
+    }
+}
+
+fn match_member_by_name<'target>(
+    local_spec: &AccessSpec<'_>,
+    local_member: &BtfMember,
+    local_name: &str,
+    target_btf: &'target Btf,
+    target_id: u32,
+    target_spec: &mut AccessSpec<'target>,
+) -> Result<Option<u32>, RelocationError> {
     let target_id = target_btf.resolve_type(target_id)?;
     let target_ty = target_btf.type_by_id(target_id)?;
 
@@ -535,10 +791,10 @@ fn match_member<'target>(
         let target_name = &*target_btf.string_at(target_member.name_offset)?;
 
         if target_name.is_empty() {
-            let ret = match_member(
-                local_btf,
+            let ret = match_member_by_name(
                 local_spec,
-                local_accessor,
+                local_member,
+                local_name,
                 target_btf,
                 target_member.btf_type,
                 target_spec,
@@ -675,7 +931,7 @@ impl<'a> AccessSpec<'a> {
                     index: parts[0],
                     name: None,
                 }];
-                let mut bit_offset = accessors[0].index * btf.type_size(type_id)?;
+                let mut bit_offset = accessors[0].index * btf.type_size(type_id)? * 8;
                 for index in parts.iter().skip(1).cloned() {
                     type_id = btf.resolve_type(type_id)?;
                     let ty = btf.type_by_id(type_id)?;
@@ -1123,3 +1379,588 @@ impl ComputedRelocation {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::ffi::CString;
+
+    use object::Endianness;
+
+    use super::*;
+    use crate::{
+        btf::{BtfEnum, BtfExt, Enum, Int, Ptr},
+        generated::btf_ext_header,
+        obj::{tests::fake_func, KernelVersion},
+        util::bytes_of,
+    };
+
+    // r0 = <imm>
+    const MOV64_IMM: u8 = (BPF_ALU64 | 0xb0 | BPF_K) as u8;
+
+    fn fake_program() -> Program {
+        let mut ins: bpf_insn = unsafe { mem::zeroed() };
+        ins.code = MOV64_IMM;
+        ins.imm = 42;
+        Program {
+            license: CString::new("GPL").unwrap(),
+            kernel_version: KernelVersion::Any,
+            section: ProgramSection::KProbe {
+                name: "test".to_string(),
+            },
+            function: fake_func("test", vec![ins]),
+        }
+    }
+
+    // struct foo { int a; [int b;] }
+    fn struct_btf(with_b: bool) -> (Btf, u32) {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        let int_id = btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+        let mut members = vec![BtfMember {
+            name_offset: btf.add_string("a".to_string()),
+            btf_type: int_id,
+            offset: 0,
+        }];
+        if with_b {
+            members.push(BtfMember {
+                name_offset: btf.add_string("b".to_string()),
+                btf_type: int_id,
+                offset: 32,
+            });
+        }
+        let size = members.len() as u32 * 4;
+        let name_offset = btf.add_string("foo".to_string());
+        let struct_id = btf.add_type(BtfType::Struct(Struct::new(name_offset, members, size)));
+        (btf, struct_id)
+    }
+
+    // enum bar { BAR_A = 0, [BAR_B = 1] }
+    fn enum_btf(with_b: bool) -> (Btf, u32) {
+        let mut btf = Btf::new();
+        let mut variants = vec![BtfEnum {
+            name_offset: btf.add_string("BAR_A".to_string()),
+            value: 0,
+        }];
+        if with_b {
+            variants.push(BtfEnum {
+                name_offset: btf.add_string("BAR_B".to_string()),
+                value: 1,
+            });
+        }
+        let name_offset = btf.add_string("bar".to_string());
+        let enum_id = btf.add_type(BtfType::Enum(Enum::new(name_offset, variants)));
+        (btf, enum_id)
+    }
+
+    fn relocate_exists(
+        kind: RelocationKind,
+        local: (Btf, u32),
+        access_str: &str,
+        target_btf: &Btf,
+    ) -> i32 {
+        relocate_with_context(
+            kind,
+            local,
+            access_str,
+            target_btf,
+            &RelocationContext::default(),
+        )
+        .unwrap()
+    }
+
+    fn relocate_with_context(
+        kind: RelocationKind,
+        local: (Btf, u32),
+        access_str: &str,
+        target_btf: &Btf,
+        ctx: &RelocationContext,
+    ) -> Result<i32, RelocationError> {
+        let (mut local_btf, type_id) = local;
+        let access_str_offset = local_btf.add_string(access_str.to_string());
+        let rel = Relocation {
+            kind,
+            ins_offset: 0,
+            type_id,
+            access_str_offset,
+            number: 0,
+        };
+        let mut program = fake_program();
+        relocate_btf_program(
+            &mut program,
+            &[rel],
+            &local_btf,
+            target_btf,
+            ctx,
+            &mut HashMap::new(),
+        )?;
+        Ok(program.function.instructions[0].imm)
+    }
+
+    // struct foo { int *ptrs[2]; int x; }
+    fn pointer_struct_btf() -> (Btf, u32) {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        let int_id = btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+        let ptr_id = btf.add_type(BtfType::Ptr(Ptr::new(0, int_id)));
+        let array_id = btf.add_type(BtfType::Array(Array::new(0, ptr_id, int_id, 2)));
+        let members = vec![
+            BtfMember {
+                name_offset: btf.add_string("ptrs".to_string()),
+                btf_type: array_id,
+                offset: 0,
+            },
+            BtfMember {
+                name_offset: btf.add_string("x".to_string()),
+                btf_type: int_id,
+                offset: 128,
+            },
+        ];
+        let name_offset = btf.add_string("foo".to_string());
+        let struct_id = btf.add_type(BtfType::Struct(Struct::new(name_offset, members, 24)));
+        (btf, struct_id)
+    }
+
+    // struct foo { int a; unsigned <elem_size> arr[4]; }
+    fn array_struct_btf(elem_size: u32) -> (Btf, u32) {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        let int_id = btf.add_type(BtfType::Int(Int::new(
+            name_offset,
+            4,
+            IntEncoding::Signed,
+            0,
+        )));
+        let name_offset = btf.add_string("elem".to_string());
+        let elem_id = btf.add_type(BtfType::Int(Int::new(
+            name_offset,
+            elem_size,
+            IntEncoding::None,
+            0,
+        )));
+        let array_id = btf.add_type(BtfType::Array(Array::new(0, elem_id, int_id, 4)));
+        // arr is aligned to the size of its elements
+        let arr_offset = elem_size.max(4);
+        let members = vec![
+            BtfMember {
+                name_offset: btf.add_string("a".to_string()),
+                btf_type: int_id,
+                offset: 0,
+            },
+            BtfMember {
+                name_offset: btf.add_string("arr".to_string()),
+                btf_type: array_id,
+                offset: arr_offset * 8,
+            },
+        ];
+        let name_offset = btf.add_string("foo".to_string());
+        let size = arr_offset + 4 * elem_size;
+        let struct_id = btf.add_type(BtfType::Struct(Struct::new(name_offset, members, size)));
+        (btf, struct_id)
+    }
+
+    #[test]
+    fn test_array_element_relocation() {
+        let (target_btf, _) = array_struct_btf(8);
+        let relocate = |kind, access_str| {
+            relocate_with_context(
+                kind,
+                array_struct_btf(4),
+                access_str,
+                &target_btf,
+                &RelocationContext::default(),
+            )
+            .unwrap()
+        };
+
+        // foo.arr[3], at 4 + 3 * 4 locally
+        assert_eq!(
+            relocate(RelocationKind::FieldByteOffset, "0:1:3"),
+            8 + 3 * 8
+        );
+        assert_eq!(relocate(RelocationKind::FieldByteSize, "0:1:3"), 8);
+        // foo.arr
+        assert_eq!(relocate(RelocationKind::FieldByteSize, "0:1"), 4 * 8);
+        // (&foo)[1].arr[3]
+        assert_eq!(
+            relocate(RelocationKind::FieldByteOffset, "1:1:3"),
+            40 + 8 + 3 * 8
+        );
+        // foo.arr[3] exists
+        assert_eq!(relocate(RelocationKind::FieldExists, "0:1:3"), 1);
+    }
+
+    #[test]
+    fn test_array_element_local_spec() {
+        let (mut btf, foo_id) = array_struct_btf(4);
+        let access_str_offset = btf.add_string("2:1:3".to_string());
+        let rel = Relocation {
+            kind: RelocationKind::FieldByteOffset,
+            ins_offset: 0,
+            type_id: foo_id,
+            access_str_offset,
+            number: 0,
+        };
+        let spec = AccessSpec::new(&btf, foo_id, "2:1:3", rel).unwrap();
+        // (&foo)[2].arr[3]: two structs of 20 bytes, then arr at 4 and 3 elements of 4 bytes
+        assert_eq!(spec.bit_offset, (2 * 20 + 4 + 3 * 4) * 8);
+
+        let spec = AccessSpec::new(&btf, foo_id, "0:1:4", rel);
+        assert!(matches!(
+            spec,
+            Err(RelocationError::InvalidAccessIndex { index: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_pointer_size() {
+        let (target_btf, foo_id) = pointer_struct_btf();
+        let relocate = |kind, access_str| {
+            relocate_with_context(
+                kind,
+                pointer_struct_btf(),
+                access_str,
+                &target_btf,
+                &RelocationContext::default(),
+            )
+            .unwrap()
+        };
+
+        // pointers are 8 bytes whatever the pointer size of the host
+        // foo.ptrs[1]
+        assert_eq!(relocate(RelocationKind::FieldByteOffset, "0:0:1"), 8);
+        assert_eq!(relocate(RelocationKind::FieldByteSize, "0:0:1"), 8);
+        // foo.ptrs
+        assert_eq!(relocate(RelocationKind::FieldByteSize, "0:0"), 16);
+        // foo.x
+        assert_eq!(relocate(RelocationKind::FieldByteOffset, "0:1"), 16);
+        // sizeof(struct foo)
+        assert_eq!(target_btf.type_size(foo_id).unwrap(), 24);
+    }
+
+    #[test]
+    fn test_field_exists() {
+        // foo.b
+        let (target_btf, _) = struct_btf(true);
+        assert_eq!(
+            relocate_exists(
+                RelocationKind::FieldExists,
+                struct_btf(true),
+                "0:1",
+                &target_btf
+            ),
+            1
+        );
+
+        let (target_btf, _) = struct_btf(false);
+        assert_eq!(
+            relocate_exists(
+                RelocationKind::FieldExists,
+                struct_btf(true),
+                "0:1",
+                &target_btf
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn test_enum_variant_exists() {
+        // BAR_B
+        let (target_btf, _) = enum_btf(true);
+        assert_eq!(
+            relocate_exists(
+                RelocationKind::EnumVariantExists,
+                enum_btf(true),
+                "1",
+                &target_btf
+            ),
+            1
+        );
+
+        let (target_btf, _) = enum_btf(false);
+        assert_eq!(
+            relocate_exists(
+                RelocationKind::EnumVariantExists,
+                enum_btf(true),
+                "1",
+                &target_btf
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn test_type_exists() {
+        let (target_btf, _) = struct_btf(false);
+        assert_eq!(
+            relocate_exists(
+                RelocationKind::TypeExists,
+                struct_btf(true),
+                "0",
+                &target_btf
+            ),
+            1
+        );
+
+        let (target_btf, _) = enum_btf(false);
+        assert_eq!(
+            relocate_exists(
+                RelocationKind::TypeExists,
+                struct_btf(true),
+                "0",
+                &target_btf
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn test_type_id() {
+        // the target has an extra type, so struct foo gets a different id
+        let mut target_btf = Btf::new();
+        let name_offset = target_btf.add_string("long".to_string());
+        target_btf.add_type(BtfType::Int(Int::new(name_offset, 8, IntEncoding::None, 0)));
+        let name_offset = target_btf.add_string("int".to_string());
+        let int_id =
+            target_btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+        let members = vec![BtfMember {
+            name_offset: target_btf.add_string("a".to_string()),
+            btf_type: int_id,
+            offset: 0,
+        }];
+        let name_offset = target_btf.add_string("foo".to_string());
+        let target_id = target_btf.add_type(BtfType::Struct(Struct::new(name_offset, members, 4)));
+
+        let (_, local_id) = struct_btf(false);
+        assert_ne!(local_id, target_id);
+        assert_eq!(
+            relocate_exists(
+                RelocationKind::TypeIdLocal,
+                struct_btf(false),
+                "0",
+                &target_btf
+            ),
+            local_id as i32
+        );
+        assert_eq!(
+            relocate_exists(
+                RelocationKind::TypeIdTarget,
+                struct_btf(false),
+                "0",
+                &target_btf
+            ),
+            target_id as i32
+        );
+    }
+
+    #[test]
+    fn test_name_overrides() {
+        // struct foo { int a; int pad; int c; }, where c used to be b
+        let mut target_btf = Btf::new();
+        let name_offset = target_btf.add_string("int".to_string());
+        let int_id =
+            target_btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+        let members = ["a", "pad", "c"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| BtfMember {
+                name_offset: target_btf.add_string(name.to_string()),
+                btf_type: int_id,
+                offset: i as u32 * 32,
+            })
+            .collect();
+        let name_offset = target_btf.add_string("foo".to_string());
+        target_btf.add_type(BtfType::Struct(Struct::new(name_offset, members, 12)));
+
+        // foo.b doesn't exist in the target so the instruction is poisoned
+        assert_eq!(
+            relocate_with_context(
+                RelocationKind::FieldByteOffset,
+                struct_btf(true),
+                "0:1",
+                &target_btf,
+                &RelocationContext::default(),
+            )
+            .unwrap(),
+            BPF_CORE_POISON_IMM
+        );
+
+        let mut overrides = BtfRelocationOverrides::new();
+        overrides.rename_field("foo", "b", "c");
+        let mut ctx = RelocationContext::new();
+        ctx.name_overrides(overrides);
+        assert_eq!(
+            relocate_with_context(
+                RelocationKind::FieldByteOffset,
+                struct_btf(true),
+                "0:1",
+                &target_btf,
+                &ctx,
+            )
+            .unwrap(),
+            8
+        );
+    }
+
+    #[test]
+    fn test_override_target_wins_over_name() {
+        let mut local_btf = Btf::new();
+        let name_offset = local_btf.add_string("foo".to_string());
+        let local_id =
+            local_btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+
+        let mut target_btf = Btf::new();
+        let name_offset = target_btf.add_string("foo".to_string());
+        let foo_id =
+            target_btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+        let name_offset = target_btf.add_string("bar".to_string());
+        let bar_id =
+            target_btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+
+        let local_ty = local_btf.type_by_id(local_id).unwrap();
+
+        let candidates = find_candidates(
+            &RelocationContext::default(),
+            local_id,
+            local_ty,
+            "foo",
+            &target_btf,
+        )
+        .unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].type_id, foo_id);
+
+        let mut ctx = RelocationContext::new();
+        ctx.override_target(local_id, bar_id);
+        let candidates = find_candidates(&ctx, local_id, local_ty, "foo", &target_btf).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].type_id, bar_id);
+        assert_eq!(candidates[0].name, "bar");
+    }
+
+    #[test]
+    fn test_poisoned_relocations() {
+        let (mut btf, struct_id) = struct_btf(true);
+        let sec_name_offset = btf.add_string("kprobe/foo".to_string());
+        let access_str_offset = btf.add_string("0:1".to_string());
+
+        // foo.b is read at instruction 1 and its existence checked at
+        // instruction 2
+        let records = [
+            (8u32, BPF_CORE_FIELD_BYTE_OFFSET),
+            (16, BPF_CORE_FIELD_EXISTS),
+        ];
+        let rec_size = mem::size_of::<bpf_core_relo>();
+        let header = btf_ext_header {
+            magic: 0xeb9f,
+            version: 1,
+            flags: 0,
+            hdr_len: mem::size_of::<btf_ext_header>() as u32,
+            func_info_off: 0,
+            func_info_len: 0,
+            line_info_off: 0,
+            line_info_len: 0,
+            core_relo_off: 0,
+            core_relo_len: (4 + 8 + records.len() * rec_size) as u32,
+        };
+        let mut data = unsafe { bytes_of(&header) }.to_vec();
+        data.extend((rec_size as u32).to_ne_bytes());
+        data.extend(sec_name_offset.to_ne_bytes());
+        data.extend((records.len() as u32).to_ne_bytes());
+        for (insn_off, kind) in records {
+            let relo = bpf_core_relo {
+                insn_off,
+                type_id: struct_id,
+                access_str_off: access_str_offset,
+                kind,
+            };
+            data.extend(unsafe { bytes_of(&relo) });
+        }
+        let btf_ext = BtfExt::parse(&data, Endianness::default(), &btf).unwrap();
+
+        let mut obj = Object::new(
+            Endianness::default(),
+            CString::new("GPL").unwrap(),
+            KernelVersion::Any,
+        );
+        let mut program = fake_program();
+        program.section = ProgramSection::KProbe {
+            name: "foo".to_string(),
+        };
+        let ins = program.function.instructions[0];
+        program.function.instructions = vec![ins; 3];
+        obj.programs.insert("foo".to_string(), program);
+        obj.btf = Some(btf);
+        obj.btf_ext = Some(btf_ext);
+
+        let (target_btf, _) = struct_btf(false);
+        obj.relocate_btf(&target_btf).unwrap();
+
+        assert_eq!(obj.poisoned_relocations("foo"), vec![1]);
+        assert!(obj.poisoned_relocations("bar").is_empty());
+        let instructions = &obj.programs["foo"].function.instructions;
+        assert_eq!(instructions[1].code, (BPF_JMP | BPF_CALL) as u8);
+        assert_eq!(instructions[1].imm, BPF_CORE_POISON_IMM);
+        // the existence check is resolved
+        assert_eq!(instructions[2].imm, 0);
+    }
+
+    #[test]
+    fn test_parse_core_relos() {
+        let (mut btf, struct_id) = struct_btf(true);
+        let sec_name_offset = btf.add_string("kprobe/foo".to_string());
+        let access_str_offset = btf.add_string("0:1".to_string());
+
+        // two relocations for the read of foo->b: the offset of the field and
+        // whether it exists
+        let records = [
+            (8u32, BPF_CORE_FIELD_BYTE_OFFSET),
+            (16, BPF_CORE_FIELD_EXISTS),
+        ];
+        let rec_size = mem::size_of::<bpf_core_relo>();
+        let core_relo_len = 4 + 8 + records.len() * rec_size;
+        let header = btf_ext_header {
+            magic: 0xeb9f,
+            version: 1,
+            flags: 0,
+            hdr_len: mem::size_of::<btf_ext_header>() as u32,
+            func_info_off: 0,
+            func_info_len: 0,
+            line_info_off: 0,
+            line_info_len: 0,
+            core_relo_off: 0,
+            core_relo_len: core_relo_len as u32,
+        };
+
+        let mut data = unsafe { bytes_of(&header) }.to_vec();
+        data.extend((rec_size as u32).to_ne_bytes());
+        data.extend(sec_name_offset.to_ne_bytes());
+        data.extend((records.len() as u32).to_ne_bytes());
+        for (insn_off, kind) in records {
+            let relo = bpf_core_relo {
+                insn_off,
+                type_id: struct_id,
+                access_str_off: access_str_offset,
+                kind,
+            };
+            data.extend(unsafe { bytes_of(&relo) });
+        }
+
+        let ext = BtfExt::parse(&data, Endianness::default(), &btf).unwrap();
+        let relocations = ext.relocations().collect::<Vec<_>>();
+        assert_eq!(relocations.len(), 1);
+        let (name_offset, relos) = relocations[0];
+        assert_eq!(*name_offset, sec_name_offset);
+        assert_eq!(relos.len(), 2);
+
+        assert!(matches!(relos[0].kind, RelocationKind::FieldByteOffset));
+        assert_eq!(relos[0].ins_offset, 8);
+        assert_eq!(relos[0].type_id, struct_id);
+        assert_eq!(relos[0].access_str_offset, access_str_offset);
+        assert_eq!(relos[0].number, 0);
+
+        assert!(matches!(relos[1].kind, RelocationKind::FieldExists));
+        assert_eq!(relos[1].ins_offset, 16);
+        assert_eq!(relos[1].number, 1);
+    }
+}
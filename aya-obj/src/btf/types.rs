@@ -178,7 +178,7 @@ impl Typedef {
         mem::size_of::<Self>()
     }
 
-    pub(crate) fn new(name_offset: u32, btf_type: u32) -> Self {
+    pub fn new(name_offset: u32, btf_type: u32) -> Self {
         let info = (BtfKind::Typedef as u32) << 24;
         Typedef {
             name_offset,
@@ -799,7 +799,10 @@ impl DeclTag {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// The size of pointers in eBPF programs, in bytes.
+pub(crate) const BPF_PTR_SIZE: u32 = 8;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 #[repr(u32)]
 pub enum BtfKind {
     Unknown = 0,
@@ -1057,7 +1060,8 @@ impl BtfType {
             BtfType::Struct(t) => Some(t.size),
             BtfType::Union(t) => Some(t.size),
             BtfType::DataSec(t) => Some(t.size),
-            BtfType::Ptr(_) => Some(mem::size_of::<&()>() as u32),
+            // eBPF is 64 bits regardless of the host
+            BtfType::Ptr(_) => Some(BPF_PTR_SIZE),
             _ => None,
         }
     }
@@ -126,6 +126,9 @@ pub const BPF_CALL: u32 = 128;
 pub const BPF_F_ALLOW_OVERRIDE: u32 = 1;
 pub const BPF_F_ALLOW_MULTI: u32 = 2;
 pub const BPF_F_REPLACE: u32 = 4;
+pub const BPF_F_BEFORE: u32 = 8;
+pub const BPF_F_AFTER: u32 = 16;
+pub const BPF_F_ID: u32 = 32;
 pub const BPF_F_STRICT_ALIGNMENT: u32 = 1;
 pub const BPF_F_ANY_ALIGNMENT: u32 = 2;
 pub const BPF_F_TEST_RND_HI32: u32 = 4;
@@ -163,6 +166,8 @@ pub const XDP_FLAGS_MODES: u32 = 14;
 pub const XDP_FLAGS_MASK: u32 = 31;
 pub const SO_ATTACH_BPF: u32 = 50;
 pub const SO_DETACH_BPF: u32 = 27;
+pub const SO_ATTACH_REUSEPORT_EBPF: u32 = 52;
+pub const SO_DETACH_REUSEPORT_BPF: u32 = 68;
 pub const TC_H_MAJ_MASK: u32 = 4294901760;
 pub const TC_H_MIN_MASK: u32 = 65535;
 pub const TC_H_UNSPEC: u32 = 0;
@@ -399,7 +404,11 @@ pub enum bpf_attach_type {
     BPF_PERF_EVENT = 41,
     BPF_TRACE_KPROBE_MULTI = 42,
     BPF_LSM_CGROUP = 43,
-    __MAX_BPF_ATTACH_TYPE = 44,
+    BPF_STRUCT_OPS = 44,
+    BPF_NETFILTER = 45,
+    BPF_TCX_INGRESS = 46,
+    BPF_TCX_EGRESS = 47,
+    __MAX_BPF_ATTACH_TYPE = 48,
 }
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
@@ -414,7 +423,9 @@ pub enum bpf_link_type {
     BPF_LINK_TYPE_PERF_EVENT = 7,
     BPF_LINK_TYPE_KPROBE_MULTI = 8,
     BPF_LINK_TYPE_STRUCT_OPS = 9,
-    MAX_BPF_LINK_TYPE = 10,
+    BPF_LINK_TYPE_NETFILTER = 10,
+    BPF_LINK_TYPE_TCX = 11,
+    MAX_BPF_LINK_TYPE = 12,
 }
 pub const BPF_ANY: _bindgen_ty_2 = 0;
 pub const BPF_NOEXIST: _bindgen_ty_2 = 1;
@@ -657,6 +668,7 @@ pub union bpf_attr__bindgen_ty_14__bindgen_ty_2 {
     pub perf_event: bpf_attr__bindgen_ty_14__bindgen_ty_2__bindgen_ty_2,
     pub kprobe_multi: bpf_attr__bindgen_ty_14__bindgen_ty_2__bindgen_ty_3,
     pub tracing: bpf_attr__bindgen_ty_14__bindgen_ty_2__bindgen_ty_4,
+    pub tcx: bpf_attr__bindgen_ty_14__bindgen_ty_2__bindgen_ty_5,
 }
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -685,6 +697,18 @@ pub struct bpf_attr__bindgen_ty_14__bindgen_ty_2__bindgen_ty_4 {
     pub cookie: __u64,
 }
 #[repr(C)]
+#[derive(Copy, Clone)]
+pub struct bpf_attr__bindgen_ty_14__bindgen_ty_2__bindgen_ty_5 {
+    pub __bindgen_anon_1: bpf_attr__bindgen_ty_14__bindgen_ty_2__bindgen_ty_5__bindgen_ty_1,
+    pub expected_revision: __u64,
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union bpf_attr__bindgen_ty_14__bindgen_ty_2__bindgen_ty_5__bindgen_ty_1 {
+    pub relative_fd: __u32,
+    pub relative_id: __u32,
+}
+#[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct bpf_attr__bindgen_ty_15 {
     pub link_fd: __u32,
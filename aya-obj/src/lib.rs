@@ -41,7 +41,8 @@
 //! object.relocate_maps(std::iter::empty()).unwrap();
 //!
 //! // Run with rbpf
-//! let instructions = &object.programs["prog_name"].function.instructions;
+//! let program = object.take_program("prog_name").unwrap();
+//! let instructions = &program.function.instructions;
 //! let data = unsafe {
 //!     core::slice::from_raw_parts(
 //!         instructions.as_ptr() as *const u8,
@@ -75,11 +76,19 @@ extern crate std;
 
 pub mod btf;
 pub mod generated;
+pub mod helpers;
 pub mod maps;
+mod min_version;
 pub mod obj;
+mod obj_ref;
+mod obj_stream;
 pub mod programs;
 pub mod relocation;
 mod util;
+mod writer;
 
 pub use maps::Map;
 pub use obj::*;
+pub use obj_ref::{ObjectRef, ProgramRef, SectionRef};
+pub use obj_stream::ObjectStream;
+pub use util::Pod;
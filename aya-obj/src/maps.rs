@@ -37,6 +37,9 @@ impl TryFrom<u32> for crate::generated::bpf_map_type {
             x if x == BPF_MAP_TYPE_CPUMAP as u32 => BPF_MAP_TYPE_CPUMAP,
             x if x == BPF_MAP_TYPE_XSKMAP as u32 => BPF_MAP_TYPE_XSKMAP,
             x if x == BPF_MAP_TYPE_SOCKHASH as u32 => BPF_MAP_TYPE_SOCKHASH,
+            x if x == BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED as u32 => {
+                BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED
+            }
             x if x == BPF_MAP_TYPE_CGRP_STORAGE as u32 => BPF_MAP_TYPE_CGRP_STORAGE,
             x if x == BPF_MAP_TYPE_REUSEPORT_SOCKARRAY as u32 => BPF_MAP_TYPE_REUSEPORT_SOCKARRAY,
             x if x == BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE as u32 => {
@@ -68,6 +71,8 @@ pub struct BtfMapDef {
     pub btf_key_type_id: u32,
     /// BTF type id of the map value
     pub btf_value_type_id: u32,
+    /// Whether the map value contains a `struct bpf_spin_lock`, `None` if unknown
+    pub(crate) spin_lock: Option<bool>,
 }
 
 /// The pinning type
@@ -270,6 +275,27 @@ impl Map {
             Map::Btf(m) => m.symbol_index,
         }
     }
+
+    /// Returns whether the map values contain a `struct bpf_spin_lock`
+    ///
+    /// Returns `None` when it can't be known, for example for maps loaded from
+    /// the kernel.
+    pub fn has_spin_lock(&self) -> Option<bool> {
+        match self {
+            // spin locks require BTF
+            Map::Legacy(_) => Some(false),
+            Map::Btf(m) => m.def.spin_lock,
+        }
+    }
+
+    /// Returns the legacy definition of a BTF map that is also declared in a
+    /// `maps` section
+    pub fn legacy_def(&self) -> Option<&bpf_map_def> {
+        match self {
+            Map::Legacy(_) => None,
+            Map::Btf(m) => m.legacy.as_ref().map(|legacy| &legacy.def),
+        }
+    }
 }
 
 /// A map declared with legacy BPF map declaration style, most likely from a `maps` section.
@@ -299,4 +325,7 @@ pub struct BtfMap {
     pub(crate) symbol_index: usize,
     pub(crate) kind: MapKind,
     pub(crate) data: Vec<u8>,
+    /// The legacy definition of the map, if it's also declared in a `maps`
+    /// section. The BTF definition takes precedence.
+    pub(crate) legacy: Option<LegacyMap>,
 }
@@ -0,0 +1,424 @@
+//! Minimum kernel version estimation.
+
+use crate::{
+    generated::{
+        bpf_insn, bpf_map_type::*, BPF_ALU, BPF_CALL, BPF_JMP, BPF_PSEUDO_CALL,
+        BPF_PSEUDO_KFUNC_CALL, BPF_STX,
+    },
+    maps::MapKind,
+    obj::{Function, KernelVersion, Object, ProgramSection},
+};
+
+// not (yet) part of the generated bindings
+const BPF_JMP32: u32 = 0x06;
+const BPF_EXIT: u32 = 0x90;
+const BPF_ATOMIC: u32 = 0xc0;
+const BPF_ADD: i32 = 0x00;
+const BPF_FUNC_LOOP: i32 = 181;
+
+const fn version(major: u32, minor: u32) -> u32 {
+    (major << 16) | (minor << 8)
+}
+
+// (first helper id, version) pairs. Helper ids are allocated sequentially, so
+// the version that introduced a helper is the one of the closest preceding id.
+const HELPER_VERSIONS: &[(u32, u32)] = &[
+    (1, version(3, 19)),
+    (4, version(4, 1)),
+    (12, version(4, 2)),
+    (17, version(4, 3)),
+    (23, version(4, 4)),
+    (26, version(4, 5)),
+    (27, version(4, 6)),
+    (31, version(4, 8)),
+    (37, version(4, 9)),
+    (42, version(4, 10)),
+    (45, version(4, 11)),
+    (46, version(4, 12)),
+    (48, version(4, 13)),
+    (51, version(4, 14)),
+    (54, version(4, 15)),
+    (58, version(4, 16)),
+    (60, version(4, 17)),
+    (65, version(4, 18)),
+    (81, version(4, 19)),
+    (84, version(4, 20)),
+    (91, version(5, 0)),
+    (93, version(5, 1)),
+    (99, version(5, 2)),
+    (109, version(5, 3)),
+    (111, version(5, 5)),
+    (119, version(5, 6)),
+    (122, version(5, 7)),
+    (125, version(5, 8)),
+    (136, version(5, 9)),
+    (142, version(5, 10)),
+    (156, version(5, 11)),
+    (163, version(5, 12)),
+    (164, version(5, 13)),
+    (166, version(5, 14)),
+    (169, version(5, 15)),
+    (176, version(5, 16)),
+    (180, version(5, 17)),
+    (186, version(5, 18)),
+    (194, version(5, 19)),
+    (204, version(6, 0)),
+    (208, version(6, 1)),
+    (210, version(6, 2)),
+];
+
+fn helper_version(helper_id: u32) -> u32 {
+    HELPER_VERSIONS
+        .iter()
+        .take_while(|(id, _)| *id <= helper_id)
+        .last()
+        .map(|(_, version)| *version)
+        .unwrap_or(0)
+}
+
+fn map_type_version(map_type: u32) -> u32 {
+    match map_type {
+        x if x == BPF_MAP_TYPE_HASH as u32 || x == BPF_MAP_TYPE_ARRAY as u32 => version(3, 19),
+        x if x == BPF_MAP_TYPE_PROG_ARRAY as u32 => version(4, 2),
+        x if x == BPF_MAP_TYPE_PERF_EVENT_ARRAY as u32 => version(4, 3),
+        x if x == BPF_MAP_TYPE_PERCPU_HASH as u32
+            || x == BPF_MAP_TYPE_PERCPU_ARRAY as u32
+            || x == BPF_MAP_TYPE_STACK_TRACE as u32 =>
+        {
+            version(4, 6)
+        }
+        x if x == BPF_MAP_TYPE_CGROUP_ARRAY as u32 => version(4, 8),
+        x if x == BPF_MAP_TYPE_LRU_HASH as u32 || x == BPF_MAP_TYPE_LRU_PERCPU_HASH as u32 => {
+            version(4, 10)
+        }
+        x if x == BPF_MAP_TYPE_LPM_TRIE as u32 => version(4, 11),
+        x if x == BPF_MAP_TYPE_ARRAY_OF_MAPS as u32 || x == BPF_MAP_TYPE_HASH_OF_MAPS as u32 => {
+            version(4, 12)
+        }
+        x if x == BPF_MAP_TYPE_DEVMAP as u32 || x == BPF_MAP_TYPE_SOCKMAP as u32 => version(4, 14),
+        x if x == BPF_MAP_TYPE_CPUMAP as u32 => version(4, 15),
+        x if x == BPF_MAP_TYPE_XSKMAP as u32 || x == BPF_MAP_TYPE_SOCKHASH as u32 => version(4, 18),
+        x if x == BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED as u32
+            || x == BPF_MAP_TYPE_REUSEPORT_SOCKARRAY as u32 =>
+        {
+            version(4, 19)
+        }
+        x if x == BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE as u32
+            || x == BPF_MAP_TYPE_QUEUE as u32
+            || x == BPF_MAP_TYPE_STACK as u32 =>
+        {
+            version(4, 20)
+        }
+        x if x == BPF_MAP_TYPE_SK_STORAGE as u32 => version(5, 2),
+        x if x == BPF_MAP_TYPE_DEVMAP_HASH as u32 => version(5, 4),
+        x if x == BPF_MAP_TYPE_STRUCT_OPS as u32 => version(5, 6),
+        x if x == BPF_MAP_TYPE_RINGBUF as u32 => version(5, 8),
+        x if x == BPF_MAP_TYPE_INODE_STORAGE as u32 => version(5, 10),
+        x if x == BPF_MAP_TYPE_TASK_STORAGE as u32 => version(5, 11),
+        x if x == BPF_MAP_TYPE_BLOOM_FILTER as u32 => version(5, 16),
+        x if x == BPF_MAP_TYPE_USER_RINGBUF as u32 => version(6, 1),
+        x if x == BPF_MAP_TYPE_CGRP_STORAGE as u32 => version(6, 2),
+        _ => 0,
+    }
+}
+
+fn program_version(section: &ProgramSection) -> u32 {
+    use ProgramSection::*;
+    match section {
+        SocketFilter { .. } => version(3, 19),
+        KProbe { .. } | KRetProbe { .. } | SchedClassifier { .. } => version(4, 1),
+        UProbe { .. } | URetProbe { .. } | Usdt { .. } => version(4, 3),
+        TracePoint { .. } => version(4, 7),
+        Xdp { .. } => version(4, 8),
+        PerfEvent { .. } => version(4, 9),
+        CgroupSkb { .. } | CgroupSkbIngress { .. } | CgroupSkbEgress { .. } | CgroupSock { .. } => {
+            version(4, 10)
+        }
+        SockOps { .. } => version(4, 13),
+        SkSkbStreamParser { .. } | SkSkbStreamVerdict { .. } => version(4, 14),
+        CgroupDevice { .. } => version(4, 15),
+        SkMsg { .. } | CgroupSockAddr { .. } | RawTracePoint { .. } => version(4, 17),
+        LircMode2 { .. } => version(4, 18),
+        SkReuseport { migrate: false, .. } => version(4, 19),
+        FlowDissector { .. } => version(4, 20),
+        CgroupSysctl { .. } => version(5, 2),
+        CgroupSockopt { .. } => version(5, 3),
+        BtfTracePoint { .. }
+        | FEntry {
+            sleepable: false, ..
+        }
+        | FExit {
+            sleepable: false, ..
+        } => version(5, 5),
+        Extension { .. } => version(5, 6),
+        Lsm {
+            sleepable: false, ..
+        } => version(5, 7),
+        Iter {
+            sleepable: false, ..
+        } => version(5, 8),
+        SkLookup { .. } => version(5, 9),
+        FEntry {
+            sleepable: true, ..
+        }
+        | FExit {
+            sleepable: true, ..
+        }
+        | Lsm {
+            sleepable: true, ..
+        } => version(5, 10),
+        Syscall { .. } | SkReuseport { migrate: true, .. } => version(5, 14),
+        KProbeMulti { .. }
+        | KRetProbeMulti { .. }
+        | Iter {
+            sleepable: true, ..
+        } => version(5, 18),
+        Tcx { .. } => version(6, 6),
+    }
+}
+
+// backward jumps are only accepted by verifiers supporting bounded loops
+fn insn_is_back_edge(ins: &bpf_insn) -> bool {
+    let class = (ins.code & 0x07) as u32;
+    let op = (ins.code & 0xF0) as u32;
+
+    (class == BPF_JMP || class == BPF_JMP32) && op != BPF_CALL && op != BPF_EXIT && ins.off < 0
+}
+
+fn insn_is_helper_call(ins: &bpf_insn, helper_id: i32) -> bool {
+    // the imm of bpf-to-bpf and kfunc calls isn't a helper id
+    ins.code == (BPF_JMP | BPF_CALL) as u8 && ins.src_reg() == 0 && ins.imm == helper_id
+}
+
+fn insn_version(ins: &bpf_insn) -> u32 {
+    let class = (ins.code & 0x07) as u32;
+    let op = (ins.code & 0xF0) as u32;
+    let mode = (ins.code & 0xE0) as u32;
+
+    match class {
+        BPF_JMP if op == BPF_CALL => match ins.src_reg() as u32 {
+            0 => helper_version(ins.imm as u32),
+            // bpf-to-bpf calls, imm is the offset of the callee
+            BPF_PSEUDO_CALL => version(4, 16),
+            // imm is the BTF id of the kernel function
+            BPF_PSEUDO_KFUNC_CALL => version(5, 13),
+            _ => 0,
+        },
+        _ if insn_is_back_edge(ins) => version(5, 3),
+        BPF_JMP32 => version(5, 1),
+        // 32-bit subregister ops, emitted by -mcpu=v3 or -mattr=+alu32
+        BPF_ALU => version(4, 14),
+        // atomic operations other than add (aka BPF_XADD)
+        BPF_STX if mode == BPF_ATOMIC && ins.imm != BPF_ADD => version(5, 12),
+        _ => 0,
+    }
+}
+
+impl Function {
+    /// Returns whether the function uses 32-bit ALU instructions.
+    ///
+    /// Programs compiled with `-mcpu=v3` or `-mattr=+alu32` operate on the
+    /// 32-bit subregisters, which kernels older than 4.14 don't support.
+    pub fn uses_alu32(&self) -> bool {
+        self.instructions
+            .iter()
+            .any(|ins| (ins.code & 0x07) as u32 == BPF_ALU)
+    }
+
+    /// Returns whether the function contains bounded loops.
+    ///
+    /// Bounded loops are compiled to backward jumps, which require kernel 5.3
+    /// or newer. Loops implemented with the `bpf_loop` helper don't, see
+    /// [`Function::calls_bpf_loop`].
+    pub fn has_bounded_loops(&self) -> bool {
+        self.instructions.iter().any(insn_is_back_edge)
+    }
+
+    /// Returns whether the function calls the `bpf_loop` helper, which requires
+    /// kernel 5.17 or newer.
+    pub fn calls_bpf_loop(&self) -> bool {
+        self.instructions
+            .iter()
+            .any(|ins| insn_is_helper_call(ins, BPF_FUNC_LOOP))
+    }
+}
+
+impl Object {
+    /// Returns an estimate of the minimum kernel version required to load
+    /// this object.
+    ///
+    /// The estimate is based on the kernel versions that introduced the map
+    /// types, program types, helpers and instructions used by the object. It
+    /// is a heuristic: features backported by distributions and features that
+    /// can't be inferred statically are not taken into account.
+    pub fn min_kernel_version(&self) -> KernelVersion {
+        let maps = self.maps.values().map(|map| match map.kind() {
+            // global data requires BPF_PSEUDO_MAP_VALUE
+            MapKind::Bss | MapKind::Data | MapKind::Rodata => version(5, 2),
+            MapKind::Other => map_type_version(map.map_type()),
+        });
+        let programs = self
+            .programs
+            .values()
+            .map(|program| program_version(&program.section));
+        let instructions = self
+            .programs
+            .values()
+            .map(|program| &program.function)
+            .chain(self.functions.values())
+            .flat_map(|function| function.instructions.iter().map(insn_version));
+
+        KernelVersion::Version(
+            maps.chain(programs)
+                .chain(instructions)
+                .max()
+                .unwrap_or_else(|| version(3, 19)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{ffi::CString, string::ToString, vec, vec::Vec};
+    use core::mem;
+
+    use object::Endianness;
+
+    use super::*;
+    use crate::{
+        generated::{BPF_ALU64, BPF_K},
+        maps::{bpf_map_def, LegacyMap, Map},
+        obj::{tests::fake_func, Object},
+    };
+
+    fn fake_obj() -> Object {
+        Object::new(
+            Endianness::Little,
+            CString::new("GPL").unwrap(),
+            KernelVersion::Any,
+        )
+    }
+
+    #[test]
+    fn test_min_kernel_version_ringbuf() {
+        let mut obj = fake_obj();
+        obj.maps.insert(
+            "events".to_string(),
+            Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_RINGBUF as u32,
+                    max_entries: 4096,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+            }),
+        );
+
+        match obj.min_kernel_version() {
+            KernelVersion::Version(v) => assert!(v >= version(5, 8)),
+            KernelVersion::Any => panic!("expected a version"),
+        }
+    }
+
+    #[test]
+    fn test_uses_alu32() {
+        const BPF_MOV: u32 = 0xb0;
+
+        let mut exit: bpf_insn = unsafe { mem::zeroed() };
+        exit.code = (BPF_JMP | BPF_EXIT) as u8;
+        // w0 = 0
+        let mut mov32: bpf_insn = unsafe { mem::zeroed() };
+        mov32.code = (BPF_ALU | BPF_MOV | BPF_K) as u8;
+        // r0 = 0
+        let mut mov64 = mov32;
+        mov64.code = (BPF_ALU64 | BPF_MOV | BPF_K) as u8;
+
+        let mut function = fake_func("foo", vec![mov64, exit]);
+        assert!(!function.uses_alu32());
+
+        function.instructions = vec![mov32, exit];
+        assert!(function.uses_alu32());
+
+        let mut obj = fake_obj();
+        obj.functions.insert(0, function);
+        match obj.min_kernel_version() {
+            KernelVersion::Version(v) => assert!(v >= version(4, 14)),
+            KernelVersion::Any => panic!("expected a version"),
+        }
+    }
+
+    #[test]
+    fn test_bounded_loop() {
+        const BPF_JA: u32 = 0x00;
+
+        let mut exit: bpf_insn = unsafe { mem::zeroed() };
+        exit.code = (BPF_JMP | BPF_EXIT) as u8;
+        // goto -2, back to the first instruction
+        let mut ja: bpf_insn = unsafe { mem::zeroed() };
+        ja.code = (BPF_JMP | BPF_JA) as u8;
+        ja.off = -2;
+
+        let function = fake_func("foo", vec![exit, ja, exit]);
+        assert!(function.has_bounded_loops());
+        assert!(!function.calls_bpf_loop());
+
+        let mut obj = fake_obj();
+        obj.functions.insert(0, function);
+        assert_eq!(
+            obj.min_kernel_version(),
+            KernelVersion::Version(version(5, 3))
+        );
+    }
+
+    #[test]
+    fn test_bpf_loop() {
+        let mut exit: bpf_insn = unsafe { mem::zeroed() };
+        exit.code = (BPF_JMP | BPF_EXIT) as u8;
+        let mut call: bpf_insn = unsafe { mem::zeroed() };
+        call.code = (BPF_JMP | BPF_CALL) as u8;
+        call.imm = BPF_FUNC_LOOP;
+
+        let function = fake_func("foo", vec![call, exit]);
+        assert!(!function.has_bounded_loops());
+        assert!(function.calls_bpf_loop());
+
+        let mut obj = fake_obj();
+        obj.functions.insert(0, function);
+        assert_eq!(
+            obj.min_kernel_version(),
+            KernelVersion::Version(version(5, 17))
+        );
+    }
+
+    #[test]
+    fn test_call_version() {
+        let mut call: bpf_insn = unsafe { mem::zeroed() };
+        call.code = (BPF_JMP | BPF_CALL) as u8;
+        call.imm = BPF_FUNC_LOOP;
+        assert_eq!(insn_version(&call), version(5, 17));
+
+        // the imm of bpf-to-bpf and kfunc calls isn't a helper id
+        let mut subprog_call = call;
+        subprog_call.set_src_reg(BPF_PSEUDO_CALL as u8);
+        assert_eq!(insn_version(&subprog_call), version(4, 16));
+        let mut kfunc_call = call;
+        kfunc_call.set_src_reg(BPF_PSEUDO_KFUNC_CALL as u8);
+        assert_eq!(insn_version(&kfunc_call), version(5, 13));
+
+        let function = fake_func("foo", vec![kfunc_call]);
+        assert!(!function.calls_bpf_loop());
+    }
+
+    #[test]
+    fn test_helper_version() {
+        // bpf_map_lookup_elem
+        assert_eq!(helper_version(1), version(3, 19));
+        // bpf_ringbuf_output
+        assert_eq!(helper_version(130), version(5, 8));
+        // bpf_loop
+        assert_eq!(helper_version(181), version(5, 17));
+    }
+}
@@ -1,7 +1,7 @@
 //! Object file loading, parsing, and relocation.
 
 use alloc::{
-    borrow::ToOwned,
+    borrow::{Cow, ToOwned},
     ffi::CString,
     string::{String, ToString},
     vec::Vec,
@@ -11,7 +11,7 @@ use log::debug;
 use object::{
     read::{Object as ElfObject, ObjectSection, Section as ObjSection},
     Endianness, ObjectSymbol, ObjectSymbolTable, RelocationTarget, SectionIndex, SectionKind,
-    SymbolKind,
+    SymbolFlags, SymbolKind,
 };
 
 use crate::{
@@ -25,7 +25,9 @@ use crate::{
     btf::{Btf, BtfError, BtfExt, BtfType},
     generated::{bpf_insn, bpf_map_info, bpf_map_type::BPF_MAP_TYPE_ARRAY, BPF_F_RDONLY_PROG},
     maps::{bpf_map_def, BtfMapDef, PinningType},
-    programs::{CgroupSockAddrAttachType, CgroupSockAttachType, CgroupSockoptAttachType},
+    programs::{
+        CgroupSockAddrAttachType, CgroupSockAttachType, CgroupSockoptAttachType, TcxAttachType,
+    },
 };
 use core::slice::from_raw_parts_mut;
 
@@ -56,10 +58,39 @@ pub struct Object {
     pub(crate) relocations: HashMap<SectionIndex, HashMap<u64, Relocation>>,
     pub(crate) symbols_by_index: HashMap<usize, Symbol>,
     pub(crate) section_sizes: HashMap<String, u64>,
+    pub(crate) section_names: HashMap<usize, String>,
     // symbol_offset_by_name caches symbols that could be referenced from a
     // BTF VAR type so the offsets can be fixed up
     pub(crate) symbol_offset_by_name: HashMap<String, u64>,
     pub(crate) text_section_index: Option<usize>,
+    pub(crate) externs: Vec<Extern>,
+    // undefined symbols referenced like maps, see Object::unresolved_maps()
+    pub(crate) unresolved_maps: Vec<usize>,
+    pub(crate) map_initial_data: HashMap<String, Vec<u8>>,
+    pub(crate) warnings: Vec<ParseWarning>,
+    // instructions poisoned by relocate_btf, by program name
+    pub(crate) poisoned_relocations: HashMap<String, Vec<usize>>,
+}
+
+/// Options for [Object::parse_with_options].
+#[derive(Debug, Default, Clone)]
+pub struct ParseOptions {
+    /// Skip the sections that can't be parsed because they're not understood,
+    /// for example program sections added by newer toolchains, instead of
+    /// failing. Skipped sections are reported by [Object::warnings].
+    pub ignore_unknown_sections: bool,
+}
+
+/// A problem found while parsing an object that didn't make parsing fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// A section that isn't understood was skipped
+    UnknownSection {
+        /// The section index
+        index: usize,
+        /// The section name
+        name: String,
+    },
 }
 
 /// An eBPF program
@@ -143,21 +174,14 @@ pub struct Function {
 /// # Unsupported Sections
 ///
 /// Currently, the following section names are not supported yet:
-/// - `flow_dissector`: `BPF_PROG_TYPE_FLOW_DISSECTOR`
 /// - `ksyscall+` or `kretsyscall+`
 /// - `uprobe.s+` or `uretprobe.s+`
-/// - `usdt+`
-/// - `kprobe.multi+` or `kretprobe.multi+`: `BPF_TRACE_KPROBE_MULTI`
-/// - `lsm_cgroup+` or `lsm.s+`
+/// - `lsm_cgroup+`
 /// - `lwt_in`, `lwt_out`, `lwt_seg6local`, `lwt_xmit`
 /// - `raw_tp.w+`, `raw_tracepoint.w+`
 /// - `action`
-/// - `sk_reuseport/migrate`, `sk_reuseport`
-/// - `syscall`
 /// - `struct_ops+`
 /// - `fmod_ret+`, `fmod_ret.s+`
-/// - `fentry.s+`, `fexit.s+`
-/// - `iter+`, `iter.s+`
 /// - `xdp.frags/cpumap`, `xdp/cpumap`
 /// - `xdp.frags/devmap`, `xdp/devmap`
 /// - `xdp.frags`
@@ -170,12 +194,21 @@ pub enum ProgramSection {
     KProbe {
         name: String,
     },
+    KRetProbeMulti {
+        name: String,
+    },
+    KProbeMulti {
+        name: String,
+    },
     UProbe {
         name: String,
     },
     URetProbe {
         name: String,
     },
+    Usdt {
+        name: String,
+    },
     TracePoint {
         name: String,
     },
@@ -200,6 +233,10 @@ pub enum ProgramSection {
     SchedClassifier {
         name: String,
     },
+    Tcx {
+        name: String,
+        attach_type: TcxAttachType,
+    },
     CgroupSkb {
         name: String,
     },
@@ -231,15 +268,18 @@ pub enum ProgramSection {
     },
     Lsm {
         name: String,
+        sleepable: bool,
     },
     BtfTracePoint {
         name: String,
     },
     FEntry {
         name: String,
+        sleepable: bool,
     },
     FExit {
         name: String,
+        sleepable: bool,
     },
     Extension {
         name: String,
@@ -247,6 +287,10 @@ pub enum ProgramSection {
     SkLookup {
         name: String,
     },
+    SkReuseport {
+        name: String,
+        migrate: bool,
+    },
     CgroupSock {
         name: String,
         attach_type: CgroupSockAttachType,
@@ -254,6 +298,17 @@ pub enum ProgramSection {
     CgroupDevice {
         name: String,
     },
+    Iter {
+        name: String,
+        target: String,
+        sleepable: bool,
+    },
+    Syscall {
+        name: String,
+    },
+    FlowDissector {
+        name: String,
+    },
 }
 
 impl ProgramSection {
@@ -262,8 +317,66 @@ impl ProgramSection {
         match self {
             ProgramSection::KRetProbe { name } => name,
             ProgramSection::KProbe { name } => name,
+            ProgramSection::KRetProbeMulti { name } => name,
+            ProgramSection::KProbeMulti { name } => name,
+            ProgramSection::UProbe { name } => name,
+            ProgramSection::URetProbe { name } => name,
+            ProgramSection::Usdt { name } => name,
+            ProgramSection::TracePoint { name } => name,
+            ProgramSection::SocketFilter { name } => name,
+            ProgramSection::Xdp { name } => name,
+            ProgramSection::SkMsg { name } => name,
+            ProgramSection::SkSkbStreamParser { name } => name,
+            ProgramSection::SkSkbStreamVerdict { name } => name,
+            ProgramSection::SockOps { name } => name,
+            ProgramSection::SchedClassifier { name } => name,
+            ProgramSection::Tcx { name, .. } => name,
+            ProgramSection::CgroupSkb { name, .. } => name,
+            ProgramSection::CgroupSkbIngress { name, .. } => name,
+            ProgramSection::CgroupSkbEgress { name, .. } => name,
+            ProgramSection::CgroupSockAddr { name, .. } => name,
+            ProgramSection::CgroupSysctl { name } => name,
+            ProgramSection::CgroupSockopt { name, .. } => name,
+            ProgramSection::LircMode2 { name } => name,
+            ProgramSection::PerfEvent { name } => name,
+            ProgramSection::RawTracePoint { name } => name,
+            ProgramSection::Lsm { name, .. } => name,
+            ProgramSection::BtfTracePoint { name } => name,
+            ProgramSection::FEntry { name, .. } => name,
+            ProgramSection::FExit { name, .. } => name,
+            ProgramSection::Extension { name } => name,
+            ProgramSection::SkLookup { name } => name,
+            ProgramSection::SkReuseport { name, .. } => name,
+            ProgramSection::CgroupSock { name, .. } => name,
+            ProgramSection::CgroupDevice { name } => name,
+            ProgramSection::Iter { name, .. } => name,
+            ProgramSection::Syscall { name } => name,
+            ProgramSection::FlowDissector { name } => name,
+        }
+    }
+
+    /// Returns `true` if the program is sleepable and must be loaded with
+    /// `BPF_F_SLEEPABLE`, as declared with the `.s` suffix of sections like
+    /// `fentry.s/` or `lsm.s/`.
+    pub fn sleepable(&self) -> bool {
+        match self {
+            ProgramSection::Lsm { sleepable, .. }
+            | ProgramSection::FEntry { sleepable, .. }
+            | ProgramSection::FExit { sleepable, .. }
+            | ProgramSection::Iter { sleepable, .. } => *sleepable,
+            _ => false,
+        }
+    }
+
+    fn name_mut(&mut self) -> &mut String {
+        match self {
+            ProgramSection::KRetProbe { name } => name,
+            ProgramSection::KProbe { name } => name,
+            ProgramSection::KRetProbeMulti { name } => name,
+            ProgramSection::KProbeMulti { name } => name,
             ProgramSection::UProbe { name } => name,
             ProgramSection::URetProbe { name } => name,
+            ProgramSection::Usdt { name } => name,
             ProgramSection::TracePoint { name } => name,
             ProgramSection::SocketFilter { name } => name,
             ProgramSection::Xdp { name } => name,
@@ -272,6 +385,7 @@ impl ProgramSection {
             ProgramSection::SkSkbStreamVerdict { name } => name,
             ProgramSection::SockOps { name } => name,
             ProgramSection::SchedClassifier { name } => name,
+            ProgramSection::Tcx { name, .. } => name,
             ProgramSection::CgroupSkb { name, .. } => name,
             ProgramSection::CgroupSkbIngress { name, .. } => name,
             ProgramSection::CgroupSkbEgress { name, .. } => name,
@@ -281,14 +395,18 @@ impl ProgramSection {
             ProgramSection::LircMode2 { name } => name,
             ProgramSection::PerfEvent { name } => name,
             ProgramSection::RawTracePoint { name } => name,
-            ProgramSection::Lsm { name } => name,
+            ProgramSection::Lsm { name, .. } => name,
             ProgramSection::BtfTracePoint { name } => name,
-            ProgramSection::FEntry { name } => name,
-            ProgramSection::FExit { name } => name,
+            ProgramSection::FEntry { name, .. } => name,
+            ProgramSection::FExit { name, .. } => name,
             ProgramSection::Extension { name } => name,
             ProgramSection::SkLookup { name } => name,
+            ProgramSection::SkReuseport { name, .. } => name,
             ProgramSection::CgroupSock { name, .. } => name,
             ProgramSection::CgroupDevice { name } => name,
+            ProgramSection::Iter { name, .. } => name,
+            ProgramSection::Syscall { name } => name,
+            ProgramSection::FlowDissector { name } => name,
         }
     }
 }
@@ -311,8 +429,11 @@ impl FromStr for ProgramSection {
         Ok(match kind {
             "kprobe" => KProbe { name },
             "kretprobe" => KRetProbe { name },
+            "kprobe.multi" => KProbeMulti { name },
+            "kretprobe.multi" => KRetProbeMulti { name },
             "uprobe" => UProbe { name },
             "uretprobe" => URetProbe { name },
+            "usdt" => Usdt { name },
             "xdp" => Xdp { name },
             "tp_btf" => BtfTracePoint { name },
             _ if kind.starts_with("tracepoint") || kind.starts_with("tp") => {
@@ -335,7 +456,31 @@ impl FromStr for ProgramSection {
             "sk_skb/stream_parser" => SkSkbStreamParser { name },
             "sk_skb/stream_verdict" => SkSkbStreamVerdict { name },
             "sockops" => SockOps { name },
+            "sk_reuseport" => SkReuseport {
+                migrate: name == "migrate",
+                name,
+            },
+            "sk_reuseport/migrate" => SkReuseport {
+                name,
+                migrate: true,
+            },
             "classifier" => SchedClassifier { name },
+            "tcx" => match TcxAttachType::try_from(name.as_str()) {
+                Ok(attach_type) => Tcx { name, attach_type },
+                Err(_) => {
+                    return Err(ParseError::InvalidProgramSection {
+                        section: section.to_owned(),
+                    })
+                }
+            },
+            "tcx/ingress" => Tcx {
+                name,
+                attach_type: TcxAttachType::Ingress,
+            },
+            "tcx/egress" => Tcx {
+                name,
+                attach_type: TcxAttachType::Egress,
+            },
             "cgroup_skb" => match &*name {
                 "ingress" => CgroupSkbIngress { name },
                 "egress" => CgroupSkbEgress { name },
@@ -465,11 +610,50 @@ impl FromStr for ProgramSection {
             "lirc_mode2" => LircMode2 { name },
             "perf_event" => PerfEvent { name },
             "raw_tp" | "raw_tracepoint" => RawTracePoint { name },
-            "lsm" => Lsm { name },
-            "fentry" => FEntry { name },
-            "fexit" => FExit { name },
+            "lsm" => Lsm {
+                name,
+                sleepable: false,
+            },
+            "lsm.s" => Lsm {
+                name,
+                sleepable: true,
+            },
+            "fentry" => FEntry {
+                name,
+                sleepable: false,
+            },
+            "fentry.s" => FEntry {
+                name,
+                sleepable: true,
+            },
+            "fexit" => FExit {
+                name,
+                sleepable: false,
+            },
+            "fexit.s" => FExit {
+                name,
+                sleepable: true,
+            },
             "freplace" => Extension { name },
             "sk_lookup" => SkLookup { name },
+            // iter sections are named `iter/target` or `iter/target/program_name`
+            "iter" | "iter.s" => Iter {
+                target: name.clone(),
+                name,
+                sleepable: kind == "iter.s",
+            },
+            _ if kind.starts_with("iter/") => Iter {
+                name,
+                target: kind["iter/".len()..].to_owned(),
+                sleepable: false,
+            },
+            _ if kind.starts_with("iter.s/") => Iter {
+                name,
+                target: kind["iter.s/".len()..].to_owned(),
+                sleepable: true,
+            },
+            "syscall" => Syscall { name },
+            "flow_dissector" => FlowDissector { name },
             _ => {
                 return Err(ParseError::InvalidProgramSection {
                     section: section.to_owned(),
@@ -482,7 +666,33 @@ impl FromStr for ProgramSection {
 impl Object {
     /// Parses the binary data as an object file into an [Object]
     pub fn parse(data: &[u8]) -> Result<Object, ParseError> {
+        Object::parse_with_options(data, &ParseOptions::default())
+    }
+
+    /// Parses the binary data as an object file into an [Object], using the
+    /// given [ParseOptions].
+    pub fn parse_with_options(data: &[u8], options: &ParseOptions) -> Result<Object, ParseError> {
         let obj = object::read::File::parse(data).map_err(ParseError::ElfError)?;
+        let mut bpf_obj = Object::parse_metadata(&obj)?;
+
+        for s in obj.sections() {
+            if let Ok(name) = s.name() {
+                if name == ".BTF" || name == ".BTF.ext" {
+                    continue;
+                }
+            }
+
+            bpf_obj.parse_section_with_options(Section::try_from(&s)?, options)?;
+        }
+
+        bpf_obj.collect_externs()?;
+
+        Ok(bpf_obj)
+    }
+
+    // Parses the license, version, symbol table and BTF, which are needed to
+    // parse the other sections.
+    pub(crate) fn parse_metadata(obj: &object::read::File) -> Result<Object, ParseError> {
         let endianness = obj.endianness();
 
         let license = if let Some(section) = obj.section_by_name("license") {
@@ -506,6 +716,22 @@ impl Object {
                     .ok()
                     .map(String::from)
                     .ok_or(BtfError::InvalidSymbolName)?;
+                let (binding, visibility) = match symbol.flags() {
+                    SymbolFlags::Elf { st_info, st_other } => (
+                        SymbolBinding::from_st_info(st_info),
+                        SymbolVisibility::from_st_other(st_other),
+                    ),
+                    _ => (
+                        if symbol.is_weak() {
+                            SymbolBinding::Weak
+                        } else if symbol.is_global() {
+                            SymbolBinding::Global
+                        } else {
+                            SymbolBinding::Local
+                        },
+                        SymbolVisibility::Default,
+                    ),
+                };
                 let sym = Symbol {
                     index: symbol.index().0,
                     name: Some(name.clone()),
@@ -514,6 +740,8 @@ impl Object {
                     size: symbol.size(),
                     is_definition: symbol.is_definition(),
                     kind: symbol.kind(),
+                    binding,
+                    visibility,
                 };
                 bpf_obj.symbols_by_index.insert(symbol.index().0, sym);
 
@@ -533,20 +761,39 @@ impl Object {
             }
         }
 
-        for s in obj.sections() {
-            if let Ok(name) = s.name() {
-                if name == ".BTF" || name == ".BTF.ext" {
-                    continue;
-                }
-            }
+        Ok(bpf_obj)
+    }
 
-            bpf_obj.parse_section(Section::try_from(&s)?)?;
+    // ksyms are declared in the .ksyms DATASEC and referenced through undefined
+    // symbols with the same name
+    pub(crate) fn collect_externs(&mut self) -> Result<(), BtfError> {
+        let btf = match &self.btf {
+            Some(btf) => btf,
+            None => return Ok(()),
+        };
+        for (name, typed) in btf.ksyms()? {
+            let symbol = self
+                .symbols_by_index
+                .values()
+                .find(|s| s.section_index.is_none() && s.name.as_deref() == Some(name.as_str()));
+            if let Some(symbol) = symbol {
+                self.externs.push(Extern {
+                    symbol_index: symbol.index,
+                    is_weak: symbol.binding == SymbolBinding::Weak,
+                    typed,
+                    name,
+                });
+            }
         }
 
-        Ok(bpf_obj)
+        Ok(())
     }
 
-    fn new(endianness: Endianness, license: CString, kernel_version: KernelVersion) -> Object {
+    pub(crate) fn new(
+        endianness: Endianness,
+        license: CString,
+        kernel_version: KernelVersion,
+    ) -> Object {
         Object {
             endianness,
             license,
@@ -559,9 +806,165 @@ impl Object {
             relocations: HashMap::new(),
             symbols_by_index: HashMap::new(),
             section_sizes: HashMap::new(),
+            section_names: HashMap::new(),
             symbol_offset_by_name: HashMap::new(),
             text_section_index: None,
+            externs: Vec::new(),
+            unresolved_maps: Vec::new(),
+            map_initial_data: HashMap::new(),
+            warnings: Vec::new(),
+            poisoned_relocations: HashMap::new(),
+        }
+    }
+
+    /// Takes ownership of the program with the given name, removing it from
+    /// the object.
+    ///
+    /// Returns `None` if the program doesn't exist. Relocations should be
+    /// applied before taking programs out of the object, since programs that
+    /// have been taken are no longer relocated.
+    pub fn take_program(&mut self, name: &str) -> Option<Program> {
+        self.programs.remove(name)
+    }
+
+    /// Renames a program.
+    ///
+    /// This is useful to avoid name clashes, for example when the same object
+    /// is loaded more than once.
+    pub fn rename_program(&mut self, old: &str, new: &str) -> Result<(), RenameError> {
+        if self.programs.contains_key(new) {
+            return Err(RenameError::AlreadyExists {
+                name: new.to_owned(),
+            });
         }
+        let mut program = self
+            .programs
+            .remove(old)
+            .ok_or_else(|| RenameError::NotFound {
+                name: old.to_owned(),
+            })?;
+        *program.section.name_mut() = new.to_owned();
+        self.programs.insert(new.to_owned(), program);
+        Ok(())
+    }
+
+    /// Renames a map.
+    ///
+    /// Relocations refer to maps by symbol and section, so they keep
+    /// resolving to the renamed map. The BTF data section of global data maps
+    /// is renamed too.
+    pub fn rename_map(&mut self, old: &str, new: &str) -> Result<(), RenameError> {
+        if self.maps.contains_key(new) {
+            return Err(RenameError::AlreadyExists {
+                name: new.to_owned(),
+            });
+        }
+        let map = self.maps.remove(old).ok_or_else(|| RenameError::NotFound {
+            name: old.to_owned(),
+        })?;
+        if map.kind() != MapKind::Other {
+            if let Some(btf) = &mut self.btf {
+                if let Err(e) = btf.rename_datasec(old, new) {
+                    self.maps.insert(old.to_owned(), map);
+                    return Err(e.into());
+                }
+            }
+            // the datasec size fixup looks up the section by name
+            if let Some(size) = self.section_sizes.get(old).copied() {
+                self.section_sizes.insert(new.to_owned(), size);
+            }
+        }
+        self.maps.insert(new.to_owned(), map);
+        Ok(())
+    }
+
+    /// Returns the problems found while parsing the object that didn't make
+    /// parsing fail.
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    /// Returns the symbols of the object, ordered by their index in the ELF
+    /// symbol table.
+    pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+        let mut symbols = self.symbols_by_index.values().collect::<Vec<_>>();
+        symbols.sort_by_key(|sym| sym.index);
+        symbols.into_iter()
+    }
+
+    /// Returns whether the object license allows using GPL-only helpers.
+    ///
+    /// See [license_is_gpl_compatible] for the accepted licenses.
+    pub fn is_gpl_compatible(&self) -> bool {
+        license_is_gpl_compatible(&self.license)
+    }
+
+    /// Returns the source location of the instruction at `insn_idx` in the
+    /// program `prog`, as a `(file, line, column)` tuple.
+    ///
+    /// The location is taken from the nearest `.BTF.ext` line info record at
+    /// or before the instruction. Returns `None` if the program doesn't exist
+    /// or the object doesn't have line info for it.
+    pub fn source_location(&self, prog: &str, insn_idx: usize) -> Option<(String, u32, u32)> {
+        let btf = self.btf.as_ref()?;
+        let program = self.programs.get(prog)?;
+        let info = program
+            .function
+            .line_info
+            .line_info
+            .iter()
+            .filter(|l| l.insn_off as usize <= insn_idx)
+            .max_by_key(|l| l.insn_off)?;
+        let file = btf.string_at(info.file_name_off).ok()?.into_owned();
+        Some((file, info.line_col >> 10, info.line_col & 0x3ff))
+    }
+
+    /// Returns the source location of the instruction a verifier log failed
+    /// at, see [Object::source_location].
+    ///
+    /// The failing instruction is the last instruction printed in the log.
+    pub fn verifier_log_source_location(
+        &self,
+        prog: &str,
+        verifier_log: &str,
+    ) -> Option<(String, u32, u32)> {
+        let insn_idx = verifier_log.lines().rev().find_map(|line| {
+            let (idx, _) = line.split_once(": ")?;
+            idx.parse::<usize>().ok()
+        })?;
+        self.source_location(prog, insn_idx)
+    }
+
+    /// Sets the contents an array map is initialized with when it's created.
+    ///
+    /// `data` holds the values of all the entries of the map one after the
+    /// other, so its size must be `value_size * max_entries`. The entries are
+    /// written right after the map is created, and before `.rodata` maps are
+    /// frozen.
+    pub fn set_map_initial_data(&mut self, name: &str, data: Vec<u8>) -> Result<(), ParseError> {
+        let map = self.maps.get(name).ok_or_else(|| ParseError::UnknownMap {
+            name: name.to_owned(),
+        })?;
+        if map.map_type() != BPF_MAP_TYPE_ARRAY as u32 {
+            return Err(ParseError::NotArrayMap {
+                name: name.to_owned(),
+            });
+        }
+        let expected = map.value_size() as usize * map.max_entries() as usize;
+        if data.len() != expected {
+            return Err(ParseError::InvalidMapInitialData {
+                name: name.to_owned(),
+                data_size: data.len(),
+                expected,
+            });
+        }
+        self.map_initial_data.insert(name.to_owned(), data);
+        Ok(())
+    }
+
+    /// Returns the initial contents of the map, see [Object::set_map_initial_data].
+    pub fn map_initial_data(&self, name: &str) -> Option<&[u8]> {
+        self.map_initial_data.get(name).map(|data| data.as_slice())
     }
 
     /// Patches map data
@@ -617,15 +1020,48 @@ impl Object {
     }
 
     fn parse_btf_ext(&mut self, section: &Section) -> Result<(), BtfError> {
+        // the instruction offsets of the records are only final once the
+        // .rel.BTF.ext relocations have been applied
+        let data = if section.relocations.is_empty() {
+            Cow::Borrowed(section.data)
+        } else {
+            Cow::Owned(self.relocate_btf_ext(section)?)
+        };
         self.btf_ext = Some(BtfExt::parse(
-            section.data,
+            &data,
             self.endianness,
             self.btf.as_ref().unwrap(),
         )?);
         Ok(())
     }
 
-    fn parse_program(&self, section: &Section) -> Result<Program, ParseError> {
+    fn relocate_btf_ext(&self, section: &Section) -> Result<Vec<u8>, BtfError> {
+        let mut data = section.data.to_vec();
+        for rel in &section.relocations {
+            let invalid = || BtfError::InvalidBtfExtRelocation { offset: rel.offset };
+            let sym = self
+                .symbols_by_index
+                .get(&rel.symbol_index)
+                .ok_or_else(invalid)?;
+            let start = rel.offset as usize;
+            let field = data.get_mut(start..start + 4).ok_or_else(invalid)?;
+            let bytes = <[u8; 4]>::try_from(&field[..]).unwrap();
+            let value = match self.endianness {
+                Endianness::Little => u32::from_le_bytes(bytes),
+                Endianness::Big => u32::from_be_bytes(bytes),
+            };
+            // the inline addend (REL) plus the explicit addend (RELA) plus the
+            // address of the symbol
+            let value = (value as i64 + rel.addend + sym.address as i64) as u32;
+            field.copy_from_slice(&match self.endianness {
+                Endianness::Little => value.to_le_bytes(),
+                Endianness::Big => value.to_be_bytes(),
+            });
+        }
+        Ok(data)
+    }
+
+    pub(crate) fn parse_program(&self, section: &Section) -> Result<Program, ParseError> {
         let prog_sec = ProgramSection::from_str(section.name)?;
         let name = prog_sec.name().to_owned();
 
@@ -741,6 +1177,12 @@ impl Object {
             offset += sym.size as usize;
         }
 
+        self.insert_relocations(&mut section);
+
+        Ok(())
+    }
+
+    pub(crate) fn insert_relocations(&mut self, section: &mut Section) {
         if !section.relocations.is_empty() {
             self.relocations.insert(
                 section.index,
@@ -751,8 +1193,6 @@ impl Object {
                     .collect(),
             );
         }
-
-        Ok(())
     }
 
     fn parse_map_section(
@@ -772,7 +1212,7 @@ impl Object {
                 .as_ref()
                 .ok_or(ParseError::MapSymbolNameNotFound { i })?;
             let def = parse_map_def(name, data)?;
-            self.maps.insert(
+            self.insert_map(
                 name.to_string(),
                 Map::Legacy(LegacyMap {
                     section_index: section.index.0,
@@ -781,11 +1221,18 @@ impl Object {
                     data: Vec::new(),
                     kind: MapKind::Other,
                 }),
-            );
+            )?;
         }
         Ok(())
     }
 
+    // Inserts a map parsed from a `maps` or `.maps` section. Maps declared in
+    // both sections must agree on their type and sizes, in which case the BTF
+    // definition is kept.
+    fn insert_map(&mut self, name: String, map: Map) -> Result<(), ParseError> {
+        insert_map(&mut self.maps, name, map)
+    }
+
     fn parse_btf_maps(
         &mut self,
         section: &Section,
@@ -795,6 +1242,7 @@ impl Object {
             return Err(ParseError::NoBTF);
         }
         let btf = self.btf.as_ref().unwrap();
+        let maps = &mut self.maps;
 
         for t in btf.types() {
             if let BtfType::DataSec(datasec) = &t {
@@ -812,7 +1260,8 @@ impl Object {
                                 name: map_name.to_string(),
                             })?
                             .index;
-                        self.maps.insert(
+                        insert_map(
+                            maps,
                             map_name,
                             Map::Btf(BtfMap {
                                 def,
@@ -820,8 +1269,9 @@ impl Object {
                                 symbol_index,
                                 kind: MapKind::Other,
                                 data: Vec::new(),
+                                legacy: None,
                             }),
-                        );
+                        )?;
                     }
                 }
             }
@@ -829,6 +1279,25 @@ impl Object {
         Ok(())
     }
 
+    pub(crate) fn parse_section_with_options(
+        &mut self,
+        section: Section,
+        options: &ParseOptions,
+    ) -> Result<(), ParseError> {
+        let (index, name) = (section.index.0, section.name);
+        match self.parse_section(section) {
+            Err(ParseError::InvalidProgramSection { .. }) if options.ignore_unknown_sections => {
+                debug!("ignoring unknown section `{}`", name);
+                self.warnings.push(ParseWarning::UnknownSection {
+                    index,
+                    name: name.to_owned(),
+                });
+                Ok(())
+            }
+            ret => ret,
+        }
+    }
+
     fn parse_section(&mut self, mut section: Section) -> Result<(), ParseError> {
         let mut parts = section.name.rsplitn(2, '/').collect::<Vec<_>>();
         parts.reverse();
@@ -843,6 +1312,8 @@ impl Object {
         }
         self.section_sizes
             .insert(section.name.to_owned(), section.size);
+        self.section_names
+            .insert(section.index.0, section.name.to_owned());
         match section.kind {
             BpfSectionKind::Data => {
                 self.maps
@@ -886,16 +1357,7 @@ impl Object {
                 let program = self.parse_program(&section)?;
                 self.programs
                     .insert(program.section.name().to_owned(), program);
-                if !section.relocations.is_empty() {
-                    self.relocations.insert(
-                        section.index,
-                        section
-                            .relocations
-                            .drain(..)
-                            .map(|rel| (rel.offset, rel))
-                            .collect(),
-                    );
-                }
+                self.insert_relocations(&mut section);
             }
             BpfSectionKind::Undefined | BpfSectionKind::License | BpfSectionKind::Version => {}
         }
@@ -904,6 +1366,28 @@ impl Object {
     }
 }
 
+/// Errors returned by [Object::rename_program] and [Object::rename_map]
+#[derive(Debug, Error)]
+pub enum RenameError {
+    /// The object doesn't contain the item being renamed.
+    #[error("`{name}` not found")]
+    NotFound {
+        /// the name of the item
+        name: String,
+    },
+
+    /// The new name is already in use.
+    #[error("`{name}` already exists")]
+    AlreadyExists {
+        /// the new name
+        name: String,
+    },
+
+    /// Renaming the BTF data section of a map failed.
+    #[error(transparent)]
+    BtfError(#[from] BtfError),
+}
+
 /// Errors caught during parsing the object file
 #[derive(Debug, Error)]
 #[allow(missing_docs)]
@@ -964,19 +1448,43 @@ pub enum ParseError {
     #[error("map for section with index {index} not found")]
     MapNotFound { index: usize },
 
+    #[error("map `{name}` not found")]
+    UnknownMap { name: String },
+
+    #[error("map `{name}` is not an array map")]
+    NotArrayMap { name: String },
+
+    #[error("initial data of map `{name}` has size `{data_size}`, expected `{expected}`")]
+    InvalidMapInitialData {
+        name: String,
+        data_size: usize,
+        expected: usize,
+    },
+
     #[error("the map number {i} in the `maps` section doesn't have a symbol name")]
     MapSymbolNameNotFound { i: usize },
 
     #[error("no symbols found for the maps included in the maps section")]
     NoSymbolsInMapSection {},
 
+    #[error("section with index {index} not found")]
+    SectionNotFound { index: usize },
+
     /// No BTF parsed for object
     #[error("no BTF parsed for object")]
     NoBTF,
+
+    /// The legacy and BTF definitions of a map don't match
+    #[error("the legacy definition of map `{name}` doesn't match its BTF definition: {legacy:?} != {btf:?}")]
+    MapDefinitionMismatch {
+        name: String,
+        legacy: bpf_map_def,
+        btf: BtfMapDef,
+    },
 }
 
 #[derive(Debug)]
-enum BpfSectionKind {
+pub(crate) enum BpfSectionKind {
     Undefined,
     Maps,
     BtfMaps,
@@ -990,7 +1498,7 @@ enum BpfSectionKind {
 }
 
 impl BpfSectionKind {
-    fn from_name(name: &str) -> BpfSectionKind {
+    pub(crate) fn from_name(name: &str) -> BpfSectionKind {
         if name.starts_with("license") {
             BpfSectionKind::License
         } else if name.starts_with("version") {
@@ -1017,14 +1525,14 @@ impl BpfSectionKind {
 }
 
 #[derive(Debug)]
-struct Section<'a> {
-    index: SectionIndex,
-    kind: BpfSectionKind,
+pub(crate) struct Section<'a> {
+    pub(crate) index: SectionIndex,
+    pub(crate) kind: BpfSectionKind,
     address: u64,
-    name: &'a str,
+    pub(crate) name: &'a str,
     data: &'a [u8],
-    size: u64,
-    relocations: Vec<Relocation>,
+    pub(crate) size: u64,
+    pub(crate) relocations: Vec<Relocation>,
 }
 
 impl<'data, 'file, 'a> TryFrom<&'a ObjSection<'data, 'file>> for Section<'a> {
@@ -1063,6 +1571,7 @@ impl<'data, 'file, 'a> TryFrom<&'a ObjSection<'data, 'file>> for Section<'a> {
                             _ => return Err(ParseError::UnsupportedRelocationTarget),
                         },
                         offset,
+                        addend: r.addend(),
                     })
                 })
                 .collect::<Result<Vec<_>, _>>()?,
@@ -1071,6 +1580,10 @@ impl<'data, 'file, 'a> TryFrom<&'a ObjSection<'data, 'file>> for Section<'a> {
 }
 
 fn parse_license(data: &[u8]) -> Result<CString, ParseError> {
+    Ok(parse_license_ref(data)?.to_owned())
+}
+
+pub(crate) fn parse_license_ref(data: &[u8]) -> Result<&CStr, ParseError> {
     if data.len() < 2 {
         return Err(ParseError::InvalidLicense {
             data: data.to_vec(),
@@ -1082,14 +1595,29 @@ fn parse_license(data: &[u8]) -> Result<CString, ParseError> {
         });
     }
 
-    Ok(CStr::from_bytes_with_nul(data)
-        .map_err(|_| ParseError::InvalidLicense {
-            data: data.to_vec(),
-        })?
-        .to_owned())
+    CStr::from_bytes_with_nul(data).map_err(|_| ParseError::InvalidLicense {
+        data: data.to_vec(),
+    })
 }
 
-fn parse_version(data: &[u8], endianness: object::Endianness) -> Result<KernelVersion, ParseError> {
+/// Returns whether `license` is one of the GPL compatible licenses accepted
+/// by the kernel, see `license_is_gpl_compatible()` in `include/linux/license.h`.
+pub fn license_is_gpl_compatible(license: &CStr) -> bool {
+    matches!(
+        license.to_bytes(),
+        b"GPL"
+            | b"GPL v2"
+            | b"GPL and additional rights"
+            | b"Dual BSD/GPL"
+            | b"Dual MIT/GPL"
+            | b"Dual MPL/GPL"
+    )
+}
+
+pub(crate) fn parse_version(
+    data: &[u8],
+    endianness: object::Endianness,
+) -> Result<KernelVersion, ParseError> {
     let data = match data.len() {
         4 => data.try_into().unwrap(),
         _ => {
@@ -1203,6 +1731,30 @@ fn parse_map_def(name: &str, data: &[u8]) -> Result<bpf_map_def, ParseError> {
     }
 }
 
+fn insert_map(maps: &mut HashMap<String, Map>, name: String, map: Map) -> Result<(), ParseError> {
+    let map = match (maps.remove(&name), map) {
+        (Some(Map::Legacy(legacy)), Map::Btf(btf)) | (Some(Map::Btf(btf)), Map::Legacy(legacy)) => {
+            if legacy.def.map_type != btf.def.map_type
+                || legacy.def.key_size != btf.def.key_size
+                || legacy.def.value_size != btf.def.value_size
+            {
+                return Err(ParseError::MapDefinitionMismatch {
+                    name,
+                    legacy: legacy.def,
+                    btf: btf.def,
+                });
+            }
+            Map::Btf(BtfMap {
+                legacy: Some(legacy),
+                ..btf
+            })
+        }
+        (_, map) => map,
+    };
+    maps.insert(name, map);
+    Ok(())
+}
+
 fn parse_btf_map_def(btf: &Btf, info: &DataSecEntry) -> Result<(String, BtfMapDef), BtfError> {
     let ty = match btf.type_by_id(info.btf_type)? {
         BtfType::Var(var) => var,
@@ -1279,6 +1831,8 @@ fn parse_btf_map_def(btf: &Btf, info: &DataSecEntry) -> Result<(String, BtfMapDe
             }
         }
     }
+    map_def.spin_lock =
+        Some(map_def.btf_value_type_id != 0 && btf.has_spin_lock(map_def.btf_value_type_id)?);
     Ok((map_name.to_string(), map_def))
 }
 
@@ -1295,12 +1849,14 @@ pub fn parse_map_info(info: bpf_map_info, pinned: PinningType) -> Map {
                 pinning: pinned,
                 btf_key_type_id: info.btf_key_type_id,
                 btf_value_type_id: info.btf_value_type_id,
+                spin_lock: None,
             },
             section_index: 0,
             symbol_index: 0,
             data: Vec::new(),
             // We should never be loading the .bss or .data or .rodata FDs
             kind: MapKind::Other,
+            legacy: None,
         })
     } else {
         Map::Legacy(LegacyMap {
@@ -1335,13 +1891,21 @@ pub fn copy_instructions(data: &[u8]) -> Result<Vec<bpf_insn>, ParseError> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use alloc::vec;
     use matches::assert_matches;
     use object::Endianness;
 
     use super::*;
-    use crate::maps::PinningType;
+    use crate::{
+        btf::{Const, DataSec, Int, IntEncoding, Var, VarLinkage},
+        generated::{
+            bpf_core_relo, bpf_core_relo_kind, btf_ext_header, BPF_DW, BPF_LD, BPF_PSEUDO_BTF_ID,
+            BPF_PSEUDO_MAP_FD,
+        },
+        maps::PinningType,
+        relocation::BPF_IMM,
+    };
 
     fn fake_section<'a>(kind: BpfSectionKind, name: &'a str, data: &'a [u8]) -> Section<'a> {
         Section {
@@ -1355,6 +1919,20 @@ mod tests {
         }
     }
 
+    pub(crate) fn fake_func(name: &str, instructions: Vec<bpf_insn>) -> Function {
+        Function {
+            address: Default::default(),
+            name: name.to_string(),
+            section_index: SectionIndex(0),
+            section_offset: Default::default(),
+            instructions,
+            func_info: Default::default(),
+            line_info: Default::default(),
+            func_info_rec_size: Default::default(),
+            line_info_rec_size: Default::default(),
+        }
+    }
+
     fn fake_ins() -> bpf_insn {
         bpf_insn {
             code: 0,
@@ -1377,6 +1955,8 @@ mod tests {
                 size,
                 is_definition: false,
                 kind: SymbolKind::Data,
+                binding: SymbolBinding::Global,
+                visibility: SymbolVisibility::Default,
             },
         );
     }
@@ -1623,6 +2203,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_take_program() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "kprobe/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.take_program("foo"),
+            Some(Program {
+                section: ProgramSection::KProbe { .. },
+                ..
+            })
+        );
+        assert!(obj.programs.get("foo").is_none());
+        assert!(obj.take_program("foo").is_none());
+    }
+
     #[test]
     fn test_parse_section_map() {
         let mut obj = fake_obj();
@@ -1645,11 +2248,72 @@ mod tests {
         assert!(obj.maps.get("foo").is_some());
     }
 
+    fn fake_legacy_and_btf_maps(value_size: u32) -> (Map, Map) {
+        let legacy = Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: 1,
+                key_size: 4,
+                value_size,
+                max_entries: 16,
+                ..Default::default()
+            },
+            section_index: 0,
+            symbol_index: 1,
+            data: Vec::new(),
+            kind: MapKind::Other,
+        });
+        let btf = Map::Btf(BtfMap {
+            def: BtfMapDef {
+                map_type: 1,
+                key_size: 4,
+                value_size: 8,
+                max_entries: 32,
+                ..Default::default()
+            },
+            section_index: 1,
+            symbol_index: 2,
+            data: Vec::new(),
+            kind: MapKind::Other,
+            legacy: None,
+        });
+        (legacy, btf)
+    }
+
     #[test]
-    fn test_parse_section_multiple_maps() {
+    fn test_legacy_and_btf_map_definitions() {
         let mut obj = fake_obj();
-        fake_sym(&mut obj, 0, 0, "foo", mem::size_of::<bpf_map_def>() as u64);
-        fake_sym(&mut obj, 0, 28, "bar", mem::size_of::<bpf_map_def>() as u64);
+        let (legacy, btf) = fake_legacy_and_btf_maps(8);
+        obj.insert_map("foo".to_string(), legacy).unwrap();
+        obj.insert_map("foo".to_string(), btf).unwrap();
+
+        let map = obj.maps.get("foo").unwrap();
+        assert_matches!(map, Map::Btf(_));
+        // the BTF definition wins
+        assert_eq!(map.max_entries(), 32);
+        assert_eq!(map.symbol_index(), 2);
+        assert_eq!(map.legacy_def().unwrap().max_entries, 16);
+    }
+
+    #[test]
+    fn test_legacy_and_btf_map_definitions_mismatch() {
+        let mut obj = fake_obj();
+        let (legacy, btf) = fake_legacy_and_btf_maps(16);
+        obj.insert_map("foo".to_string(), btf).unwrap();
+        match obj.insert_map("foo".to_string(), legacy) {
+            Err(ParseError::MapDefinitionMismatch { name, legacy, btf }) => {
+                assert_eq!(name, "foo");
+                assert_eq!(legacy.value_size, 16);
+                assert_eq!(btf.value_size, 8);
+            }
+            _ => panic!("expected a map definition mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_parse_section_multiple_maps() {
+        let mut obj = fake_obj();
+        fake_sym(&mut obj, 0, 0, "foo", mem::size_of::<bpf_map_def>() as u64);
+        fake_sym(&mut obj, 0, 28, "bar", mem::size_of::<bpf_map_def>() as u64);
         fake_sym(&mut obj, 0, 60, "baz", mem::size_of::<bpf_map_def>() as u64);
         let def = &bpf_map_def {
             map_type: 1,
@@ -1720,6 +2384,437 @@ mod tests {
         assert!(obj.maps.get(".data.boo").is_some());
     }
 
+    #[test]
+    fn test_is_gpl_compatible() {
+        for license in [
+            "GPL",
+            "GPL v2",
+            "GPL and additional rights",
+            "Dual BSD/GPL",
+            "Dual MIT/GPL",
+            "Dual MPL/GPL",
+        ] {
+            let mut obj = fake_obj();
+            obj.license = CString::new(license).unwrap();
+            assert!(obj.is_gpl_compatible(), "{license}");
+        }
+
+        let mut obj = fake_obj();
+        obj.license = CString::new("Proprietary").unwrap();
+        assert!(!obj.is_gpl_compatible());
+    }
+
+    #[test]
+    fn test_rename_map() {
+        let mut obj = fake_obj();
+        let mut lddw = fake_ins();
+        lddw.code = (BPF_LD | BPF_IMM | BPF_DW) as u8;
+        obj.parse_section(fake_section(
+            BpfSectionKind::Program,
+            "kprobe/foo",
+            bytes_of(&[lddw, fake_ins()]),
+        ))
+        .unwrap();
+        fake_sym(&mut obj, 1, 0, "map_1", 0);
+        obj.maps.insert(
+            "map_1".to_string(),
+            Map::Legacy(LegacyMap {
+                def: Default::default(),
+                section_index: 1,
+                symbol_index: 1,
+                data: Vec::new(),
+                kind: MapKind::Other,
+            }),
+        );
+        obj.maps
+            .insert("map_2".to_string(), obj.maps["map_1"].clone());
+        obj.relocations.insert(
+            SectionIndex(0),
+            HashMap::from([(
+                0,
+                Relocation {
+                    offset: 0,
+                    symbol_index: 1,
+                    addend: 0,
+                },
+            )]),
+        );
+
+        assert_matches!(
+            obj.rename_map("map_1", "map_2"),
+            Err(RenameError::AlreadyExists { .. })
+        );
+        assert_matches!(
+            obj.rename_map("missing", "map_3"),
+            Err(RenameError::NotFound { .. })
+        );
+        obj.maps.remove("map_2");
+        obj.rename_map("map_1", "renamed").unwrap();
+        assert!(!obj.maps.contains_key("map_1"));
+
+        let maps = obj.maps.clone();
+        obj.relocate_maps(
+            maps.iter()
+                .map(|(name, map)| (name.as_str(), Some(42), map)),
+        )
+        .unwrap();
+        let ins = &obj.programs["foo"].function.instructions[0];
+        assert_eq!(ins.src_reg() as u32, BPF_PSEUDO_MAP_FD);
+        assert_eq!(ins.imm, 42);
+    }
+
+    #[test]
+    fn test_parse_ksyms() {
+        let mut obj = fake_obj();
+
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        let int_type_id = btf.add_type(BtfType::Int(Int::new(
+            name_offset,
+            4,
+            IntEncoding::Signed,
+            0,
+        )));
+        let name_offset = btf.add_string("bpf_prog_active".to_string());
+        let typed_type_id = btf.add_type(BtfType::Var(Var::new(
+            name_offset,
+            int_type_id,
+            VarLinkage::Extern,
+        )));
+        let const_void_type_id = btf.add_type(BtfType::Const(Const::new(0)));
+        let name_offset = btf.add_string("bpf_init".to_string());
+        let typeless_type_id = btf.add_type(BtfType::Var(Var::new(
+            name_offset,
+            const_void_type_id,
+            VarLinkage::Extern,
+        )));
+        let name_offset = btf.add_string(".ksyms".to_string());
+        let entries = [typed_type_id, typeless_type_id]
+            .into_iter()
+            .map(|btf_type| DataSecEntry {
+                btf_type,
+                offset: 0,
+                size: 0,
+            })
+            .collect();
+        btf.add_type(BtfType::DataSec(DataSec::new(name_offset, entries, 0)));
+        obj.btf = Some(btf);
+
+        for (index, name, binding) in [
+            (1, "bpf_prog_active", SymbolBinding::Global),
+            (2, "bpf_init", SymbolBinding::Weak),
+        ] {
+            obj.symbols_by_index.insert(
+                index,
+                Symbol {
+                    index,
+                    section_index: None,
+                    name: Some(name.to_string()),
+                    address: 0,
+                    size: 0,
+                    is_definition: false,
+                    kind: SymbolKind::Unknown,
+                    binding,
+                    visibility: SymbolVisibility::Default,
+                },
+            );
+        }
+        obj.collect_externs().unwrap();
+
+        let mut externs = obj.externs().collect::<Vec<_>>();
+        externs.sort_by_key(|ext| ext.name().to_string());
+        assert_eq!(externs.len(), 2);
+        assert_eq!(externs[0].name(), "bpf_init");
+        assert!(!externs[0].is_typed());
+        assert!(externs[0].is_weak());
+        assert_eq!(externs[1].name(), "bpf_prog_active");
+        assert!(externs[1].is_typed());
+        assert!(!externs[1].is_weak());
+
+        let mut lddw = fake_ins();
+        lddw.code = (BPF_LD | BPF_IMM | BPF_DW) as u8;
+        obj.parse_section(fake_section(
+            BpfSectionKind::Program,
+            "kprobe/foo",
+            bytes_of(&[lddw, fake_ins(), lddw, fake_ins()]),
+        ))
+        .unwrap();
+        obj.relocations.insert(
+            SectionIndex(0),
+            HashMap::from([
+                (
+                    0,
+                    Relocation {
+                        offset: 0,
+                        symbol_index: 1,
+                        addend: 0,
+                    },
+                ),
+                (
+                    16,
+                    Relocation {
+                        offset: 16,
+                        symbol_index: 2,
+                        addend: 0,
+                    },
+                ),
+            ]),
+        );
+
+        // the weak symbol is missing, the strong one must resolve
+        assert_matches!(obj.relocate_ksyms(|_| None), Err(BpfRelocationError { .. }));
+        obj.relocate_ksyms(|ext| ext.is_typed().then_some(KsymValue::BtfId(42)))
+            .unwrap();
+        let ins = &obj.programs["foo"].function.instructions;
+        assert_eq!(ins[0].src_reg() as u32, BPF_PSEUDO_BTF_ID);
+        assert_eq!(ins[0].imm, 42);
+        assert_eq!(ins[1].imm, 0);
+        assert_eq!(ins[2].src_reg(), 0);
+        assert_eq!(ins[2].imm, 0);
+        assert_eq!(ins[3].imm, 0);
+
+        obj.relocate_ksyms(|ext| {
+            Some(if ext.is_typed() {
+                KsymValue::BtfId(42)
+            } else {
+                KsymValue::Address(0xffff_ffff_8100_0000)
+            })
+        })
+        .unwrap();
+        let ins = &obj.programs["foo"].function.instructions;
+        assert_eq!(ins[2].imm as u32, 0x8100_0000);
+        assert_eq!(ins[3].imm as u32, 0xffff_ffff);
+    }
+
+    #[test]
+    fn test_parse_unknown_section() {
+        let mut obj = fake_obj();
+        assert_matches!(
+            obj.parse_section_with_options(
+                fake_section(BpfSectionKind::Program, "bogus/foo", bytes_of(&fake_ins())),
+                &ParseOptions::default(),
+            ),
+            Err(ParseError::InvalidProgramSection { .. })
+        );
+        assert!(obj.warnings().is_empty());
+
+        let options = ParseOptions {
+            ignore_unknown_sections: true,
+        };
+        assert_matches!(
+            obj.parse_section_with_options(
+                fake_section(BpfSectionKind::Program, "bogus/foo", bytes_of(&fake_ins())),
+                &options,
+            ),
+            Ok(())
+        );
+        assert_matches!(
+            obj.parse_section_with_options(
+                fake_section(BpfSectionKind::Program, "kprobe/foo", bytes_of(&fake_ins())),
+                &options,
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            obj.warnings(),
+            &[ParseWarning::UnknownSection {
+                index: 0,
+                name: "bogus/foo".to_string(),
+            }]
+        );
+        assert!(obj.programs.contains_key("foo"));
+    }
+
+    #[test]
+    fn test_referenced_maps() {
+        let mut obj = fake_obj();
+        let mut lddw = fake_ins();
+        lddw.code = (BPF_LD | BPF_IMM | BPF_DW) as u8;
+        obj.parse_section(fake_section(
+            BpfSectionKind::Program,
+            "kprobe/foo",
+            bytes_of(&[lddw, fake_ins(), lddw, fake_ins(), fake_ins()]),
+        ))
+        .unwrap();
+        for name in ["map_1", "map_2", "map_3"] {
+            fake_sym(&mut obj, 1, 0, name, 0);
+            obj.maps.insert(
+                name.to_string(),
+                Map::Legacy(LegacyMap {
+                    def: Default::default(),
+                    section_index: 1,
+                    symbol_index: obj.symbols_by_index.len(),
+                    data: Vec::new(),
+                    kind: MapKind::Other,
+                }),
+            );
+        }
+        obj.relocations.insert(
+            SectionIndex(0),
+            HashMap::from([
+                (
+                    0,
+                    Relocation {
+                        offset: 0,
+                        symbol_index: 1,
+                        addend: 0,
+                    },
+                ),
+                (
+                    16,
+                    Relocation {
+                        offset: 16,
+                        symbol_index: 2,
+                        addend: 0,
+                    },
+                ),
+            ]),
+        );
+
+        assert_eq!(
+            obj.referenced_maps("foo").collect::<Vec<_>>(),
+            vec!["map_1", "map_2"]
+        );
+        assert_eq!(obj.referenced_maps("missing").count(), 0);
+    }
+
+    #[test]
+    fn test_unresolved_maps() {
+        let mut obj = fake_obj();
+        let mut lddw = fake_ins();
+        lddw.code = (BPF_LD | BPF_IMM | BPF_DW) as u8;
+        obj.parse_section(fake_section(
+            BpfSectionKind::Program,
+            "kprobe/foo",
+            bytes_of(&[lddw, fake_ins(), lddw, fake_ins(), fake_ins()]),
+        ))
+        .unwrap();
+        fake_sym(&mut obj, 1, 0, "local_map", 0);
+        obj.maps.insert(
+            "local_map".to_string(),
+            Map::Legacy(LegacyMap {
+                def: Default::default(),
+                section_index: 1,
+                symbol_index: 1,
+                data: Vec::new(),
+                kind: MapKind::Other,
+            }),
+        );
+        // extern maps are undefined symbols
+        fake_sym(&mut obj, 0, 0, "extern_map", 0);
+        obj.symbols_by_index.get_mut(&2).unwrap().section_index = None;
+        obj.relocations.insert(
+            SectionIndex(0),
+            HashMap::from([
+                (
+                    0,
+                    Relocation {
+                        offset: 0,
+                        symbol_index: 1,
+                        addend: 0,
+                    },
+                ),
+                (
+                    16,
+                    Relocation {
+                        offset: 16,
+                        symbol_index: 2,
+                        addend: 0,
+                    },
+                ),
+            ]),
+        );
+
+        let local_map = obj.maps["local_map"].clone();
+        obj.relocate_maps([("local_map", Some(1), &local_map)].into_iter())
+            .unwrap();
+        assert_eq!(
+            obj.unresolved_maps().collect::<Vec<_>>(),
+            vec!["extern_map"]
+        );
+        let insns = &obj.programs["foo"].function.instructions;
+        assert_eq!(insns[0].imm, 1);
+        assert_eq!(insns[2].imm, 0);
+
+        // maps missing from the other objects stay unresolved
+        obj.relocate_extern_maps(core::iter::empty()).unwrap();
+        assert_eq!(obj.unresolved_maps().count(), 1);
+
+        let extern_map = local_map.clone();
+        obj.relocate_extern_maps([("extern_map", Some(2), &extern_map)].into_iter())
+            .unwrap();
+        assert_eq!(obj.unresolved_maps().count(), 0);
+        let insns = &obj.programs["foo"].function.instructions;
+        assert_eq!(insns[0].imm, 1);
+        assert_eq!(insns[2].imm, 2);
+        assert_eq!(insns[2].src_reg(), BPF_PSEUDO_MAP_FD as u8);
+    }
+
+    #[test]
+    fn test_rename_program() {
+        let mut obj = fake_obj();
+        obj.parse_section(fake_section(
+            BpfSectionKind::Program,
+            "kprobe/foo",
+            bytes_of(&fake_ins()),
+        ))
+        .unwrap();
+
+        obj.rename_program("foo", "bar").unwrap();
+        assert!(!obj.programs.contains_key("foo"));
+        assert_eq!(obj.programs["bar"].section.name(), "bar");
+        assert_matches!(
+            obj.rename_program("foo", "baz"),
+            Err(RenameError::NotFound { .. })
+        );
+    }
+
+    #[test]
+    fn test_source_location() {
+        let mut obj = fake_obj();
+        let mut btf = Btf::new();
+        let file_name_off = btf.add_string("foo.c".to_string());
+        obj.btf = Some(btf);
+        let insns = [fake_ins(); 4];
+        obj.parse_section(fake_section(
+            BpfSectionKind::Program,
+            "kprobe/foo",
+            bytes_of(&insns),
+        ))
+        .unwrap();
+
+        // insn_off is in bytes in the ELF file
+        let mut data = Vec::new();
+        for (insn_off, line, col) in [(0u32, 10u32, 1u32), (16, 12, 5)] {
+            data.extend_from_slice(&insn_off.to_le_bytes());
+            data.extend_from_slice(&file_name_off.to_le_bytes());
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&((line << 10) | col).to_le_bytes());
+        }
+        obj.programs.get_mut("foo").unwrap().function.line_info =
+            LineSecInfo::parse(0, 2, 16, &data, Endianness::Little);
+
+        assert_eq!(
+            obj.source_location("foo", 0),
+            Some(("foo.c".to_string(), 10, 1))
+        );
+        assert_eq!(
+            obj.source_location("foo", 1),
+            Some(("foo.c".to_string(), 10, 1))
+        );
+        assert_eq!(
+            obj.source_location("foo", 3),
+            Some(("foo.c".to_string(), 12, 5))
+        );
+        assert_eq!(obj.source_location("bar", 0), None);
+
+        let log = "0: (b7) r0 = 0\n3: (95) exit\nR0 !read_ok\n";
+        assert_eq!(
+            obj.verifier_log_source_location("foo", log),
+            Some(("foo.c".to_string(), 12, 5))
+        );
+    }
+
     #[test]
     fn test_parse_section_kprobe() {
         let mut obj = fake_obj();
@@ -1762,6 +2857,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_usdt() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "usdt/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::Usdt { .. },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_trace_point() {
         let mut obj = fake_obj();
@@ -1878,6 +2994,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_raw_tp_sched_switch() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "raw_tp/sched_switch",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("sched_switch"),
+            Some(Program {
+                section: ProgramSection::RawTracePoint { name },
+                ..
+            }) if name == "sched_switch"
+        );
+    }
+
     #[test]
     fn test_parse_section_lsm() {
         let mut obj = fake_obj();
@@ -1918,6 +3055,22 @@ mod tests {
                 ..
             })
         );
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "tp_btf/sched_switch",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("sched_switch"),
+            Some(Program {
+                section: ProgramSection::BtfTracePoint { name },
+                ..
+            }) if name == "sched_switch"
+        );
     }
 
     #[test]
@@ -1983,6 +3136,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_sleepable() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "fentry.s/vfs_read",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        let program = obj.programs.get("vfs_read").unwrap();
+        assert_matches!(
+            program.section,
+            ProgramSection::FEntry {
+                sleepable: true,
+                ..
+            }
+        );
+        assert!(program.section.sleepable());
+
+        for (section, name) in [
+            ("fexit.s/foo", "foo"),
+            ("lsm.s/file_open", "file_open"),
+            ("iter.s/task_file", "task_file"),
+            ("iter.s/task_vma/dump_vmas", "dump_vmas"),
+        ] {
+            let section = ProgramSection::from_str(section).unwrap();
+            assert_eq!(section.name(), name);
+            assert!(section.sleepable());
+        }
+        let section = ProgramSection::from_str("iter.s/task_vma/dump_vmas").unwrap();
+        assert_matches!(section, ProgramSection::Iter { target, .. } if target == "task_vma");
+
+        assert!(!ProgramSection::from_str("fentry/vfs_read")
+            .unwrap()
+            .sleepable());
+        // only some program types can sleep
+        assert_matches!(
+            ProgramSection::from_str("kprobe.s/vfs_read"),
+            Err(ParseError::InvalidProgramSection { .. })
+        );
+    }
+
     #[test]
     fn test_parse_section_fexit() {
         let mut obj = fake_obj();
@@ -2136,6 +3334,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_sock_ops() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sockops",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("sockops"),
+            Some(Program {
+                section: ProgramSection::SockOps { .. },
+                ..
+            })
+        );
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sockops/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::SockOps { .. },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_sockopt_named() {
         let mut obj = fake_obj();
@@ -2185,20 +3420,297 @@ mod tests {
     }
 
     #[test]
-    fn test_patch_map_data() {
+    fn test_parse_section_setsockopt() {
         let mut obj = fake_obj();
-        obj.maps.insert(
-            ".rodata".to_string(),
-            Map::Legacy(LegacyMap {
-                def: bpf_map_def {
-                    map_type: BPF_MAP_TYPE_ARRAY as u32,
-                    key_size: mem::size_of::<u32>() as u32,
-                    value_size: 3,
-                    max_entries: 1,
-                    map_flags: BPF_F_RDONLY_PROG,
-                    id: 1,
-                    pinning: PinningType::None,
-                    ..Default::default()
+
+        for section in ["cgroup/setsockopt/foo", "cgroup/setsockopt"] {
+            assert_matches!(
+                obj.parse_section(fake_section(
+                    BpfSectionKind::Program,
+                    section,
+                    bytes_of(&fake_ins())
+                )),
+                Ok(())
+            );
+        }
+        for name in ["foo", "setsockopt"] {
+            assert_matches!(
+                obj.programs.get(name),
+                Some(Program {
+                    section: ProgramSection::CgroupSockopt {
+                        attach_type: CgroupSockoptAttachType::Set,
+                        ..
+                    },
+                    ..
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_section_iter() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "iter/task",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("task"),
+            Some(Program {
+                section: ProgramSection::Iter { target, .. },
+                ..
+            }) if target == "task"
+        );
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "iter/bpf_map/dump_maps",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("dump_maps"),
+            Some(Program {
+                section: ProgramSection::Iter { target, .. },
+                ..
+            }) if target == "bpf_map"
+        );
+    }
+
+    #[test]
+    fn test_parse_section_syscall() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "syscall",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("syscall"),
+            Some(Program {
+                section: ProgramSection::Syscall { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_kprobe_multi() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "kprobe.multi/tcp_*",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("tcp_*"),
+            Some(Program {
+                section: ProgramSection::KProbeMulti { .. },
+                ..
+            })
+        );
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "kretprobe.multi/vfs_read",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("vfs_read"),
+            Some(Program {
+                section: ProgramSection::KRetProbeMulti { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_tcx() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "tcx/ingress",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("ingress"),
+            Some(Program {
+                section: ProgramSection::Tcx {
+                    attach_type: TcxAttachType::Ingress,
+                    ..
+                },
+                ..
+            })
+        );
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "tcx/egress/redirect",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("redirect"),
+            Some(Program {
+                section: ProgramSection::Tcx {
+                    attach_type: TcxAttachType::Egress,
+                    ..
+                },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_flow_dissector() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "flow_dissector",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("flow_dissector"),
+            Some(Program {
+                section: ProgramSection::FlowDissector { .. },
+                ..
+            })
+        );
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "flow_dissector/dissect",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("dissect"),
+            Some(Program {
+                section: ProgramSection::FlowDissector { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_sk_lookup() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sk_lookup",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("sk_lookup"),
+            Some(Program {
+                section: ProgramSection::SkLookup { .. },
+                ..
+            })
+        );
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sk_lookup/dispatcher",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("dispatcher"),
+            Some(Program {
+                section: ProgramSection::SkLookup { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_sk_reuseport() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sk_reuseport/select",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("select"),
+            Some(Program {
+                section: ProgramSection::SkReuseport { migrate: false, .. },
+                ..
+            })
+        );
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sk_reuseport/migrate/migrate_sock",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("migrate_sock"),
+            Some(Program {
+                section: ProgramSection::SkReuseport { migrate: true, .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_patch_map_data() {
+        let mut obj = fake_obj();
+        obj.maps.insert(
+            ".rodata".to_string(),
+            Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: mem::size_of::<u32>() as u32,
+                    value_size: 3,
+                    max_entries: 1,
+                    map_flags: BPF_F_RDONLY_PROG,
+                    id: 1,
+                    pinning: PinningType::None,
+                    ..Default::default()
                 },
                 section_index: 1,
                 symbol_index: 1,
@@ -2216,6 +3728,8 @@ mod tests {
                 size: 3,
                 is_definition: true,
                 kind: SymbolKind::Data,
+                binding: SymbolBinding::Global,
+                visibility: SymbolVisibility::Default,
             },
         );
 
@@ -2227,88 +3741,134 @@ mod tests {
         assert_eq!(test_data, map.data());
     }
 
+    #[test]
+    fn test_set_map_initial_data() {
+        let mut obj = fake_obj();
+        for (name, map_type) in [
+            ("table", BPF_MAP_TYPE_ARRAY),
+            ("hash", crate::generated::bpf_map_type::BPF_MAP_TYPE_HASH),
+        ] {
+            obj.maps.insert(
+                name.to_string(),
+                Map::Legacy(LegacyMap {
+                    def: bpf_map_def {
+                        map_type: map_type as u32,
+                        key_size: mem::size_of::<u32>() as u32,
+                        value_size: 2,
+                        max_entries: 3,
+                        ..Default::default()
+                    },
+                    section_index: 1,
+                    symbol_index: 1,
+                    data: Vec::new(),
+                    kind: MapKind::Other,
+                }),
+            );
+        }
+
+        obj.set_map_initial_data("table", vec![1, 2, 3, 4, 5, 6])
+            .unwrap();
+        assert_eq!(obj.map_initial_data("table"), Some(&[1, 2, 3, 4, 5, 6][..]));
+        // the map data is left alone so the map is still relocated as a map
+        assert!(obj.maps["table"].data().is_empty());
+
+        assert_matches!(
+            obj.set_map_initial_data("table", vec![1, 2, 3, 4]),
+            Err(ParseError::InvalidMapInitialData {
+                data_size: 4,
+                expected: 6,
+                ..
+            })
+        );
+        assert_matches!(
+            obj.set_map_initial_data("hash", vec![0; 6]),
+            Err(ParseError::NotArrayMap { .. })
+        );
+        assert_matches!(
+            obj.set_map_initial_data("missing", vec![0; 6]),
+            Err(ParseError::UnknownMap { .. })
+        );
+        assert_eq!(obj.map_initial_data("hash"), None);
+    }
+
+    // generated from:
+    // objcopy --dump-section .BTF=test.btf ./target/bpfel-unknown-none/debug/multimap-btf.bpf.o
+    // hexdump -v  -e '7/1 "0x%02X, " 1/1  " 0x%02X,\n"' test.btf
+    const MULTIMAP_BTF: &[u8] = &[
+        0x9F, 0xEB, 0x01, 0x00, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF0, 0x01, 0x00,
+        0x00, 0xF0, 0x01, 0x00, 0x00, 0xCC, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x02, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x04,
+        0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03,
+        0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00,
+        0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x20, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x06, 0x00, 0x00, 0x00, 0x19,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x07, 0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x02, 0x09, 0x00, 0x00, 0x00, 0x2C, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x08, 0x0A, 0x00, 0x00, 0x00, 0x32, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08,
+        0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+        0x0C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+        0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x04, 0x00, 0x00, 0x04, 0x20, 0x00, 0x00, 0x00, 0x45, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4A, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00,
+        0x40, 0x00, 0x00, 0x00, 0x4E, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00,
+        0x00, 0x54, 0x00, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x60, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x0E, 0x0D, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x04, 0x20, 0x00, 0x00, 0x00, 0x45, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4A, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00,
+        0x00, 0x40, 0x00, 0x00, 0x00, 0x4E, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x80, 0x00,
+        0x00, 0x00, 0x54, 0x00, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x66,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0E, 0x0F, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x00, 0x0D, 0x02, 0x00, 0x00, 0x00, 0x6C, 0x00, 0x00, 0x00, 0x11, 0x00,
+        0x00, 0x00, 0x70, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x0C, 0x12, 0x00, 0x00, 0x00, 0xB0,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00,
+        0x00, 0x04, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0xB5, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x0E, 0x15, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xBE, 0x01, 0x00, 0x00, 0x02,
+        0x00, 0x00, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x0E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x20, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00,
+        0x00, 0xC4, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x16, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x69, 0x6E, 0x74, 0x00,
+        0x5F, 0x5F, 0x41, 0x52, 0x52, 0x41, 0x59, 0x5F, 0x53, 0x49, 0x5A, 0x45, 0x5F, 0x54, 0x59,
+        0x50, 0x45, 0x5F, 0x5F, 0x00, 0x5F, 0x5F, 0x75, 0x33, 0x32, 0x00, 0x75, 0x6E, 0x73, 0x69,
+        0x67, 0x6E, 0x65, 0x64, 0x20, 0x69, 0x6E, 0x74, 0x00, 0x5F, 0x5F, 0x75, 0x36, 0x34, 0x00,
+        0x75, 0x6E, 0x73, 0x69, 0x67, 0x6E, 0x65, 0x64, 0x20, 0x6C, 0x6F, 0x6E, 0x67, 0x20, 0x6C,
+        0x6F, 0x6E, 0x67, 0x00, 0x74, 0x79, 0x70, 0x65, 0x00, 0x6B, 0x65, 0x79, 0x00, 0x76, 0x61,
+        0x6C, 0x75, 0x65, 0x00, 0x6D, 0x61, 0x78, 0x5F, 0x65, 0x6E, 0x74, 0x72, 0x69, 0x65, 0x73,
+        0x00, 0x6D, 0x61, 0x70, 0x5F, 0x31, 0x00, 0x6D, 0x61, 0x70, 0x5F, 0x32, 0x00, 0x63, 0x74,
+        0x78, 0x00, 0x62, 0x70, 0x66, 0x5F, 0x70, 0x72, 0x6F, 0x67, 0x00, 0x74, 0x72, 0x61, 0x63,
+        0x65, 0x70, 0x6F, 0x69, 0x6E, 0x74, 0x00, 0x2F, 0x76, 0x61, 0x72, 0x2F, 0x68, 0x6F, 0x6D,
+        0x65, 0x2F, 0x64, 0x61, 0x76, 0x65, 0x2F, 0x64, 0x65, 0x76, 0x2F, 0x61, 0x79, 0x61, 0x2D,
+        0x72, 0x73, 0x2F, 0x61, 0x79, 0x61, 0x2F, 0x74, 0x65, 0x73, 0x74, 0x2F, 0x69, 0x6E, 0x74,
+        0x65, 0x67, 0x72, 0x61, 0x74, 0x69, 0x6F, 0x6E, 0x2D, 0x65, 0x62, 0x70, 0x66, 0x2F, 0x73,
+        0x72, 0x63, 0x2F, 0x62, 0x70, 0x66, 0x2F, 0x6D, 0x75, 0x6C, 0x74, 0x69, 0x6D, 0x61, 0x70,
+        0x2D, 0x62, 0x74, 0x66, 0x2E, 0x62, 0x70, 0x66, 0x2E, 0x63, 0x00, 0x69, 0x6E, 0x74, 0x20,
+        0x62, 0x70, 0x66, 0x5F, 0x70, 0x72, 0x6F, 0x67, 0x28, 0x76, 0x6F, 0x69, 0x64, 0x20, 0x2A,
+        0x63, 0x74, 0x78, 0x29, 0x00, 0x09, 0x5F, 0x5F, 0x75, 0x33, 0x32, 0x20, 0x6B, 0x65, 0x79,
+        0x20, 0x3D, 0x20, 0x30, 0x3B, 0x00, 0x09, 0x5F, 0x5F, 0x75, 0x36, 0x34, 0x20, 0x74, 0x77,
+        0x65, 0x6E, 0x74, 0x79, 0x5F, 0x66, 0x6F, 0x75, 0x72, 0x20, 0x3D, 0x20, 0x32, 0x34, 0x3B,
+        0x00, 0x09, 0x5F, 0x5F, 0x75, 0x36, 0x34, 0x20, 0x66, 0x6F, 0x72, 0x74, 0x79, 0x5F, 0x74,
+        0x77, 0x6F, 0x20, 0x3D, 0x20, 0x34, 0x32, 0x3B, 0x00, 0x20, 0x20, 0x20, 0x20, 0x62, 0x70,
+        0x66, 0x5F, 0x6D, 0x61, 0x70, 0x5F, 0x75, 0x70, 0x64, 0x61, 0x74, 0x65, 0x5F, 0x65, 0x6C,
+        0x65, 0x6D, 0x28, 0x26, 0x6D, 0x61, 0x70, 0x5F, 0x31, 0x2C, 0x20, 0x26, 0x6B, 0x65, 0x79,
+        0x2C, 0x20, 0x26, 0x74, 0x77, 0x65, 0x6E, 0x74, 0x79, 0x5F, 0x66, 0x6F, 0x75, 0x72, 0x2C,
+        0x20, 0x42, 0x50, 0x46, 0x5F, 0x41, 0x4E, 0x59, 0x29, 0x3B, 0x00, 0x20, 0x20, 0x20, 0x20,
+        0x62, 0x70, 0x66, 0x5F, 0x6D, 0x61, 0x70, 0x5F, 0x75, 0x70, 0x64, 0x61, 0x74, 0x65, 0x5F,
+        0x65, 0x6C, 0x65, 0x6D, 0x28, 0x26, 0x6D, 0x61, 0x70, 0x5F, 0x32, 0x2C, 0x20, 0x26, 0x6B,
+        0x65, 0x79, 0x2C, 0x20, 0x26, 0x66, 0x6F, 0x72, 0x74, 0x79, 0x5F, 0x74, 0x77, 0x6F, 0x2C,
+        0x20, 0x42, 0x50, 0x46, 0x5F, 0x41, 0x4E, 0x59, 0x29, 0x3B, 0x00, 0x09, 0x72, 0x65, 0x74,
+        0x75, 0x72, 0x6E, 0x20, 0x30, 0x3B, 0x00, 0x63, 0x68, 0x61, 0x72, 0x00, 0x5F, 0x6C, 0x69,
+        0x63, 0x65, 0x6E, 0x73, 0x65, 0x00, 0x2E, 0x6D, 0x61, 0x70, 0x73, 0x00, 0x6C, 0x69, 0x63,
+        0x65, 0x6E, 0x73, 0x65, 0x00,
+    ];
+
     #[test]
     fn test_parse_btf_map_section() {
         let mut obj = fake_obj();
         fake_sym(&mut obj, 0, 0, "map_1", 0);
         fake_sym(&mut obj, 0, 0, "map_2", 0);
-        // generated from:
-        // objcopy --dump-section .BTF=test.btf ./target/bpfel-unknown-none/debug/multimap-btf.bpf.o
-        // hexdump -v  -e '7/1 "0x%02X, " 1/1  " 0x%02X,\n"' test.btf
-        let data: &[u8] = &[
-            0x9F, 0xEB, 0x01, 0x00, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF0, 0x01,
-            0x00, 0x00, 0xF0, 0x01, 0x00, 0x00, 0xCC, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x02, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
-            0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
-            0x04, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x02, 0x06, 0x00, 0x00, 0x00, 0x19, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08,
-            0x07, 0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x04, 0x00,
-            0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
-            0x09, 0x00, 0x00, 0x00, 0x2C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x0A, 0x00,
-            0x00, 0x00, 0x32, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00, 0x00, 0x00,
-            0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x0C, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00,
-            0x02, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x04, 0x00, 0x00, 0x04, 0x20, 0x00, 0x00, 0x00, 0x45, 0x00, 0x00, 0x00,
-            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4A, 0x00, 0x00, 0x00, 0x05, 0x00,
-            0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x4E, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
-            0x80, 0x00, 0x00, 0x00, 0x54, 0x00, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x00, 0xC0, 0x00,
-            0x00, 0x00, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0E, 0x0D, 0x00, 0x00, 0x00,
-            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x04, 0x20, 0x00,
-            0x00, 0x00, 0x45, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x4A, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x4E, 0x00,
-            0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x54, 0x00, 0x00, 0x00,
-            0x0B, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x66, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x0E, 0x0F, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
-            0x00, 0x0D, 0x02, 0x00, 0x00, 0x00, 0x6C, 0x00, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00,
-            0x70, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x0C, 0x12, 0x00, 0x00, 0x00, 0xB0, 0x01,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x01,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x14, 0x00,
-            0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0xB5, 0x01, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x0E, 0x15, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xBE, 0x01,
-            0x00, 0x00, 0x02, 0x00, 0x00, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x0E, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0xC4, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x0F,
-            0x00, 0x00, 0x00, 0x00, 0x16, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00,
-            0x00, 0x00, 0x00, 0x69, 0x6E, 0x74, 0x00, 0x5F, 0x5F, 0x41, 0x52, 0x52, 0x41, 0x59,
-            0x5F, 0x53, 0x49, 0x5A, 0x45, 0x5F, 0x54, 0x59, 0x50, 0x45, 0x5F, 0x5F, 0x00, 0x5F,
-            0x5F, 0x75, 0x33, 0x32, 0x00, 0x75, 0x6E, 0x73, 0x69, 0x67, 0x6E, 0x65, 0x64, 0x20,
-            0x69, 0x6E, 0x74, 0x00, 0x5F, 0x5F, 0x75, 0x36, 0x34, 0x00, 0x75, 0x6E, 0x73, 0x69,
-            0x67, 0x6E, 0x65, 0x64, 0x20, 0x6C, 0x6F, 0x6E, 0x67, 0x20, 0x6C, 0x6F, 0x6E, 0x67,
-            0x00, 0x74, 0x79, 0x70, 0x65, 0x00, 0x6B, 0x65, 0x79, 0x00, 0x76, 0x61, 0x6C, 0x75,
-            0x65, 0x00, 0x6D, 0x61, 0x78, 0x5F, 0x65, 0x6E, 0x74, 0x72, 0x69, 0x65, 0x73, 0x00,
-            0x6D, 0x61, 0x70, 0x5F, 0x31, 0x00, 0x6D, 0x61, 0x70, 0x5F, 0x32, 0x00, 0x63, 0x74,
-            0x78, 0x00, 0x62, 0x70, 0x66, 0x5F, 0x70, 0x72, 0x6F, 0x67, 0x00, 0x74, 0x72, 0x61,
-            0x63, 0x65, 0x70, 0x6F, 0x69, 0x6E, 0x74, 0x00, 0x2F, 0x76, 0x61, 0x72, 0x2F, 0x68,
-            0x6F, 0x6D, 0x65, 0x2F, 0x64, 0x61, 0x76, 0x65, 0x2F, 0x64, 0x65, 0x76, 0x2F, 0x61,
-            0x79, 0x61, 0x2D, 0x72, 0x73, 0x2F, 0x61, 0x79, 0x61, 0x2F, 0x74, 0x65, 0x73, 0x74,
-            0x2F, 0x69, 0x6E, 0x74, 0x65, 0x67, 0x72, 0x61, 0x74, 0x69, 0x6F, 0x6E, 0x2D, 0x65,
-            0x62, 0x70, 0x66, 0x2F, 0x73, 0x72, 0x63, 0x2F, 0x62, 0x70, 0x66, 0x2F, 0x6D, 0x75,
-            0x6C, 0x74, 0x69, 0x6D, 0x61, 0x70, 0x2D, 0x62, 0x74, 0x66, 0x2E, 0x62, 0x70, 0x66,
-            0x2E, 0x63, 0x00, 0x69, 0x6E, 0x74, 0x20, 0x62, 0x70, 0x66, 0x5F, 0x70, 0x72, 0x6F,
-            0x67, 0x28, 0x76, 0x6F, 0x69, 0x64, 0x20, 0x2A, 0x63, 0x74, 0x78, 0x29, 0x00, 0x09,
-            0x5F, 0x5F, 0x75, 0x33, 0x32, 0x20, 0x6B, 0x65, 0x79, 0x20, 0x3D, 0x20, 0x30, 0x3B,
-            0x00, 0x09, 0x5F, 0x5F, 0x75, 0x36, 0x34, 0x20, 0x74, 0x77, 0x65, 0x6E, 0x74, 0x79,
-            0x5F, 0x66, 0x6F, 0x75, 0x72, 0x20, 0x3D, 0x20, 0x32, 0x34, 0x3B, 0x00, 0x09, 0x5F,
-            0x5F, 0x75, 0x36, 0x34, 0x20, 0x66, 0x6F, 0x72, 0x74, 0x79, 0x5F, 0x74, 0x77, 0x6F,
-            0x20, 0x3D, 0x20, 0x34, 0x32, 0x3B, 0x00, 0x20, 0x20, 0x20, 0x20, 0x62, 0x70, 0x66,
-            0x5F, 0x6D, 0x61, 0x70, 0x5F, 0x75, 0x70, 0x64, 0x61, 0x74, 0x65, 0x5F, 0x65, 0x6C,
-            0x65, 0x6D, 0x28, 0x26, 0x6D, 0x61, 0x70, 0x5F, 0x31, 0x2C, 0x20, 0x26, 0x6B, 0x65,
-            0x79, 0x2C, 0x20, 0x26, 0x74, 0x77, 0x65, 0x6E, 0x74, 0x79, 0x5F, 0x66, 0x6F, 0x75,
-            0x72, 0x2C, 0x20, 0x42, 0x50, 0x46, 0x5F, 0x41, 0x4E, 0x59, 0x29, 0x3B, 0x00, 0x20,
-            0x20, 0x20, 0x20, 0x62, 0x70, 0x66, 0x5F, 0x6D, 0x61, 0x70, 0x5F, 0x75, 0x70, 0x64,
-            0x61, 0x74, 0x65, 0x5F, 0x65, 0x6C, 0x65, 0x6D, 0x28, 0x26, 0x6D, 0x61, 0x70, 0x5F,
-            0x32, 0x2C, 0x20, 0x26, 0x6B, 0x65, 0x79, 0x2C, 0x20, 0x26, 0x66, 0x6F, 0x72, 0x74,
-            0x79, 0x5F, 0x74, 0x77, 0x6F, 0x2C, 0x20, 0x42, 0x50, 0x46, 0x5F, 0x41, 0x4E, 0x59,
-            0x29, 0x3B, 0x00, 0x09, 0x72, 0x65, 0x74, 0x75, 0x72, 0x6E, 0x20, 0x30, 0x3B, 0x00,
-            0x63, 0x68, 0x61, 0x72, 0x00, 0x5F, 0x6C, 0x69, 0x63, 0x65, 0x6E, 0x73, 0x65, 0x00,
-            0x2E, 0x6D, 0x61, 0x70, 0x73, 0x00, 0x6C, 0x69, 0x63, 0x65, 0x6E, 0x73, 0x65, 0x00,
-        ];
-
-        let btf_section = fake_section(BpfSectionKind::Btf, ".BTF", data);
+        let btf_section = fake_section(BpfSectionKind::Btf, ".BTF", MULTIMAP_BTF);
         obj.parse_section(btf_section).unwrap();
 
         let map_section = fake_section(BpfSectionKind::BtfMaps, ".maps", &[]);
@@ -2323,4 +3883,331 @@ mod tests {
             panic!("expected a BTF map")
         }
     }
+
+    #[test]
+    fn test_parse_btf_without_btf_ext() {
+        let mut obj = fake_obj();
+        fake_sym(&mut obj, 0, 0, "map_1", 0);
+        fake_sym(&mut obj, 0, 0, "map_2", 0);
+        obj.parse_section(fake_section(BpfSectionKind::Btf, ".BTF", MULTIMAP_BTF))
+            .unwrap();
+        obj.parse_section(fake_section(BpfSectionKind::BtfMaps, ".maps", &[]))
+            .unwrap();
+        obj.parse_section(fake_section(
+            BpfSectionKind::Program,
+            "kprobe/foo",
+            bytes_of(&fake_ins()),
+        ))
+        .unwrap();
+
+        assert!(obj.btf.is_some());
+        assert!(obj.btf_ext.is_none());
+        assert_matches!(obj.maps.get("map_1"), Some(Map::Btf(_)));
+        assert_matches!(obj.maps.get("map_2"), Some(Map::Btf(_)));
+
+        let function = &obj.programs["foo"].function;
+        assert!(function.func_info.func_info.is_empty());
+        assert!(function.line_info.line_info.is_empty());
+        assert_eq!(function.func_info_rec_size, 0);
+        assert_eq!(function.line_info_rec_size, 0);
+
+        // there are no CO-RE relocations to apply
+        let target_btf = obj.btf.clone().unwrap();
+        assert_matches!(obj.relocate_btf(&target_btf), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_btf_map_def_flags_and_pinning() {
+        use crate::{
+            btf::{BtfMember, Int, IntEncoding, Ptr, Struct, Var, VarLinkage},
+            generated::{bpf_map_type::BPF_MAP_TYPE_HASH, BPF_F_NO_PREALLOC},
+        };
+
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        let int_type = btf.add_type(BtfType::Int(Int::new(
+            name_offset,
+            4,
+            IntEncoding::Signed,
+            0,
+        )));
+
+        // fields are encoded as `int (*name)[value]`
+        let mut members = Vec::new();
+        for (i, (name, value)) in [
+            ("type", BPF_MAP_TYPE_HASH as u32),
+            ("key_size", 4),
+            ("value_size", 8),
+            ("max_entries", 16),
+            ("map_flags", BPF_F_NO_PREALLOC),
+            ("pinning", 1),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let array_type = btf.add_type(BtfType::Array(Array::new(0, int_type, int_type, value)));
+            let ptr_type = btf.add_type(BtfType::Ptr(Ptr::new(0, array_type)));
+            members.push(BtfMember {
+                name_offset: btf.add_string(name.to_string()),
+                btf_type: ptr_type,
+                offset: i as u32 * 64,
+            });
+        }
+        let struct_type = btf.add_type(BtfType::Struct(Struct::new(0, members, 48)));
+        let name_offset = btf.add_string("my_map".to_string());
+        let var_type = btf.add_type(BtfType::Var(Var::new(
+            name_offset,
+            struct_type,
+            VarLinkage::Global,
+        )));
+
+        let (name, def) = parse_btf_map_def(
+            &btf,
+            &DataSecEntry {
+                btf_type: var_type,
+                offset: 0,
+                size: 48,
+            },
+        )
+        .unwrap();
+        assert_eq!(name, "my_map");
+        assert_eq!(def.map_type, BPF_MAP_TYPE_HASH as u32);
+        assert_eq!(def.key_size, 4);
+        assert_eq!(def.value_size, 8);
+        assert_eq!(def.max_entries, 16);
+        assert_eq!(def.map_flags, BPF_F_NO_PREALLOC);
+        assert_eq!(def.pinning, PinningType::ByName);
+        assert_eq!(def.spin_lock, Some(false));
+    }
+
+    #[test]
+    fn test_parse_btf_map_def_spin_lock() {
+        use crate::btf::{BtfMember, Int, IntEncoding, Ptr, Struct, Var, VarLinkage};
+
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        let int_type = btf.add_type(BtfType::Int(Int::new(
+            name_offset,
+            4,
+            IntEncoding::Signed,
+            0,
+        )));
+        let name_offset = btf.add_string("bpf_spin_lock".to_string());
+        let val_offset = btf.add_string("val".to_string());
+        let lock_type = btf.add_type(BtfType::Struct(Struct::new(
+            name_offset,
+            vec![BtfMember {
+                name_offset: val_offset,
+                btf_type: int_type,
+                offset: 0,
+            }],
+            4,
+        )));
+
+        let mut map_def = |value_members: Vec<BtfMember>| {
+            let value_type = btf.add_type(BtfType::Struct(Struct::new(0, value_members, 8)));
+            let ptr_type = btf.add_type(BtfType::Ptr(Ptr::new(0, value_type)));
+            let value_offset = btf.add_string("value".to_string());
+            let members = vec![BtfMember {
+                name_offset: value_offset,
+                btf_type: ptr_type,
+                offset: 0,
+            }];
+            let struct_type = btf.add_type(BtfType::Struct(Struct::new(0, members, 8)));
+            let name_offset = btf.add_string("my_map".to_string());
+            let var_type = btf.add_type(BtfType::Var(Var::new(
+                name_offset,
+                struct_type,
+                VarLinkage::Global,
+            )));
+            parse_btf_map_def(
+                &btf,
+                &DataSecEntry {
+                    btf_type: var_type,
+                    offset: 0,
+                    size: 8,
+                },
+            )
+            .unwrap()
+            .1
+        };
+
+        let counter = BtfMember {
+            name_offset: 0,
+            btf_type: int_type,
+            offset: 0,
+        };
+        let lock = BtfMember {
+            name_offset: 0,
+            btf_type: lock_type,
+            offset: 32,
+        };
+        assert_eq!(map_def(vec![counter.clone(), lock]).spin_lock, Some(true));
+        assert_eq!(map_def(vec![counter]).spin_lock, Some(false));
+    }
+
+    #[test]
+    fn test_parse_btf_ext_relocations() {
+        let mut obj = fake_obj();
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string(".text".to_string());
+        obj.btf = Some(btf);
+        // .text holds two functions, the second starts at instruction 4
+        fake_sym(&mut obj, 1, 0, ".text", 0);
+        fake_sym(&mut obj, 1, 32, "func2", 32);
+
+        let core_relo = |insn_off| bpf_core_relo {
+            insn_off,
+            type_id: 1,
+            access_str_off: 0,
+            kind: bpf_core_relo_kind::BPF_CORE_FIELD_BYTE_OFFSET,
+        };
+        let relos = [core_relo(8), core_relo(8)];
+        let header = btf_ext_header {
+            magic: 0xeb9f,
+            version: 1,
+            flags: 0,
+            hdr_len: mem::size_of::<btf_ext_header>() as u32,
+            func_info_off: 0,
+            func_info_len: 0,
+            line_info_off: 0,
+            line_info_len: 0,
+            core_relo_off: 0,
+            core_relo_len: (12 + mem::size_of_val(&relos)) as u32,
+        };
+        let mut data = bytes_of(&header).to_vec();
+        data.extend_from_slice(&(mem::size_of::<bpf_core_relo>() as u32).to_ne_bytes());
+        data.extend_from_slice(&name_offset.to_ne_bytes());
+        data.extend_from_slice(&(relos.len() as u32).to_ne_bytes());
+        let relos_offset = data.len() as u64;
+        for relo in &relos {
+            data.extend_from_slice(bytes_of(relo));
+        }
+
+        // the offset of the first record is relative to the section, the
+        // offset of the second one relative to func2
+        let mut section = fake_section(BpfSectionKind::BtfExt, ".BTF.ext", &data);
+        section.relocations = vec![
+            Relocation {
+                offset: relos_offset,
+                symbol_index: 1,
+                addend: 0,
+            },
+            Relocation {
+                offset: relos_offset + mem::size_of::<bpf_core_relo>() as u64,
+                symbol_index: 2,
+                addend: 0,
+            },
+        ];
+        obj.parse_section(section).unwrap();
+
+        let btf_ext = obj.btf_ext.as_ref().unwrap();
+        let (sec_name_offset, relos) = btf_ext.relocations().next().unwrap();
+        assert_eq!(*sec_name_offset, name_offset);
+        let offsets = relos.iter().map(|r| r.ins_offset).collect::<Vec<_>>();
+        assert_eq!(offsets, [8, 40]);
+
+        let mut section = fake_section(BpfSectionKind::BtfExt, ".BTF.ext", &data);
+        section.relocations = vec![Relocation {
+            offset: data.len() as u64 - 2,
+            symbol_index: 1,
+            addend: 0,
+        }];
+        assert_matches!(
+            obj.parse_section(section),
+            Err(ParseError::BtfError(
+                BtfError::InvalidBtfExtRelocation { .. }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_btf_ext_func_info_all_sections() {
+        use crate::generated::bpf_func_info;
+
+        let mut obj = fake_obj();
+        let mut btf = Btf::new();
+        let text_name_offset = btf.add_string(".text".to_string());
+        let prog_name_offset = btf.add_string("xdp/foo".to_string());
+        obj.btf = Some(btf);
+
+        // one record for the program and one for each of the two functions
+        // in .text, with offsets in bytes
+        let func_info = |insn_off, type_id| bpf_func_info { insn_off, type_id };
+        let sections = [
+            (prog_name_offset, vec![func_info(0, 1)]),
+            (text_name_offset, vec![func_info(0, 2), func_info(16, 3)]),
+        ];
+        let mut func_info_data = (mem::size_of::<bpf_func_info>() as u32)
+            .to_ne_bytes()
+            .to_vec();
+        for (name_offset, records) in &sections {
+            func_info_data.extend_from_slice(&name_offset.to_ne_bytes());
+            func_info_data.extend_from_slice(&(records.len() as u32).to_ne_bytes());
+            for record in records {
+                func_info_data.extend_from_slice(bytes_of(record));
+            }
+        }
+        let header = btf_ext_header {
+            magic: 0xeb9f,
+            version: 1,
+            flags: 0,
+            hdr_len: mem::size_of::<btf_ext_header>() as u32,
+            func_info_off: 0,
+            func_info_len: func_info_data.len() as u32,
+            line_info_off: func_info_data.len() as u32,
+            line_info_len: 0,
+            core_relo_off: func_info_data.len() as u32,
+            core_relo_len: 0,
+        };
+        let mut data = bytes_of(&header).to_vec();
+        data.extend_from_slice(&func_info_data);
+        obj.parse_section(fake_section(BpfSectionKind::BtfExt, ".BTF.ext", &data))
+            .unwrap();
+
+        let btf_ext = obj.btf_ext.as_ref().unwrap();
+        let mut names = btf_ext.func_info().data.keys().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, [".text", "xdp/foo"]);
+
+        // .text holds the functions `main_helper` and `other_helper`, two
+        // instructions each
+        for (address, name) in [(0, "main_helper"), (16, "other_helper")] {
+            let index = obj.symbols_by_index.len() + 1;
+            obj.symbols_by_index.insert(
+                index,
+                Symbol {
+                    index,
+                    section_index: Some(1),
+                    name: Some(name.to_string()),
+                    address,
+                    size: 16,
+                    is_definition: true,
+                    kind: SymbolKind::Text,
+                    binding: SymbolBinding::Global,
+                    visibility: SymbolVisibility::Default,
+                },
+            );
+        }
+        let text = bytes_of(&[fake_ins(); 4]).to_vec();
+        let mut section = fake_section(BpfSectionKind::Text, ".text", &text);
+        section.index = SectionIndex(1);
+        obj.parse_section(section).unwrap();
+
+        let prog = bytes_of(&fake_ins()).to_vec();
+        obj.parse_section(fake_section(BpfSectionKind::Program, "xdp/foo", &prog))
+            .unwrap();
+
+        let records = |func_info: &FuncSecInfo| {
+            func_info
+                .func_info
+                .iter()
+                .map(|info| (info.insn_off, info.type_id))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(records(&obj.programs["foo"].function.func_info), [(0, 1)]);
+        assert_eq!(records(&obj.functions[&0].func_info), [(0, 2)]);
+        assert_eq!(obj.functions[&16].name, "other_helper");
+        assert_eq!(records(&obj.functions[&16].func_info), [(2, 3)]);
+    }
 }
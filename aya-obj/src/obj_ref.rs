@@ -0,0 +1,299 @@
+//! Zero-copy, read-only view of an object file.
+
+use core::{ffi::CStr, mem, ptr, str::FromStr};
+
+use object::{
+    elf::FileHeader64,
+    read::{
+        elf::{FileHeader, SectionTable},
+        Object as ElfObject, ObjectSection, Section as ObjSection,
+    },
+    Endianness, SectionIndex, SectionKind,
+};
+
+use crate::{
+    generated::bpf_insn,
+    obj::{
+        parse_license_ref, parse_version, BpfSectionKind, KernelVersion, Object, ParseError,
+        ProgramSection,
+    },
+};
+
+/// A read-only view of an object file borrowing from the caller's buffer.
+///
+/// Unlike [Object], an `ObjectRef` doesn't copy instructions or section data
+/// out of the ELF file, which makes it cheap to inspect very large objects.
+/// It can't be relocated or loaded: use [ObjectRef::to_object] to get a full
+/// [Object] first.
+pub struct ObjectRef<'a> {
+    data: &'a [u8],
+    elf: object::read::File<'a>,
+    // the section headers, whose names borrow from `data`
+    sections: SectionTable<'a, FileHeader64<Endianness>>,
+    license: &'a CStr,
+    kernel_version: KernelVersion,
+}
+
+/// A section of an [ObjectRef].
+#[derive(Debug, Clone, Copy)]
+pub struct SectionRef<'a> {
+    /// The section index
+    pub index: usize,
+    /// The section name
+    pub name: &'a str,
+    /// The section data. Empty for sections without data in the file, like `.bss`.
+    pub data: &'a [u8],
+    /// The section size
+    pub size: u64,
+}
+
+/// A program of an [ObjectRef].
+#[derive(Debug, Clone)]
+pub struct ProgramRef<'a> {
+    /// The section containing the program
+    pub section: ProgramSection,
+    /// The section index
+    pub section_index: usize,
+    /// The section name
+    pub section_name: &'a str,
+    /// The raw eBPF byte code
+    pub data: &'a [u8],
+}
+
+impl Object {
+    /// Parses the object file without copying, see [ObjectRef].
+    pub fn parse_borrowed(data: &[u8]) -> Result<ObjectRef<'_>, ParseError> {
+        ObjectRef::parse(data)
+    }
+}
+
+impl<'a> ObjectRef<'a> {
+    /// Parses the object file without copying its data.
+    pub fn parse(data: &'a [u8]) -> Result<ObjectRef<'a>, ParseError> {
+        let elf = object::read::File::parse(data).map_err(ParseError::ElfError)?;
+        let sections = FileHeader64::<Endianness>::parse(data)
+            .and_then(|header| header.sections(elf.endianness(), data))
+            .map_err(ParseError::ElfError)?;
+
+        let license = match elf.section_by_name("license") {
+            Some(section) => parse_license_ref(section_data(&section)?)?,
+            None => c"GPL",
+        };
+
+        let kernel_version = match elf.section_by_name("version") {
+            Some(section) => parse_version(section_data(&section)?, elf.endianness())?,
+            None => KernelVersion::Any,
+        };
+
+        Ok(ObjectRef {
+            data,
+            elf,
+            sections,
+            license,
+            kernel_version,
+        })
+    }
+
+    /// Returns the raw ELF data.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the endianness.
+    pub fn endianness(&self) -> Endianness {
+        self.elf.endianness()
+    }
+
+    /// Returns the program license.
+    pub fn license(&self) -> &'a CStr {
+        self.license
+    }
+
+    /// Returns the kernel version.
+    pub fn kernel_version(&self) -> KernelVersion {
+        self.kernel_version
+    }
+
+    /// Returns an iterator over the sections of the object.
+    pub fn sections(&self) -> impl Iterator<Item = Result<SectionRef<'a>, ParseError>> + '_ {
+        self.elf
+            .sections()
+            .map(|section| self.section_ref(&section))
+    }
+
+    /// Returns the section with the given name.
+    pub fn section_by_name(&self, name: &str) -> Result<Option<SectionRef<'a>>, ParseError> {
+        self.elf
+            .section_by_name(name)
+            .map(|section| self.section_ref(&section))
+            .transpose()
+    }
+
+    /// Returns the data of the `.BTF` section, if any.
+    pub fn btf_data(&self) -> Result<Option<&'a [u8]>, ParseError> {
+        Ok(self.section_by_name(".BTF")?.map(|s| s.data))
+    }
+
+    /// Returns the data of the `.BTF.ext` section, if any.
+    pub fn btf_ext_data(&self) -> Result<Option<&'a [u8]>, ParseError> {
+        Ok(self.section_by_name(".BTF.ext")?.map(|s| s.data))
+    }
+
+    /// Returns an iterator over the programs of the object.
+    pub fn programs(&self) -> impl Iterator<Item = Result<ProgramRef<'a>, ParseError>> + '_ {
+        self.elf.sections().filter_map(|section| {
+            let name = match section.name() {
+                Ok(name) => name,
+                Err(error) => {
+                    return Some(Err(ParseError::SectionError {
+                        index: section.index().0,
+                        error,
+                    }))
+                }
+            };
+            let is_program = match BpfSectionKind::from_name(name) {
+                BpfSectionKind::Undefined => {
+                    section.kind() == SectionKind::Text && section.size() > 0
+                }
+                _ => false,
+            };
+            if !is_program {
+                return None;
+            }
+            Some(self.section_ref(&section).and_then(ProgramRef::parse))
+        })
+    }
+
+    /// Returns the program with the given name.
+    pub fn program(&self, name: &str) -> Result<Option<ProgramRef<'a>>, ParseError> {
+        for program in self.programs() {
+            let program = program?;
+            if program.name() == name {
+                return Ok(Some(program));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Parses the object again into an owned [Object], which can be relocated
+    /// and loaded.
+    pub fn to_object(&self) -> Result<Object, ParseError> {
+        Object::parse(self.data)
+    }
+
+    fn section_ref(&self, section: &ObjSection<'a, '_>) -> Result<SectionRef<'a>, ParseError> {
+        let index = section.index().0;
+        let error = |error| ParseError::SectionError { index, error };
+        // Section::name borrows from the section, so once it's validated the
+        // name is looked up again in the string table, which borrows from the
+        // input buffer
+        section.name().map_err(error)?;
+        let header = self.sections.section(SectionIndex(index)).map_err(error)?;
+        let name = self
+            .sections
+            .section_name(self.endianness(), header)
+            .map_err(error)?;
+        let name = core::str::from_utf8(name).map_err(|_| ParseError::SectionNotFound { index })?;
+        Ok(SectionRef {
+            index,
+            name,
+            data: section_data(section)?,
+            size: section.size(),
+        })
+    }
+}
+
+impl<'a> ProgramRef<'a> {
+    fn parse(section: SectionRef<'a>) -> Result<ProgramRef<'a>, ParseError> {
+        if !section.data.len().is_multiple_of(mem::size_of::<bpf_insn>()) {
+            return Err(ParseError::InvalidProgramCode);
+        }
+        Ok(ProgramRef {
+            section: ProgramSection::from_str(section.name)?,
+            section_index: section.index,
+            section_name: section.name,
+            data: section.data,
+        })
+    }
+
+    /// Returns the program name.
+    pub fn name(&self) -> &str {
+        self.section.name()
+    }
+
+    /// Returns the number of instructions.
+    pub fn instruction_count(&self) -> usize {
+        self.data.len() / mem::size_of::<bpf_insn>()
+    }
+
+    /// Returns an iterator over the instructions.
+    ///
+    /// The section data isn't necessarily aligned, so the instructions are
+    /// read one at a time.
+    pub fn instructions(&self) -> impl Iterator<Item = bpf_insn> + 'a {
+        self.data
+            .chunks_exact(mem::size_of::<bpf_insn>())
+            .map(|d| unsafe { ptr::read_unaligned(d.as_ptr() as *const bpf_insn) })
+    }
+}
+
+fn section_data<'a>(section: &ObjSection<'a, '_>) -> Result<&'a [u8], ParseError> {
+    section.data().map_err(|error| ParseError::SectionError {
+        index: section.index().0,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::ToOwned, ffi::CString, vec, vec::Vec};
+
+    use object::SectionIndex;
+
+    use super::*;
+    use crate::obj::{tests::fake_func, Function, Program};
+
+    fn big_elf(insn_count: usize) -> Vec<u8> {
+        let mut obj = Object::new(
+            Endianness::Little,
+            CString::new("GPL").unwrap(),
+            KernelVersion::Any,
+        );
+        obj.section_names.insert(1, "xdp/big".to_owned());
+        let mut exit: bpf_insn = unsafe { mem::zeroed() };
+        exit.code = 0x95;
+        obj.programs.insert(
+            "big".to_owned(),
+            Program {
+                license: obj.license.clone(),
+                kernel_version: obj.kernel_version,
+                section: ProgramSection::Xdp {
+                    name: "big".to_owned(),
+                },
+                function: Function {
+                    section_index: SectionIndex(1),
+                    ..fake_func("big", vec![exit; insn_count])
+                },
+            },
+        );
+        obj.to_elf().unwrap()
+    }
+
+    #[test]
+    fn test_parse_borrowed() {
+        let data = big_elf(4);
+        let obj = Object::parse_borrowed(&data).unwrap();
+
+        assert_eq!(obj.license().to_bytes(), b"GPL");
+        assert_eq!(obj.kernel_version(), KernelVersion::Any);
+        let program = obj.program("big").unwrap().unwrap();
+        assert!(matches!(program.section, ProgramSection::Xdp { .. }));
+        assert_eq!(program.section_name, "xdp/big");
+        assert_eq!(program.instruction_count(), 4);
+        assert!(program.instructions().all(|insn| insn.code == 0x95));
+        assert!(obj.program("missing").unwrap().is_none());
+
+        let owned = obj.to_object().unwrap();
+        assert_eq!(owned.programs["big"].function.instructions.len(), 4);
+    }
+}
@@ -0,0 +1,203 @@
+//! Incremental parsing of large object files.
+
+use alloc::{borrow::ToOwned, collections::VecDeque};
+
+use log::debug;
+use object::{
+    read::{Object as ElfObject, ObjectSection},
+    SectionIndex,
+};
+
+use crate::obj::{
+    BpfSectionKind, Object, ParseError, ParseOptions, ParseWarning, Program, Section,
+};
+
+/// An object file whose programs are parsed one at a time.
+///
+/// Everything but the programs is parsed upfront: maps, BTF, symbols and the
+/// functions in `.text`. Programs are then yielded by the [Iterator]
+/// implementation as their sections are parsed, so that the instructions of
+/// all the programs are never in memory at the same time.
+///
+/// The relocations of the yielded programs are recorded in the underlying
+/// [Object]. To relocate some of them, get the object with
+/// [ObjectStream::into_object] and insert the programs into
+/// [Object::programs].
+pub struct ObjectStream<'a> {
+    elf: object::read::File<'a>,
+    object: Object,
+    pending: VecDeque<SectionIndex>,
+    options: ParseOptions,
+}
+
+impl Object {
+    /// Parses the object file incrementally, see [ObjectStream].
+    pub fn parse_streaming(data: &[u8]) -> Result<ObjectStream<'_>, ParseError> {
+        ObjectStream::parse(data, &ParseOptions::default())
+    }
+
+    /// Parses the object file incrementally using the given [ParseOptions],
+    /// see [ObjectStream].
+    pub fn parse_streaming_with_options<'a>(
+        data: &'a [u8],
+        options: &ParseOptions,
+    ) -> Result<ObjectStream<'a>, ParseError> {
+        ObjectStream::parse(data, options)
+    }
+}
+
+impl<'a> ObjectStream<'a> {
+    fn parse(data: &'a [u8], options: &ParseOptions) -> Result<ObjectStream<'a>, ParseError> {
+        let elf = object::read::File::parse(data).map_err(ParseError::ElfError)?;
+        let mut object = Object::parse_metadata(&elf)?;
+
+        let mut pending = VecDeque::new();
+        for s in elf.sections() {
+            if let Ok(name) = s.name() {
+                if name == ".BTF" || name == ".BTF.ext" {
+                    continue;
+                }
+            }
+
+            let section = Section::try_from(&s)?;
+            if matches!(section.kind, BpfSectionKind::Program) {
+                object
+                    .section_sizes
+                    .insert(section.name.to_owned(), section.size);
+                object
+                    .section_names
+                    .insert(section.index.0, section.name.to_owned());
+                pending.push_back(section.index);
+            } else {
+                object.parse_section_with_options(section, options)?;
+            }
+        }
+
+        object.collect_externs()?;
+
+        Ok(ObjectStream {
+            elf,
+            object,
+            pending,
+            options: options.clone(),
+        })
+    }
+
+    /// Returns the object parsed so far.
+    ///
+    /// The programs yielded by the stream aren't part of it.
+    pub fn object(&self) -> &Object {
+        &self.object
+    }
+
+    /// Returns the number of programs that haven't been parsed yet.
+    pub fn remaining(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Consumes the stream and returns the parsed object, without the
+    /// programs that haven't been parsed yet.
+    pub fn into_object(self) -> Object {
+        self.object
+    }
+
+    fn parse_program(&mut self, index: SectionIndex) -> Result<Program, ParseError> {
+        let section =
+            self.elf
+                .section_by_index(index)
+                .map_err(|error| ParseError::SectionError {
+                    index: index.0,
+                    error,
+                })?;
+        let mut section = Section::try_from(&section)?;
+        let program = self.object.parse_program(&section)?;
+        self.object.insert_relocations(&mut section);
+        Ok(program)
+    }
+}
+
+impl Iterator for ObjectStream<'_> {
+    type Item = Result<Program, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(index) = self.pending.pop_front() {
+            match self.parse_program(index) {
+                Err(ParseError::InvalidProgramSection { section })
+                    if self.options.ignore_unknown_sections =>
+                {
+                    debug!("ignoring unknown section `{}`", section);
+                    self.object.warnings.push(ParseWarning::UnknownSection {
+                        index: index.0,
+                        name: section,
+                    });
+                }
+                ret => return Some(ret),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(all(test, not(feature = "no_std")))]
+mod tests {
+    use alloc::{ffi::CString, format, vec, vec::Vec};
+    use core::mem;
+
+    use object::Endianness;
+
+    use super::*;
+    use crate::{
+        generated::bpf_insn,
+        obj::{tests::fake_func, Function, KernelVersion, ProgramSection},
+    };
+
+    fn many_programs_elf(program_count: usize, insn_count: usize) -> Vec<u8> {
+        let mut obj = Object::new(
+            Endianness::Little,
+            CString::new("GPL").unwrap(),
+            KernelVersion::Any,
+        );
+        let mut exit: bpf_insn = unsafe { mem::zeroed() };
+        exit.code = 0x95;
+        for i in 0..program_count {
+            let name = format!("prog{i}");
+            let index = i + 1;
+            obj.section_names.insert(index, format!("xdp/{name}"));
+            obj.programs.insert(
+                name.clone(),
+                Program {
+                    license: obj.license.clone(),
+                    kernel_version: obj.kernel_version,
+                    section: ProgramSection::Xdp { name: name.clone() },
+                    function: Function {
+                        section_index: SectionIndex(index),
+                        ..fake_func(&name, vec![exit; insn_count])
+                    },
+                },
+            );
+        }
+        obj.to_elf().unwrap()
+    }
+
+    #[test]
+    fn test_parse_streaming() {
+        let data = many_programs_elf(3, 4);
+        let mut stream = Object::parse_streaming(&data).unwrap();
+        assert_eq!(stream.remaining(), 3);
+        assert!(stream.object().programs.is_empty());
+
+        let mut names = Vec::new();
+        for program in &mut stream {
+            let program = program.unwrap();
+            assert_eq!(program.function.instructions.len(), 4);
+            names.push(program.section.name().to_owned());
+        }
+        names.sort();
+        assert_eq!(names, ["prog0", "prog1", "prog2"]);
+        assert_eq!(stream.remaining(), 0);
+
+        let obj = stream.into_object();
+        assert!(obj.programs.is_empty());
+        assert_eq!(obj.section_names[&1], "xdp/prog0");
+    }
+}
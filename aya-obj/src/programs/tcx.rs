@@ -0,0 +1,39 @@
+//! TCX programs.
+use alloc::{borrow::ToOwned, string::String};
+
+use crate::{
+    generated::bpf_attach_type,
+    thiserror::{self, Error},
+};
+
+/// Defines where to attach a `Tcx` program.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TcxAttachType {
+    /// Attach to ingress.
+    Ingress,
+    /// Attach to egress.
+    Egress,
+}
+
+impl From<TcxAttachType> for bpf_attach_type {
+    fn from(s: TcxAttachType) -> bpf_attach_type {
+        match s {
+            TcxAttachType::Ingress => bpf_attach_type::BPF_TCX_INGRESS,
+            TcxAttachType::Egress => bpf_attach_type::BPF_TCX_EGRESS,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{0} is not a valid attach type for a TCX program")]
+pub(crate) struct InvalidAttachType(String);
+
+impl TcxAttachType {
+    pub(crate) fn try_from(value: &str) -> Result<TcxAttachType, InvalidAttachType> {
+        match value {
+            "ingress" => Ok(TcxAttachType::Ingress),
+            "egress" => Ok(TcxAttachType::Egress),
+            _ => Err(InvalidAttachType(value.to_owned())),
+        }
+    }
+}
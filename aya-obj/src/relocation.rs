@@ -2,16 +2,17 @@
 
 use core::mem;
 
-use alloc::{borrow::ToOwned, string::String};
+use alloc::{borrow::ToOwned, string::String, vec::Vec};
 use log::debug;
 use object::{SectionIndex, SymbolKind};
 
 use crate::{
     generated::{
-        bpf_insn, BPF_CALL, BPF_JMP, BPF_K, BPF_PSEUDO_CALL, BPF_PSEUDO_FUNC, BPF_PSEUDO_MAP_FD,
+        bpf_insn, BPF_CALL, BPF_DW, BPF_JMP, BPF_K, BPF_LD, BPF_PSEUDO_BTF_ID, BPF_PSEUDO_CALL,
+        BPF_PSEUDO_FUNC, BPF_PSEUDO_MAP_FD, BPF_PSEUDO_MAP_IDX, BPF_PSEUDO_MAP_IDX_VALUE,
         BPF_PSEUDO_MAP_VALUE,
     },
-    maps::Map,
+    maps::{BtfMap, Map},
     obj::{Function, Object, Program},
     thiserror::{self, Error},
     util::HashMap,
@@ -19,7 +20,11 @@ use crate::{
 
 pub(crate) const INS_SIZE: usize = mem::size_of::<bpf_insn>();
 
-/// The error type returned by [`Object::relocate_maps`] and [`Object::relocate_calls`]
+// BPF_IMM isn't part of the generated bindings
+pub(crate) const BPF_IMM: u32 = 0x00;
+
+/// The error type returned by [`Object::relocate_maps`], [`Object::relocate_ksyms`] and
+/// [`Object::relocate_calls`]
 #[derive(Error, Debug)]
 #[error("error relocating `{function}`")]
 pub struct BpfRelocationError {
@@ -78,6 +83,32 @@ pub enum RelocationError {
         /// The relocation number
         relocation_number: usize,
     },
+
+    /// A non weak kernel symbol could not be resolved
+    #[error("kernel symbol `{name}` not found")]
+    UnresolvedKsym {
+        /// The symbol name
+        name: String,
+    },
+
+    /// A kernel symbol is referenced by an instruction that isn't a 64 bit
+    /// immediate load
+    #[error("invalid instruction referencing kernel symbol `{name}` applying relocation #{relocation_number}")]
+    InvalidKsymInstruction {
+        /// The symbol name
+        name: String,
+        /// The relocation number
+        relocation_number: usize,
+    },
+
+    /// A relocation to a map value doesn't point to a 64-bit immediate load
+    #[error("invalid instruction referencing the value of map `{name}` applying relocation #{relocation_number}")]
+    InvalidMapValueInstruction {
+        /// The map name
+        name: String,
+        /// The relocation number
+        relocation_number: usize,
+    },
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -86,10 +117,14 @@ pub(crate) struct Relocation {
     pub(crate) offset: u64,
     // index of the symbol to relocate to
     pub(crate) symbol_index: usize,
+    // explicit addend of RELA relocations. REL relocations store the addend
+    // inline in the instruction being relocated.
+    pub(crate) addend: i64,
 }
 
+/// A symbol of the object file.
 #[derive(Debug, Clone)]
-pub(crate) struct Symbol {
+pub struct Symbol {
     pub(crate) index: usize,
     pub(crate) section_index: Option<usize>,
     pub(crate) name: Option<String>,
@@ -97,9 +132,284 @@ pub(crate) struct Symbol {
     pub(crate) size: u64,
     pub(crate) is_definition: bool,
     pub(crate) kind: SymbolKind,
+    pub(crate) binding: SymbolBinding,
+    pub(crate) visibility: SymbolVisibility,
+}
+
+/// The binding of a [Symbol].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolBinding {
+    /// `STB_LOCAL`
+    Local,
+    /// `STB_GLOBAL`
+    Global,
+    /// `STB_WEAK`
+    Weak,
+}
+
+impl SymbolBinding {
+    pub(crate) fn from_st_info(st_info: u8) -> SymbolBinding {
+        match st_info >> 4 {
+            object::elf::STB_LOCAL => SymbolBinding::Local,
+            object::elf::STB_WEAK => SymbolBinding::Weak,
+            // STB_GLOBAL and GNU extensions like STB_GNU_UNIQUE
+            _ => SymbolBinding::Global,
+        }
+    }
+
+    pub(crate) fn st_bind(&self) -> u8 {
+        match self {
+            SymbolBinding::Local => object::elf::STB_LOCAL,
+            SymbolBinding::Global => object::elf::STB_GLOBAL,
+            SymbolBinding::Weak => object::elf::STB_WEAK,
+        }
+    }
+}
+
+/// The visibility of a [Symbol].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolVisibility {
+    /// `STV_DEFAULT`
+    Default,
+    /// `STV_INTERNAL`
+    Internal,
+    /// `STV_HIDDEN`
+    Hidden,
+    /// `STV_PROTECTED`
+    Protected,
+}
+
+impl SymbolVisibility {
+    pub(crate) fn from_st_other(st_other: u8) -> SymbolVisibility {
+        match st_other & 0x3 {
+            object::elf::STV_INTERNAL => SymbolVisibility::Internal,
+            object::elf::STV_HIDDEN => SymbolVisibility::Hidden,
+            object::elf::STV_PROTECTED => SymbolVisibility::Protected,
+            _ => SymbolVisibility::Default,
+        }
+    }
+
+    pub(crate) fn st_other(&self) -> u8 {
+        match self {
+            SymbolVisibility::Default => object::elf::STV_DEFAULT,
+            SymbolVisibility::Internal => object::elf::STV_INTERNAL,
+            SymbolVisibility::Hidden => object::elf::STV_HIDDEN,
+            SymbolVisibility::Protected => object::elf::STV_PROTECTED,
+        }
+    }
+}
+
+impl Symbol {
+    /// Returns the index of the symbol in the symbol table.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the symbol name.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the index of the section the symbol is defined in, if any.
+    pub fn section_index(&self) -> Option<usize> {
+        self.section_index
+    }
+
+    /// Returns the value of the symbol, usually its offset in its section.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// Returns the size of the symbol.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the kind of the symbol.
+    ///
+    /// `STT_FUNC` symbols are [SymbolKind::Text], `STT_OBJECT` symbols are
+    /// [SymbolKind::Data] and `STT_SECTION` symbols are [SymbolKind::Section].
+    pub fn kind(&self) -> SymbolKind {
+        self.kind
+    }
+
+    /// Returns the binding of the symbol.
+    pub fn binding(&self) -> SymbolBinding {
+        self.binding
+    }
+
+    /// Returns the visibility of the symbol.
+    pub fn visibility(&self) -> SymbolVisibility {
+        self.visibility
+    }
+
+    /// Returns true if the symbol is defined in the object.
+    pub fn is_definition(&self) -> bool {
+        self.is_definition
+    }
+}
+
+/// A kernel symbol declared as an extern in the `.ksyms` section.
+///
+/// In eBPF C, ksyms are declared as `extern const void sym __ksym;` or, for
+/// typed ksyms, with the type of the kernel variable.
+#[derive(Debug, Clone)]
+pub struct Extern {
+    pub(crate) name: String,
+    pub(crate) symbol_index: usize,
+    pub(crate) typed: bool,
+    pub(crate) is_weak: bool,
+}
+
+impl Extern {
+    /// The symbol name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the symbol is declared with a type, and so must be resolved
+    /// using the kernel BTF, or as `void`, and so must be resolved to its
+    /// address
+    pub fn is_typed(&self) -> bool {
+        self.typed
+    }
+
+    /// Whether the symbol is declared `__weak`, in which case it's allowed to
+    /// be missing from the kernel
+    pub fn is_weak(&self) -> bool {
+        self.is_weak
+    }
+}
+
+/// The value an [Extern] resolves to in the running kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KsymValue {
+    /// The address of a typeless symbol, as found in `/proc/kallsyms`
+    Address(u64),
+    /// The id of the `VAR` of a typed symbol in the kernel BTF
+    BtfId(u32),
 }
 
 impl Object {
+    /// Returns the kernel symbols referenced as externs by the object.
+    pub fn externs(&self) -> impl Iterator<Item = &Extern> {
+        self.externs.iter()
+    }
+
+    /// Relocates the loads of kernel symbols.
+    ///
+    /// `resolve` is called once for every [Extern] and must return the value
+    /// it resolves to in the running kernel, or `None` if the kernel doesn't
+    /// have it. Missing weak symbols are relocated to 0, while missing non weak
+    /// symbols fail with [`RelocationError::UnresolvedKsym`].
+    pub fn relocate_ksyms<F: Fn(&Extern) -> Option<KsymValue>>(
+        &mut self,
+        resolve: F,
+    ) -> Result<(), BpfRelocationError> {
+        let mut ksyms = HashMap::new();
+        for ext in &self.externs {
+            ksyms.insert(ext.symbol_index, (ext, resolve(ext)));
+        }
+        if ksyms.is_empty() {
+            return Ok(());
+        }
+
+        let functions = self
+            .programs
+            .values_mut()
+            .map(|p| &mut p.function)
+            .chain(self.functions.values_mut());
+
+        for function in functions {
+            if let Some(relocations) = self.relocations.get(&function.section_index) {
+                relocate_ksyms(function, relocations.values(), &ksyms).map_err(|error| {
+                    BpfRelocationError {
+                        function: function.name.clone(),
+                        error,
+                    }
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the names of the maps referenced by the program `prog`,
+    /// including the references made by the functions it calls.
+    ///
+    /// The maps are resolved from the relocations of the object, so this must
+    /// be called before [`Object::relocate_calls`]. Returns an empty iterator
+    /// if the program doesn't exist.
+    pub fn referenced_maps(&self, prog: &str) -> impl Iterator<Item = &str> {
+        let mut names = Vec::new();
+        let mut pending = self
+            .programs
+            .get(prog)
+            .map(|p| &p.function)
+            .into_iter()
+            .collect::<Vec<_>>();
+        let mut visited = HashMap::new();
+
+        while let Some(fun) = pending.pop() {
+            let relocations = self.relocations.get(&fun.section_index);
+            for (ins_index, ins) in fun.instructions.iter().enumerate() {
+                let offset = (fun.section_offset + ins_index * INS_SIZE) as u64;
+                let sym = relocations
+                    .and_then(|relocations| relocations.get(&offset))
+                    .and_then(|rel| self.symbols_by_index.get(&rel.symbol_index));
+
+                // see FunctionLinker::relocate() for how calls are resolved
+                let callee_address = match sym {
+                    Some(sym) if insn_is_call(ins) => Some(sym.address),
+                    Some(sym) if sym.section_index == self.text_section_index => match sym.kind {
+                        SymbolKind::Text => Some(sym.address),
+                        SymbolKind::Section => Some(sym.address + ins.imm as u64),
+                        _ => None,
+                    },
+                    Some(sym) => {
+                        if let Some(name) = self.map_name(sym) {
+                            names.push(name);
+                        }
+                        None
+                    }
+                    None if insn_is_call(ins) => Some(
+                        (fun.section_offset as i64
+                            + (ins_index as i64 + ins.imm as i64 + 1) * INS_SIZE as i64)
+                            as u64,
+                    ),
+                    None => None,
+                };
+
+                if let Some(address) = callee_address {
+                    if visited.insert(address, ()).is_none() {
+                        pending.extend(self.functions.get(&address));
+                    }
+                }
+            }
+        }
+
+        names.sort_unstable();
+        names.dedup();
+        names.into_iter()
+    }
+
+    // resolves a relocation symbol to the name of the map it references, the
+    // same way relocate_maps() does
+    fn map_name(&self, sym: &Symbol) -> Option<&str> {
+        let section_index = sym.section_index?;
+        let by_symbol = self.maps.iter().find(|(_, map)| {
+            map.symbol_index() == sym.index
+                || matches!(map, Map::Btf(BtfMap { legacy: Some(legacy), .. }) if legacy.symbol_index == sym.index)
+        });
+        by_symbol
+            .or_else(|| {
+                self.maps
+                    .iter()
+                    .find(|(_, map)| map.section_index() == section_index)
+            })
+            .map(|(name, _)| name.as_str())
+    }
+
     /// Relocates the map references
     pub fn relocate_maps<'a, I: Iterator<Item = (&'a str, Option<i32>, &'a Map)>>(
         &mut self,
@@ -110,6 +420,85 @@ impl Object {
         for (name, fd, map) in maps {
             maps_by_section.insert(map.section_index(), (name, fd, map));
             maps_by_symbol.insert(map.symbol_index(), (name, fd, map));
+            // references to the legacy declaration of the map resolve to the BTF map
+            if let Map::Btf(BtfMap {
+                legacy: Some(legacy),
+                ..
+            }) = map
+            {
+                maps_by_symbol.insert(legacy.symbol_index, (name, fd, map));
+            }
+        }
+
+        let functions = self
+            .programs
+            .values_mut()
+            .map(|p| &mut p.function)
+            .chain(self.functions.values_mut());
+
+        let mut unresolved = Vec::new();
+        for function in functions {
+            if let Some(relocations) = self.relocations.get(&function.section_index) {
+                unresolved.extend(
+                    relocate_maps(
+                        function,
+                        relocations.values(),
+                        &maps_by_section,
+                        &maps_by_symbol,
+                        &self.symbols_by_index,
+                        self.text_section_index,
+                    )
+                    .map_err(|error| BpfRelocationError {
+                        function: function.name.clone(),
+                        error,
+                    })?,
+                );
+            }
+        }
+
+        // kernel symbols are undefined too, they're relocated by relocate_ksyms()
+        unresolved.retain(|index| !self.externs.iter().any(|e| e.symbol_index == *index));
+        unresolved.sort_unstable();
+        unresolved.dedup();
+        self.unresolved_maps = unresolved;
+
+        Ok(())
+    }
+
+    /// Returns the names of the maps that the object references but doesn't
+    /// define, because they're defined in another object.
+    ///
+    /// The references are found by [Object::relocate_maps], which leaves them
+    /// unrelocated, and can be relocated with [Object::relocate_extern_maps].
+    pub fn unresolved_maps(&self) -> impl Iterator<Item = &str> {
+        self.unresolved_maps
+            .iter()
+            .filter_map(|index| self.symbols_by_index.get(index)?.name.as_deref())
+    }
+
+    /// Relocates the references to maps defined in other objects.
+    ///
+    /// `maps` yields the name, fd and definition of the maps defined by other
+    /// objects. References to maps that aren't in `maps` are left
+    /// unrelocated, see [Object::unresolved_maps]. Like
+    /// [Object::relocate_maps], this must be called before
+    /// [Object::relocate_calls].
+    pub fn relocate_extern_maps<'a, I: Iterator<Item = (&'a str, Option<i32>, &'a Map)>>(
+        &mut self,
+        maps: I,
+    ) -> Result<(), BpfRelocationError> {
+        let maps = maps
+            .map(|(name, fd, map)| (name, (name, fd, map)))
+            .collect::<HashMap<_, _>>();
+        let mut maps_by_symbol = HashMap::new();
+        for index in &self.unresolved_maps {
+            let name = self
+                .symbols_by_index
+                .get(index)
+                .and_then(|sym| sym.name.as_deref());
+            if let Some(map) = name.and_then(|name| maps.get(name)) {
+                maps_by_symbol.insert(*index, *map);
+            }
         }
 
         let functions = self
@@ -122,8 +511,10 @@ impl Object {
             if let Some(relocations) = self.relocations.get(&function.section_index) {
                 relocate_maps(
                     function,
-                    relocations.values(),
-                    &maps_by_section,
+                    relocations
+                        .values()
+                        .filter(|rel| maps_by_symbol.contains_key(&rel.symbol_index)),
+                    &HashMap::new(),
                     &maps_by_symbol,
                     &self.symbols_by_index,
                     self.text_section_index,
@@ -135,6 +526,9 @@ impl Object {
             }
         }
 
+        self.unresolved_maps
+            .retain(|index| !maps_by_symbol.contains_key(index));
+
         Ok(())
     }
 
@@ -157,6 +551,8 @@ impl Object {
     }
 }
 
+// Relocates the maps referenced by `fun`. Returns the indices of the undefined
+// symbols that are loaded like maps but aren't in `maps_by_symbol`.
 fn relocate_maps<'a, I: Iterator<Item = &'a Relocation>>(
     fun: &mut Function,
     relocations: I,
@@ -164,10 +560,11 @@ fn relocate_maps<'a, I: Iterator<Item = &'a Relocation>>(
     maps_by_symbol: &HashMap<usize, (&str, Option<i32>, &Map)>,
     symbol_table: &HashMap<usize, Symbol>,
     text_section_index: Option<usize>,
-) -> Result<(), RelocationError> {
+) -> Result<Vec<usize>, RelocationError> {
     let section_offset = fun.section_offset;
     let instructions = &mut fun.instructions;
     let function_size = instructions.len() * INS_SIZE;
+    let mut unresolved = Vec::new();
 
     for (rel_n, rel) in relocations.enumerate() {
         let rel_offset = rel.offset as usize;
@@ -193,49 +590,124 @@ fn relocate_maps<'a, I: Iterator<Item = &'a Relocation>>(
                 index: rel.symbol_index,
             })?;
 
-        let section_index = match sym.section_index {
-            Some(index) => index,
-            // this is not a map relocation
-            None => continue,
-        };
-
         // calls and relocation to .text symbols are handled in a separate step
-        if insn_is_call(&instructions[ins_index]) || sym.section_index == text_section_index {
+        if insn_is_call(&instructions[ins_index])
+            || (sym.section_index.is_some() && sym.section_index == text_section_index)
+        {
             continue;
         }
 
-        let (name, fd, map) = if maps_by_symbol.contains_key(&rel.symbol_index) {
-            maps_by_symbol
-                .get(&rel.symbol_index)
-                .ok_or(RelocationError::SectionNotFound {
-                    symbol_index: rel.symbol_index,
-                    symbol_name: sym.name.clone(),
-                    section_index,
-                })?
-        } else {
-            maps_by_section
-                .get(&section_index)
-                .ok_or(RelocationError::SectionNotFound {
-                    symbol_index: rel.symbol_index,
-                    symbol_name: sym.name.clone(),
-                    section_index,
-                })?
+        let (name, fd, map) = match (maps_by_symbol.get(&rel.symbol_index), sym.section_index) {
+            (Some(map), _) => map,
+            (None, Some(section_index)) => {
+                maps_by_section
+                    .get(&section_index)
+                    .ok_or(RelocationError::SectionNotFound {
+                        symbol_index: rel.symbol_index,
+                        symbol_name: sym.name.clone(),
+                        section_index,
+                    })?
+            }
+            // an undefined symbol is either not a map relocation, or a
+            // reference to a map defined in another object, which is left for
+            // Object::relocate_extern_maps()
+            (None, None) => {
+                if instructions[ins_index].code == (BPF_LD | BPF_IMM | BPF_DW) as u8 {
+                    unresolved.push(rel.symbol_index);
+                }
+                continue;
+            }
         };
 
         let map_fd = fd.ok_or_else(|| RelocationError::MapNotCreated {
             name: (*name).into(),
-            section_index,
+            section_index: map.section_index(),
         })?;
 
         if !map.data().is_empty() {
+            // the fd goes in the imm of the first slot of the ldimm64, the
+            // offset within the map value in the imm of the second one
+            if instructions[ins_index].code != (BPF_LD | BPF_IMM | BPF_DW) as u8
+                || ins_index + 1 >= instructions.len()
+            {
+                return Err(RelocationError::InvalidMapValueInstruction {
+                    name: (*name).into(),
+                    relocation_number: rel_n,
+                });
+            }
+            // the offset is the inline addend (REL) plus the explicit addend
+            // (RELA) plus the address of the symbol
             instructions[ins_index].set_src_reg(BPF_PSEUDO_MAP_VALUE as u8);
-            instructions[ins_index + 1].imm = instructions[ins_index].imm + sym.address as i32;
+            instructions[ins_index + 1].imm = instructions[ins_index]
+                .imm
+                .wrapping_add(rel.addend as i32)
+                .wrapping_add(sym.address as i32);
         } else {
             instructions[ins_index].set_src_reg(BPF_PSEUDO_MAP_FD as u8);
         }
         instructions[ins_index].imm = map_fd;
     }
 
+    Ok(unresolved)
+}
+
+fn relocate_ksyms<'a, I: Iterator<Item = &'a Relocation>>(
+    fun: &mut Function,
+    relocations: I,
+    ksyms: &HashMap<usize, (&Extern, Option<KsymValue>)>,
+) -> Result<(), RelocationError> {
+    let section_offset = fun.section_offset;
+    let instructions = &mut fun.instructions;
+    let function_size = instructions.len() * INS_SIZE;
+
+    for (rel_n, rel) in relocations.enumerate() {
+        let (ext, value) = match ksyms.get(&rel.symbol_index) {
+            Some(ksym) => ksym,
+            // not a ksym relocation
+            None => continue,
+        };
+
+        let rel_offset = rel.offset as usize;
+        if rel_offset < section_offset || rel_offset >= section_offset + function_size {
+            // the relocation doesn't apply to this function
+            continue;
+        }
+
+        let ins_offset = rel_offset - section_offset;
+        if !ins_offset.is_multiple_of(INS_SIZE) {
+            return Err(RelocationError::InvalidRelocationOffset {
+                offset: rel.offset,
+                relocation_number: rel_n,
+            });
+        }
+        let ins_index = ins_offset / INS_SIZE;
+
+        if instructions[ins_index].code != (BPF_LD | BPF_IMM | BPF_DW) as u8
+            || ins_index + 1 >= instructions.len()
+        {
+            return Err(RelocationError::InvalidKsymInstruction {
+                name: ext.name.clone(),
+                relocation_number: rel_n,
+            });
+        }
+
+        let (src_reg, lo, hi) = match value {
+            Some(KsymValue::Address(address)) => (0, *address as u32, (*address >> 32) as u32),
+            // the upper half is the fd of the BTF object the id belongs to,
+            // 0 means vmlinux
+            Some(KsymValue::BtfId(id)) => (BPF_PSEUDO_BTF_ID as u8, *id, 0),
+            None if ext.is_weak => (0, 0, 0),
+            None => {
+                return Err(RelocationError::UnresolvedKsym {
+                    name: ext.name.clone(),
+                })
+            }
+        };
+        instructions[ins_index].set_src_reg(src_reg);
+        instructions[ins_index].imm = lo as i32;
+        instructions[ins_index + 1].imm = hi as i32;
+    }
+
     Ok(())
 }
 
@@ -472,11 +944,54 @@ fn insn_is_call(ins: &bpf_insn) -> bool {
         && ins.off == 0
 }
 
+/// Rewrites the map references of relocated instructions to index into an
+/// `fd_array`.
+///
+/// `BPF_PSEUDO_MAP_FD` and `BPF_PSEUDO_MAP_VALUE` loads are converted to
+/// `BPF_PSEUDO_MAP_IDX` and `BPF_PSEUDO_MAP_IDX_VALUE` loads. The returned
+/// vector contains the map file descriptors in index order, and must be passed
+/// to the kernel as `fd_array` when loading the instructions.
+pub fn relocate_maps_to_fd_array(instructions: &mut [bpf_insn]) -> Vec<i32> {
+    let mut fd_array = Vec::new();
+    let mut ins_index = 0;
+    while ins_index < instructions.len() {
+        let ins = &mut instructions[ins_index];
+        if ins.code == (BPF_LD | BPF_IMM | BPF_DW) as u8 {
+            let src = ins.src_reg() as u32;
+            if src == BPF_PSEUDO_MAP_FD || src == BPF_PSEUDO_MAP_VALUE {
+                let fd = ins.imm;
+                let index = match fd_array.iter().position(|f| *f == fd) {
+                    Some(index) => index,
+                    None => {
+                        fd_array.push(fd);
+                        fd_array.len() - 1
+                    }
+                };
+                ins.imm = index as i32;
+                ins.set_src_reg(if src == BPF_PSEUDO_MAP_FD {
+                    BPF_PSEUDO_MAP_IDX as u8
+                } else {
+                    BPF_PSEUDO_MAP_IDX_VALUE as u8
+                });
+            }
+            // 64 bit immediate loads span two instructions
+            ins_index += 1;
+        }
+        ins_index += 1;
+    }
+
+    fd_array
+}
+
 #[cfg(test)]
 mod test {
     use alloc::{string::ToString, vec, vec::Vec};
+    use matches::assert_matches;
 
-    use crate::maps::{bpf_map_def, BtfMap, BtfMapDef, LegacyMap, Map, MapKind};
+    use crate::{
+        maps::{bpf_map_def, BtfMap, BtfMapDef, LegacyMap, Map, MapKind},
+        obj::tests::fake_func,
+    };
 
     use super::*;
 
@@ -489,6 +1004,8 @@ mod test {
             size,
             is_definition: false,
             kind: SymbolKind::Data,
+            binding: SymbolBinding::Global,
+            visibility: SymbolVisibility::Default,
         }
     }
 
@@ -517,21 +1034,20 @@ mod test {
             symbol_index,
             data: Vec::new(),
             kind: MapKind::Other,
+            legacy: None,
         })
     }
 
-    fn fake_func(name: &str, instructions: Vec<bpf_insn>) -> Function {
-        Function {
-            address: Default::default(),
-            name: name.to_string(),
-            section_index: SectionIndex(0),
-            section_offset: Default::default(),
-            instructions,
-            func_info: Default::default(),
-            line_info: Default::default(),
-            func_info_rec_size: Default::default(),
-            line_info_rec_size: Default::default(),
-        }
+    fn fake_data_map(section_index: usize, size: usize) -> Map {
+        Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                ..Default::default()
+            },
+            section_index,
+            symbol_index: 0,
+            data: vec![0; size],
+            kind: MapKind::Data,
+        })
     }
 
     #[test]
@@ -549,6 +1065,7 @@ mod test {
         let relocations = vec![Relocation {
             offset: 0x0,
             symbol_index: 1,
+            addend: 0,
         }];
         let maps_by_section = HashMap::new();
 
@@ -571,6 +1088,56 @@ mod test {
         mem::forget(map);
     }
 
+    #[test]
+    fn test_undefined_map_relocation() {
+        let mut fun = fake_func(
+            "test",
+            vec![ins(&[
+                0x18, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ])],
+        );
+
+        let mut sym = fake_sym(1, 0, 0, "extern_map", 0);
+        sym.section_index = None;
+        let symbol_table = HashMap::from([(1, sym)]);
+
+        let relocations = vec![Relocation {
+            offset: 0x0,
+            symbol_index: 1,
+            addend: 0,
+        }];
+
+        let unresolved = relocate_maps(
+            &mut fun,
+            relocations.iter(),
+            &HashMap::new(),
+            &HashMap::new(),
+            &symbol_table,
+            None,
+        )
+        .unwrap();
+        assert_eq!(unresolved, vec![1]);
+        assert_eq!(fun.instructions[0].src_reg(), 0);
+        assert_eq!(fun.instructions[0].imm, 0);
+
+        // once the map is known the reference is relocated
+        let map = fake_legacy_map(7);
+        let maps_by_symbol = HashMap::from([(1, ("extern_map", Some(3), &map))]);
+        let unresolved = relocate_maps(
+            &mut fun,
+            relocations.iter(),
+            &HashMap::new(),
+            &maps_by_symbol,
+            &symbol_table,
+            None,
+        )
+        .unwrap();
+        assert!(unresolved.is_empty());
+        assert_eq!(fun.instructions[0].src_reg(), BPF_PSEUDO_MAP_FD as u8);
+        assert_eq!(fun.instructions[0].imm, 3);
+    }
+
     #[test]
     fn test_multiple_legacy_map_relocation() {
         let mut fun = fake_func(
@@ -596,10 +1163,12 @@ mod test {
             Relocation {
                 offset: 0x0,
                 symbol_index: 1,
+                addend: 0,
             },
             Relocation {
                 offset: mem::size_of::<bpf_insn>() as u64,
                 symbol_index: 2,
+                addend: 0,
             },
         ];
         let maps_by_section = HashMap::new();
@@ -646,6 +1215,7 @@ mod test {
         let relocations = vec![Relocation {
             offset: 0x0,
             symbol_index: 1,
+            addend: 0,
         }];
         let maps_by_section = HashMap::new();
 
@@ -693,10 +1263,12 @@ mod test {
             Relocation {
                 offset: 0x0,
                 symbol_index: 1,
+                addend: 0,
             },
             Relocation {
                 offset: mem::size_of::<bpf_insn>() as u64,
                 symbol_index: 2,
+                addend: 0,
             },
         ];
         let maps_by_section = HashMap::new();
@@ -727,4 +1299,191 @@ mod test {
         mem::forget(map_1);
         mem::forget(map_2);
     }
+
+    #[test]
+    fn test_data_relocation_with_addend() {
+        let mut fun = fake_func(
+            "test",
+            vec![
+                ins(&[
+                    0x18, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00,
+                ]),
+                ins(&[
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00,
+                ]),
+                ins(&[
+                    0x18, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00,
+                ]),
+                ins(&[
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00,
+                ]),
+            ],
+        );
+
+        // two consecutive 4 byte globals in .data
+        let symbol_table = HashMap::from([
+            (1, fake_sym(1, 1, 0, ".data", 0)),
+            (2, fake_sym(2, 1, 0, "global_1", 4)),
+            (3, fake_sym(3, 1, 4, "global_2", 4)),
+        ]);
+
+        let relocations = vec![
+            // relocation against the symbol of the second global
+            Relocation {
+                offset: 0x0,
+                symbol_index: 3,
+                addend: 0,
+            },
+            // relocation against the section symbol, with the offset of the
+            // second global as addend
+            Relocation {
+                offset: 2 * mem::size_of::<bpf_insn>() as u64,
+                symbol_index: 1,
+                addend: 4,
+            },
+        ];
+
+        let map = fake_data_map(1, 8);
+        let maps_by_section = HashMap::from([(1, (".data", Some(1), &map))]);
+        let maps_by_symbol = HashMap::new();
+
+        relocate_maps(
+            &mut fun,
+            relocations.iter(),
+            &maps_by_section,
+            &maps_by_symbol,
+            &symbol_table,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(fun.instructions[0].src_reg(), BPF_PSEUDO_MAP_VALUE as u8);
+        assert_eq!(fun.instructions[0].imm, 1);
+        assert_eq!(fun.instructions[1].imm, 4);
+
+        assert_eq!(fun.instructions[2].src_reg(), BPF_PSEUDO_MAP_VALUE as u8);
+        assert_eq!(fun.instructions[2].imm, 1);
+        assert_eq!(fun.instructions[3].imm, 4);
+
+        mem::forget(map);
+    }
+
+    #[test]
+    fn test_rodata_value_relocation() {
+        // r0 = 0; r1 = *(u64 *)&.rodata[12]; exit
+        let mut fun = fake_func(
+            "test",
+            vec![
+                ins(&[
+                    0xb7, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00,
+                ]),
+                ins(&[
+                    0x18, 0x01, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00,
+                ]),
+                ins(&[
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00,
+                ]),
+                ins(&[
+                    0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00,
+                ]),
+            ],
+        );
+
+        // the relocation is against the section symbol, the offset of the
+        // value is the inline addend
+        let symbol_table = HashMap::from([(1, fake_sym(1, 2, 0, ".rodata", 0))]);
+        let relocations = vec![Relocation {
+            offset: mem::size_of::<bpf_insn>() as u64,
+            symbol_index: 1,
+            addend: 0,
+        }];
+
+        let map = Map::Legacy(LegacyMap {
+            def: Default::default(),
+            section_index: 2,
+            symbol_index: 0,
+            data: vec![0; 16],
+            kind: MapKind::Rodata,
+        });
+        let maps_by_section = HashMap::from([(2, (".rodata", Some(7), &map))]);
+        let maps_by_symbol = HashMap::new();
+
+        relocate_maps(
+            &mut fun,
+            relocations.iter(),
+            &maps_by_section,
+            &maps_by_symbol,
+            &symbol_table,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(fun.instructions[1].src_reg(), BPF_PSEUDO_MAP_VALUE as u8);
+        assert_eq!(fun.instructions[1].imm, 7);
+        assert_eq!(fun.instructions[2].src_reg(), 0);
+        assert_eq!(fun.instructions[2].imm, 12);
+        // the other instructions are untouched
+        assert_eq!(fun.instructions[0].imm, 0);
+        assert_eq!(fun.instructions[3].imm, 0);
+
+        // a relocation to the last instruction can't be a ldimm64
+        let relocations = vec![Relocation {
+            offset: 3 * mem::size_of::<bpf_insn>() as u64,
+            symbol_index: 1,
+            addend: 0,
+        }];
+        assert_matches!(
+            relocate_maps(
+                &mut fun,
+                relocations.iter(),
+                &maps_by_section,
+                &maps_by_symbol,
+                &symbol_table,
+                None,
+            ),
+            Err(RelocationError::InvalidMapValueInstruction { name, relocation_number: 0 })
+                if name == ".rodata"
+        );
+
+        mem::forget(map);
+    }
+
+    #[test]
+    fn test_relocate_maps_to_fd_array() {
+        let mut instructions = vec![
+            ins(&[0x18, 0x01, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00]),
+            ins(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            ins(&[0x18, 0x21, 0x00, 0x00, 0x2b, 0x00, 0x00, 0x00]),
+            ins(&[0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00]),
+            ins(&[0x18, 0x01, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00]),
+            ins(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            ins(&[0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+        ];
+        instructions[0].set_src_reg(BPF_PSEUDO_MAP_FD as u8);
+        instructions[2].set_src_reg(BPF_PSEUDO_MAP_VALUE as u8);
+        instructions[4].set_src_reg(BPF_PSEUDO_MAP_FD as u8);
+
+        let fd_array = relocate_maps_to_fd_array(&mut instructions);
+        assert_eq!(fd_array, vec![42, 43]);
+
+        assert_eq!(instructions[0].src_reg(), BPF_PSEUDO_MAP_IDX as u8);
+        assert_eq!(instructions[0].imm, 0);
+
+        assert_eq!(instructions[2].src_reg(), BPF_PSEUDO_MAP_IDX_VALUE as u8);
+        assert_eq!(instructions[2].imm, 1);
+        // the offset within the map value is left untouched
+        assert_eq!(instructions[3].imm, 8);
+
+        // the same map is referenced with the same index
+        assert_eq!(instructions[4].src_reg(), BPF_PSEUDO_MAP_IDX as u8);
+        assert_eq!(instructions[4].imm, 0);
+    }
 }
@@ -5,6 +5,22 @@ pub(crate) use hashbrown::HashMap;
 #[cfg(not(feature = "no_std"))]
 pub(crate) use std::collections::HashMap;
 
+/// Marker trait for types that can safely be converted to and from byte slices.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! unsafe_impl_pod {
+    ($($struct_name:ident),+ $(,)?) => {
+        $(
+            unsafe impl Pod for $struct_name { }
+        )+
+    }
+}
+
+unsafe_impl_pod!(i8, u8, i16, u16, i32, u32, i64, u64, u128, i128);
+
+// It only makes sense that an array of POD types is itself POD
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
 /// bytes_of converts a <T> to a byte slice
 pub(crate) unsafe fn bytes_of<T>(val: &T) -> &[u8] {
     let size = mem::size_of::<T>();
@@ -0,0 +1,694 @@
+//! Serialization of an [Object] back to an ELF file.
+
+use alloc::{
+    collections::{btree_map::Entry, BTreeMap},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::mem;
+
+use object::{elf, Endianness, SymbolKind};
+
+use crate::{
+    generated::{bpf_insn, BPF_CALL, BPF_JMP},
+    maps::{bpf_map_def, LegacyMap, Map, MapKind},
+    obj::{KernelVersion, Object, ParseError},
+    relocation::{Symbol, SymbolBinding, INS_SIZE},
+    util::{bytes_of, HashMap},
+};
+
+const FILE_HEADER_SIZE: usize = 64;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+const REL_SIZE: usize = 16;
+
+struct OutSection {
+    name: String,
+    sh_type: u32,
+    flags: u64,
+    data: Vec<u8>,
+    // only used for SHT_NOBITS sections, otherwise the size is data.len()
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+impl OutSection {
+    fn new(name: &str, sh_type: u32, flags: u64, data: Vec<u8>, align: u64) -> OutSection {
+        OutSection {
+            name: name.to_string(),
+            sh_type,
+            flags,
+            size: data.len() as u64,
+            data,
+            link: 0,
+            info: 0,
+            align,
+            entsize: 0,
+        }
+    }
+
+    fn code(name: &str, data: Vec<u8>) -> OutSection {
+        OutSection::new(
+            name,
+            elf::SHT_PROGBITS,
+            (elf::SHF_ALLOC | elf::SHF_EXECINSTR) as u64,
+            data,
+            INS_SIZE as u64,
+        )
+    }
+}
+
+#[derive(Default)]
+struct StringTable {
+    data: Vec<u8>,
+}
+
+impl StringTable {
+    fn add(&mut self, s: &str) -> u32 {
+        if self.data.is_empty() {
+            self.data.push(0);
+        }
+        if s.is_empty() {
+            return 0;
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(s.as_bytes());
+        self.data.push(0);
+        offset
+    }
+}
+
+struct Writer {
+    buf: Vec<u8>,
+    endianness: Endianness,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        match self.endianness {
+            Endianness::Big => self.buf.extend_from_slice(&v.to_be_bytes()),
+            Endianness::Little => self.buf.extend_from_slice(&v.to_le_bytes()),
+        }
+    }
+
+    fn u32(&mut self, v: u32) {
+        match self.endianness {
+            Endianness::Big => self.buf.extend_from_slice(&v.to_be_bytes()),
+            Endianness::Little => self.buf.extend_from_slice(&v.to_le_bytes()),
+        }
+    }
+
+    fn u64(&mut self, v: u64) {
+        match self.endianness {
+            Endianness::Big => self.buf.extend_from_slice(&v.to_be_bytes()),
+            Endianness::Little => self.buf.extend_from_slice(&v.to_le_bytes()),
+        }
+    }
+
+    fn align(&mut self, align: u64) {
+        let align = align.max(1) as usize;
+        let len = self.buf.len().div_ceil(align) * align;
+        self.buf.resize(len, 0);
+    }
+}
+
+fn instructions_bytes(instructions: &[bpf_insn]) -> Vec<u8> {
+    instructions
+        .iter()
+        .flat_map(|ins| unsafe { bytes_of(ins) }.iter().copied())
+        .collect()
+}
+
+fn is_local(sym: &Symbol) -> bool {
+    sym.binding == SymbolBinding::Local
+}
+
+fn symbol_type(kind: SymbolKind) -> u8 {
+    match kind {
+        SymbolKind::Text => elf::STT_FUNC,
+        SymbolKind::Data => elf::STT_OBJECT,
+        SymbolKind::Section => elf::STT_SECTION,
+        SymbolKind::File => elf::STT_FILE,
+        _ => elf::STT_NOTYPE,
+    }
+}
+
+impl Object {
+    /// Serializes the object to a BPF ELF relocatable file.
+    ///
+    /// The programs, functions and maps are written as they currently are in
+    /// memory, so any relocation or patching applied to the object is
+    /// preserved. Relocations, symbols, `.BTF` and `.BTF.ext` are written too,
+    /// so the resulting file can be parsed again with [Object::parse] or
+    /// inspected with standard ELF tools.
+    pub fn to_elf(&self) -> Result<Vec<u8>, ParseError> {
+        // sections holding code and data, indexed by their original index
+        let mut contents = BTreeMap::new();
+
+        for program in self.programs.values() {
+            let index = program.function.section_index.0;
+            let data = instructions_bytes(&program.function.instructions);
+            contents.insert(index, OutSection::code(self.section_name(index)?, data));
+        }
+
+        if let Some(index) = self.text_section_index {
+            let mut data = Vec::new();
+            for function in self.functions.values() {
+                let start = function.section_offset;
+                let end = start + function.instructions.len() * INS_SIZE;
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[start..end].copy_from_slice(&instructions_bytes(&function.instructions));
+            }
+            contents.insert(index, OutSection::code(self.section_name(index)?, data));
+        }
+
+        for (name, map) in &self.maps {
+            match map {
+                Map::Legacy(m) if m.kind == MapKind::Other => {
+                    self.write_map_def(&mut contents, m)?;
+                }
+                Map::Legacy(m) => {
+                    let flags = match m.kind {
+                        MapKind::Rodata => elf::SHF_ALLOC as u64,
+                        _ => (elf::SHF_ALLOC | elf::SHF_WRITE) as u64,
+                    };
+                    let section = if m.kind == MapKind::Bss && m.data.is_empty() {
+                        let mut section =
+                            OutSection::new(name, elf::SHT_NOBITS, flags, Vec::new(), 8);
+                        section.size = m.def.value_size as u64;
+                        section
+                    } else {
+                        OutSection::new(name, elf::SHT_PROGBITS, flags, m.data.clone(), 8)
+                    };
+                    contents.insert(m.section_index, section);
+                }
+                Map::Btf(m) => {
+                    if let Entry::Vacant(entry) = contents.entry(m.section_index) {
+                        let name = self.section_name(m.section_index)?;
+                        let size = self.section_sizes.get(name).copied().unwrap_or(0);
+                        entry.insert(OutSection::new(
+                            name,
+                            elf::SHT_PROGBITS,
+                            (elf::SHF_ALLOC | elf::SHF_WRITE) as u64,
+                            vec![0; size as usize],
+                            8,
+                        ));
+                    }
+                    if let Some(legacy) = &m.legacy {
+                        self.write_map_def(&mut contents, legacy)?;
+                    }
+                }
+            }
+        }
+
+        let mut sections = vec![OutSection::new("", elf::SHT_NULL, 0, Vec::new(), 0)];
+        let mut section_map = HashMap::new();
+        for (index, section) in contents {
+            section_map.insert(index, sections.len());
+            sections.push(section);
+        }
+
+        sections.push(OutSection::new(
+            "license",
+            elf::SHT_PROGBITS,
+            (elf::SHF_ALLOC | elf::SHF_WRITE) as u64,
+            self.license.as_bytes_with_nul().to_vec(),
+            1,
+        ));
+        if let KernelVersion::Version(v) = self.kernel_version {
+            let mut w = Writer {
+                buf: Vec::new(),
+                endianness: self.endianness,
+            };
+            w.u32(v);
+            sections.push(OutSection::new(
+                "version",
+                elf::SHT_PROGBITS,
+                (elf::SHF_ALLOC | elf::SHF_WRITE) as u64,
+                w.buf,
+                4,
+            ));
+        }
+        if let Some(btf) = &self.btf {
+            sections.push(OutSection::new(
+                ".BTF",
+                elf::SHT_PROGBITS,
+                0,
+                btf.to_bytes(),
+                4,
+            ));
+            if let Some(btf_ext) = &self.btf_ext {
+                sections.push(OutSection::new(
+                    ".BTF.ext",
+                    elf::SHT_PROGBITS,
+                    0,
+                    btf_ext.data().to_vec(),
+                    4,
+                ));
+            }
+        }
+
+        let mut relocations = self
+            .relocations
+            .iter()
+            .filter_map(|(index, rels)| {
+                section_map
+                    .get(&index.0)
+                    .map(|new_index| (*new_index, rels))
+            })
+            .collect::<Vec<_>>();
+        relocations.sort_by_key(|(index, _)| *index);
+
+        let symtab_index = sections.len() + relocations.len();
+        let strtab_index = symtab_index + 1;
+
+        // locals must come before globals in the symbol table. Index 0 is the
+        // null symbol.
+        let mut symbols = self
+            .symbols_by_index
+            .values()
+            .filter(|sym| sym.index != 0)
+            .collect::<Vec<_>>();
+        symbols.sort_by_key(|sym| (!is_local(sym), sym.index));
+        let symbol_map = symbols
+            .iter()
+            .enumerate()
+            .map(|(i, sym)| (sym.index, i + 1))
+            .collect::<HashMap<_, _>>();
+
+        for (target, rels) in relocations {
+            let mut rels = rels.values().collect::<Vec<_>>();
+            rels.sort_by_key(|rel| rel.offset);
+            let mut w = Writer {
+                buf: Vec::new(),
+                endianness: self.endianness,
+            };
+            for rel in rels {
+                let sym = symbol_map
+                    .get(&rel.symbol_index)
+                    .ok_or(ParseError::InvalidSymbol {
+                        index: rel.symbol_index,
+                        name: None,
+                    })?;
+                let code = sections[target].data.get(rel.offset as usize).copied();
+                let r_type = if code == Some((BPF_JMP | BPF_CALL) as u8) {
+                    elf::R_BPF_64_32
+                } else {
+                    elf::R_BPF_64_64
+                };
+                w.u64(rel.offset);
+                w.u64(((*sym as u64) << 32) | r_type as u64);
+            }
+            let mut section = OutSection::new(
+                &format!(".rel{}", sections[target].name),
+                elf::SHT_REL,
+                0,
+                w.buf,
+                8,
+            );
+            section.link = symtab_index as u32;
+            section.info = target as u32;
+            section.entsize = REL_SIZE as u64;
+            sections.push(section);
+        }
+
+        let mut strtab = StringTable::default();
+        let mut w = Writer {
+            buf: vec![0; SYMBOL_SIZE],
+            endianness: self.endianness,
+        };
+        for sym in &symbols {
+            let shndx = match sym.section_index {
+                Some(index) => section_map
+                    .get(&index)
+                    .map(|index| *index as u16)
+                    .unwrap_or(elf::SHN_UNDEF),
+                None if sym.kind == SymbolKind::File => elf::SHN_ABS,
+                None => elf::SHN_UNDEF,
+            };
+            w.u32(strtab.add(sym.name.as_deref().unwrap_or("")));
+            w.u8((sym.binding.st_bind() << 4) | symbol_type(sym.kind));
+            w.u8(sym.visibility.st_other());
+            w.u16(shndx);
+            w.u64(sym.address);
+            w.u64(sym.size);
+        }
+        let mut symtab = OutSection::new(".symtab", elf::SHT_SYMTAB, 0, w.buf, 8);
+        symtab.link = strtab_index as u32;
+        symtab.info = symbols.iter().filter(|sym| is_local(sym)).count() as u32 + 1;
+        symtab.entsize = SYMBOL_SIZE as u64;
+        sections.push(symtab);
+        strtab.add("");
+        sections.push(OutSection::new(
+            ".strtab",
+            elf::SHT_STRTAB,
+            0,
+            strtab.data,
+            1,
+        ));
+
+        let mut shstrtab = StringTable::default();
+        let mut names = sections
+            .iter()
+            .map(|s| shstrtab.add(&s.name))
+            .collect::<Vec<_>>();
+        names.push(shstrtab.add(".shstrtab"));
+        let shstrtab_index = sections.len();
+        sections.push(OutSection::new(
+            ".shstrtab",
+            elf::SHT_STRTAB,
+            0,
+            shstrtab.data,
+            1,
+        ));
+
+        // section contents follow the file header, section headers come last
+        let mut w = Writer {
+            buf: vec![0; FILE_HEADER_SIZE],
+            endianness: self.endianness,
+        };
+        let mut offsets = Vec::with_capacity(sections.len());
+        for section in &sections {
+            w.align(section.align);
+            offsets.push(w.buf.len() as u64);
+            if section.sh_type != elf::SHT_NOBITS {
+                w.buf.extend_from_slice(&section.data);
+            }
+        }
+        w.align(8);
+        let shoff = w.buf.len() as u64;
+        for ((section, offset), name) in sections.iter().zip(offsets).zip(names) {
+            w.u32(name);
+            w.u32(section.sh_type);
+            w.u64(section.flags);
+            w.u64(0);
+            w.u64(if section.sh_type == elf::SHT_NULL {
+                0
+            } else {
+                offset
+            });
+            w.u64(section.size);
+            w.u32(section.link);
+            w.u32(section.info);
+            w.u64(section.align);
+            w.u64(section.entsize);
+        }
+
+        let mut header = Writer {
+            buf: Vec::with_capacity(FILE_HEADER_SIZE),
+            endianness: self.endianness,
+        };
+        header.buf.extend_from_slice(&elf::ELFMAG);
+        header.u8(elf::ELFCLASS64);
+        header.u8(match self.endianness {
+            Endianness::Big => elf::ELFDATA2MSB,
+            Endianness::Little => elf::ELFDATA2LSB,
+        });
+        header.u8(elf::EV_CURRENT);
+        header.u8(elf::ELFOSABI_NONE);
+        header.align(16);
+        header.u16(elf::ET_REL);
+        header.u16(elf::EM_BPF);
+        header.u32(elf::EV_CURRENT as u32);
+        // e_entry, e_phoff
+        header.u64(0);
+        header.u64(0);
+        header.u64(shoff);
+        // e_flags
+        header.u32(0);
+        header.u16(FILE_HEADER_SIZE as u16);
+        // e_phentsize, e_phnum
+        header.u16(0);
+        header.u16(0);
+        header.u16(SECTION_HEADER_SIZE as u16);
+        header.u16(sections.len() as u16);
+        header.u16(shstrtab_index as u16);
+        w.buf[..FILE_HEADER_SIZE].copy_from_slice(&header.buf);
+
+        Ok(w.buf)
+    }
+
+    fn section_name(&self, index: usize) -> Result<&str, ParseError> {
+        self.section_names
+            .get(&index)
+            .map(String::as_str)
+            .ok_or(ParseError::SectionNotFound { index })
+    }
+
+    // Writes a legacy map definition at the address of its symbol in the maps
+    // section.
+    fn write_map_def(
+        &self,
+        contents: &mut BTreeMap<usize, OutSection>,
+        map: &LegacyMap,
+    ) -> Result<(), ParseError> {
+        let section = match contents.entry(map.section_index) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let name = self.section_name(map.section_index)?;
+                let size = self.section_sizes.get(name).copied().unwrap_or(0);
+                entry.insert(OutSection::new(
+                    name,
+                    elf::SHT_PROGBITS,
+                    (elf::SHF_ALLOC | elf::SHF_WRITE) as u64,
+                    vec![0; size as usize],
+                    4,
+                ))
+            }
+        };
+        let sym =
+            self.symbols_by_index
+                .get(&map.symbol_index)
+                .ok_or(ParseError::InvalidSymbol {
+                    index: map.symbol_index,
+                    name: None,
+                })?;
+        let def = unsafe { bytes_of(&map.def) };
+        let len = (sym.size as usize).min(mem::size_of::<bpf_map_def>());
+        let start = sym.address as usize;
+        let end = start + len;
+        if section.data.len() < end {
+            section.data.resize(end, 0);
+            section.size = end as u64;
+        }
+        section.data[start..end].copy_from_slice(&def[..len]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::ToOwned, ffi::CString};
+    use object::SectionIndex;
+
+    use super::*;
+    use crate::{
+        generated::{
+            bpf_map_type::{BPF_MAP_TYPE_ARRAY, BPF_MAP_TYPE_HASH},
+            BPF_PSEUDO_MAP_FD,
+        },
+        maps::PinningType,
+        obj::{tests::fake_func, Function, Program, ProgramSection},
+        relocation::{Relocation, SymbolVisibility},
+    };
+
+    fn fake_insns() -> Vec<bpf_insn> {
+        let mut lddw: bpf_insn = unsafe { mem::zeroed() };
+        lddw.code = 0x18;
+        lddw.set_dst_reg(1);
+        lddw.set_src_reg(BPF_PSEUDO_MAP_FD as u8);
+        let mut exit: bpf_insn = unsafe { mem::zeroed() };
+        exit.code = 0x95;
+        vec![lddw, unsafe { mem::zeroed() }, exit]
+    }
+
+    fn fake_symbol(index: usize, name: &str, section_index: usize, kind: SymbolKind) -> Symbol {
+        Symbol {
+            index,
+            section_index: Some(section_index),
+            name: Some(name.to_owned()),
+            address: 0,
+            size: match kind {
+                SymbolKind::Text => (3 * INS_SIZE) as u64,
+                _ => mem::size_of::<bpf_map_def>() as u64,
+            },
+            is_definition: true,
+            kind,
+            binding: SymbolBinding::Global,
+            visibility: SymbolVisibility::Default,
+        }
+    }
+
+    fn fake_obj() -> Object {
+        let mut obj = Object::new(
+            Endianness::Little,
+            CString::new("Dual MIT/GPL").unwrap(),
+            KernelVersion::Version(0x050f00),
+        );
+        for (index, name) in [(2, "xdp/pass"), (3, "maps"), (4, ".data")] {
+            obj.section_names.insert(index, name.to_owned());
+        }
+        obj.section_sizes
+            .insert("maps".to_owned(), mem::size_of::<bpf_map_def>() as u64);
+        obj.symbols_by_index
+            .insert(1, fake_symbol(1, "map_1", 3, SymbolKind::Data));
+        obj.symbols_by_index
+            .insert(2, fake_symbol(2, "pass", 2, SymbolKind::Text));
+        obj.programs.insert(
+            "pass".to_owned(),
+            Program {
+                license: obj.license.clone(),
+                kernel_version: obj.kernel_version,
+                section: ProgramSection::Xdp {
+                    name: "pass".to_owned(),
+                },
+                function: Function {
+                    section_index: SectionIndex(2),
+                    ..fake_func("pass", fake_insns())
+                },
+            },
+        );
+        obj.relocations.insert(
+            SectionIndex(2),
+            [(
+                0,
+                Relocation {
+                    offset: 0,
+                    symbol_index: 1,
+                    addend: 0,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        );
+        obj.maps.insert(
+            "map_1".to_owned(),
+            Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_HASH as u32,
+                    key_size: 4,
+                    value_size: 8,
+                    max_entries: 1024,
+                    map_flags: 0,
+                    id: 0,
+                    pinning: PinningType::ByName,
+                },
+                section_index: 3,
+                symbol_index: 1,
+                data: Vec::new(),
+                kind: MapKind::Other,
+            }),
+        );
+        obj.maps.insert(
+            ".data".to_owned(),
+            Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 1,
+                    ..Default::default()
+                },
+                section_index: 4,
+                symbol_index: 0,
+                data: vec![1, 2, 3, 4],
+                kind: MapKind::Data,
+            }),
+        );
+        obj
+    }
+
+    #[test]
+    fn test_to_elf_roundtrip() {
+        let obj = fake_obj();
+        let data = obj.to_elf().unwrap();
+        let parsed = Object::parse(&data).unwrap();
+
+        assert_eq!(parsed.license, obj.license);
+        assert_eq!(parsed.kernel_version, obj.kernel_version);
+
+        let program = parsed.programs.get("pass").unwrap();
+        assert!(matches!(program.section, ProgramSection::Xdp { .. }));
+        let instructions = &program.function.instructions;
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(instructions[0].code, 0x18);
+        assert_eq!(instructions[0].src_reg(), BPF_PSEUDO_MAP_FD as u8);
+        assert_eq!(instructions[2].code, 0x95);
+
+        // the map relocation points to the map symbol
+        let rels = parsed
+            .relocations
+            .get(&program.function.section_index)
+            .unwrap();
+        assert_eq!(rels.len(), 1);
+        let rel = rels.get(&0).unwrap();
+        let sym = parsed.symbols_by_index.get(&rel.symbol_index).unwrap();
+        assert_eq!(sym.name.as_deref(), Some("map_1"));
+
+        assert_eq!(parsed.maps.len(), 2);
+        match parsed.maps.get("map_1") {
+            Some(Map::Legacy(m)) => {
+                assert_eq!(m.def.map_type, BPF_MAP_TYPE_HASH as u32);
+                assert_eq!(m.def.key_size, 4);
+                assert_eq!(m.def.value_size, 8);
+                assert_eq!(m.def.max_entries, 1024);
+                assert_eq!(m.def.pinning, PinningType::ByName);
+                assert_eq!(m.symbol_index, rel.symbol_index);
+            }
+            other => panic!("unexpected map {other:?}"),
+        }
+        let data_map = parsed.maps.get(".data").unwrap();
+        assert_eq!(data_map.kind(), MapKind::Data);
+        assert_eq!(data_map.data(), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let mut obj = fake_obj();
+        obj.symbols_by_index.get_mut(&1).unwrap().visibility = SymbolVisibility::Hidden;
+        let data = obj.to_elf().unwrap();
+        let parsed = Object::parse(&data).unwrap();
+
+        let pass = parsed
+            .symbols()
+            .find(|sym| sym.name() == Some("pass"))
+            .unwrap();
+        assert_eq!(pass.kind(), SymbolKind::Text);
+        assert_eq!(pass.binding(), SymbolBinding::Global);
+        assert_eq!(pass.visibility(), SymbolVisibility::Default);
+        assert_eq!(pass.size(), (3 * INS_SIZE) as u64);
+        assert!(pass.is_definition());
+
+        let map = parsed
+            .symbols()
+            .find(|sym| sym.name() == Some("map_1"))
+            .unwrap();
+        assert_eq!(map.kind(), SymbolKind::Data);
+        assert_eq!(map.visibility(), SymbolVisibility::Hidden);
+
+        // symbols are ordered by index
+        let indices = parsed.symbols().map(|sym| sym.index()).collect::<Vec<_>>();
+        assert!(indices.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_to_elf_missing_section_name() {
+        let mut obj = fake_obj();
+        obj.section_names.remove(&2);
+        assert!(matches!(
+            obj.to_elf(),
+            Err(ParseError::SectionNotFound { index: 2 })
+        ));
+    }
+}
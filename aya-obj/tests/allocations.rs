@@ -0,0 +1,205 @@
+//! Checks the memory allocated by the parsers.
+//!
+//! The counting allocator is the global allocator of this test binary, so that
+//! it doesn't change how the unit tests of the crate allocate.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use aya_obj::{generated::bpf_insn, Object};
+
+// Tracks the largest allocation and the peak of allocated memory of the
+// current thread while enabled.
+struct CountingAllocator;
+
+thread_local! {
+    static TRACKING: Cell<bool> = const { Cell::new(false) };
+    static LARGEST: Cell<usize> = const { Cell::new(0) };
+    static CURRENT: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if TRACKING.with(|t| t.get()) {
+            LARGEST.with(|l| l.set(l.get().max(layout.size())));
+            let current = CURRENT.with(|c| {
+                c.set(c.get() + layout.size() as isize);
+                c.get()
+            });
+            PEAK.with(|p| p.set(p.get().max(current)));
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if TRACKING.with(|t| t.get()) {
+            CURRENT.with(|c| c.set(c.get() - layout.size() as isize));
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn track<T>(f: impl FnOnce() -> T) -> T {
+    LARGEST.with(|l| l.set(0));
+    CURRENT.with(|c| c.set(0));
+    PEAK.with(|p| p.set(0));
+    TRACKING.with(|t| t.set(true));
+    let ret = f();
+    TRACKING.with(|t| t.set(false));
+    ret
+}
+
+// Returns the largest allocation made while running `f`.
+fn largest_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let ret = track(f);
+    (ret, LARGEST.with(|l| l.get()))
+}
+
+// Returns the peak amount of memory allocated while running `f`.
+fn peak_allocated<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let ret = track(f);
+    (ret, PEAK.with(|p| p.get()) as usize)
+}
+
+const INS_SIZE: usize = std::mem::size_of::<bpf_insn>();
+
+// Builds a little endian BPF relocatable file with a `license` section and one
+// XDP program section of `insn_count` exit instructions per name in `names`.
+fn xdp_elf(names: &[String], insn_count: usize) -> Vec<u8> {
+    const EHDR_SIZE: usize = 64;
+    const SHDR_SIZE: usize = 64;
+    const SHT_PROGBITS: u32 = 1;
+    const SHT_STRTAB: u32 = 3;
+    const SHF_ALLOC: u64 = 0x2;
+    const SHF_EXECINSTR: u64 = 0x4;
+
+    let mut exit = [0u8; INS_SIZE];
+    exit[0] = 0x95;
+
+    // (name, type, flags, data)
+    let mut sections = vec![(
+        "license".to_owned(),
+        SHT_PROGBITS,
+        SHF_ALLOC,
+        b"GPL\0".to_vec(),
+    )];
+    for name in names {
+        sections.push((
+            format!("xdp/{name}"),
+            SHT_PROGBITS,
+            SHF_ALLOC | SHF_EXECINSTR,
+            exit.repeat(insn_count),
+        ));
+    }
+    let mut strtab = vec![0u8];
+    let mut name_offsets = Vec::new();
+    for (name, ..) in &sections {
+        name_offsets.push(strtab.len() as u32);
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+    }
+    name_offsets.push(strtab.len() as u32);
+    strtab.extend_from_slice(b".shstrtab\0");
+    sections.push((".shstrtab".to_owned(), SHT_STRTAB, 0, strtab));
+
+    let mut data = vec![0u8; EHDR_SIZE];
+    let mut offsets = Vec::new();
+    for (.., section_data) in &sections {
+        while data.len() % 8 != 0 {
+            data.push(0);
+        }
+        offsets.push(data.len() as u64);
+        data.extend_from_slice(section_data);
+    }
+    while data.len() % 8 != 0 {
+        data.push(0);
+    }
+    let shoff = data.len() as u64;
+    // the null section header
+    data.extend_from_slice(&[0; SHDR_SIZE]);
+    for (i, (_, sh_type, sh_flags, section_data)) in sections.iter().enumerate() {
+        data.extend_from_slice(&name_offsets[i].to_le_bytes());
+        data.extend_from_slice(&sh_type.to_le_bytes());
+        data.extend_from_slice(&sh_flags.to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes()); // sh_addr
+        data.extend_from_slice(&offsets[i].to_le_bytes());
+        data.extend_from_slice(&(section_data.len() as u64).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes()); // sh_link
+        data.extend_from_slice(&0u32.to_le_bytes()); // sh_info
+        data.extend_from_slice(&8u64.to_le_bytes()); // sh_addralign
+        data.extend_from_slice(&0u64.to_le_bytes()); // sh_entsize
+    }
+
+    let shnum = sections.len() as u16 + 1;
+    let mut ehdr = Vec::with_capacity(EHDR_SIZE);
+    // ELFCLASS64, ELFDATA2LSB, EV_CURRENT
+    ehdr.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    ehdr.extend_from_slice(&1u16.to_le_bytes()); // ET_REL
+    ehdr.extend_from_slice(&247u16.to_le_bytes()); // EM_BPF
+    ehdr.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    ehdr.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    ehdr.extend_from_slice(&0u64.to_le_bytes()); // e_phoff
+    ehdr.extend_from_slice(&shoff.to_le_bytes());
+    ehdr.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    ehdr.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    ehdr.extend_from_slice(&0u16.to_le_bytes()); // e_phentsize
+    ehdr.extend_from_slice(&0u16.to_le_bytes()); // e_phnum
+    ehdr.extend_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+    ehdr.extend_from_slice(&shnum.to_le_bytes());
+    ehdr.extend_from_slice(&(shnum - 1).to_le_bytes()); // e_shstrndx
+    data[..EHDR_SIZE].copy_from_slice(&ehdr);
+
+    data
+}
+
+#[test]
+fn parse_borrowed_no_large_allocations() {
+    const INSN_COUNT: usize = 64 * 1024;
+    let data = xdp_elf(&["big".to_owned()], INSN_COUNT);
+
+    let (count, largest) = largest_allocation(|| {
+        let obj = Object::parse_borrowed(&data).unwrap();
+        let program = obj.program("big").unwrap().unwrap();
+        program.instructions().count()
+    });
+    assert_eq!(count, INSN_COUNT);
+    assert!(largest < 4096, "largest allocation was {largest} bytes");
+
+    // sanity check, the owned parser does copy the instructions
+    let (_, largest) = largest_allocation(|| Object::parse(&data).unwrap());
+    assert!(largest >= INSN_COUNT * INS_SIZE);
+}
+
+#[test]
+fn parse_streaming_peak_memory() {
+    const PROGRAM_COUNT: usize = 64;
+    const INSN_COUNT: usize = 1024;
+    let names = (0..PROGRAM_COUNT)
+        .map(|i| format!("prog{i}"))
+        .collect::<Vec<_>>();
+    let data = xdp_elf(&names, INSN_COUNT);
+
+    let (count, streaming_peak) = peak_allocated(|| {
+        Object::parse_streaming(&data)
+            .unwrap()
+            .map(|program| program.unwrap().function.instructions.len())
+            .sum::<usize>()
+    });
+    assert_eq!(count, PROGRAM_COUNT * INSN_COUNT);
+
+    let (obj, full_peak) = peak_allocated(|| Object::parse(&data).unwrap());
+    assert_eq!(obj.programs.len(), PROGRAM_COUNT);
+
+    let program_size = INSN_COUNT * INS_SIZE;
+    assert!(full_peak >= PROGRAM_COUNT * program_size);
+    assert!(
+        streaming_peak < full_peak / 4,
+        "streaming peak {streaming_peak} bytes, full peak {full_peak} bytes"
+    );
+}
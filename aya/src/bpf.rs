@@ -3,13 +3,16 @@ use std::{
     collections::{HashMap, HashSet},
     ffi::CString,
     fs, io,
-    os::{raw::c_int, unix::io::RawFd},
+    os::{
+        raw::c_int,
+        unix::{ffi::OsStrExt, io::RawFd},
+    },
     path::{Path, PathBuf},
 };
 
 use aya_obj::{
-    btf::{BtfFeatures, BtfRelocationError},
-    relocation::BpfRelocationError,
+    btf::{BtfFeatures, BtfKind, BtfRelocationError},
+    relocation::{BpfRelocationError, KsymValue},
 };
 use log::debug;
 use thiserror::Error;
@@ -25,19 +28,24 @@ use crate::{
         maps::MapKind,
         Object, ParseError, ProgramSection,
     },
+    pin::PinError,
     programs::{
-        BtfTracePoint, CgroupDevice, CgroupSkb, CgroupSkbAttachType, CgroupSock, CgroupSockAddr,
-        CgroupSockopt, CgroupSysctl, Extension, FEntry, FExit, KProbe, LircMode2, Lsm, PerfEvent,
-        ProbeKind, Program, ProgramData, ProgramError, RawTracePoint, SchedClassifier, SkLookup,
-        SkMsg, SkSkb, SkSkbKind, SockOps, SocketFilter, TracePoint, UProbe, Xdp,
+        usdt, BtfTracePoint, CgroupDevice, CgroupSkb, CgroupSkbAttachType, CgroupSock,
+        CgroupSockAddr, CgroupSockopt, CgroupSysctl, Extension, FEntry, FExit, FlowDissector, Iter,
+        KProbe, LircMode2, Lsm, PerfEvent, ProbeKind, Program, ProgramData, ProgramError,
+        RawTracePoint, SchedClassifier, SkLookup, SkMsg, SkReuseport, SkSkb, SkSkbKind, SockOps,
+        SocketFilter, Syscall, TcAttachType, Tcx, TracePoint, UProbe, Usdt, Xdp,
     },
     sys::{
-        bpf_load_btf, bpf_map_freeze, bpf_map_update_elem_ptr, is_btf_datasec_supported,
-        is_btf_decl_tag_supported, is_btf_float_supported, is_btf_func_global_supported,
-        is_btf_func_supported, is_btf_supported, is_btf_type_tag_supported, is_prog_name_supported,
-        retry_with_verifier_logs,
+        bpf_get_object, bpf_load_btf, bpf_map_lookup_elem_ptr, bpf_map_update_elem_ptr,
+        is_btf_datasec_supported, is_btf_decl_tag_supported, is_btf_float_supported,
+        is_btf_func_global_supported, is_btf_func_supported, is_btf_supported,
+        is_btf_type_tag_supported, is_prog_name_supported, retry_with_verifier_logs,
+    },
+    util::{
+        bytes_of, kernel_symbols, online_cpus, possible_cpus, VerifierLog, ONLINE_CPUS,
+        POSSIBLE_CPUS,
     },
-    util::{bytes_of, possible_cpus, VerifierLog, POSSIBLE_CPUS},
 };
 
 pub(crate) const BPF_OBJ_NAME_LEN: usize = 16;
@@ -46,23 +54,10 @@ pub(crate) const PERF_EVENT_IOC_ENABLE: c_int = AYA_PERF_EVENT_IOC_ENABLE;
 pub(crate) const PERF_EVENT_IOC_DISABLE: c_int = AYA_PERF_EVENT_IOC_DISABLE;
 pub(crate) const PERF_EVENT_IOC_SET_BPF: c_int = AYA_PERF_EVENT_IOC_SET_BPF;
 
-/// Marker trait for types that can safely be converted to and from byte slices.
-pub unsafe trait Pod: Copy + 'static {}
-
-macro_rules! unsafe_impl_pod {
-    ($($struct_name:ident),+ $(,)?) => {
-        $(
-            unsafe impl Pod for $struct_name { }
-        )+
-    }
-}
-
-unsafe_impl_pod!(i8, u8, i16, u16, i32, u32, i64, u64, u128, i128);
-
-// It only makes sense that an array of POD types is itself POD
-unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
-
-pub use aya_obj::maps::{bpf_map_def, PinningType};
+pub use aya_obj::{
+    maps::{bpf_map_def, PinningType},
+    Pod,
+};
 
 // Features implements BPF and BTF feature detection
 #[derive(Default, Debug)]
@@ -138,9 +133,43 @@ pub struct BpfLoader<'a> {
     map_pin_path: Option<PathBuf>,
     globals: HashMap<&'a str, &'a [u8]>,
     max_entries: HashMap<&'a str, u32>,
+    map_initial_data: HashMap<&'a str, &'a [u8]>,
+    existing_maps: HashMap<&'a str, ExistingMap>,
+    inner_maps: HashMap<&'a str, &'a str>,
     features: Features,
     extensions: HashSet<&'a str>,
     verifier_log_level: VerifierLogLevel,
+    btf_verifier_log_level: VerifierLogLevel,
+    use_fd_array: bool,
+}
+
+/// An existing map to use instead of creating a new one, see
+/// [BpfLoader::map_existing].
+#[derive(Debug)]
+pub enum ExistingMap {
+    /// A map file descriptor. The descriptor is duplicated, the caller keeps
+    /// ownership of it.
+    Fd(RawFd),
+    /// The path of a map pinned in bpffs.
+    PinPath(PathBuf),
+}
+
+impl From<RawFd> for ExistingMap {
+    fn from(fd: RawFd) -> ExistingMap {
+        ExistingMap::Fd(fd)
+    }
+}
+
+impl From<PathBuf> for ExistingMap {
+    fn from(path: PathBuf) -> ExistingMap {
+        ExistingMap::PinPath(path)
+    }
+}
+
+impl From<&Path> for ExistingMap {
+    fn from(path: &Path) -> ExistingMap {
+        ExistingMap::PinPath(path.to_owned())
+    }
 }
 
 bitflags! {
@@ -175,9 +204,14 @@ impl<'a> BpfLoader<'a> {
             map_pin_path: None,
             globals: HashMap::new(),
             max_entries: HashMap::new(),
+            map_initial_data: HashMap::new(),
+            existing_maps: HashMap::new(),
+            inner_maps: HashMap::new(),
             features,
             extensions: HashSet::new(),
             verifier_log_level: VerifierLogLevel::default(),
+            btf_verifier_log_level: VerifierLogLevel::DEBUG,
+            use_fd_array: false,
         }
     }
 
@@ -208,6 +242,10 @@ impl<'a> BpfLoader<'a> {
     /// Pinned maps will be loaded from `path/MAP_NAME`.
     /// The caller is responsible for ensuring the directory exists.
     ///
+    /// Maps defined with `__uint(pinning, LIBBPF_PIN_BY_NAME)` are pinned
+    /// automatically when the object is loaded. If a map is already pinned at
+    /// `path/MAP_NAME`, the pinned map is reused instead of creating a new one.
+    ///
     /// # Example
     ///
     /// ```no_run
@@ -268,6 +306,10 @@ impl<'a> BpfLoader<'a> {
     /// Overwrite the value of max_entries of the map that matches
     /// the provided name before the map is created.
     ///
+    /// Maps declared with max_entries set to 0 must be sized this way, except
+    /// for per-cpu maps and perf event arrays which default to the number of
+    /// CPUs. Loading fails with [`BpfError::MaxEntriesNotSet`] otherwise.
+    ///
     /// # Example
     ///
     /// ```no_run
@@ -284,6 +326,91 @@ impl<'a> BpfLoader<'a> {
         self
     }
 
+    /// Sets the contents of the array map with the given name.
+    ///
+    /// `data` holds the values of all the entries of the map one after the
+    /// other, so its size must be `value_size * max_entries`, with the max
+    /// entries set by [BpfLoader::set_max_entries] if any. The entries are
+    /// written right after the map is created, before programs can use it and
+    /// before `.rodata` maps are frozen. See [Object::set_map_initial_data].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let table: [u32; 4] = [1, 2, 4, 8];
+    /// let bytes = table
+    ///     .iter()
+    ///     .flat_map(|v| v.to_ne_bytes())
+    ///     .collect::<Vec<_>>();
+    /// let bpf = BpfLoader::new()
+    ///     .set_map_initial_data("TABLE", &bytes)
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn set_map_initial_data(&mut self, name: &'a str, data: &'a [u8]) -> &mut BpfLoader<'a> {
+        self.map_initial_data.insert(name, data);
+        self
+    }
+
+    /// Uses an existing map instead of creating the map with the given name.
+    ///
+    /// This is useful when maps are created, and usually pinned, by another
+    /// process and shared between programs. The map of the object is bound to
+    /// the existing map and the programs reference it. Loading fails with
+    /// [`MapError::IncompatibleMap`] if the type, key size, value size or max
+    /// entries of the existing map don't match the definition in the object.
+    ///
+    /// The initial data of `.data` and `.rodata` maps isn't written to
+    /// existing maps.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    /// use std::path::Path;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .map_existing("SHARED", Path::new("/sys/fs/bpf/shared"))
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn map_existing(
+        &mut self,
+        name: &'a str,
+        map: impl Into<ExistingMap>,
+    ) -> &mut BpfLoader<'a> {
+        self.existing_maps.insert(name, map.into());
+        self
+    }
+
+    /// Sets the map `inner` as the template of the inner maps of the map of
+    /// maps `outer`.
+    ///
+    /// The kernel needs a map with the type, key size, value size and max
+    /// entries of the inner maps to create an [`ArrayOfMaps`](crate::maps::ArrayOfMaps)
+    /// or a [`HashOfMaps`](crate::maps::HashOfMaps). `inner` is a map of the
+    /// same object, which is created before `outer`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .set_inner_map("OUTER", "INNER_TEMPLATE")
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn set_inner_map(&mut self, outer: &'a str, inner: &'a str) -> &mut BpfLoader<'a> {
+        self.inner_maps.insert(outer, inner);
+        self
+    }
+
     /// Treat the provided program as an [`Extension`]
     ///
     /// When attempting to load the program with the provided `name`
@@ -324,6 +451,53 @@ impl<'a> BpfLoader<'a> {
         self
     }
 
+    /// Sets the BTF verifier log level.
+    ///
+    /// The log is captured when the kernel rejects the BTF of the object and
+    /// is returned in [`BtfError::LoadError`]. Defaults to
+    /// [`VerifierLogLevel::DEBUG`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::{BpfLoader, VerifierLogLevel};
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .btf_verifier_log_level(VerifierLogLevel::VERBOSE)
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn btf_verifier_log_level(&mut self, level: VerifierLogLevel) -> &mut BpfLoader<'a> {
+        self.btf_verifier_log_level = level;
+        self
+    }
+
+    /// Passes the map file descriptors used by programs in a separate array.
+    ///
+    /// By default map file descriptors are embedded in the instructions that
+    /// reference them. When enabled, the file descriptors are passed to the
+    /// kernel in the `fd_array` field of the program load attributes and
+    /// instructions reference maps by their index in the array instead.
+    ///
+    /// The minimum kernel version required to use this feature is 5.14.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .use_fd_array(true)
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn use_fd_array(&mut self, enabled: bool) -> &mut BpfLoader<'a> {
+        self.use_fd_array = enabled;
+        self
+    }
+
     /// Loads eBPF bytecode from a file.
     ///
     /// # Examples
@@ -356,13 +530,14 @@ impl<'a> BpfLoader<'a> {
     /// ```
     pub fn load(&mut self, data: &[u8]) -> Result<Bpf, BpfError> {
         let verifier_log_level = self.verifier_log_level.bits;
+        let use_fd_array = self.use_fd_array;
         let mut obj = Object::parse(data)?;
         obj.patch_map_data(self.globals.clone())?;
 
         let btf_fd = if let Some(ref btf) = self.features.btf {
             if let Some(btf) = obj.fixup_and_sanitize_btf(btf)? {
                 // load btf to the kernel
-                Some(load_btf(btf.to_bytes())?)
+                Some(load_btf(btf.to_bytes(), self.btf_verifier_log_level.bits)?)
             } else {
                 None
             }
@@ -373,54 +548,88 @@ impl<'a> BpfLoader<'a> {
         if let Some(btf) = &self.btf {
             obj.relocate_btf(btf)?;
         }
+        // the size of the initial data depends on the max entries
+        for (name, obj) in obj.maps.iter_mut() {
+            if let Some(size) = self.max_entries.get(name.as_str()) {
+                obj.set_max_entries(*size);
+            }
+        }
+        for (name, data) in &self.map_initial_data {
+            obj.set_map_initial_data(name, data.to_vec())?;
+        }
+        let mut initial_data = obj
+            .maps
+            .keys()
+            .filter_map(|name| Some((name.clone(), obj.map_initial_data(name)?.to_vec())))
+            .collect::<HashMap<_, _>>();
+
+        // the templates of the inner maps are created before the maps of maps
+        let mut map_objs = obj.maps.drain().collect::<Vec<_>>();
+        map_objs.sort_by_key(|(_, obj)| is_map_of_maps(obj.map_type()));
+
         let mut maps = HashMap::new();
-        for (name, mut obj) in obj.maps.drain() {
-            match self.max_entries.get(name.as_str()) {
-                Some(size) => obj.set_max_entries(*size),
-                None => {
-                    if obj.map_type() == BPF_MAP_TYPE_PERF_EVENT_ARRAY as u32
-                        && obj.max_entries() == 0
-                    {
-                        obj.set_max_entries(
-                            possible_cpus()
-                                .map_err(|error| BpfError::FileError {
-                                    path: PathBuf::from(POSSIBLE_CPUS),
-                                    error,
-                                })?
-                                .len() as u32,
-                        );
-                    }
+        for (name, mut obj) in map_objs {
+            if max_entries_must_be_zero(obj.map_type()) {
+                if obj.max_entries() != 0 {
+                    debug!(
+                        "ignoring max_entries = {} of local storage map {}",
+                        obj.max_entries(),
+                        name
+                    );
+                    obj.set_max_entries(0);
+                }
+            } else if !self.max_entries.contains_key(name.as_str()) && obj.max_entries() == 0 {
+                if let Some(max_entries) = default_max_entries(obj.map_type())? {
+                    obj.set_max_entries(max_entries);
+                } else if !self.existing_maps.contains_key(name.as_str()) {
+                    return Err(BpfError::MaxEntriesNotSet { name });
                 }
             }
             let mut map = MapData {
                 obj,
                 fd: None,
                 pinned: false,
+                name: Some(name.clone()),
                 btf_fd,
             };
+            if let Some(existing) = self.existing_maps.get(name.as_str()) {
+                let fd = match existing {
+                    ExistingMap::Fd(fd) => dup_fd(*fd)?,
+                    ExistingMap::PinPath(path) => {
+                        map.pinned = true;
+                        open_pinned_map(path)?
+                    }
+                };
+                if let Err(e) = map.adopt(&name, fd) {
+                    // the descriptor is only owned by the map once adopted
+                    unsafe { libc::close(fd) };
+                    return Err(e.into());
+                }
+                maps.insert(name, map);
+                continue;
+            }
+            let inner_map_fd = if is_map_of_maps(map.obj.map_type()) {
+                let inner = self
+                    .inner_maps
+                    .get(name.as_str())
+                    .ok_or_else(|| BpfError::InnerMapNotSet { name: name.clone() })?;
+                let fd = maps.get(*inner).and_then(|inner: &MapData| inner.fd);
+                Some(fd.ok_or_else(|| BpfError::InnerMapNotFound {
+                    name: name.clone(),
+                    inner: inner.to_string(),
+                })?)
+            } else {
+                None
+            };
             let fd = match map.obj.pinning() {
                 PinningType::ByName => {
                     let path = match &self.map_pin_path {
                         Some(p) => p,
                         None => return Err(BpfError::NoPinPath),
                     };
-                    // try to open map in case it's already pinned
-                    match map.open_pinned(&name, path) {
-                        Ok(fd) => {
-                            map.pinned = true;
-                            fd as RawFd
-                        }
-                        Err(_) => {
-                            let fd = map.create(&name)?;
-                            map.pin(&name, path).map_err(|error| MapError::PinError {
-                                name: Some(name.to_string()),
-                                error,
-                            })?;
-                            fd
-                        }
-                    }
+                    map.create_pinned(&name, path, inner_map_fd)?
                 }
-                PinningType::None => map.create(&name)?,
+                PinningType::None => map.create_with_inner_map(&name, inner_map_fd)?,
             };
             if !map.obj.data().is_empty() && map.obj.kind() != MapKind::Bss {
                 bpf_map_update_elem_ptr(fd, &0 as *const _, map.obj.data_mut().as_mut_ptr(), 0)
@@ -429,11 +638,11 @@ impl<'a> BpfLoader<'a> {
                         io_error,
                     })?;
             }
+            if let Some(data) = initial_data.remove(&name) {
+                write_initial_data(fd, map.obj.value_size(), data)?;
+            }
             if map.obj.kind() == MapKind::Rodata {
-                bpf_map_freeze(fd).map_err(|(_, io_error)| MapError::SyscallError {
-                    call: "bpf_map_freeze".to_owned(),
-                    io_error,
-                })?;
+                map.freeze()?;
             }
             maps.insert(name, map);
         }
@@ -442,8 +651,45 @@ impl<'a> BpfLoader<'a> {
             maps.iter()
                 .map(|(s, data)| (s.as_str(), data.fd, &data.obj)),
         )?;
+        if obj.externs().next().is_some() {
+            // typeless ksyms resolve to their address, typed ones to their id
+            // in the kernel BTF
+            let kallsyms: HashMap<String, u64> = if obj.externs().any(|ext| !ext.is_typed()) {
+                kernel_symbols()
+                    .map_err(|error| BpfError::FileError {
+                        path: PathBuf::from("/proc/kallsyms"),
+                        error,
+                    })?
+                    .into_iter()
+                    // addresses read as 0 when they're hidden by kptr_restrict
+                    .filter(|(address, _)| *address != 0)
+                    .map(|(address, name)| (name, address))
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+            let btf = self.btf.as_deref();
+            obj.relocate_ksyms(|ext| {
+                if ext.is_typed() {
+                    btf?.id_by_type_name_kind(ext.name(), BtfKind::Var)
+                        .ok()
+                        .map(KsymValue::BtfId)
+                } else {
+                    kallsyms.get(ext.name()).copied().map(KsymValue::Address)
+                }
+            })?;
+        }
         obj.relocate_calls()?;
 
+        let usdt_maps = match (
+            maps.get(usdt::USDT_SPECS_MAP).and_then(|map| map.fd),
+            maps.get(usdt::USDT_IP_TO_SPEC_ID_MAP)
+                .and_then(|map| map.fd),
+        ) {
+            (Some(specs_fd), Some(ip_to_spec_id_fd)) => Some((specs_fd, ip_to_spec_id_fd)),
+            _ => None,
+        };
+
         let programs = obj
             .programs
             .drain()
@@ -457,65 +703,175 @@ impl<'a> BpfLoader<'a> {
 
                 let program = if self.extensions.contains(name.as_str()) {
                     Program::Extension(Extension {
-                        data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                        data: ProgramData::new(
+                            prog_name,
+                            obj,
+                            btf_fd,
+                            verifier_log_level,
+                            use_fd_array,
+                        ),
                     })
                 } else {
                     match &section {
                         ProgramSection::KProbe { .. } => Program::KProbe(KProbe {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
+                            kind: ProbeKind::KProbe,
+                        }),
+                        ProgramSection::KProbeMulti { .. } => Program::KProbe(KProbe {
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                             kind: ProbeKind::KProbe,
                         }),
+                        ProgramSection::KRetProbeMulti { .. } => Program::KProbe(KProbe {
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
+                            kind: ProbeKind::KRetProbe,
+                        }),
                         ProgramSection::KRetProbe { .. } => Program::KProbe(KProbe {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                             kind: ProbeKind::KRetProbe,
                         }),
                         ProgramSection::UProbe { .. } => Program::UProbe(UProbe {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                             kind: ProbeKind::UProbe,
                         }),
                         ProgramSection::URetProbe { .. } => Program::UProbe(UProbe {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                             kind: ProbeKind::URetProbe,
                         }),
                         ProgramSection::TracePoint { .. } => Program::TracePoint(TracePoint {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::SocketFilter { .. } => {
                             Program::SocketFilter(SocketFilter {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
                             })
                         }
                         ProgramSection::Xdp { .. } => Program::Xdp(Xdp {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::SkMsg { .. } => Program::SkMsg(SkMsg {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::CgroupSysctl { .. } => {
                             Program::CgroupSysctl(CgroupSysctl {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
                             })
                         }
                         ProgramSection::CgroupSockopt { attach_type, .. } => {
                             Program::CgroupSockopt(CgroupSockopt {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
                                 attach_type: *attach_type,
                             })
                         }
                         ProgramSection::SkSkbStreamParser { .. } => Program::SkSkb(SkSkb {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                             kind: SkSkbKind::StreamParser,
                         }),
                         ProgramSection::SkSkbStreamVerdict { .. } => Program::SkSkb(SkSkb {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                             kind: SkSkbKind::StreamVerdict,
                         }),
                         ProgramSection::SockOps { .. } => Program::SockOps(SockOps {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::SchedClassifier { .. } => {
                             Program::SchedClassifier(SchedClassifier {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
                                 name: unsafe {
                                     CString::from_vec_unchecked(Vec::from(name.clone()))
                                         .into_boxed_c_str()
@@ -523,65 +879,218 @@ impl<'a> BpfLoader<'a> {
                             })
                         }
                         ProgramSection::CgroupSkb { .. } => Program::CgroupSkb(CgroupSkb {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                             expected_attach_type: None,
                         }),
                         ProgramSection::CgroupSkbIngress { .. } => Program::CgroupSkb(CgroupSkb {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                             expected_attach_type: Some(CgroupSkbAttachType::Ingress),
                         }),
                         ProgramSection::CgroupSkbEgress { .. } => Program::CgroupSkb(CgroupSkb {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                             expected_attach_type: Some(CgroupSkbAttachType::Egress),
                         }),
                         ProgramSection::CgroupSockAddr { attach_type, .. } => {
                             Program::CgroupSockAddr(CgroupSockAddr {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
                                 attach_type: *attach_type,
                             })
                         }
                         ProgramSection::LircMode2 { .. } => Program::LircMode2(LircMode2 {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::PerfEvent { .. } => Program::PerfEvent(PerfEvent {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::RawTracePoint { .. } => {
                             Program::RawTracePoint(RawTracePoint {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
                             })
                         }
                         ProgramSection::Lsm { .. } => Program::Lsm(Lsm {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::BtfTracePoint { .. } => {
                             Program::BtfTracePoint(BtfTracePoint {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
                             })
                         }
                         ProgramSection::FEntry { .. } => Program::FEntry(FEntry {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::FExit { .. } => Program::FExit(FExit {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::Extension { .. } => Program::Extension(Extension {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::SkLookup { .. } => Program::SkLookup(SkLookup {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
                         }),
                         ProgramSection::CgroupSock { attach_type, .. } => {
                             Program::CgroupSock(CgroupSock {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
                                 attach_type: *attach_type,
                             })
                         }
                         ProgramSection::CgroupDevice { .. } => {
                             Program::CgroupDevice(CgroupDevice {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
                             })
                         }
+                        ProgramSection::Iter { .. } => Program::Iter(Iter {
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
+                        }),
+                        ProgramSection::Syscall { .. } => Program::Syscall(Syscall {
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
+                        }),
+                        ProgramSection::Tcx { attach_type, .. } => Program::Tcx(Tcx {
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
+                            attach_type: *attach_type,
+                        }),
+                        ProgramSection::SkReuseport { migrate, .. } => {
+                            Program::SkReuseport(SkReuseport {
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
+                                migrate: *migrate,
+                            })
+                        }
+                        ProgramSection::FlowDissector { .. } => {
+                            Program::FlowDissector(FlowDissector {
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    use_fd_array,
+                                ),
+                            })
+                        }
+                        ProgramSection::Usdt { .. } => Program::Usdt(Usdt {
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                use_fd_array,
+                            ),
+                            maps: usdt_maps.and_then(|(specs_fd, ip_to_spec_id_fd)| {
+                                usdt::dup_maps(specs_fd, ip_to_spec_id_fd)
+                            }),
+                        }),
                     }
                 };
                 (name, program)
@@ -596,6 +1105,85 @@ impl<'a> BpfLoader<'a> {
     }
 }
 
+// Returns the number of entries of maps declared with max_entries = 0: the
+// number of CPUs for per-cpu maps and perf event arrays, None for the other
+// maps.
+fn default_max_entries(map_type: u32) -> Result<Option<u32>, BpfError> {
+    let (cpus, path) = match bpf_map_type::try_from(map_type) {
+        Ok(BPF_MAP_TYPE_PERF_EVENT_ARRAY) => (possible_cpus(), POSSIBLE_CPUS),
+        Ok(BPF_MAP_TYPE_PERCPU_ARRAY | BPF_MAP_TYPE_PERCPU_HASH | BPF_MAP_TYPE_LRU_PERCPU_HASH) => {
+            (online_cpus(), ONLINE_CPUS)
+        }
+        _ => return Ok(None),
+    };
+    let cpus = cpus.map_err(|error| BpfError::FileError {
+        path: PathBuf::from(path),
+        error,
+    })?;
+    Ok(Some(cpus.len() as u32))
+}
+
+fn is_map_of_maps(map_type: u32) -> bool {
+    map_type == BPF_MAP_TYPE_ARRAY_OF_MAPS as u32 || map_type == BPF_MAP_TYPE_HASH_OF_MAPS as u32
+}
+
+// Local storage maps are sized by the objects they're attached to and must be
+// created with max_entries = 0.
+fn max_entries_must_be_zero(map_type: u32) -> bool {
+    matches!(
+        bpf_map_type::try_from(map_type),
+        Ok(BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED
+            | BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE
+            | BPF_MAP_TYPE_SK_STORAGE
+            | BPF_MAP_TYPE_INODE_STORAGE
+            | BPF_MAP_TYPE_TASK_STORAGE
+            | BPF_MAP_TYPE_CGRP_STORAGE)
+    )
+}
+
+// Writes `data` to the array map `fd`, one value_size chunk per entry.
+fn write_initial_data(fd: RawFd, value_size: u32, mut data: Vec<u8>) -> Result<(), MapError> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    for (index, value) in data.chunks_exact_mut(value_size as usize).enumerate() {
+        let index = index as u32;
+        bpf_map_update_elem_ptr(fd, &index, value.as_mut_ptr(), 0).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+    }
+    Ok(())
+}
+
+fn dup_fd(fd: RawFd) -> Result<RawFd, MapError> {
+    match unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) } {
+        fd if fd < 0 => Err(MapError::SyscallError {
+            call: "fcntl".to_owned(),
+            io_error: io::Error::last_os_error(),
+        }),
+        fd => Ok(fd),
+    }
+}
+
+fn open_pinned_map(path: &Path) -> Result<RawFd, MapError> {
+    let path_string =
+        CString::new(path.as_os_str().as_bytes()).map_err(|e| MapError::PinError {
+            name: None,
+            error: PinError::InvalidPinPath {
+                error: e.to_string(),
+            },
+        })?;
+    bpf_get_object(&path_string)
+        .map(|fd| fd as RawFd)
+        .map_err(|(_, io_error)| MapError::SyscallError {
+            call: "BPF_OBJ_GET".to_owned(),
+            io_error,
+        })
+}
+
 fn parse_map(data: (String, MapData)) -> Result<(String, Map), BpfError> {
     let name = data.0;
     let map = data.1;
@@ -619,6 +1207,11 @@ fn parse_map(data: (String, MapData)) -> Result<(String, Map), BpfError> {
         BPF_MAP_TYPE_STACK => Ok(Map::Stack(map)),
         BPF_MAP_TYPE_STACK_TRACE => Ok(Map::StackTraceMap(map)),
         BPF_MAP_TYPE_QUEUE => Ok(Map::Queue(map)),
+        BPF_MAP_TYPE_REUSEPORT_SOCKARRAY => Ok(Map::ReusePortSockArray(map)),
+        BPF_MAP_TYPE_ARRAY_OF_MAPS => Ok(Map::ArrayOfMaps(map)),
+        BPF_MAP_TYPE_HASH_OF_MAPS => Ok(Map::HashOfMaps(map)),
+        BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED => Ok(Map::CgroupStorage(map)),
+        BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE => Ok(Map::PerCpuCgroupStorage(map)),
         m => Err(BpfError::MapError(MapError::InvalidMapType {
             map_type: m as u32,
         })),
@@ -743,6 +1336,48 @@ impl Bpf {
         self.maps.iter().map(|(name, map)| (name.as_str(), map))
     }
 
+    /// Returns the current contents of a read-only data section map.
+    ///
+    /// `section` is the name of the section the map was created from, eg
+    /// `.rodata`. The bytes are read back from the kernel, so they reflect
+    /// the values the loaded programs see, including any set with
+    /// [`BpfLoader::set_global`]. Reading is possible even after the map has
+    /// been frozen.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// let rodata = bpf.rodata_bytes(".rodata")?;
+    /// println!("rodata: {:x?}", rodata);
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn rodata_bytes(&self, section: &str) -> Result<Vec<u8>, MapError> {
+        let map = match self.maps.get(section) {
+            Some(Map::Array(map)) if map.obj.kind() == MapKind::Rodata => map,
+            Some(map) => {
+                return Err(MapError::InvalidMapType {
+                    map_type: map.map_type(),
+                })
+            }
+            None => {
+                return Err(MapError::InvalidName {
+                    name: section.to_owned(),
+                })
+            }
+        };
+        let fd = map.fd_or_err()?;
+
+        let mut value = vec![0u8; map.obj.value_size() as usize];
+        match bpf_map_lookup_elem_ptr(fd, Some(&0u32), value.as_mut_ptr(), 0) {
+            Ok(Some(())) => Ok(value),
+            Ok(None) => Err(MapError::KeyNotFound),
+            Err((_, io_error)) => Err(MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            }),
+        }
+    }
+
     /// Returns a reference to the program with the given name.
     ///
     /// You can use this to inspect a program and its properties. To load and attach a program, use
@@ -823,6 +1458,138 @@ impl Bpf {
     pub fn programs_mut(&mut self) -> impl Iterator<Item = (&str, &mut Program)> {
         self.programs.iter_mut().map(|(s, p)| (s.as_str(), p))
     }
+
+    /// Reloads the program `name` from the object code in `data`.
+    ///
+    /// Only the program `name` is taken from `data`. Its references to maps
+    /// are relocated against the maps of this `Bpf`, which keep their file
+    /// descriptors and their contents: every map referenced by `data` must
+    /// already exist in this `Bpf`, under the same name. If the program is
+    /// loaded, the new code is loaded in place of the old one, otherwise it's
+    /// used the next time the program is loaded.
+    ///
+    /// The links of an attached program are atomically moved to the new code,
+    /// so that no event is missed and the link ids returned when attaching
+    /// remain valid. This is supported by the programs attached with a
+    /// `bpf_link` that can be updated (XDP, TCX, cgroup, sk_lookup and
+    /// iterator programs), with `bpf_prog_attach` (cgroup, sock map and flow
+    /// dissector programs), with netlink (XDP and tc programs) or with a socket
+    /// option (socket filter and reuseport programs). Perf event based
+    /// programs (kprobes, uprobes, USDT probes, tracepoints and perf events),
+    /// tracing programs (raw and BTF tracepoints, fentry, fexit, LSM and
+    /// extension programs) and lirc programs remain bound to the program they
+    /// were attached with: they must be detached before the reload and
+    /// attached again after it.
+    ///
+    /// CO-RE relocations are applied against the BTF of the running kernel,
+    /// but the BTF of `data` isn't loaded, so the new code is loaded without
+    /// func and line info.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BpfError::MapNotFound`] if `data` references a map that
+    /// doesn't exist in this `Bpf`, and [`ProgramError::AlreadyAttached`],
+    /// wrapped in [`BpfError::ProgramError`], if the links of the program
+    /// can't be moved to the new code. If loading the new code or moving a
+    /// link fails, the program keeps running the old code.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use std::fs;
+    /// use aya::programs::{Xdp, XdpFlags};
+    ///
+    /// let program: &mut Xdp = bpf.program_mut("prog").unwrap().try_into()?;
+    /// program.load()?;
+    /// program.attach("eth0", XdpFlags::default())?;
+    ///
+    /// // ...
+    ///
+    /// // eth0 runs the new code from now on
+    /// let data = fs::read("file.o")?;
+    /// bpf.reload_program("prog", &data)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn reload_program(&mut self, name: &str, data: &[u8]) -> Result<(), BpfError> {
+        let mut obj = Object::parse(data)?;
+        if let Ok(btf) = Btf::from_sys_fs() {
+            obj.relocate_btf(&btf)?;
+        }
+
+        let map_objs = obj.maps.drain().collect::<HashMap<_, _>>();
+        let mut maps = Vec::with_capacity(map_objs.len());
+        for (map_name, map_obj) in &map_objs {
+            let map = self
+                .maps
+                .get(map_name)
+                .ok_or_else(|| BpfError::MapNotFound {
+                    name: map_name.clone(),
+                })?;
+            maps.push((map_name.as_str(), map.data().fd, map_obj));
+        }
+        obj.relocate_maps(maps.into_iter())?;
+        obj.relocate_calls()?;
+
+        let not_found = || BpfError::ProgramNotFound {
+            name: name.to_owned(),
+        };
+        let prog_obj = obj.programs.remove(name).ok_or_else(not_found)?;
+        let program = self.programs.get_mut(name).ok_or_else(not_found)?;
+        program.reload(prog_obj)?;
+        Ok(())
+    }
+
+    /// Detaches the XDP program and the tc ingress and egress filters attached
+    /// to the interface with the given `if_index`.
+    ///
+    /// Only the [`Xdp`] and [`SchedClassifier`] programs loaded by this
+    /// instance are detached, unless `force` is true in which case everything
+    /// attached to the interface is detached, including programs attached by
+    /// other processes. Filters attached to custom tc parents are left alone.
+    ///
+    /// Programs are matched by the id the kernel gave them when they were
+    /// loaded by this instance. Programs left behind by a previous instance of
+    /// the process have other ids, even when they're loaded from the same
+    /// object file, so they're only detached when `force` is true.
+    ///
+    /// # Errors
+    ///
+    /// A failure to detach the XDP program or to list or detach the filters of
+    /// a tc hook doesn't stop the other programs from being detached. All the
+    /// failures are returned together in [`ProgramError::DetachErrors`].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// // detach anything a previous instance of this process left behind
+    /// bpf.detach_all(2, true)?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn detach_all(&self, if_index: u32, force: bool) -> Result<(), ProgramError> {
+        let owned = self
+            .programs
+            .values()
+            .filter_map(|program| match program {
+                Program::Xdp(p) => p.data.prog_id().ok(),
+                Program::SchedClassifier(p) => p.data.prog_id().ok(),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        let owned = |id: u32| force || owned.contains(&id);
+
+        let mut errors = Vec::new();
+        crate::programs::xdp::detach_interface_programs(if_index, owned, &mut errors);
+        for attach_type in [TcAttachType::Ingress, TcAttachType::Egress] {
+            crate::programs::tc::detach_bpf_filters(if_index, attach_type, owned, &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ProgramError::DetachErrors { errors })
+        }
+    }
 }
 
 /// The error type returned by [`Bpf::load_file`] and [`Bpf::load`].
@@ -876,6 +1643,43 @@ pub enum BpfError {
     #[error("no BTF parsed for object")]
     NoBTF,
 
+    /// A map was declared with max_entries = 0 and no size was provided
+    #[error("map `{name}` has max_entries set to 0, set it with `BpfLoader::set_max_entries`")]
+    MaxEntriesNotSet {
+        /// The map name
+        name: String,
+    },
+
+    /// The program to reload doesn't exist
+    #[error("program `{name}` not found")]
+    ProgramNotFound {
+        /// The program name
+        name: String,
+    },
+
+    /// A map of the reloaded object doesn't exist
+    #[error("map `{name}` not found")]
+    MapNotFound {
+        /// The map name
+        name: String,
+    },
+
+    /// A map of maps was declared without the template of its inner maps
+    #[error("map `{name}` is a map of maps, set its inner map with `BpfLoader::set_inner_map`")]
+    InnerMapNotSet {
+        /// The map name
+        name: String,
+    },
+
+    /// The template of the inner maps of a map of maps doesn't exist
+    #[error("inner map `{inner}` of map `{name}` not found")]
+    InnerMapNotFound {
+        /// The map name
+        name: String,
+        /// The name of the inner map
+        inner: String,
+    },
+
     #[error("map error")]
     /// A map error
     MapError(#[from] MapError),
@@ -885,11 +1689,16 @@ pub enum BpfError {
     ProgramError(#[from] ProgramError),
 }
 
-fn load_btf(raw_btf: Vec<u8>) -> Result<RawFd, BtfError> {
+fn load_btf(raw_btf: Vec<u8>, verifier_log_level: u32) -> Result<RawFd, BtfError> {
     let mut logger = VerifierLog::new();
-    let ret = retry_with_verifier_logs(10, &mut logger, |logger| {
-        bpf_load_btf(raw_btf.as_slice(), logger)
-    });
+    let ret = if verifier_log_level == 0 {
+        // there's no log to capture, so retrying with a larger buffer is pointless
+        bpf_load_btf(raw_btf.as_slice(), &mut logger, verifier_log_level)
+    } else {
+        retry_with_verifier_logs(10, &mut logger, |logger| {
+            bpf_load_btf(raw_btf.as_slice(), logger, verifier_log_level)
+        })
+    };
     match ret {
         Ok(fd) => Ok(fd as RawFd),
         Err((_, io_error)) => {
@@ -904,3 +1713,435 @@ fn load_btf(raw_btf: Vec<u8>) -> Result<RawFd, BtfError> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, fs::File, io, os::unix::io::IntoRawFd, ptr, slice};
+
+    use libc::EINVAL;
+
+    use super::*;
+    use crate::{
+        generated::{bpf_cmd, bpf_insn, BPF_PSEUDO_MAP_FD},
+        obj::{self, maps::LegacyMap},
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    #[test]
+    fn test_load_btf_error_with_log() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_BTF_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_7 };
+                if u.btf_log_size > 0 {
+                    assert_eq!(u.btf_log_level, VerifierLogLevel::VERBOSE.bits);
+                    let log = b"[1] STRUCT foo size=4 vlen=1 Invalid member\0";
+                    unsafe {
+                        ptr::copy_nonoverlapping(log.as_ptr(), u.btf_log_buf as *mut u8, log.len())
+                    };
+                }
+                Err((-1, io::Error::from_raw_os_error(EINVAL)))
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let ret = load_btf(vec![0; 24], VerifierLogLevel::VERBOSE.bits);
+        assert!(matches!(
+            ret,
+            Err(BtfError::LoadError { io_error, verifier_log })
+                if io_error.raw_os_error() == Some(EINVAL)
+                    && verifier_log == "[1] STRUCT foo size=4 vlen=1 Invalid member"
+        ));
+    }
+
+    #[test]
+    fn test_load_btf_log_disabled() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_BTF_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_7 };
+                assert_eq!(u.btf_log_level, 0);
+                assert_eq!(u.btf_log_buf, 0);
+                Err((-1, io::Error::from_raw_os_error(EINVAL)))
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let ret = load_btf(vec![0; 24], VerifierLogLevel::DISABLE.bits);
+        assert!(matches!(
+            ret,
+            Err(BtfError::LoadError { verifier_log, .. }) if verifier_log == "[none]"
+        ));
+    }
+
+    #[test]
+    fn test_write_initial_data() {
+        thread_local! {
+            static ENTRIES: RefCell<Vec<(u32, Vec<u8>)>> = RefCell::new(Vec::new());
+        }
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                assert_eq!(u.map_fd, 42);
+                let key = unsafe { *(u.key as *const u32) };
+                let value =
+                    unsafe { slice::from_raw_parts(u.__bindgen_anon_1.value as *const u8, 2) };
+                ENTRIES.with(|e| e.borrow_mut().push((key, value.to_vec())));
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        write_initial_data(42, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        ENTRIES.with(|e| {
+            assert_eq!(
+                *e.borrow(),
+                vec![(0, vec![1, 2]), (1, vec![3, 4]), (2, vec![5, 6])]
+            )
+        });
+    }
+
+    #[test]
+    fn test_percpu_max_entries_zero() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_CREATE,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_1 };
+                assert_eq!(u.map_type, BPF_MAP_TYPE_PERCPU_ARRAY as u32);
+                assert_eq!(u.max_entries, online_cpus().unwrap().len() as u32);
+                Ok(42)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let mut obj = obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: BPF_MAP_TYPE_PERCPU_ARRAY as u32,
+                key_size: 4,
+                value_size: 8,
+                max_entries: 0,
+                ..Default::default()
+            },
+            section_index: 0,
+            symbol_index: 0,
+            data: Vec::new(),
+            kind: MapKind::Other,
+        });
+        let max_entries = default_max_entries(obj.map_type()).unwrap().unwrap();
+        assert!(max_entries > 0);
+        obj.set_max_entries(max_entries);
+
+        let mut map = MapData {
+            obj,
+            fd: None,
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        map.create("foo").unwrap();
+    }
+
+    #[test]
+    fn test_max_entries_zero() {
+        assert!(matches!(
+            default_max_entries(BPF_MAP_TYPE_HASH as u32),
+            Ok(None)
+        ));
+        assert!(!max_entries_must_be_zero(BPF_MAP_TYPE_HASH as u32));
+        assert!(matches!(
+            default_max_entries(BPF_MAP_TYPE_SK_STORAGE as u32),
+            Ok(None)
+        ));
+        assert!(max_entries_must_be_zero(BPF_MAP_TYPE_SK_STORAGE as u32));
+        assert!(max_entries_must_be_zero(
+            BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED as u32
+        ));
+    }
+
+    // Builds a relocatable ELF object with a `socket/prog` program that loads
+    // the address of the legacy map `map_1`.
+    fn fake_object_with_map() -> Vec<u8> {
+        fn section(
+            out: &mut Vec<u8>,
+            name: u32,
+            ty: u32,
+            flags: u64,
+            (offset, size): (usize, usize),
+            (link, info): (u32, u32),
+            entsize: u64,
+        ) {
+            out.extend_from_slice(&name.to_le_bytes());
+            out.extend_from_slice(&ty.to_le_bytes());
+            out.extend_from_slice(&flags.to_le_bytes());
+            out.extend_from_slice(&0u64.to_le_bytes());
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            out.extend_from_slice(&(size as u64).to_le_bytes());
+            out.extend_from_slice(&link.to_le_bytes());
+            out.extend_from_slice(&info.to_le_bytes());
+            out.extend_from_slice(&8u64.to_le_bytes());
+            out.extend_from_slice(&entsize.to_le_bytes());
+        }
+        fn symbol(out: &mut Vec<u8>, name: u32, info: u8, shndx: u16, size: u64) {
+            out.extend_from_slice(&name.to_le_bytes());
+            out.extend_from_slice(&[info, 0]);
+            out.extend_from_slice(&shndx.to_le_bytes());
+            out.extend_from_slice(&0u64.to_le_bytes());
+            out.extend_from_slice(&size.to_le_bytes());
+        }
+
+        let strtab = b"\0.strtab\0socket/prog\0maps\0.symtab\0.relsocket/prog\0prog\0map_1\0";
+        let name = |n: &str| {
+            strtab
+                .windows(n.len() + 2)
+                .position(|w| w[0] == 0 && &w[1..=n.len()] == n.as_bytes() && w[n.len() + 1] == 0)
+                .unwrap() as u32
+                + 1
+        };
+
+        let mut lddw: bpf_insn = unsafe { std::mem::zeroed() };
+        lddw.code = 0x18;
+        lddw.set_dst_reg(1);
+        let mut exit: bpf_insn = unsafe { std::mem::zeroed() };
+        exit.code = 0x95;
+        let insns = [lddw, unsafe { std::mem::zeroed() }, exit];
+        let code = unsafe {
+            slice::from_raw_parts(insns.as_ptr() as *const u8, std::mem::size_of_val(&insns))
+        };
+
+        let mut maps = Vec::new();
+        for field in [BPF_MAP_TYPE_ARRAY as u32, 4, 8, 1, 0] {
+            maps.extend_from_slice(&field.to_le_bytes());
+        }
+
+        let mut symtab = vec![0; 24];
+        // STB_GLOBAL | STT_FUNC in the program section
+        symbol(&mut symtab, name("prog"), 0x12, 2, code.len() as u64);
+        // STB_GLOBAL | STT_OBJECT in the maps section
+        symbol(&mut symtab, name("map_1"), 0x11, 3, maps.len() as u64);
+
+        // R_BPF_64_64 of map_1 at the lddw instruction
+        let mut rel = 0u64.to_le_bytes().to_vec();
+        rel.extend_from_slice(&((2u64 << 32) | 1).to_le_bytes());
+
+        let mut data = vec![0; 64];
+        let mut ranges = Vec::new();
+        for contents in [&strtab[..], code, &maps, &symtab, &rel] {
+            data.resize(data.len().next_multiple_of(8), 0);
+            ranges.push((data.len(), contents.len()));
+            data.extend_from_slice(contents);
+        }
+        data.resize(data.len().next_multiple_of(8), 0);
+        let shoff = data.len();
+
+        let rel_name = name(".relsocket/prog");
+        section(&mut data, 0, 0, 0, (0, 0), (0, 0), 0);
+        section(&mut data, name(".strtab"), 3, 0, ranges[0], (0, 0), 0);
+        section(&mut data, name("socket/prog"), 1, 0x6, ranges[1], (0, 0), 0);
+        section(&mut data, name("maps"), 1, 0x3, ranges[2], (0, 0), 0);
+        section(&mut data, name(".symtab"), 2, 0, ranges[3], (1, 1), 24);
+        section(&mut data, rel_name, 9, 0, ranges[4], (4, 2), 16);
+
+        let header = &mut data[..64];
+        header[..8].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+        // ET_REL, EM_BPF, EV_CURRENT
+        header[16..18].copy_from_slice(&1u16.to_le_bytes());
+        header[18..20].copy_from_slice(&247u16.to_le_bytes());
+        header[20..24].copy_from_slice(&1u32.to_le_bytes());
+        header[40..48].copy_from_slice(&(shoff as u64).to_le_bytes());
+        header[52..54].copy_from_slice(&64u16.to_le_bytes());
+        header[58..60].copy_from_slice(&64u16.to_le_bytes());
+        header[60..62].copy_from_slice(&6u16.to_le_bytes());
+        header[62..64].copy_from_slice(&1u16.to_le_bytes());
+        data
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_reload_program() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                let insns = unsafe {
+                    slice::from_raw_parts(u.insns as *const bpf_insn, u.insn_cnt as usize)
+                };
+                // the new code references the existing map
+                assert_eq!(insns.len(), 3);
+                assert_eq!(insns[0].src_reg(), BPF_PSEUDO_MAP_FD as u8);
+                assert_eq!(insns[0].imm, 42);
+                Ok(File::open("/dev/null").unwrap().into_raw_fd() as _)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let map = MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: 4,
+                    value_size: 8,
+                    max_entries: 1,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+            }),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let old_fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let mut data = fake_program_data(false);
+        data.fd = Some(old_fd);
+
+        let mut bpf = Bpf {
+            maps: HashMap::from([("map_1".to_owned(), Map::Array(map))]),
+            programs: HashMap::from([(
+                "prog".to_owned(),
+                Program::SocketFilter(SocketFilter { data }),
+            )]),
+        };
+        bpf.reload_program("prog", &fake_object_with_map()).unwrap();
+
+        let prog: &SocketFilter = bpf.program("prog").unwrap().try_into().unwrap();
+        assert!(matches!(prog.data.fd, Some(fd) if fd != old_fd));
+        assert_eq!(bpf.map("map_1").unwrap().data().fd, Some(42));
+
+        assert!(matches!(
+            bpf.reload_program("missing", &fake_object_with_map()),
+            Err(BpfError::ProgramNotFound { name }) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn test_parse_cgroup_storage_maps() {
+        let map_data = |map_type: bpf_map_type| MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: map_type as u32,
+                    key_size: 16,
+                    value_size: 8,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+            }),
+            fd: None,
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+
+        assert!(matches!(
+            parse_map((
+                "storage".to_owned(),
+                map_data(BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED)
+            )),
+            Ok((name, Map::CgroupStorage(_))) if name == "storage"
+        ));
+        assert!(matches!(
+            parse_map((
+                "percpu_storage".to_owned(),
+                map_data(BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE)
+            )),
+            Ok((name, Map::PerCpuCgroupStorage(_))) if name == "percpu_storage"
+        ));
+    }
+
+    #[test]
+    fn test_rodata_bytes() {
+        thread_local! {
+            static VALUE: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+        }
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                assert_eq!(u.map_fd, 42);
+                let value =
+                    unsafe { slice::from_raw_parts(u.__bindgen_anon_1.value as *const u8, 4) };
+                VALUE.with(|v| *v.borrow_mut() = value.to_vec());
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_FREEZE,
+                ..
+            } => Ok(0),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                assert_eq!(u.map_fd, 42);
+                assert_eq!(unsafe { *(u.key as *const u32) }, 0);
+                VALUE.with(|v| {
+                    let v = v.borrow();
+                    unsafe {
+                        ptr::copy_nonoverlapping(
+                            v.as_ptr(),
+                            u.__bindgen_anon_1.value as *mut u8,
+                            v.len(),
+                        )
+                    }
+                });
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let mut map = MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 1,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: vec![1, 2, 3, 4],
+                kind: MapKind::Rodata,
+            }),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        bpf_map_update_elem_ptr(42, &0 as *const _, map.obj.data_mut().as_mut_ptr(), 0).unwrap();
+        let map = Map::Array(map);
+        map.freeze().unwrap();
+
+        let mut maps = HashMap::new();
+        maps.insert(".rodata".to_owned(), map);
+        let bpf = Bpf {
+            maps,
+            programs: HashMap::new(),
+        };
+
+        assert_eq!(bpf.rodata_bytes(".rodata").unwrap(), vec![1, 2, 3, 4]);
+        assert!(matches!(
+            bpf.rodata_bytes(".data"),
+            Err(MapError::InvalidName { name }) if name == ".data"
+        ));
+    }
+}
@@ -1,12 +1,14 @@
 use std::{
     borrow::Borrow,
     convert::{AsMut, AsRef},
+    fmt,
     marker::PhantomData,
 };
 
 use crate::{
-    maps::{check_bounds, check_kv_size, IterableMap, MapData, MapError},
-    sys::{bpf_map_lookup_elem, bpf_map_update_elem},
+    generated::BPF_F_LOCK,
+    maps::{check_bounds, check_kv_size, check_spin_lock, IterableMap, MapData, MapError},
+    sys::{bpf_map_lookup_elem_sized, bpf_map_update_elem},
     Pod,
 };
 
@@ -35,6 +37,12 @@ pub struct Array<T, V: Pod> {
     _v: PhantomData<V>,
 }
 
+impl<T: AsRef<MapData>, V: Pod> fmt::Debug for Array<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "Array").finish()
+    }
+}
+
 impl<T: AsRef<MapData>, V: Pod> Array<T, V> {
     pub(crate) fn new(map: T) -> Result<Array<T, V>, MapError> {
         let data = map.as_ref();
@@ -66,15 +74,30 @@ impl<T: AsRef<MapData>, V: Pod> Array<T, V> {
         check_bounds(data, *index)?;
         let fd = data.fd_or_err()?;
 
-        let value = bpf_map_lookup_elem(fd, index, flags).map_err(|(_, io_error)| {
-            MapError::SyscallError {
-                call: "bpf_map_lookup_elem".to_owned(),
-                io_error,
-            }
-        })?;
+        let value_size = data.obj.value_size() as usize;
+        let value =
+            bpf_map_lookup_elem_sized(fd, index, value_size, flags).map_err(|(_, io_error)| {
+                MapError::SyscallError {
+                    call: "bpf_map_lookup_elem".to_owned(),
+                    io_error,
+                }
+            })?;
         value.ok_or(MapError::KeyNotFound)
     }
 
+    /// Returns the value stored at the given index, read while holding the
+    /// `struct bpf_spin_lock` of the value.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::MissingSpinLock`] if the value type of the map
+    /// doesn't contain a `struct bpf_spin_lock`, otherwise the errors of
+    /// [`Array::get`].
+    pub fn get_locked(&self, index: &u32) -> Result<V, MapError> {
+        check_spin_lock(self.inner.as_ref())?;
+        self.get(index, BPF_F_LOCK as u64)
+    }
+
     /// An iterator over the elements of the array. The iterator item type is `Result<V,
     /// MapError>`.
     pub fn iter(&self) -> impl Iterator<Item = Result<V, MapError>> + '_ {
@@ -101,6 +124,26 @@ impl<T: AsMut<MapData>, V: Pod> Array<T, V> {
         })?;
         Ok(())
     }
+
+    /// Sets the value of the element at the given index while holding the
+    /// `struct bpf_spin_lock` of the existing value.
+    ///
+    /// The lock itself isn't updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::MissingSpinLock`] if the value type of the map
+    /// doesn't contain a `struct bpf_spin_lock`, otherwise the errors of
+    /// [`Array::set`].
+    pub fn set_locked(
+        &mut self,
+        index: u32,
+        value: impl Borrow<V>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        check_spin_lock(self.inner.as_mut())?;
+        self.set(index, value, flags | BPF_F_LOCK as u64)
+    }
 }
 
 impl<T: AsRef<MapData>, V: Pod> IterableMap<u32, V> for Array<T, V> {
@@ -112,3 +155,63 @@ impl<T: AsRef<MapData>, V: Pod> IterableMap<u32, V> for Array<T, V> {
         self.get(index, 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{io, slice};
+
+    use libc::EFAULT;
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_ARRAY},
+        obj::{
+            self,
+            maps::{LegacyMap, MapKind},
+        },
+        sys::{override_syscall, Syscall},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_get_large_value() {
+        const VALUE_SIZE: usize = 16 * 1024;
+
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                // the kernel writes value_size bytes
+                let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value } as *mut u8;
+                unsafe { slice::from_raw_parts_mut(value, VALUE_SIZE) }.fill(0xab);
+                Ok(1)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+        let map = MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: 4,
+                    value_size: VALUE_SIZE as u32,
+                    max_entries: 1,
+                    ..Default::default()
+                },
+                section_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+                symbol_index: 0,
+            }),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let array = Array::<_, [u8; VALUE_SIZE]>::new(&map).unwrap();
+
+        let value = array.get(&0, 0).unwrap();
+        assert!(value.iter().all(|b| *b == 0xab));
+    }
+}
@@ -0,0 +1,242 @@
+//! An array of eBPF maps.
+
+use std::{
+    convert::{AsMut, AsRef},
+    fmt,
+    os::unix::io::RawFd,
+};
+
+use crate::{
+    maps::{check_bounds, check_kv_size, MapData, MapError, MapKeys},
+    sys::{bpf_map_delete_elem, bpf_map_lookup_elem, bpf_map_update_elem},
+};
+
+/// An array of eBPF maps.
+///
+/// The maps stored in the array, the inner maps, are usually created at
+/// runtime. They must all be compatible with the template the array was
+/// created with, see [`BpfLoader::set_inner_map`](crate::BpfLoader::set_inner_map).
+/// eBPF programs look up an inner map with `bpf_map_lookup_elem(&outer, &index)`.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.12.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::BpfLoader::new().set_inner_map("OUTER", "INNER").load(&[])?;
+/// use aya::maps::{ArrayOfMaps, MapData};
+///
+/// let inner = MapData::from_pin("/sys/fs/bpf/inner")?;
+/// let mut outer = ArrayOfMaps::try_from(bpf.map_mut("OUTER").unwrap())?;
+///
+/// // programs looking up index 0 of OUTER get INNER
+/// outer.set(0, &inner, 0)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_ARRAY_OF_MAPS")]
+pub struct ArrayOfMaps<T> {
+    inner: T,
+}
+
+impl<T: AsRef<MapData>> fmt::Debug for ArrayOfMaps<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "ArrayOfMaps").finish()
+    }
+}
+
+impl<T: AsRef<MapData>> ArrayOfMaps<T> {
+    pub(crate) fn new(map: T) -> Result<ArrayOfMaps<T>, MapError> {
+        let data = map.as_ref();
+        check_kv_size::<u32, RawFd>(data)?;
+
+        let _fd = data.fd_or_err()?;
+
+        Ok(ArrayOfMaps { inner: map })
+    }
+
+    /// Returns the number of elements in the array.
+    pub fn len(&self) -> u32 {
+        self.inner.as_ref().obj.max_entries()
+    }
+
+    /// Returns the id of the inner map stored at the given index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds,
+    /// [`MapError::KeyNotFound`] if no map is stored at `index`.
+    pub fn get(&self, index: &u32, flags: u64) -> Result<u32, MapError> {
+        let data = self.inner.as_ref();
+        check_bounds(data, *index)?;
+        let fd = data.fd_or_err()?;
+
+        let value = bpf_map_lookup_elem(fd, index, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        value.ok_or(MapError::KeyNotFound)
+    }
+
+    /// An iterator over the indices of the array that store a map. The
+    /// iterator item type is `Result<u32, MapError>`.
+    pub fn indices(&self) -> MapKeys<'_, u32> {
+        MapKeys::new(self.inner.as_ref())
+    }
+}
+
+impl<T: AsMut<MapData>> ArrayOfMaps<T> {
+    /// Stores the map `inner` at the given index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds,
+    /// [`MapError::SyscallError`] if `bpf_map_update_elem` fails, for example
+    /// because `inner` isn't compatible with the template of the inner maps.
+    pub fn set(&mut self, index: u32, inner: &MapData, flags: u64) -> Result<(), MapError> {
+        let data = self.inner.as_mut();
+        check_bounds(data, index)?;
+        let fd = data.fd_or_err()?;
+        let inner_fd = inner.fd_or_err()?;
+
+        bpf_map_update_elem(fd, Some(&index), &inner_fd, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Removes the map stored at the given index.
+    pub fn clear_index(&mut self, index: &u32) -> Result<(), MapError> {
+        let data = self.inner.as_mut();
+        check_bounds(data, *index)?;
+        let fd = data.fd_or_err()?;
+
+        bpf_map_delete_elem(fd, index)
+            .map(|_| ())
+            .map_err(|(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_delete_elem".to_owned(),
+                io_error,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_ARRAY_OF_MAPS},
+        maps::Map,
+        obj::{
+            self,
+            maps::{LegacyMap, MapKind},
+        },
+        sys::{override_syscall, Syscall},
+    };
+
+    use super::*;
+
+    fn new_obj_map() -> obj::Map {
+        obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: BPF_MAP_TYPE_ARRAY_OF_MAPS as u32,
+                key_size: 4,
+                value_size: 4,
+                max_entries: 4,
+                ..Default::default()
+            },
+            section_index: 0,
+            data: Vec::new(),
+            kind: MapKind::Other,
+            symbol_index: 0,
+        })
+    }
+
+    fn new_map(fd: RawFd) -> MapData {
+        MapData {
+            obj: new_obj_map(),
+            fd: Some(fd),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        }
+    }
+
+    #[test]
+    fn test_try_from_wrong_map() {
+        let map = Map::Array(new_map(42));
+        assert!(matches!(
+            ArrayOfMaps::try_from(&map),
+            Err(MapError::InvalidMapType { .. })
+        ));
+        std::mem::forget(map);
+
+        let map = Map::ArrayOfMaps(new_map(42));
+        assert!(ArrayOfMaps::try_from(&map).is_ok());
+        std::mem::forget(map);
+    }
+
+    #[test]
+    fn test_set_passes_inner_fd() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                assert_eq!(u.map_fd, 42);
+                assert_eq!(unsafe { *(u.key as *const u32) }, 2);
+                assert_eq!(unsafe { *(u.__bindgen_anon_1.value as *const RawFd) }, 7);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let mut outer = new_map(42);
+        let inner = new_map(7);
+        let mut array = ArrayOfMaps::new(&mut outer).unwrap();
+        assert!(array.set(2, &inner, 0).is_ok());
+        assert!(matches!(
+            array.set(4, &inner, 0),
+            Err(MapError::OutOfBounds { index: 4, .. })
+        ));
+
+        let mut not_created = new_map(0);
+        not_created.fd = None;
+        assert!(matches!(
+            array.set(2, &not_created, 0),
+            Err(MapError::NotCreated)
+        ));
+
+        // don't close the fake fds
+        outer.fd = None;
+        std::mem::forget(inner);
+    }
+
+    #[test]
+    fn test_get_returns_inner_id() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                unsafe { *(u.__bindgen_anon_1.value as *mut u32) = 12 };
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let map = new_map(42);
+        let array = ArrayOfMaps::new(&map).unwrap();
+        assert_eq!(array.get(&0, 0).unwrap(), 12);
+        std::mem::forget(map);
+    }
+}
@@ -1,10 +1,14 @@
 use std::{
     convert::{AsMut, AsRef},
+    fmt,
     marker::PhantomData,
 };
 
 use crate::{
-    maps::{check_bounds, check_kv_size, IterableMap, MapData, MapError, PerCpuValues},
+    maps::{
+        check_bounds, check_kv_size, check_nr_cpus, possible_cpus, IterableMap, MapData, MapError,
+        PerCpuValues,
+    },
     sys::{bpf_map_lookup_elem_per_cpu, bpf_map_update_elem_per_cpu},
     Pod,
 };
@@ -50,22 +54,56 @@ use crate::{
 #[doc(alias = "BPF_MAP_TYPE_PERCPU_ARRAY")]
 pub struct PerCpuArray<T, V: Pod> {
     inner: T,
+    nr_cpus: usize,
     _v: PhantomData<V>,
 }
 
+impl<T: AsRef<MapData>, V: Pod> fmt::Debug for PerCpuArray<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "PerCpuArray").finish()
+    }
+}
+
 impl<T: AsRef<MapData>, V: Pod> PerCpuArray<T, V> {
     pub(crate) fn new(map: T) -> Result<PerCpuArray<T, V>, MapError> {
         let data = map.as_ref();
         check_kv_size::<u32, V>(data)?;
 
         let _fd = data.fd_or_err()?;
+        let nr_cpus = possible_cpus()?;
 
         Ok(PerCpuArray {
             inner: map,
+            nr_cpus,
             _v: PhantomData,
         })
     }
 
+    /// Returns the number of CPUs the values are stored for.
+    ///
+    /// This is the number of possible CPUs listed in
+    /// `/sys/devices/system/cpu/possible` unless overridden with
+    /// [`PerCpuArray::set_nr_cpus`].
+    pub fn nr_cpus(&self) -> usize {
+        self.nr_cpus
+    }
+
+    /// Overrides the number of CPUs the values are stored for.
+    ///
+    /// The kernel always reads and writes a value for each possible CPU of the
+    /// running system, so `nr_cpus` can't be lower than that. Raising it is
+    /// useful to exchange values with a system that has more CPUs, for
+    /// example in tests.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::InvalidCpuCount`] if `nr_cpus` is lower than the
+    /// number of possible CPUs.
+    pub fn set_nr_cpus(&mut self, nr_cpus: usize) -> Result<(), MapError> {
+        self.nr_cpus = check_nr_cpus(nr_cpus)?;
+        Ok(())
+    }
+
     /// Returns the number of elements in the array.
     ///
     /// This corresponds to the value of `bpf_map_def::max_entries` on the eBPF side.
@@ -84,12 +122,12 @@ impl<T: AsRef<MapData>, V: Pod> PerCpuArray<T, V> {
         check_bounds(data, *index)?;
         let fd = data.fd_or_err()?;
 
-        let value = bpf_map_lookup_elem_per_cpu(fd, index, flags).map_err(|(_, io_error)| {
-            MapError::SyscallError {
+        let value = bpf_map_lookup_elem_per_cpu(fd, index, self.nr_cpus, flags).map_err(
+            |(_, io_error)| MapError::SyscallError {
                 call: "bpf_map_lookup_elem".to_owned(),
                 io_error,
-            }
-        })?;
+            },
+        )?;
         value.ok_or(MapError::KeyNotFound)
     }
 
@@ -105,12 +143,19 @@ impl<T: AsMut<MapData>, V: Pod> PerCpuArray<T, V> {
     ///
     /// # Errors
     ///
-    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds, [`MapError::SyscallError`]
-    /// if `bpf_map_update_elem` fails.
+    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds,
+    /// [`MapError::InvalidCpuCount`] if there isn't one value for each CPU,
+    /// [`MapError::SyscallError`] if `bpf_map_update_elem` fails.
     pub fn set(&mut self, index: u32, values: PerCpuValues<V>, flags: u64) -> Result<(), MapError> {
         let data = self.inner.as_mut();
         check_bounds(data, index)?;
         let fd = data.fd_or_err()?;
+        if values.len() != self.nr_cpus {
+            return Err(MapError::InvalidCpuCount {
+                count: values.len(),
+                expected: self.nr_cpus,
+            });
+        }
 
         bpf_map_update_elem_per_cpu(fd, &index, &values, flags).map_err(|(_, io_error)| {
             MapError::SyscallError {
@@ -2,6 +2,7 @@
 
 use std::{
     convert::{AsMut, AsRef},
+    fmt,
     os::unix::prelude::{AsRawFd, RawFd},
 };
 
@@ -51,6 +52,12 @@ pub struct ProgramArray<T> {
     inner: T,
 }
 
+impl<T: AsRef<MapData>> fmt::Debug for ProgramArray<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "ProgramArray").finish()
+    }
+}
+
 impl<T: AsRef<MapData>> ProgramArray<T> {
     pub(crate) fn new(map: T) -> Result<ProgramArray<T>, MapError> {
         let data = map.as_ref();
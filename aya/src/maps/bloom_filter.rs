@@ -1,5 +1,5 @@
 //! A Bloom Filter.
-use std::{borrow::Borrow, convert::AsRef, marker::PhantomData};
+use std::{borrow::Borrow, convert::AsRef, fmt, marker::PhantomData};
 
 use crate::{
     maps::{check_v_size, MapData, MapError},
@@ -35,6 +35,12 @@ pub struct BloomFilter<T, V: Pod> {
     _v: PhantomData<V>,
 }
 
+impl<T: AsRef<MapData>, V: Pod> fmt::Debug for BloomFilter<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "BloomFilter").finish()
+    }
+}
+
 impl<T: AsRef<MapData>, V: Pod> BloomFilter<T, V> {
     pub(crate) fn new(map: T) -> Result<BloomFilter<T, V>, MapError> {
         let data = map.as_ref();
@@ -119,6 +125,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         assert!(matches!(
@@ -148,6 +155,7 @@ mod tests {
             }),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -165,6 +173,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -180,6 +189,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -192,6 +202,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -207,6 +218,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let bloom_filter = BloomFilter::<_, u32>::new(&mut map).unwrap();
@@ -231,6 +243,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -245,6 +258,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let bloom_filter = BloomFilter::<_, u32>::new(&map).unwrap();
@@ -268,6 +282,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let bloom_filter = BloomFilter::<_, u32>::new(&map).unwrap();
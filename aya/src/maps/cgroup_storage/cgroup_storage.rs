@@ -0,0 +1,262 @@
+use std::{
+    borrow::Borrow,
+    convert::{AsMut, AsRef},
+    fmt,
+    marker::PhantomData,
+};
+
+use crate::{
+    maps::{
+        cgroup_storage::CgroupStorageKey, check_kv_size, IterableMap, MapData, MapError, MapIter,
+        MapKeys,
+    },
+    sys::{bpf_map_lookup_elem, bpf_map_update_elem},
+    Pod,
+};
+
+/// A cgroup storage map.
+///
+/// Each cgroup a program using the map is attached to gets its own value,
+/// which the program accesses with `bpf_get_local_storage`. Values are keyed
+/// by [`CgroupStorageKey`] and can only be updated once the program has been
+/// attached.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.19.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::CgroupStorage;
+///
+/// let mut storage = CgroupStorage::<_, u64>::try_from(bpf.map_mut("PACKETS").unwrap())?;
+/// let keys = storage.keys().collect::<Result<Vec<_>, _>>()?;
+/// for key in keys {
+///     let packets = storage.get(&key, 0)?;
+///     println!("cgroup {} sent {} packets", key.cgroup_inode_id, packets);
+///     storage.set(&key, 0u64, 0)?;
+/// }
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_CGROUP_STORAGE")]
+pub struct CgroupStorage<T, V> {
+    inner: T,
+    _v: PhantomData<V>,
+}
+
+impl<T: AsRef<MapData>, V> fmt::Debug for CgroupStorage<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner
+            .as_ref()
+            .debug_struct(f, "CgroupStorage")
+            .finish()
+    }
+}
+
+impl<T: AsRef<MapData>, V: Pod> CgroupStorage<T, V> {
+    pub(crate) fn new(map: T) -> Result<CgroupStorage<T, V>, MapError> {
+        let data = map.as_ref();
+        check_kv_size::<CgroupStorageKey, V>(data)?;
+
+        let _ = data.fd_or_err()?;
+
+        Ok(CgroupStorage {
+            inner: map,
+            _v: PhantomData,
+        })
+    }
+
+    /// Returns the value stored for the given key.
+    pub fn get(&self, key: &CgroupStorageKey, flags: u64) -> Result<V, MapError> {
+        let fd = self.inner.as_ref().fd_or_err()?;
+        let value = bpf_map_lookup_elem(fd, key, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        value.ok_or(MapError::KeyNotFound)
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator item type is `Result<(CgroupStorageKey, V), MapError>`.
+    pub fn iter(&self) -> MapIter<'_, CgroupStorageKey, V, Self> {
+        MapIter::new(self)
+    }
+
+    /// An iterator visiting all keys in arbitrary order. The iterator element
+    /// type is `Result<CgroupStorageKey, MapError>`.
+    pub fn keys(&self) -> MapKeys<'_, CgroupStorageKey> {
+        MapKeys::new(self.inner.as_ref())
+    }
+}
+
+impl<T: AsMut<MapData>, V: Pod> CgroupStorage<T, V> {
+    /// Sets the value stored for the given key.
+    ///
+    /// The entry must already exist, that is a program using the map must be
+    /// attached to the cgroup with the attach type of the key.
+    pub fn set(
+        &mut self,
+        key: &CgroupStorageKey,
+        value: impl Borrow<V>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        let fd = self.inner.as_mut().fd_or_err()?;
+        bpf_map_update_elem(fd, Some(key), value.borrow(), flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(())
+    }
+}
+
+impl<T: AsRef<MapData>, V: Pod> IterableMap<CgroupStorageKey, V> for CgroupStorage<T, V> {
+    fn map(&self) -> &MapData {
+        self.inner.as_ref()
+    }
+
+    fn get(&self, key: &CgroupStorageKey) -> Result<V, MapError> {
+        CgroupStorage::get(self, key, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, mem};
+
+    use libc::{EFAULT, ENOENT};
+
+    use crate::{
+        bpf_map_def,
+        generated::{
+            bpf_cmd,
+            bpf_map_type::{BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED, BPF_MAP_TYPE_HASH},
+        },
+        maps::Map,
+        obj::{
+            self,
+            maps::{LegacyMap, MapKind},
+        },
+        sys::{override_syscall, Syscall},
+    };
+
+    use super::*;
+
+    fn new_obj_map(key_size: u32) -> obj::Map {
+        obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED as u32,
+                key_size,
+                value_size: 8,
+                max_entries: 0,
+                ..Default::default()
+            },
+            section_index: 0,
+            data: Vec::new(),
+            kind: MapKind::Other,
+            symbol_index: 0,
+        })
+    }
+
+    fn new_map(key_size: u32) -> MapData {
+        MapData {
+            obj: new_obj_map(key_size),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        }
+    }
+
+    #[test]
+    fn test_key_size() {
+        assert_eq!(mem::size_of::<CgroupStorageKey>(), 16);
+    }
+
+    #[test]
+    fn test_wrong_key_size() {
+        // a storage shared by all the attach types is keyed by the cgroup id only
+        let map = new_map(8);
+        assert!(matches!(
+            CgroupStorage::<_, u64>::new(&map),
+            Err(MapError::InvalidKeySize {
+                size: 16,
+                expected: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn test_wrong_value_size() {
+        let map = new_map(16);
+        assert!(matches!(
+            CgroupStorage::<_, u32>::new(&map),
+            Err(MapError::InvalidValueSize {
+                size: 4,
+                expected: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn test_try_from_wrong_map() {
+        let mut map = new_map(16);
+        map.obj = obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: BPF_MAP_TYPE_HASH as u32,
+                key_size: 16,
+                value_size: 8,
+                max_entries: 1024,
+                ..Default::default()
+            },
+            section_index: 0,
+            data: Vec::new(),
+            kind: MapKind::Other,
+            symbol_index: 0,
+        });
+        let map = Map::HashMap(map);
+        assert!(matches!(
+            CgroupStorage::<_, u64>::try_from(&map),
+            Err(MapError::InvalidMapType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_new_ok() {
+        let map = Map::CgroupStorage(new_map(16));
+        assert!(CgroupStorage::<_, u64>::try_from(&map).is_ok());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_get() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                let key = unsafe { *(u.key as *const CgroupStorageKey) };
+                if key != CgroupStorageKey::new(1234, 1) {
+                    return Err((-1, io::Error::from_raw_os_error(ENOENT)));
+                }
+                unsafe { *(u.__bindgen_anon_1.value as *mut u64) = 42 };
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let map = new_map(16);
+        let storage = CgroupStorage::<_, u64>::new(&map).unwrap();
+        assert_eq!(storage.get(&CgroupStorageKey::new(1234, 1), 0).unwrap(), 42);
+        assert!(matches!(
+            storage.get(&CgroupStorageKey::new(1234, 2), 0),
+            Err(MapError::KeyNotFound)
+        ));
+    }
+}
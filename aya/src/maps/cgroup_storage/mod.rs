@@ -0,0 +1,42 @@
+//! Cgroup storage map types.
+//!
+//! Cgroup storage maps hold a value for each cgroup a program using the map
+//! is attached to. The storage is allocated by the kernel when the program is
+//! attached and freed when it is detached, so entries can be looked up and
+//! updated from user space but not inserted or removed.
+#[allow(clippy::module_inception)]
+mod cgroup_storage;
+mod per_cpu_cgroup_storage;
+
+pub use cgroup_storage::CgroupStorage;
+pub use per_cpu_cgroup_storage::PerCpuCgroupStorage;
+
+use crate::Pod;
+
+/// The key of a cgroup storage map, `struct bpf_cgroup_storage_key`.
+///
+/// An entry is identified by the cgroup the program is attached to and the
+/// attach type of the program.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CgroupStorageKey {
+    /// The inode id of the cgroup.
+    pub cgroup_inode_id: u64,
+    /// The `bpf_attach_type` the program is attached with.
+    pub attach_type: u32,
+    _pad: u32,
+}
+
+impl CgroupStorageKey {
+    /// Creates a new key.
+    pub fn new(cgroup_inode_id: u64, attach_type: u32) -> Self {
+        Self {
+            cgroup_inode_id,
+            attach_type,
+            _pad: 0,
+        }
+    }
+}
+
+// A Pod impl is required as CgroupStorageKey is a key for a map.
+unsafe impl Pod for CgroupStorageKey {}
@@ -0,0 +1,217 @@
+use std::{
+    convert::{AsMut, AsRef},
+    fmt,
+    marker::PhantomData,
+};
+
+use crate::{
+    maps::{
+        cgroup_storage::CgroupStorageKey, check_kv_size, check_nr_cpus, possible_cpus, IterableMap,
+        MapData, MapError, MapIter, MapKeys, PerCpuValues,
+    },
+    sys::{bpf_map_lookup_elem_per_cpu, bpf_map_update_elem_per_cpu},
+    Pod,
+};
+
+/// Similar to [`CgroupStorage`](crate::maps::CgroupStorage) but each CPU
+/// holds a separate value for a given cgroup.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.20.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::PerCpuCgroupStorage;
+///
+/// let storage = PerCpuCgroupStorage::<_, u64>::try_from(bpf.map("PACKETS").unwrap())?;
+/// for entry in storage.iter() {
+///     let (key, packets) = entry?;
+///     let total: u64 = packets.iter().sum();
+///     println!("cgroup {} sent {} packets", key.cgroup_inode_id, total);
+/// }
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE")]
+pub struct PerCpuCgroupStorage<T, V: Pod> {
+    inner: T,
+    nr_cpus: usize,
+    _v: PhantomData<V>,
+}
+
+impl<T: AsRef<MapData>, V: Pod> fmt::Debug for PerCpuCgroupStorage<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner
+            .as_ref()
+            .debug_struct(f, "PerCpuCgroupStorage")
+            .finish()
+    }
+}
+
+impl<T: AsRef<MapData>, V: Pod> PerCpuCgroupStorage<T, V> {
+    pub(crate) fn new(map: T) -> Result<PerCpuCgroupStorage<T, V>, MapError> {
+        let data = map.as_ref();
+        check_kv_size::<CgroupStorageKey, V>(data)?;
+
+        let _ = data.fd_or_err()?;
+        let nr_cpus = possible_cpus()?;
+
+        Ok(PerCpuCgroupStorage {
+            inner: map,
+            nr_cpus,
+            _v: PhantomData,
+        })
+    }
+
+    /// Returns the number of CPUs the values are stored for.
+    ///
+    /// This is the number of possible CPUs listed in
+    /// `/sys/devices/system/cpu/possible` unless overridden with
+    /// [`PerCpuCgroupStorage::set_nr_cpus`].
+    pub fn nr_cpus(&self) -> usize {
+        self.nr_cpus
+    }
+
+    /// Overrides the number of CPUs the values are stored for, see
+    /// [`PerCpuHashMap::set_nr_cpus`](crate::maps::PerCpuHashMap::set_nr_cpus).
+    pub fn set_nr_cpus(&mut self, nr_cpus: usize) -> Result<(), MapError> {
+        self.nr_cpus = check_nr_cpus(nr_cpus)?;
+        Ok(())
+    }
+
+    /// Returns a slice of values - one for each CPU - stored for the given key.
+    pub fn get(&self, key: &CgroupStorageKey, flags: u64) -> Result<PerCpuValues<V>, MapError> {
+        let fd = self.inner.as_ref().fd_or_err()?;
+        let values = bpf_map_lookup_elem_per_cpu(fd, key, self.nr_cpus, flags).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            },
+        )?;
+        values.ok_or(MapError::KeyNotFound)
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator item type is `Result<(CgroupStorageKey, PerCpuValues<V>), MapError>`.
+    pub fn iter(&self) -> MapIter<'_, CgroupStorageKey, PerCpuValues<V>, Self> {
+        MapIter::new(self)
+    }
+
+    /// An iterator visiting all keys in arbitrary order. The iterator element
+    /// type is `Result<CgroupStorageKey, MapError>`.
+    pub fn keys(&self) -> MapKeys<'_, CgroupStorageKey> {
+        MapKeys::new(self.inner.as_ref())
+    }
+}
+
+impl<T: AsMut<MapData>, V: Pod> PerCpuCgroupStorage<T, V> {
+    /// Sets the values - one for each CPU - stored for the given key.
+    ///
+    /// The entry must already exist, see [`CgroupStorage::set`](crate::maps::CgroupStorage::set).
+    /// Fails with [`MapError::InvalidCpuCount`] if there isn't one value for
+    /// each CPU, see [`PerCpuCgroupStorage::nr_cpus`].
+    pub fn set(
+        &mut self,
+        key: &CgroupStorageKey,
+        values: PerCpuValues<V>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        let fd = self.inner.as_mut().fd_or_err()?;
+        if values.len() != self.nr_cpus {
+            return Err(MapError::InvalidCpuCount {
+                count: values.len(),
+                expected: self.nr_cpus,
+            });
+        }
+        bpf_map_update_elem_per_cpu(fd, key, &values, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+
+        Ok(())
+    }
+}
+
+impl<T: AsRef<MapData>, V: Pod> IterableMap<CgroupStorageKey, PerCpuValues<V>>
+    for PerCpuCgroupStorage<T, V>
+{
+    fn map(&self) -> &MapData {
+        self.inner.as_ref()
+    }
+
+    fn get(&self, key: &CgroupStorageKey) -> Result<PerCpuValues<V>, MapError> {
+        PerCpuCgroupStorage::get(self, key, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bpf_map_def,
+        generated::bpf_map_type::BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE,
+        maps::Map,
+        obj::maps::{LegacyMap, MapKind},
+        util::nr_cpus,
+    };
+
+    use super::*;
+
+    fn new_map(key_size: u32) -> MapData {
+        MapData {
+            obj: crate::obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE as u32,
+                    key_size,
+                    value_size: 8,
+                    max_entries: 0,
+                    ..Default::default()
+                },
+                section_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+                symbol_index: 0,
+            }),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        }
+    }
+
+    #[test]
+    fn test_wrong_key_size() {
+        let map = new_map(8);
+        assert!(matches!(
+            PerCpuCgroupStorage::<_, u64>::new(&map),
+            Err(MapError::InvalidKeySize {
+                size: 16,
+                expected: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn test_new_ok() {
+        let mut map = Map::PerCpuCgroupStorage(new_map(16));
+        let mut storage = PerCpuCgroupStorage::<_, u64>::try_from(&mut map).unwrap();
+        assert_eq!(storage.nr_cpus(), nr_cpus().unwrap());
+
+        assert!(matches!(
+            storage.set_nr_cpus(0),
+            Err(MapError::InvalidCpuCount { count: 0, .. })
+        ));
+        storage.set_nr_cpus(8192).unwrap();
+        let values = PerCpuValues::with_cpu_count(vec![0u64; 4], 4).unwrap();
+        assert!(matches!(
+            storage.set(&CgroupStorageKey::new(1, 0), values, 0),
+            Err(MapError::InvalidCpuCount {
+                count: 4,
+                expected: 8192
+            })
+        ));
+    }
+}
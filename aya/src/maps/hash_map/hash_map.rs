@@ -1,12 +1,16 @@
 use std::{
     borrow::Borrow,
     convert::{AsMut, AsRef},
+    fmt,
     marker::PhantomData,
 };
 
 use crate::{
-    maps::{check_kv_size, hash_map, IterableMap, MapData, MapError, MapIter, MapKeys},
-    sys::bpf_map_lookup_elem,
+    generated::BPF_F_LOCK,
+    maps::{
+        check_kv_size, check_spin_lock, hash_map, IterableMap, MapData, MapError, MapIter, MapKeys,
+    },
+    sys::bpf_map_lookup_elem_sized,
     Pod,
 };
 
@@ -32,13 +36,18 @@ use crate::{
 /// ```
 #[doc(alias = "BPF_MAP_TYPE_HASH")]
 #[doc(alias = "BPF_MAP_TYPE_LRU_HASH")]
-#[derive(Debug)]
 pub struct HashMap<T, K, V> {
     inner: T,
     _k: PhantomData<K>,
     _v: PhantomData<V>,
 }
 
+impl<T: AsRef<MapData>, K, V> fmt::Debug for HashMap<T, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "HashMap").finish()
+    }
+}
+
 impl<T: AsRef<MapData>, K: Pod, V: Pod> HashMap<T, K, V> {
     pub(crate) fn new(map: T) -> Result<HashMap<T, K, V>, MapError> {
         let data = map.as_ref();
@@ -54,16 +63,31 @@ impl<T: AsRef<MapData>, K: Pod, V: Pod> HashMap<T, K, V> {
 
     /// Returns a copy of the value associated with the key.
     pub fn get(&self, key: &K, flags: u64) -> Result<V, MapError> {
-        let fd = self.inner.as_ref().fd_or_err()?;
-        let value = bpf_map_lookup_elem(fd, key, flags).map_err(|(_, io_error)| {
-            MapError::SyscallError {
-                call: "bpf_map_lookup_elem".to_owned(),
-                io_error,
-            }
-        })?;
+        let data = self.inner.as_ref();
+        let fd = data.fd_or_err()?;
+        let value_size = data.obj.value_size() as usize;
+        let value =
+            bpf_map_lookup_elem_sized(fd, key, value_size, flags).map_err(|(_, io_error)| {
+                MapError::SyscallError {
+                    call: "bpf_map_lookup_elem".to_owned(),
+                    io_error,
+                }
+            })?;
         value.ok_or(MapError::KeyNotFound)
     }
 
+    /// Returns a copy of the value associated with the key, read while holding
+    /// the `struct bpf_spin_lock` of the value.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::MissingSpinLock`] if the value type of the map
+    /// doesn't contain a `struct bpf_spin_lock`.
+    pub fn get_locked(&self, key: &K) -> Result<V, MapError> {
+        check_spin_lock(self.inner.as_ref())?;
+        self.get(key, BPF_F_LOCK as u64)
+    }
+
     /// An iterator visiting all key-value pairs in arbitrary order. The
     /// iterator item type is `Result<(K, V), MapError>`.
     pub fn iter(&self) -> MapIter<'_, K, V, Self> {
@@ -88,12 +112,60 @@ impl<T: AsMut<MapData>, K: Pod, V: Pod> HashMap<T, K, V> {
         hash_map::insert(self.inner.as_mut(), key.borrow(), value.borrow(), flags)
     }
 
+    /// Inserts a key-value pair into the map while holding the
+    /// `struct bpf_spin_lock` of the existing value.
+    ///
+    /// The lock itself isn't updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::MissingSpinLock`] if the value type of the map
+    /// doesn't contain a `struct bpf_spin_lock`.
+    pub fn insert_locked(
+        &mut self,
+        key: impl Borrow<K>,
+        value: impl Borrow<V>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        let data = self.inner.as_mut();
+        check_spin_lock(data)?;
+        hash_map::insert(
+            data,
+            key.borrow(),
+            value.borrow(),
+            flags | BPF_F_LOCK as u64,
+        )
+    }
+
     /// Removes a key from the map.
     pub fn remove(&mut self, key: &K) -> Result<(), MapError> {
         hash_map::remove(self.inner.as_mut(), key)
     }
 }
 
+impl<T: AsRef<MapData>, K, V> HashMap<T, K, V> {
+    /// Returns a copy of the value associated with the key, as raw bytes.
+    ///
+    /// Unlike [HashMap::get], the key doesn't need to be a [`Pod`] type. The
+    /// length of `key` must match the key size of the map. Maps whose key or
+    /// value types aren't [`Pod`] can be used without a `HashMap`, see
+    /// [`MapData::get_bytes`].
+    pub fn get_bytes(&self, key: &[u8], flags: u64) -> Result<Vec<u8>, MapError> {
+        self.inner.as_ref().get_bytes(key, flags)
+    }
+}
+
+impl<T: AsMut<MapData>, K, V> HashMap<T, K, V> {
+    /// Inserts a key-value pair given as raw bytes into the map.
+    ///
+    /// Unlike [HashMap::insert], the key and value don't need to be [`Pod`]
+    /// types. Their lengths must match the key and value sizes of the map,
+    /// see [`MapData::insert_bytes`].
+    pub fn insert_bytes(&mut self, key: &[u8], value: &[u8], flags: u64) -> Result<(), MapError> {
+        self.inner.as_mut().insert_bytes(key, value, flags)
+    }
+}
+
 impl<T: AsRef<MapData>, K: Pod, V: Pod> IterableMap<K, V> for HashMap<T, K, V> {
     fn map(&self) -> &MapData {
         self.inner.as_ref()
@@ -106,14 +178,14 @@ impl<T: AsRef<MapData>, K: Pod, V: Pod> IterableMap<K, V> for HashMap<T, K, V> {
 
 #[cfg(test)]
 mod tests {
-    use std::io;
+    use std::{io, mem, slice};
 
     use libc::{EFAULT, ENOENT};
 
     use crate::{
         bpf_map_def,
         generated::{
-            bpf_attr, bpf_cmd,
+            bpf_attr, bpf_cmd, bpf_map_info,
             bpf_map_type::{BPF_MAP_TYPE_HASH, BPF_MAP_TYPE_LRU_HASH},
         },
         maps::{Map, MapData},
@@ -122,6 +194,7 @@ mod tests {
             maps::{LegacyMap, MapKind},
         },
         sys::{override_syscall, SysResult, Syscall},
+        PinningType,
     };
 
     use super::*;
@@ -152,6 +225,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         assert!(matches!(
@@ -169,6 +243,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         assert!(matches!(
@@ -186,6 +261,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -202,6 +278,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -221,6 +298,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -236,18 +314,39 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
         assert!(HashMap::<_, u32, u32>::new(&mut map).is_ok());
     }
 
+    #[test]
+    fn test_debug() {
+        let map = MapData {
+            obj: new_obj_map(),
+            fd: Some(4242),
+            pinned: true,
+            name: None,
+            btf_fd: None,
+        };
+        let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
+
+        let debug = format!("{hm:?}");
+        assert!(debug.starts_with("HashMap"), "{debug}");
+        assert!(debug.contains("BPF_MAP_TYPE_HASH"), "{debug}");
+        assert!(debug.contains("max_entries: 1024"), "{debug}");
+        assert!(debug.contains("pinned: true"), "{debug}");
+        assert!(!debug.contains("4242"), "{debug}");
+    }
+
     #[test]
     fn test_try_from_ok() {
         let map_data = MapData {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -273,6 +372,7 @@ mod tests {
             }),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -289,6 +389,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
@@ -313,6 +414,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
@@ -320,6 +422,59 @@ mod tests {
         assert!(hm.insert(1, 42, 0).is_ok());
     }
 
+    #[test]
+    fn test_insert_locked() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                assert_eq!(unsafe { attr.__bindgen_anon_2.flags }, BPF_F_LOCK as u64);
+                Ok(1)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        // the spin lock of maps loaded from the kernel can't be checked
+        let mut info = unsafe { mem::zeroed::<bpf_map_info>() };
+        info.type_ = BPF_MAP_TYPE_HASH as u32;
+        info.key_size = 4;
+        info.value_size = 8;
+        info.max_entries = 1024;
+        info.btf_key_type_id = 1;
+        info.btf_value_type_id = 2;
+        let mut map = MapData {
+            obj: obj::parse_map_info(info, PinningType::None),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let mut hm = HashMap::<_, u32, u64>::new(&mut map).unwrap();
+
+        assert!(hm.insert_locked(1, 42, 0).is_ok());
+    }
+
+    #[test]
+    fn test_insert_locked_without_spin_lock() {
+        override_syscall(|_| sys_error(EFAULT));
+
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
+
+        assert!(matches!(
+            hm.insert_locked(1, 42, 0),
+            Err(MapError::MissingSpinLock)
+        ));
+        assert!(matches!(hm.get_locked(&1), Err(MapError::MissingSpinLock)));
+    }
+
     #[test]
     fn test_insert_boxed_ok() {
         override_syscall(|call| match call {
@@ -334,6 +489,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
@@ -349,6 +505,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
@@ -373,6 +530,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
@@ -387,6 +545,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
@@ -410,6 +569,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
@@ -417,6 +577,117 @@ mod tests {
         assert!(matches!(hm.get(&1, 0), Err(MapError::KeyNotFound)));
     }
 
+    #[test]
+    fn test_get_large_value() {
+        const VALUE_SIZE: usize = 16 * 1024;
+
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                // the kernel writes value_size bytes
+                let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value } as *mut u8;
+                unsafe { slice::from_raw_parts_mut(value, VALUE_SIZE) }.fill(0xab);
+                Ok(1)
+            }
+            _ => sys_error(EFAULT),
+        });
+        let map = MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_HASH as u32,
+                    key_size: 4,
+                    value_size: VALUE_SIZE as u32,
+                    max_entries: 1,
+                    ..Default::default()
+                },
+                section_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+                symbol_index: 0,
+            }),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let hm = HashMap::<_, u32, [u8; VALUE_SIZE]>::new(&map).unwrap();
+
+        let value = hm.get(&1, 0).unwrap();
+        assert!(value.iter().all(|b| *b == 0xab));
+    }
+
+    #[test]
+    fn test_bytes_wrong_size() {
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
+
+        assert!(matches!(
+            hm.get_bytes(&[1, 2], 0),
+            Err(MapError::InvalidKeySize {
+                size: 2,
+                expected: 4
+            })
+        ));
+        assert!(matches!(
+            hm.insert_bytes(&[1, 2, 3, 4, 5], &[0; 4], 0),
+            Err(MapError::InvalidKeySize {
+                size: 5,
+                expected: 4
+            })
+        ));
+        assert!(matches!(
+            hm.insert_bytes(&[1, 2, 3, 4], &[0; 8], 0),
+            Err(MapError::InvalidValueSize {
+                size: 8,
+                expected: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn test_insert_get_bytes() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                assert_eq!(bpf_key::<[u8; 4]>(attr), Some(*b"key\0"));
+                assert_eq!(bpf_value::<[u8; 4]>(attr), Some([1, 2, 3, 4]));
+                Ok(1)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                assert_eq!(bpf_key::<[u8; 4]>(attr), Some(*b"key\0"));
+                let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value } as *mut u8;
+                unsafe { slice::from_raw_parts_mut(value, 4) }.copy_from_slice(&[1, 2, 3, 4]);
+                Ok(1)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
+
+        assert!(hm.insert_bytes(b"key\0", &[1, 2, 3, 4], 0).is_ok());
+        assert_eq!(hm.get_bytes(b"key\0", 0).unwrap(), vec![1, 2, 3, 4]);
+    }
+
     fn bpf_key<T: Copy>(attr: &bpf_attr) -> Option<T> {
         match unsafe { attr.__bindgen_anon_2.key } as *const T {
             p if p.is_null() => None,
@@ -424,6 +695,13 @@ mod tests {
         }
     }
 
+    fn bpf_value<T: Copy>(attr: &bpf_attr) -> Option<T> {
+        match unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value } as *const T {
+            p if p.is_null() => None,
+            p => Some(unsafe { *p }),
+        }
+    }
+
     fn set_next_key<T: Copy>(attr: &bpf_attr, next: T) {
         let key = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.next_key } as *const T as *mut T;
         unsafe { *key = next };
@@ -447,6 +725,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
@@ -496,6 +775,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
@@ -529,6 +809,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
@@ -543,6 +824,48 @@ mod tests {
         assert!(matches!(keys.next(), None));
     }
 
+    #[test]
+    fn test_keys_error_map_name() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_GET_NEXT_KEY,
+                attr,
+            } => {
+                match bpf_key::<u32>(attr) {
+                    None => set_next_key(attr, 10),
+                    Some(_) => return sys_error(EFAULT),
+                };
+
+                Ok(1)
+            }
+            _ => sys_error(EFAULT),
+        });
+        let map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            name: Some("foo".to_owned()),
+            btf_fd: None,
+        };
+        let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
+
+        let mut keys = hm.keys();
+        assert!(matches!(keys.next(), Some(Ok(10))));
+        let err = keys.next().unwrap().unwrap_err();
+        assert!(err.to_string().contains("`foo`"), "{err}");
+        match err {
+            MapError::IterationError { name, error } => {
+                assert_eq!(name, "foo");
+                assert!(matches!(
+                    *error,
+                    MapError::SyscallError { call, .. } if call == "bpf_map_get_next_key"
+                ));
+            }
+            e => panic!("unexpected error {e:?}"),
+        }
+        assert!(matches!(keys.next(), None));
+    }
+
     #[test]
     // Syscall overrides are performing integer-to-pointer conversions, which
     // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
@@ -564,6 +887,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
@@ -602,6 +926,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
@@ -641,6 +966,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
@@ -686,6 +1012,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
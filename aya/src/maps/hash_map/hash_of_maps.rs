@@ -0,0 +1,178 @@
+use std::{
+    borrow::Borrow,
+    convert::{AsMut, AsRef},
+    fmt,
+    marker::PhantomData,
+    os::unix::io::RawFd,
+};
+
+use crate::{
+    maps::{check_kv_size, hash_map, MapData, MapError, MapKeys},
+    sys::bpf_map_lookup_elem,
+    Pod,
+};
+
+/// A hash map of eBPF maps.
+///
+/// The maps stored in the hash map, the inner maps, are usually created at
+/// runtime. They must all be compatible with the template the hash map was
+/// created with, see [`BpfLoader::set_inner_map`](crate::BpfLoader::set_inner_map).
+/// eBPF programs look up an inner map with `bpf_map_lookup_elem(&outer, &key)`.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.12.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::BpfLoader::new().set_inner_map("OUTER", "INNER").load(&[])?;
+/// use aya::maps::{HashOfMaps, MapData};
+///
+/// let inner = MapData::from_pin("/sys/fs/bpf/inner")?;
+/// let mut outer = HashOfMaps::<_, u32>::try_from(bpf.map_mut("OUTER").unwrap())?;
+///
+/// // programs looking up the key 1234 of OUTER get INNER
+/// outer.insert(1234, &inner, 0)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_HASH_OF_MAPS")]
+pub struct HashOfMaps<T, K> {
+    inner: T,
+    _k: PhantomData<K>,
+}
+
+impl<T: AsRef<MapData>, K> fmt::Debug for HashOfMaps<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "HashOfMaps").finish()
+    }
+}
+
+impl<T: AsRef<MapData>, K: Pod> HashOfMaps<T, K> {
+    pub(crate) fn new(map: T) -> Result<HashOfMaps<T, K>, MapError> {
+        let data = map.as_ref();
+        check_kv_size::<K, RawFd>(data)?;
+        let _ = data.fd_or_err()?;
+
+        Ok(HashOfMaps {
+            inner: map,
+            _k: PhantomData,
+        })
+    }
+
+    /// Returns the id of the inner map associated with the key.
+    pub fn get(&self, key: &K, flags: u64) -> Result<u32, MapError> {
+        let fd = self.inner.as_ref().fd_or_err()?;
+        let value = bpf_map_lookup_elem(fd, key, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        value.ok_or(MapError::KeyNotFound)
+    }
+
+    /// An iterator visiting all keys in arbitrary order. The iterator element
+    /// type is `Result<K, MapError>`.
+    pub fn keys(&self) -> MapKeys<'_, K> {
+        MapKeys::new(self.inner.as_ref())
+    }
+}
+
+impl<T: AsMut<MapData>, K: Pod> HashOfMaps<T, K> {
+    /// Associates the map `inner` with the key.
+    ///
+    /// Fails with [`MapError::SyscallError`] if `inner` isn't compatible with
+    /// the template of the inner maps.
+    pub fn insert(
+        &mut self,
+        key: impl Borrow<K>,
+        inner: &MapData,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        let inner_fd = inner.fd_or_err()?;
+        hash_map::insert(self.inner.as_mut(), key.borrow(), &inner_fd, flags)
+    }
+
+    /// Removes a key from the map.
+    pub fn remove(&mut self, key: &K) -> Result<(), MapError> {
+        hash_map::remove(self.inner.as_mut(), key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_HASH_OF_MAPS},
+        obj::{
+            self,
+            maps::{LegacyMap, MapKind},
+        },
+        sys::{override_syscall, Syscall},
+    };
+
+    use super::*;
+
+    fn new_map(fd: Option<RawFd>) -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_HASH_OF_MAPS as u32,
+                    key_size: 8,
+                    value_size: 4,
+                    max_entries: 16,
+                    ..Default::default()
+                },
+                section_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+                symbol_index: 0,
+            }),
+            fd,
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        }
+    }
+
+    #[test]
+    fn test_wrong_key_size() {
+        let map = new_map(None);
+        assert!(matches!(
+            HashOfMaps::<_, u32>::new(&map),
+            Err(MapError::InvalidKeySize {
+                size: 4,
+                expected: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn test_insert_passes_inner_fd() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                assert_eq!(u.map_fd, 42);
+                assert_eq!(unsafe { *(u.key as *const u64) }, 1234);
+                assert_eq!(unsafe { *(u.__bindgen_anon_1.value as *const RawFd) }, 7);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let mut outer = new_map(Some(42));
+        let mut inner = new_map(Some(7));
+        let mut hash = HashOfMaps::<_, u64>::new(&mut outer).unwrap();
+        assert!(hash.insert(1234, &inner, 0).is_ok());
+
+        // don't close the fake fds
+        outer.fd = None;
+        inner.fd = None;
+    }
+}
@@ -2,12 +2,15 @@
 use std::{
     borrow::Borrow,
     convert::{AsMut, AsRef},
+    fmt,
     marker::PhantomData,
+    mem,
 };
 
 use crate::{
     maps::{
-        check_kv_size, hash_map, IterableMap, MapData, MapError, MapIter, MapKeys, PerCpuValues,
+        check_kv_size, check_nr_cpus, hash_map, possible_cpus, IterableMap, MapData, MapError,
+        MapIter, MapKeys, PerCpuValues,
     },
     sys::{bpf_map_lookup_elem_per_cpu, bpf_map_update_elem_per_cpu},
     Pod,
@@ -44,36 +47,89 @@ use crate::{
 #[doc(alias = "BPF_MAP_TYPE_PERCPU_HASH")]
 pub struct PerCpuHashMap<T, K: Pod, V: Pod> {
     inner: T,
+    nr_cpus: usize,
     _k: PhantomData<K>,
     _v: PhantomData<V>,
 }
 
+impl<T: AsRef<MapData>, K: Pod, V: Pod> fmt::Debug for PerCpuHashMap<T, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner
+            .as_ref()
+            .debug_struct(f, "PerCpuHashMap")
+            .finish()
+    }
+}
+
 impl<T: AsRef<MapData>, K: Pod, V: Pod> PerCpuHashMap<T, K, V> {
     pub(crate) fn new(map: T) -> Result<PerCpuHashMap<T, K, V>, MapError> {
         let data = map.as_ref();
         check_kv_size::<K, V>(data)?;
 
         let _ = data.fd_or_err()?;
+        let nr_cpus = possible_cpus()?;
 
         Ok(PerCpuHashMap {
             inner: map,
+            nr_cpus,
             _k: PhantomData,
             _v: PhantomData,
         })
     }
 
+    /// Returns the number of CPUs the values are stored for.
+    ///
+    /// This is the number of possible CPUs listed in
+    /// `/sys/devices/system/cpu/possible` unless overridden with
+    /// [`PerCpuHashMap::set_nr_cpus`].
+    pub fn nr_cpus(&self) -> usize {
+        self.nr_cpus
+    }
+
+    /// Overrides the number of CPUs the values are stored for.
+    ///
+    /// The kernel always reads and writes a value for each possible CPU of the
+    /// running system, so `nr_cpus` can't be lower than that. Raising it is
+    /// useful to exchange values with a system that has more CPUs, for
+    /// example in tests.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::InvalidCpuCount`] if `nr_cpus` is lower than the
+    /// number of possible CPUs.
+    pub fn set_nr_cpus(&mut self, nr_cpus: usize) -> Result<(), MapError> {
+        self.nr_cpus = check_nr_cpus(nr_cpus)?;
+        Ok(())
+    }
+
     /// Returns a slice of values - one for each CPU - associated with the key.
     pub fn get(&self, key: &K, flags: u64) -> Result<PerCpuValues<V>, MapError> {
         let fd = self.inner.as_ref().fd_or_err()?;
-        let values = bpf_map_lookup_elem_per_cpu(fd, key, flags).map_err(|(_, io_error)| {
-            MapError::SyscallError {
+        let values = bpf_map_lookup_elem_per_cpu(fd, key, self.nr_cpus, flags).map_err(
+            |(_, io_error)| MapError::SyscallError {
                 call: "bpf_map_lookup_elem".to_owned(),
                 io_error,
-            }
-        })?;
+            },
+        )?;
         values.ok_or(MapError::KeyNotFound)
     }
 
+    /// Returns the value associated with the key on the given `cpu`.
+    ///
+    /// The kernel always copies the values of all the CPUs, so this performs
+    /// the same lookup as [`PerCpuHashMap::get`] and only saves the caller
+    /// from dealing with [`PerCpuValues`].
+    pub fn get_for_cpu(&self, key: &K, cpu: u32, flags: u64) -> Result<V, MapError> {
+        let values = self.get(key, flags)?;
+        values
+            .get(cpu as usize)
+            .copied()
+            .ok_or(MapError::OutOfBounds {
+                index: cpu,
+                max_entries: values.len() as u32,
+            })
+    }
+
     /// An iterator visiting all key-value pairs in arbitrary order. The
     /// iterator item type is `Result<(K, PerCpuValues<V>), MapError>`.
     pub fn iter(&self) -> MapIter<'_, K, PerCpuValues<V>, Self> {
@@ -90,6 +146,9 @@ impl<T: AsRef<MapData>, K: Pod, V: Pod> PerCpuHashMap<T, K, V> {
 impl<T: AsMut<MapData>, K: Pod, V: Pod> PerCpuHashMap<T, K, V> {
     /// Inserts a slice of values - one for each CPU - for the given key.
     ///
+    /// Fails with [`MapError::InvalidCpuCount`] if there isn't one value for
+    /// each CPU, see [`PerCpuHashMap::nr_cpus`].
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -123,6 +182,12 @@ impl<T: AsMut<MapData>, K: Pod, V: Pod> PerCpuHashMap<T, K, V> {
         flags: u64,
     ) -> Result<(), MapError> {
         let fd = self.inner.as_mut().fd_or_err()?;
+        if values.len() != self.nr_cpus {
+            return Err(MapError::InvalidCpuCount {
+                count: values.len(),
+                expected: self.nr_cpus,
+            });
+        }
         bpf_map_update_elem_per_cpu(fd, key.borrow(), &values, flags).map_err(
             |(_, io_error)| MapError::SyscallError {
                 call: "bpf_map_update_elem".to_owned(),
@@ -133,6 +198,33 @@ impl<T: AsMut<MapData>, K: Pod, V: Pod> PerCpuHashMap<T, K, V> {
         Ok(())
     }
 
+    /// Inserts a value for the given key on the given `cpu`.
+    ///
+    /// The kernel can only update the values of all the CPUs at once, so the
+    /// values of the other CPUs are overwritten with zeroes. Use
+    /// [`PerCpuHashMap::insert`] to preserve them.
+    pub fn insert_for_cpu(
+        &mut self,
+        key: impl Borrow<K>,
+        cpu: u32,
+        value: V,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        let nr_cpus = self.nr_cpus;
+        if cpu as usize >= nr_cpus {
+            return Err(MapError::OutOfBounds {
+                index: cpu,
+                max_entries: nr_cpus as u32,
+            });
+        }
+        // Safety: V is Pod, so all zeroes is a valid value
+        let mut values = vec![unsafe { mem::zeroed::<V>() }; nr_cpus];
+        values[cpu as usize] = value;
+        // can't fail since we allocated exactly nr_cpus values
+        let values = PerCpuValues::with_cpu_count(values, nr_cpus).unwrap();
+        self.insert(key, values, flags)
+    }
+
     /// Removes a key from the map.
     pub fn remove(&mut self, key: &K) -> Result<(), MapError> {
         hash_map::remove(self.inner.as_mut(), key)
@@ -148,3 +240,191 @@ impl<T: AsRef<MapData>, K: Pod, V: Pod> IterableMap<K, PerCpuValues<V>> for PerC
         PerCpuHashMap::get(self, key, 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{io, slice};
+
+    use libc::EFAULT;
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_PERCPU_HASH},
+        maps::MapData,
+        obj::{
+            self,
+            maps::{LegacyMap, MapKind},
+        },
+        sys::{override_syscall, SysResult, Syscall},
+        util::nr_cpus,
+    };
+
+    use super::*;
+
+    fn new_obj_map() -> obj::Map {
+        obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: BPF_MAP_TYPE_PERCPU_HASH as u32,
+                key_size: 4,
+                value_size: 4,
+                max_entries: 1024,
+                ..Default::default()
+            },
+            section_index: 0,
+            data: Vec::new(),
+            kind: MapKind::Other,
+            symbol_index: 0,
+        })
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    // per-cpu values are 8 bytes aligned
+    fn per_cpu_slots<'a>(value: u64) -> &'a mut [u64] {
+        unsafe { slice::from_raw_parts_mut(value as *mut u64, nr_cpus().unwrap()) }
+    }
+
+    #[test]
+    fn test_insert_for_cpu() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value };
+                for (cpu, slot) in per_cpu_slots(value).iter().enumerate() {
+                    assert_eq!(*slot, if cpu == 0 { 42 } else { 0 });
+                }
+                Ok(1)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let mut hm = PerCpuHashMap::<_, u32, u32>::new(&mut map).unwrap();
+
+        assert!(hm.insert_for_cpu(1, 0, 42, 0).is_ok());
+    }
+
+    #[test]
+    fn test_insert_for_cpu_out_of_bounds() {
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let mut hm = PerCpuHashMap::<_, u32, u32>::new(&mut map).unwrap();
+
+        let nr_cpus = nr_cpus().unwrap() as u32;
+        assert!(matches!(
+            hm.insert_for_cpu(1, nr_cpus, 42, 0),
+            Err(MapError::OutOfBounds { index, max_entries }) if index == nr_cpus && max_entries == nr_cpus
+        ));
+    }
+
+    #[test]
+    fn test_get_for_cpu() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value };
+                for (cpu, slot) in per_cpu_slots(value).iter_mut().enumerate() {
+                    *slot = cpu as u64 + 10;
+                }
+                Ok(0)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let hm = PerCpuHashMap::<_, u32, u32>::new(&map).unwrap();
+
+        assert_eq!(hm.get_for_cpu(&1, 0, 0).unwrap(), 10);
+        let nr_cpus = nr_cpus().unwrap() as u32;
+        assert_eq!(hm.get_for_cpu(&1, nr_cpus - 1, 0).unwrap(), nr_cpus + 9);
+        assert!(matches!(
+            hm.get_for_cpu(&1, nr_cpus, 0),
+            Err(MapError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_set_nr_cpus() {
+        // more than any machine running the tests
+        const NR_CPUS: usize = 8192;
+
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value };
+                let slots = unsafe { slice::from_raw_parts_mut(value as *mut u64, NR_CPUS) };
+                for (cpu, slot) in slots.iter_mut().enumerate() {
+                    *slot = cpu as u64;
+                }
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value };
+                let slots = unsafe { slice::from_raw_parts(value as *const u64, NR_CPUS) };
+                assert!(slots.iter().all(|slot| *slot == 42));
+                Ok(0)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let mut hm = PerCpuHashMap::<_, u32, u32>::new(&mut map).unwrap();
+        assert_eq!(hm.nr_cpus(), nr_cpus().unwrap());
+        assert!(matches!(
+            hm.set_nr_cpus(0),
+            Err(MapError::InvalidCpuCount { count: 0, .. })
+        ));
+        hm.set_nr_cpus(NR_CPUS).unwrap();
+
+        let values = hm.get(&1, 0).unwrap();
+        assert_eq!(values.len(), NR_CPUS);
+        assert_eq!(values[NR_CPUS - 1], NR_CPUS as u32 - 1);
+
+        let values = PerCpuValues::with_cpu_count(vec![42u32; NR_CPUS], NR_CPUS).unwrap();
+        assert!(hm.insert(1, values, 0).is_ok());
+
+        let values = PerCpuValues::with_cpu_count(vec![42u32; 2], 2).unwrap();
+        assert!(matches!(
+            hm.insert(1, values, 0),
+            Err(MapError::InvalidCpuCount {
+                count: 2,
+                expected: NR_CPUS
+            })
+        ));
+        assert!(PerCpuValues::with_cpu_count(vec![42u32; 2], NR_CPUS).is_err());
+    }
+}
@@ -2,6 +2,7 @@
 use std::{
     borrow::Borrow,
     convert::{AsMut, AsRef},
+    fmt,
     marker::PhantomData,
 };
 
@@ -52,6 +53,12 @@ pub struct LpmTrie<T, K, V> {
     _v: PhantomData<V>,
 }
 
+impl<T: AsRef<MapData>, K, V> fmt::Debug for LpmTrie<T, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "LpmTrie").finish()
+    }
+}
+
 /// A Key for and LpmTrie map.
 ///
 /// # Examples
@@ -282,6 +289,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         assert!(matches!(
@@ -299,6 +307,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         assert!(matches!(
@@ -329,6 +338,7 @@ mod tests {
             fd: None,
             btf_fd: None,
             pinned: false,
+            name: None,
         };
 
         let map = Map::PerfEventArray(map_data);
@@ -345,6 +355,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -360,6 +371,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -372,6 +384,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -387,6 +400,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let mut trie = LpmTrie::<_, u32, u32>::new(&mut map).unwrap();
@@ -412,6 +426,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
 
@@ -429,6 +444,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let mut trie = LpmTrie::<_, u32, u32>::new(&mut map).unwrap();
@@ -454,6 +470,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let mut trie = LpmTrie::<_, u32, u32>::new(&mut map).unwrap();
@@ -469,6 +486,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let trie = LpmTrie::<_, u32, u32>::new(&map).unwrap();
@@ -494,6 +512,7 @@ mod tests {
             obj: new_obj_map(),
             fd: Some(42),
             pinned: false,
+            name: None,
             btf_fd: None,
         };
         let trie = LpmTrie::<_, u32, u32>::new(&map).unwrap();
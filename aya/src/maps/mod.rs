@@ -48,16 +48,18 @@ use std::{
     ptr,
 };
 
-use libc::{getrlimit, rlimit, RLIMIT_MEMLOCK, RLIM_INFINITY};
+use libc::{getrlimit, rlimit, EBUSY, RLIMIT_MEMLOCK, RLIM_INFINITY};
 use log::warn;
 use thiserror::Error;
 
 use crate::{
+    generated::{bpf_map_info, bpf_map_type},
     obj::{self, parse_map_info},
     pin::PinError,
     sys::{
-        bpf_create_map, bpf_get_object, bpf_map_get_info_by_fd, bpf_map_get_next_key,
-        bpf_pin_object, kernel_version,
+        bpf_create_map, bpf_get_object, bpf_map_freeze, bpf_map_get_fd_by_id,
+        bpf_map_get_info_by_fd, bpf_map_get_next_key, bpf_map_get_next_key_ptr,
+        bpf_map_lookup_elem_ptr, bpf_map_update_elem_ptr, bpf_pin_object, kernel_version,
     },
     util::nr_cpus,
     PinningType, Pod,
@@ -65,6 +67,7 @@ use crate::{
 
 pub mod array;
 pub mod bloom_filter;
+pub mod cgroup_storage;
 pub mod hash_map;
 pub mod lpm_trie;
 pub mod perf;
@@ -73,16 +76,17 @@ pub mod sock;
 pub mod stack;
 pub mod stack_trace;
 
-pub use array::{Array, PerCpuArray, ProgramArray};
+pub use array::{Array, ArrayOfMaps, PerCpuArray, ProgramArray};
 pub use bloom_filter::BloomFilter;
-pub use hash_map::{HashMap, PerCpuHashMap};
+pub use cgroup_storage::{CgroupStorage, PerCpuCgroupStorage};
+pub use hash_map::{HashMap, HashOfMaps, PerCpuHashMap};
 pub use lpm_trie::LpmTrie;
 #[cfg(feature = "async")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
 pub use perf::AsyncPerfEventArray;
 pub use perf::PerfEventArray;
 pub use queue::Queue;
-pub use sock::{SockHash, SockMap};
+pub use sock::{ReusePortSockArray, SockHash, SockMap};
 pub use stack::Stack;
 pub use stack_trace::StackTraceMap;
 
@@ -174,6 +178,48 @@ pub enum MapError {
         io_error: io::Error,
     },
 
+    /// An error occurred while iterating a map
+    #[error("failed to iterate map `{name}`")]
+    IterationError {
+        /// Map name
+        name: String,
+        /// The error returned by the iteration
+        #[source]
+        error: Box<MapError>,
+    },
+
+    /// The map is already frozen
+    #[error("the map is already frozen")]
+    AlreadyFrozen,
+
+    /// The number of per-cpu values doesn't match the number of CPUs of the map
+    #[error("{count} per-cpu values, expected one for each of the {expected} possible CPUs")]
+    InvalidCpuCount {
+        /// The number of values
+        count: usize,
+        /// The number of possible CPUs of the map
+        expected: usize,
+    },
+
+    /// The map values don't contain a `struct bpf_spin_lock`
+    #[error("the values of the map don't contain a `struct bpf_spin_lock`")]
+    MissingSpinLock,
+
+    /// An existing map doesn't match the definition of the map in the object
+    #[error(
+        "existing map `{name}` doesn't match its definition: {field} is {actual}, expected {expected}"
+    )]
+    IncompatibleMap {
+        /// The map name
+        name: String,
+        /// The mismatching field
+        field: &'static str,
+        /// The value of the existing map
+        actual: u32,
+        /// The value defined in the object
+        expected: u32,
+    },
+
     /// Could not pin map by name
     #[error("map `{name:?}` requested pinning by name. pinning failed")]
     PinError {
@@ -258,11 +304,21 @@ pub enum Map {
     StackTraceMap(MapData),
     /// A [`Queue`] map
     Queue(MapData),
+    /// A [`ReusePortSockArray`] map
+    ReusePortSockArray(MapData),
+    /// A [`ArrayOfMaps`] map
+    ArrayOfMaps(MapData),
+    /// A [`HashOfMaps`] map
+    HashOfMaps(MapData),
+    /// A [`CgroupStorage`] map
+    CgroupStorage(MapData),
+    /// A [`PerCpuCgroupStorage`] map
+    PerCpuCgroupStorage(MapData),
 }
 
 impl Map {
     /// Returns the low level map type.
-    fn map_type(&self) -> u32 {
+    pub(crate) fn map_type(&self) -> u32 {
         match self {
             Map::Array(map) => map.obj.map_type(),
             Map::PerCpuArray(map) => map.obj.map_type(),
@@ -277,6 +333,77 @@ impl Map {
             Map::Stack(map) => map.obj.map_type(),
             Map::StackTraceMap(map) => map.obj.map_type(),
             Map::Queue(map) => map.obj.map_type(),
+            Map::ReusePortSockArray(map) => map.obj.map_type(),
+            Map::ArrayOfMaps(map) => map.obj.map_type(),
+            Map::HashOfMaps(map) => map.obj.map_type(),
+            Map::CgroupStorage(map) => map.obj.map_type(),
+            Map::PerCpuCgroupStorage(map) => map.obj.map_type(),
+        }
+    }
+
+    /// Returns the data of the map.
+    pub(crate) fn data(&self) -> &MapData {
+        match self {
+            Map::Array(map) => map,
+            Map::PerCpuArray(map) => map,
+            Map::ProgramArray(map) => map,
+            Map::HashMap(map) => map,
+            Map::PerCpuHashMap(map) => map,
+            Map::PerfEventArray(map) => map,
+            Map::SockHash(map) => map,
+            Map::SockMap(map) => map,
+            Map::BloomFilter(map) => map,
+            Map::LpmTrie(map) => map,
+            Map::Stack(map) => map,
+            Map::StackTraceMap(map) => map,
+            Map::Queue(map) => map,
+            Map::ReusePortSockArray(map) => map,
+            Map::ArrayOfMaps(map) => map,
+            Map::HashOfMaps(map) => map,
+            Map::CgroupStorage(map) => map,
+            Map::PerCpuCgroupStorage(map) => map,
+        }
+    }
+
+    /// Returns the maximum number of entries of the map, see
+    /// [`MapData::max_entries`].
+    pub fn max_entries(&self) -> u32 {
+        self.data().max_entries()
+    }
+
+    /// Returns the number of entries currently in the map, see
+    /// [`MapData::len`].
+    pub fn len(&self) -> Result<usize, MapError> {
+        self.data().len()
+    }
+
+    /// Returns `true` if the map contains no entries, see
+    /// [`MapData::is_empty`].
+    pub fn is_empty(&self) -> Result<bool, MapError> {
+        self.data().is_empty()
+    }
+
+    /// Freezes the map, see [`MapData::freeze`].
+    pub fn freeze(&self) -> Result<(), MapError> {
+        match self {
+            Map::Array(map) => map.freeze(),
+            Map::PerCpuArray(map) => map.freeze(),
+            Map::ProgramArray(map) => map.freeze(),
+            Map::HashMap(map) => map.freeze(),
+            Map::PerCpuHashMap(map) => map.freeze(),
+            Map::PerfEventArray(map) => map.freeze(),
+            Map::SockHash(map) => map.freeze(),
+            Map::SockMap(map) => map.freeze(),
+            Map::BloomFilter(map) => map.freeze(),
+            Map::LpmTrie(map) => map.freeze(),
+            Map::Stack(map) => map.freeze(),
+            Map::StackTraceMap(map) => map.freeze(),
+            Map::Queue(map) => map.freeze(),
+            Map::ReusePortSockArray(map) => map.freeze(),
+            Map::ArrayOfMaps(map) => map.freeze(),
+            Map::HashOfMaps(map) => map.freeze(),
+            Map::CgroupStorage(map) => map.freeze(),
+            Map::PerCpuCgroupStorage(map) => map.freeze(),
         }
     }
 }
@@ -331,6 +458,8 @@ impl_try_from_map!(
     SockMap from Map::SockMap,
     PerfEventArray from Map::PerfEventArray,
     StackTraceMap from Map::StackTraceMap,
+    ReusePortSockArray from Map::ReusePortSockArray,
+    ArrayOfMaps from Map::ArrayOfMaps,
 );
 
 #[cfg(feature = "async")]
@@ -384,7 +513,17 @@ macro_rules! impl_try_from_map_generic_key_or_value {
    }
 }
 
-impl_try_from_map_generic_key_or_value!(Array, PerCpuArray, SockHash, BloomFilter, Queue, Stack,);
+impl_try_from_map_generic_key_or_value!(
+    Array,
+    PerCpuArray,
+    SockHash,
+    BloomFilter,
+    Queue,
+    Stack,
+    HashOfMaps,
+    CgroupStorage,
+    PerCpuCgroupStorage,
+);
 
 macro_rules! impl_try_from_map_generic_key_and_value {
     ($($ty:ident),+ $(,)?) => {
@@ -440,6 +579,15 @@ pub(crate) fn check_bounds(map: &MapData, index: u32) -> Result<(), MapError> {
     }
 }
 
+pub(crate) fn check_spin_lock(map: &MapData) -> Result<(), MapError> {
+    // maps loaded from the kernel are checked by the kernel itself
+    if map.obj.has_spin_lock() == Some(false) {
+        Err(MapError::MissingSpinLock)
+    } else {
+        Ok(())
+    }
+}
+
 pub(crate) fn check_kv_size<K, V>(map: &MapData) -> Result<(), MapError> {
     let size = mem::size_of::<K>();
     let expected = map.obj.key_size() as usize;
@@ -463,16 +611,42 @@ pub(crate) fn check_v_size<V>(map: &MapData) -> Result<(), MapError> {
     Ok(())
 }
 
+pub(crate) fn possible_cpus() -> Result<usize, MapError> {
+    nr_cpus().map_err(|io_error| MapError::SyscallError {
+        call: "nr_cpus".to_owned(),
+        io_error,
+    })
+}
+
+// The kernel copies a value for each possible CPU in and out of the buffers of
+// per-cpu maps, so they can't be sized for fewer CPUs.
+pub(crate) fn check_nr_cpus(nr_cpus: usize) -> Result<usize, MapError> {
+    let possible_cpus = possible_cpus()?;
+    if nr_cpus < possible_cpus {
+        return Err(MapError::InvalidCpuCount {
+            count: nr_cpus,
+            expected: possible_cpus,
+        });
+    }
+    Ok(nr_cpus)
+}
+
 /// A generic handle to a BPF map.
 ///
 /// You should never need to use this unless you're implementing a new map type.
-#[derive(Debug)]
 pub struct MapData {
     pub(crate) obj: obj::Map,
     pub(crate) fd: Option<RawFd>,
     pub(crate) btf_fd: Option<RawFd>,
     /// Indicates if this map has been pinned to bpffs
     pub pinned: bool,
+    pub(crate) name: Option<String>,
+}
+
+impl fmt::Debug for MapData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.debug_struct(f, "MapData").finish()
+    }
 }
 
 impl AsRef<MapData> for MapData {
@@ -490,24 +664,71 @@ impl AsMut<MapData> for MapData {
 impl MapData {
     /// Creates a new map with the provided `name`
     pub fn create(&mut self, name: &str) -> Result<RawFd, MapError> {
+        self.create_with_inner_map(name, None)
+    }
+
+    /// Creates a new map with the provided `name`, passing `inner_map_fd` as
+    /// the template of the inner maps of a map of maps.
+    pub(crate) fn create_with_inner_map(
+        &mut self,
+        name: &str,
+        inner_map_fd: Option<RawFd>,
+    ) -> Result<RawFd, MapError> {
         if self.fd.is_some() {
             return Err(MapError::AlreadyCreated { name: name.into() });
         }
 
         let c_name = CString::new(name).map_err(|_| MapError::InvalidName { name: name.into() })?;
 
-        let fd = bpf_create_map(&c_name, &self.obj, self.btf_fd).map_err(|(code, io_error)| {
-            let k_ver = kernel_version().unwrap();
-            if k_ver < (5, 11, 0) {
-                maybe_warn_rlimit();
-            }
+        let fd = bpf_create_map(&c_name, &self.obj, self.btf_fd, inner_map_fd).map_err(
+            |(code, io_error)| {
+                let k_ver = kernel_version().unwrap();
+                if k_ver < (5, 11, 0) {
+                    maybe_warn_rlimit();
+                }
 
-            MapError::CreateError {
-                name: name.into(),
-                code,
-                io_error,
+                MapError::CreateError {
+                    name: name.into(),
+                    code,
+                    io_error,
+                }
+            },
+        )? as RawFd;
+
+        self.fd = Some(fd);
+        self.name = Some(name.into());
+
+        Ok(fd)
+    }
+
+    /// Uses the existing map referenced by `fd` instead of creating a new one.
+    ///
+    /// Fails with [`MapError::IncompatibleMap`] if the type, key size, value
+    /// size or max entries of the existing map don't match the definition.
+    pub(crate) fn adopt(&mut self, name: &str, fd: RawFd) -> Result<RawFd, MapError> {
+        if self.fd.is_some() {
+            return Err(MapError::AlreadyCreated { name: name.into() });
+        }
+
+        let info = bpf_map_get_info_by_fd(fd).map_err(|io_error| MapError::SyscallError {
+            call: "BPF_OBJ_GET_INFO_BY_FD".to_owned(),
+            io_error,
+        })?;
+        for (field, actual, expected) in [
+            ("type", info.type_, self.obj.map_type()),
+            ("key size", info.key_size, self.obj.key_size()),
+            ("value size", info.value_size, self.obj.value_size()),
+            ("max entries", info.max_entries, self.obj.max_entries()),
+        ] {
+            if actual != expected {
+                return Err(MapError::IncompatibleMap {
+                    name: name.into(),
+                    field,
+                    actual,
+                    expected,
+                });
             }
-        })? as RawFd;
+        }
 
         self.fd = Some(fd);
 
@@ -534,6 +755,31 @@ impl MapData {
         Ok(fd)
     }
 
+    /// Opens the map pinned at `path/name`, or creates the map and pins it if
+    /// it isn't pinned yet.
+    pub(crate) fn create_pinned<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        path: P,
+        inner_map_fd: Option<RawFd>,
+    ) -> Result<RawFd, MapError> {
+        // try to open map in case it's already pinned
+        match self.open_pinned(name, &path) {
+            Ok(fd) => {
+                self.pinned = true;
+                Ok(fd)
+            }
+            Err(_) => {
+                let fd = self.create_with_inner_map(name, inner_map_fd)?;
+                self.pin(name, &path).map_err(|error| MapError::PinError {
+                    name: Some(name.to_string()),
+                    error,
+                })?;
+                Ok(fd)
+            }
+        }
+    }
+
     /// Loads a map from a pinned path in bpffs.
     pub fn from_pin<P: AsRef<Path>>(path: P) -> Result<MapData, MapError> {
         let path_string =
@@ -557,6 +803,7 @@ impl MapData {
         })?;
 
         Ok(MapData {
+            name: map_info_name(&info),
             obj: parse_map_info(info, PinningType::ByName),
             fd: Some(fd),
             btf_fd: None,
@@ -569,13 +816,55 @@ impl MapData {
     /// If loading from a BPF Filesystem (bpffs) you should use [`Map::from_pin`](crate::maps::MapData::from_pin).
     /// This API is intended for cases where you have received a valid BPF FD from some other means.
     /// For example, you received an FD over Unix Domain Socket.
+    ///
+    /// The definition of the map is read from the kernel. The returned map
+    /// takes ownership of `fd` and closes it when dropped. If reading the
+    /// definition fails, `fd` is closed too.
     pub fn from_fd(fd: RawFd) -> Result<MapData, MapError> {
-        let info = bpf_map_get_info_by_fd(fd).map_err(|io_error| MapError::SyscallError {
-            call: "BPF_OBJ_GET".to_owned(),
+        let info = match bpf_map_get_info_by_fd(fd) {
+            Ok(info) => info,
+            Err(io_error) => {
+                unsafe { libc::close(fd) };
+                return Err(MapError::SyscallError {
+                    call: "BPF_OBJ_GET_INFO_BY_FD".to_owned(),
+                    io_error,
+                });
+            }
+        };
+
+        Ok(MapData {
+            name: map_info_name(&info),
+            obj: parse_map_info(info, PinningType::None),
+            fd: Some(fd),
+            btf_fd: None,
+            pinned: false,
+        })
+    }
+
+    /// Loads a map from its id.
+    ///
+    /// Map ids are global and can be used to share a map with other threads
+    /// or processes without pinning it. The returned map owns a new file
+    /// descriptor referring to the map.
+    pub fn from_id(id: u32) -> Result<MapData, MapError> {
+        let fd = bpf_map_get_fd_by_id(id).map_err(|io_error| MapError::SyscallError {
+            call: "BPF_MAP_GET_FD_BY_ID".to_owned(),
             io_error,
         })?;
 
+        let info = match bpf_map_get_info_by_fd(fd) {
+            Ok(info) => info,
+            Err(io_error) => {
+                unsafe { libc::close(fd) };
+                return Err(MapError::SyscallError {
+                    call: "BPF_MAP_GET_INFO_BY_FD".to_owned(),
+                    io_error,
+                });
+            }
+        };
+
         Ok(MapData {
+            name: map_info_name(&info),
             obj: parse_map_info(info, PinningType::None),
             fd: Some(fd),
             btf_fd: None,
@@ -583,10 +872,208 @@ impl MapData {
         })
     }
 
+    // Starts formatting the map as a struct called `name`, with the fields of
+    // the map definition. The fd is left out on purpose so it doesn't end up
+    // in logs.
+    pub(crate) fn debug_struct<'a, 'b: 'a>(
+        &self,
+        f: &'a mut fmt::Formatter<'b>,
+        name: &str,
+    ) -> fmt::DebugStruct<'a, 'b> {
+        let mut s = f.debug_struct(name);
+        if let Some(name) = &self.name {
+            s.field("name", name);
+        }
+        match bpf_map_type::try_from(self.obj.map_type()) {
+            Ok(map_type) => s.field("map_type", &map_type),
+            Err(_) => s.field("map_type", &self.obj.map_type()),
+        };
+        s.field("key_size", &self.obj.key_size())
+            .field("value_size", &self.obj.value_size())
+            .field("max_entries", &self.obj.max_entries())
+            .field("pinned", &self.pinned);
+        s
+    }
+
+    /// Returns the name of the map, if known.
+    ///
+    /// This is the name of the map in the object file for maps created by
+    /// [`Bpf`](crate::Bpf), and the name reported by the kernel for maps
+    /// loaded with [`from_pin`](Self::from_pin), [`from_fd`](Self::from_fd)
+    /// or [`from_id`](Self::from_id).
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the maximum number of entries the map can hold, as defined
+    /// when the map was created.
+    pub fn max_entries(&self) -> u32 {
+        self.obj.max_entries()
+    }
+
+    /// Returns the number of entries currently in the map.
+    ///
+    /// The kernel doesn't keep track of the number of entries of a map, so
+    /// this walks all the keys with `BPF_MAP_GET_NEXT_KEY` and is O(n). Since
+    /// eBPF programs can update the map concurrently, the result is only a
+    /// snapshot and may be stale by the time it is returned.
+    ///
+    /// Array maps always contain `max_entries` entries. Maps that can't be
+    /// iterated, like queues and stacks, return a [`MapError::SyscallError`].
+    pub fn len(&self) -> Result<usize, MapError> {
+        let fd = self.fd_or_err()?;
+        let key_size = self.obj.key_size() as usize;
+        let mut key = vec![0u8; key_size];
+        let mut next_key = vec![0u8; key_size];
+        let mut count = 0;
+        loop {
+            let prev = if count == 0 { None } else { Some(key.as_ptr()) };
+            match bpf_map_get_next_key_ptr(fd, prev, next_key.as_mut_ptr()) {
+                Ok(Some(())) => {
+                    count += 1;
+                    mem::swap(&mut key, &mut next_key);
+                }
+                Ok(None) => return Ok(count),
+                Err((_, io_error)) => {
+                    return Err(self.iteration_error(MapError::SyscallError {
+                        call: "bpf_map_get_next_key".to_owned(),
+                        io_error,
+                    }))
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the map contains no entries.
+    ///
+    /// Unlike [`len`](Self::len), this only looks up the first key.
+    pub fn is_empty(&self) -> Result<bool, MapError> {
+        let fd = self.fd_or_err()?;
+        let mut key = vec![0u8; self.obj.key_size() as usize];
+        match bpf_map_get_next_key_ptr(fd, None, key.as_mut_ptr()) {
+            Ok(found) => Ok(found.is_none()),
+            Err((_, io_error)) => Err(self.iteration_error(MapError::SyscallError {
+                call: "bpf_map_get_next_key".to_owned(),
+                io_error,
+            })),
+        }
+    }
+
+    /// Returns a copy of the value associated with the key, as raw bytes.
+    ///
+    /// Unlike the typed maps, this doesn't need [`Pod`] key and value types,
+    /// so it can be used with any map whose values can be looked up, like
+    /// hash maps and arrays. The length of `key` must match the key size of
+    /// the map.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::maps::Map;
+    ///
+    /// if let Some(Map::HashMap(map)) = bpf.map_mut("NAMES") {
+    ///     // keys are NUL-padded strings of 16 bytes
+    ///     let mut key = [0u8; 16];
+    ///     key[..4].copy_from_slice(b"eth0");
+    ///     map.insert_bytes(&key, &1u32.to_ne_bytes(), 0)?;
+    ///     assert_eq!(map.get_bytes(&key, 0)?, 1u32.to_ne_bytes());
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn get_bytes(&self, key: &[u8], flags: u64) -> Result<Vec<u8>, MapError> {
+        self.check_key_bytes(key)?;
+        let fd = self.fd_or_err()?;
+
+        let mut value = vec![0u8; self.obj.value_size() as usize];
+        let found = bpf_map_lookup_elem_ptr(fd, key.first(), value.as_mut_ptr(), flags).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            },
+        )?;
+        found.map(|_| value).ok_or(MapError::KeyNotFound)
+    }
+
+    /// Inserts a key-value pair given as raw bytes into the map.
+    ///
+    /// Unlike the typed maps, this doesn't need [`Pod`] key and value types.
+    /// The lengths of `key` and `value` must match the key and value sizes of
+    /// the map. See [`get_bytes`](Self::get_bytes).
+    pub fn insert_bytes(&mut self, key: &[u8], value: &[u8], flags: u64) -> Result<(), MapError> {
+        self.check_key_bytes(key)?;
+        let expected = self.obj.value_size() as usize;
+        if value.len() != expected {
+            return Err(MapError::InvalidValueSize {
+                size: value.len(),
+                expected,
+            });
+        }
+        let fd = self.fd_or_err()?;
+
+        // the kernel only reads the value, the pointer is mutable because the
+        // same attr field is used for lookups
+        bpf_map_update_elem_ptr(fd, key.as_ptr(), value.as_ptr() as *mut u8, flags).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    fn check_key_bytes(&self, key: &[u8]) -> Result<(), MapError> {
+        let expected = self.obj.key_size() as usize;
+        if key.len() != expected {
+            return Err(MapError::InvalidKeySize {
+                size: key.len(),
+                expected,
+            });
+        }
+        Ok(())
+    }
+
+    // Adds the name of the map, if known, to an error that happened while
+    // iterating it.
+    fn iteration_error(&self, error: MapError) -> MapError {
+        match &self.name {
+            Some(name) => MapError::IterationError {
+                name: name.clone(),
+                error: Box::new(error),
+            },
+            None => error,
+        }
+    }
+
     pub(crate) fn fd_or_err(&self) -> Result<RawFd, MapError> {
         self.fd.ok_or(MapError::NotCreated)
     }
 
+    /// Freezes the map, making it read-only from user space.
+    ///
+    /// eBPF programs can still update a frozen map, unless it was created
+    /// with `BPF_F_RDONLY_PROG`. Freezing can't be undone, and fails with
+    /// [`MapError::AlreadyFrozen`] if the map is already frozen.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.2.
+    pub fn freeze(&self) -> Result<(), MapError> {
+        let fd = self.fd_or_err()?;
+        bpf_map_freeze(fd).map_err(|(_, io_error)| {
+            if io_error.raw_os_error() == Some(EBUSY) {
+                MapError::AlreadyFrozen
+            } else {
+                MapError::SyscallError {
+                    call: "bpf_map_freeze".to_owned(),
+                    io_error,
+                }
+            }
+        })?;
+        Ok(())
+    }
+
     pub(crate) fn pin<P: AsRef<Path>>(&mut self, name: &str, path: P) -> Result<(), PinError> {
         if self.pinned {
             return Err(PinError::AlreadyPinned { name: name.into() });
@@ -637,10 +1124,22 @@ impl Clone for MapData {
             },
             btf_fd: self.btf_fd,
             pinned: self.pinned,
+            name: self.name.clone(),
         }
     }
 }
 
+// Returns the name of the map in `info`, or None if the map has no name.
+fn map_info_name(info: &bpf_map_info) -> Option<String> {
+    let name = info
+        .name
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8)
+        .collect::<Vec<_>>();
+    String::from_utf8(name).ok().filter(|name| !name.is_empty())
+}
+
 /// An iterable map
 pub trait IterableMap<K: Pod, V> {
     /// Get a generic map handle
@@ -679,7 +1178,7 @@ impl<K: Pod> Iterator for MapKeys<'_, K> {
             Ok(fd) => fd,
             Err(e) => {
                 self.err = true;
-                return Some(Err(e));
+                return Some(Err(self.map.iteration_error(e)));
             }
         };
 
@@ -694,10 +1193,10 @@ impl<K: Pod> Iterator for MapKeys<'_, K> {
             }
             Err((_, io_error)) => {
                 self.err = true;
-                Some(Err(MapError::SyscallError {
+                Some(Err(self.map.iteration_error(MapError::SyscallError {
                     call: "bpf_map_get_next_key".to_owned(),
                     io_error,
-                }))
+                })))
             }
         }
     }
@@ -729,7 +1228,7 @@ impl<K: Pod, V, I: IterableMap<K, V>> Iterator for MapIter<'_, K, V, I> {
                 Some(Ok(key)) => match self.map.get(&key) {
                     Ok(value) => return Some(Ok((key, value))),
                     Err(MapError::KeyNotFound) => continue,
-                    Err(e) => return Some(Err(e)),
+                    Err(e) => return Some(Err(self.map.map().iteration_error(e))),
                 },
                 Some(Err(e)) => return Some(Err(e)),
                 None => return None,
@@ -780,7 +1279,19 @@ impl<T: Pod> TryFrom<Vec<T>> for PerCpuValues<T> {
     type Error = io::Error;
 
     fn try_from(values: Vec<T>) -> Result<Self, Self::Error> {
-        let nr_cpus = nr_cpus()?;
+        PerCpuValues::with_cpu_count(values, nr_cpus()?)
+    }
+}
+
+impl<T: Pod> PerCpuValues<T> {
+    /// Creates the values of `nr_cpus` CPUs.
+    ///
+    /// Unlike [`PerCpuValues::try_from`], which expects one value for each
+    /// possible CPU listed in `/sys/devices/system/cpu/possible`, this takes
+    /// the number of CPUs explicitly. Use it with maps whose number of CPUs
+    /// was overridden, see [`PerCpuArray::set_nr_cpus`] and
+    /// [`PerCpuHashMap::set_nr_cpus`].
+    pub fn with_cpu_count(values: Vec<T>, nr_cpus: usize) -> Result<Self, io::Error> {
         if values.len() != nr_cpus {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
@@ -791,14 +1302,12 @@ impl<T: Pod> TryFrom<Vec<T>> for PerCpuValues<T> {
             values: values.into_boxed_slice(),
         })
     }
-}
 
-impl<T: Pod> PerCpuValues<T> {
-    pub(crate) fn alloc_kernel_mem() -> Result<PerCpuKernelMem, io::Error> {
+    pub(crate) fn alloc_kernel_mem(nr_cpus: usize) -> PerCpuKernelMem {
         let value_size = (mem::size_of::<T>() + 7) & !7;
-        Ok(PerCpuKernelMem {
-            bytes: vec![0u8; nr_cpus()? * value_size],
-        })
+        PerCpuKernelMem {
+            bytes: vec![0u8; nr_cpus * value_size],
+        }
     }
 
     pub(crate) unsafe fn from_kernel_mem(mem: PerCpuKernelMem) -> PerCpuValues<T> {
@@ -816,15 +1325,15 @@ impl<T: Pod> PerCpuValues<T> {
         }
     }
 
-    pub(crate) fn build_kernel_mem(&self) -> Result<PerCpuKernelMem, io::Error> {
-        let mut mem = PerCpuValues::<T>::alloc_kernel_mem()?;
+    pub(crate) fn build_kernel_mem(&self) -> PerCpuKernelMem {
+        let mut mem = PerCpuValues::<T>::alloc_kernel_mem(self.values.len());
         let mem_ptr = mem.as_mut_ptr() as usize;
         let value_size = (mem::size_of::<T>() + 7) & !7;
         for i in 0..self.values.len() {
             unsafe { ptr::write_unaligned((mem_ptr + i * value_size) as *mut _, self.values[i]) };
         }
 
-        Ok(mem)
+        mem
     }
 }
 
@@ -838,11 +1347,16 @@ impl<T: Pod> Deref for PerCpuValues<T> {
 
 #[cfg(test)]
 mod tests {
-    use libc::EFAULT;
+    use std::{cell::Cell, slice};
+
+    use libc::{EFAULT, ENOENT};
 
     use crate::{
         bpf_map_def,
-        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_HASH},
+        generated::{
+            bpf_cmd, bpf_map_info,
+            bpf_map_type::{BPF_MAP_TYPE_HASH, BPF_MAP_TYPE_PERCPU_ARRAY},
+        },
         maps::MapData,
         obj::maps::{LegacyMap, MapKind},
         sys::{override_syscall, Syscall},
@@ -871,6 +1385,7 @@ mod tests {
             obj: new_obj_map(),
             fd: None,
             pinned: false,
+            name: None,
             btf_fd: None,
         }
     }
@@ -894,6 +1409,393 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_create_name() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_CREATE,
+                attr,
+            } => {
+                let name = unsafe { attr.__bindgen_anon_1.map_name };
+                let name = name.map(|c| c as u8);
+                assert_eq!(&name, b"a_very_long_map\0");
+                Ok(42)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = new_map();
+        assert!(matches!(map.create("a-very-long-map-name"), Ok(42)));
+    }
+
+    #[test]
+    fn test_create_with_inner_map() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_CREATE,
+                attr,
+            } => {
+                assert_eq!(unsafe { attr.__bindgen_anon_1.inner_map_fd }, 7);
+                Ok(42)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = new_map();
+        assert!(matches!(map.create_with_inner_map("foo", Some(7)), Ok(42)));
+    }
+
+    fn override_map_info(info: bpf_map_info) {
+        thread_local! {
+            static INFO: Cell<Option<bpf_map_info>> = Cell::new(None);
+        }
+        INFO.with(|i| i.set(Some(info)));
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                assert_eq!(unsafe { attr.info.bpf_fd }, 42);
+                let ptr = unsafe { attr.info.info } as *mut bpf_map_info;
+                INFO.with(|i| unsafe { *ptr = i.get().unwrap() });
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+    }
+
+    #[test]
+    fn test_adopt() {
+        let mut info = unsafe { mem::zeroed::<bpf_map_info>() };
+        info.type_ = BPF_MAP_TYPE_HASH as u32;
+        info.key_size = 4;
+        info.value_size = 4;
+        info.max_entries = 1024;
+        override_map_info(info);
+
+        let mut map = new_map();
+        assert!(matches!(map.adopt("foo", 42), Ok(42)));
+        assert_eq!(map.fd, Some(42));
+        assert!(matches!(
+            map.adopt("foo", 42),
+            Err(MapError::AlreadyCreated { .. })
+        ));
+    }
+
+    #[test]
+    fn test_adopt_incompatible() {
+        let mut info = unsafe { mem::zeroed::<bpf_map_info>() };
+        info.type_ = BPF_MAP_TYPE_HASH as u32;
+        info.key_size = 4;
+        info.value_size = 8;
+        info.max_entries = 1024;
+        override_map_info(info);
+
+        let mut map = new_map();
+        assert!(matches!(
+            map.adopt("foo", 42),
+            Err(MapError::IncompatibleMap {
+                field: "value size",
+                actual: 8,
+                expected: 4,
+                ..
+            })
+        ));
+        assert_eq!(map.fd, None);
+    }
+
+    #[test]
+    fn test_from_id() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_GET_FD_BY_ID,
+                attr,
+            } => {
+                assert_eq!(
+                    unsafe { attr.__bindgen_anon_6.__bindgen_anon_1.map_id },
+                    1234
+                );
+                Ok(42)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                assert_eq!(unsafe { attr.info.bpf_fd }, 42);
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_map_info) };
+                info.type_ = BPF_MAP_TYPE_HASH as u32;
+                info.id = 1234;
+                info.key_size = 4;
+                info.value_size = 8;
+                info.max_entries = 1024;
+                info.map_flags = 1;
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let map = MapData::from_id(1234).unwrap();
+        assert_eq!(map.fd, Some(42));
+        assert!(!map.pinned);
+        assert!(matches!(
+            map.obj,
+            obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type,
+                    key_size: 4,
+                    value_size: 8,
+                    max_entries: 1024,
+                    map_flags: 1,
+                    id: 1234,
+                    ..
+                },
+                ..
+            }) if map_type == BPF_MAP_TYPE_HASH as u32
+        ));
+    }
+
+    #[test]
+    fn test_from_fd() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                assert_eq!(unsafe { attr.info.bpf_fd }, 42);
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_map_info) };
+                info.type_ = BPF_MAP_TYPE_PERCPU_ARRAY as u32;
+                info.id = 1234;
+                info.key_size = 4;
+                info.value_size = 16;
+                info.max_entries = 8;
+                info.name[..3].copy_from_slice(&[b'f' as _, b'o' as _, b'o' as _]);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let map = MapData::from_fd(42).unwrap();
+        assert_eq!(map.fd, Some(42));
+        assert_eq!(map.name.as_deref(), Some("foo"));
+        assert!(!map.pinned);
+        assert!(matches!(
+            map.obj,
+            obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type,
+                    key_size: 4,
+                    value_size: 16,
+                    max_entries: 8,
+                    id: 1234,
+                    ..
+                },
+                ..
+            }) if map_type == BPF_MAP_TYPE_PERCPU_ARRAY as u32
+        ));
+    }
+
+    #[test]
+    fn test_per_cpu_values_sized_to_possible_cpus() {
+        use std::slice;
+
+        use crate::util::{online_cpus, override_cpu_file, ONLINE_CPUS, POSSIBLE_CPUS};
+
+        override_cpu_file(ONLINE_CPUS, "0-3\n");
+        override_cpu_file(POSSIBLE_CPUS, "0-15\n");
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                // the kernel writes the values of all the possible CPUs
+                let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value };
+                let slots = unsafe { slice::from_raw_parts_mut(value as *mut u64, 16) };
+                for (cpu, slot) in slots.iter_mut().enumerate() {
+                    *slot = cpu as u64;
+                }
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        assert_eq!(online_cpus().unwrap().len(), 4);
+        assert_eq!(nr_cpus().unwrap(), 16);
+        assert_eq!(
+            PerCpuValues::<u32>::alloc_kernel_mem(16).bytes.len(),
+            16 * 8
+        );
+        assert!(PerCpuValues::try_from(vec![0u32; 4]).is_err());
+        assert!(PerCpuValues::try_from(vec![0u32; 16]).is_ok());
+
+        let map = MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_PERCPU_ARRAY as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 1,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+            }),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let array = PerCpuArray::<_, u32>::new(&map).unwrap();
+        assert_eq!(array.nr_cpus(), 16);
+        let values = array.get(&0, 0).unwrap();
+        assert_eq!(values.len(), 16);
+        assert_eq!(values[15], 15);
+    }
+
+    #[test]
+    fn test_insert_get_bytes() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                let key = unsafe { slice::from_raw_parts(u.key as *const u8, 5) };
+                let value =
+                    unsafe { slice::from_raw_parts(u.__bindgen_anon_1.value as *const u8, 3) };
+                assert_eq!(key, b"hello");
+                assert_eq!(value, [1, 2, 3]);
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                let key = unsafe { slice::from_raw_parts(u.key as *const u8, 5) };
+                assert_eq!(key, b"hello");
+                unsafe { slice::from_raw_parts_mut(u.__bindgen_anon_1.value as *mut u8, 3) }
+                    .copy_from_slice(&[1, 2, 3]);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        // a key and value size no Pod type is needed for
+        let mut map = new_map();
+        if let obj::Map::Legacy(LegacyMap { def, .. }) = &mut map.obj {
+            def.key_size = 5;
+            def.value_size = 3;
+        }
+        map.fd = Some(42);
+
+        map.insert_bytes(b"hello", &[1, 2, 3], 0).unwrap();
+        assert_eq!(map.get_bytes(b"hello", 0).unwrap(), [1, 2, 3]);
+        assert!(matches!(
+            map.insert_bytes(b"hello", &[1, 2, 3, 4], 0),
+            Err(MapError::InvalidValueSize {
+                size: 4,
+                expected: 3
+            })
+        ));
+        assert!(matches!(
+            map.get_bytes(b"hell", 0),
+            Err(MapError::InvalidKeySize {
+                size: 4,
+                expected: 5
+            })
+        ));
+        map.fd = None;
+    }
+
+    #[test]
+    fn test_freeze() {
+        thread_local! {
+            static FROZEN: Cell<bool> = Cell::new(false);
+        }
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_FREEZE,
+                attr,
+            } => {
+                assert_eq!(unsafe { attr.__bindgen_anon_2.map_fd }, 42);
+                if FROZEN.with(|frozen| frozen.replace(true)) {
+                    Err((-1, io::Error::from_raw_os_error(EBUSY)))
+                } else {
+                    Ok(0)
+                }
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = new_map();
+        map.fd = Some(42);
+        let map = Map::Array(map);
+        assert!(map.freeze().is_ok());
+        assert!(matches!(map.freeze(), Err(MapError::AlreadyFrozen)));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_max_entries_and_len() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_GET_NEXT_KEY,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                assert_eq!(u.map_fd, 42);
+                let next = if u.key == 0 {
+                    10
+                } else {
+                    unsafe { *(u.key as *const u32) + 10 }
+                };
+                if next > 30 {
+                    return Err((-1, io::Error::from_raw_os_error(ENOENT)));
+                }
+                unsafe { *(u.__bindgen_anon_1.next_key as *mut u32) = next };
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = new_map();
+        map.fd = Some(42);
+        let map = Map::HashMap(map);
+        assert_eq!(map.max_entries(), 1024);
+        assert_eq!(map.len().unwrap(), 3);
+        assert!(!map.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_len_empty() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_GET_NEXT_KEY,
+                ..
+            } => Err((-1, io::Error::from_raw_os_error(ENOENT))),
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = new_map();
+        map.fd = Some(42);
+        assert_eq!(map.len().unwrap(), 0);
+        assert!(map.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_len_not_created() {
+        let map = new_map();
+        assert!(matches!(map.len(), Err(MapError::NotCreated)));
+    }
+
+    #[test]
+    fn test_freeze_not_created() {
+        let map = new_map();
+        assert!(matches!(map.freeze(), Err(MapError::NotCreated)));
+    }
+
     #[test]
     fn test_create_failed() {
         override_syscall(|_| Err((-42, io::Error::from_raw_os_error(EFAULT))));
@@ -913,4 +1815,57 @@ mod tests {
         }
         assert_eq!(map.fd, None);
     }
+
+    #[test]
+    fn test_create_pinned_reuses_pinned_map() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET,
+                ..
+            } => Ok(42),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_CREATE,
+                ..
+            } => panic!("the pinned map should be reused"),
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = new_map();
+        assert!(matches!(
+            map.create_pinned("foo", "/sys/fs/bpf", None),
+            Ok(42)
+        ));
+        assert_eq!(map.fd, Some(42));
+        assert!(map.pinned);
+    }
+
+    #[test]
+    fn test_create_pinned_creates_and_pins() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET,
+                ..
+            } => Err((-1, io::Error::from_raw_os_error(ENOENT))),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_CREATE,
+                ..
+            } => Ok(42),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_PIN,
+                attr,
+            } => {
+                assert_eq!(unsafe { attr.__bindgen_anon_4.bpf_fd }, 42);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = new_map();
+        assert!(matches!(
+            map.create_pinned("foo", "/sys/fs/bpf", None),
+            Ok(42)
+        ));
+        assert_eq!(map.fd, Some(42));
+        assert!(map.pinned);
+    }
 }
@@ -1,6 +1,7 @@
 use bytes::BytesMut;
 use std::{
     convert::AsMut,
+    fmt,
     os::unix::prelude::{AsRawFd, RawFd},
 };
 
@@ -89,6 +90,14 @@ pub struct AsyncPerfEventArray<T> {
     perf_map: PerfEventArray<T>,
 }
 
+impl<T: AsRef<MapData>> fmt::Debug for AsyncPerfEventArray<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncPerfEventArray")
+            .field("perf_map", &self.perf_map)
+            .finish()
+    }
+}
+
 impl<T: AsMut<MapData> + AsRef<MapData>> AsyncPerfEventArray<T> {
     /// Opens the perf buffer at the given index.
     ///
@@ -137,6 +146,15 @@ pub struct AsyncPerfEventArrayBuffer<T> {
     async_fd: Async<RawFd>,
 }
 
+impl<T: AsMut<MapData> + AsRef<MapData>> AsyncPerfEventArrayBuffer<T> {
+    /// Returns the total number of events lost on this buffer's CPU.
+    ///
+    /// See [`PerfEventArrayBuffer::lost_events`].
+    pub fn lost_events(&self) -> u64 {
+        self.buf.lost_events()
+    }
+}
+
 #[cfg(any(feature = "async_tokio"))]
 impl<T: AsMut<MapData> + AsRef<MapData>> AsyncPerfEventArrayBuffer<T> {
     /// Reads events from the buffer.
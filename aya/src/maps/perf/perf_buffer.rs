@@ -16,7 +16,7 @@ use crate::{
         perf_event_type::{PERF_RECORD_LOST, PERF_RECORD_SAMPLE},
     },
     sys::{perf_event_ioctl, perf_event_open_bpf},
-    PERF_EVENT_IOC_DISABLE, PERF_EVENT_IOC_ENABLE,
+    Pod, PERF_EVENT_IOC_DISABLE, PERF_EVENT_IOC_ENABLE,
 };
 
 /// Perf buffer error.
@@ -69,6 +69,15 @@ pub enum PerfBufferError {
         size: usize,
     },
 
+    /// `read_event()` read an event that is smaller than the requested type.
+    #[error("the event is {size} bytes long, expected at least {expected} bytes")]
+    EventTooShort {
+        /// the size of the event
+        size: usize,
+        /// the size of the requested type
+        expected: usize,
+    },
+
     /// An IO error occurred.
     #[error(transparent)]
     IOError(#[from] io::Error),
@@ -88,6 +97,7 @@ pub(crate) struct PerfBuffer {
     size: usize,
     page_size: usize,
     fd: RawFd,
+    lost_events: u64,
 }
 
 impl PerfBuffer {
@@ -125,6 +135,7 @@ impl PerfBuffer {
             fd,
             size,
             page_size,
+            lost_events: 0,
         };
 
         perf_event_ioctl(fd, PERF_EVENT_IOC_ENABLE, 0)
@@ -238,6 +249,7 @@ impl PerfBuffer {
                         events.read += read;
                     }
                     events.lost += lost;
+                    self.lost_events += lost as u64;
                 }
                 Ok(None) => { /* skip unknown event type */ }
                 Err(e) => {
@@ -256,6 +268,29 @@ impl PerfBuffer {
 
         Ok(events)
     }
+
+    pub(crate) fn lost_events(&self) -> u64 {
+        self.lost_events
+    }
+
+    pub(crate) fn read_event<T: Pod>(&mut self) -> Result<Option<T>, PerfBufferError> {
+        let mut buffers = [BytesMut::with_capacity(mem::size_of::<T>())];
+        let events = self.read_events(&mut buffers)?;
+        if events.read == 0 {
+            return Ok(None);
+        }
+
+        let buf = &buffers[0];
+        if buf.len() < mem::size_of::<T>() {
+            return Err(PerfBufferError::EventTooShort {
+                size: buf.len(),
+                expected: mem::size_of::<T>(),
+            });
+        }
+        Ok(Some(unsafe {
+            ptr::read_unaligned(buf.as_ptr() as *const T)
+        }))
+    }
 }
 
 impl AsRawFd for PerfBuffer {
@@ -406,6 +441,38 @@ mod tests {
         assert_eq!(events.lost, 0xCAFEBABE);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_lost_events() {
+        let mut mmapped_buf = MMappedBuf {
+            data: [0; PAGE_SIZE * 2],
+        };
+        fake_mmap(&mut mmapped_buf);
+
+        let lost = |count| LostSamples {
+            header: perf_event_header {
+                type_: PERF_RECORD_LOST as u32,
+                misc: 0,
+                size: mem::size_of::<LostSamples>() as u16,
+            },
+            id: 1,
+            count,
+        };
+        write(&mut mmapped_buf, 0, lost(3));
+        write(&mut mmapped_buf, mem::size_of::<LostSamples>(), lost(4));
+
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        assert_eq!(buf.lost_events(), 0);
+
+        let events = buf.read_events(&mut [BytesMut::new()]).unwrap();
+        assert_eq!(events, Events { read: 0, lost: 7 });
+        assert_eq!(buf.lost_events(), 7);
+
+        write(&mut mmapped_buf, 2 * mem::size_of::<LostSamples>(), lost(5));
+        buf.read_events(&mut [BytesMut::new()]).unwrap();
+        assert_eq!(buf.lost_events(), 12);
+    }
+
     #[repr(C)]
     #[derive(Debug)]
     struct PerfSample<T: Debug> {
@@ -590,4 +657,41 @@ mod tests {
         assert_eq!(events, Events { lost: 0, read: 1 });
         assert_eq!(u64_from_buf(&out_bufs[0]), 0xBAADCAFECAFEBABE);
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_read_event() {
+        let mut mmapped_buf = MMappedBuf {
+            data: [0; PAGE_SIZE * 2],
+        };
+        fake_mmap(&mut mmapped_buf);
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+
+        // the event is longer than the requested type
+        write_sample(&mut mmapped_buf, 0, [0xCAFEBABEu32, 0xBAADCAFEu32]);
+
+        let value = buf.read_event::<u32>().unwrap();
+        assert_eq!(value, Some(0xCAFEBABE));
+        assert_eq!(buf.read_event::<u32>().unwrap(), None);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_read_event_too_short() {
+        let mut mmapped_buf = MMappedBuf {
+            data: [0; PAGE_SIZE * 2],
+        };
+        fake_mmap(&mut mmapped_buf);
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+
+        write_sample(&mut mmapped_buf, 0, 0xCAFEBABEu32);
+
+        assert!(matches!(
+            buf.read_event::<u64>(),
+            Err(PerfBufferError::EventTooShort {
+                size: 4,
+                expected: 8
+            })
+        ));
+    }
 }
@@ -3,6 +3,7 @@
 //! [`perf`]: https://perf.wiki.kernel.org/index.php/Main_Page.
 use std::{
     convert::AsMut,
+    fmt,
     ops::Deref,
     os::unix::io::{AsRawFd, RawFd},
     sync::Arc,
@@ -17,6 +18,7 @@ use crate::{
     },
     sys::bpf_map_update_elem,
     util::page_size,
+    Pod,
 };
 
 /// A ring buffer that can receive events from eBPF programs.
@@ -53,6 +55,31 @@ impl<T: AsMut<MapData> + AsRef<MapData>> PerfEventArrayBuffer<T> {
     pub fn read_events(&mut self, out_bufs: &mut [BytesMut]) -> Result<Events, PerfBufferError> {
         self.buf.read_events(out_bufs)
     }
+
+    /// Reads the next event from the buffer as a value of type `E`.
+    ///
+    /// Returns `None` when there are no events to read. Lost events are
+    /// skipped, use [`read_events`](PerfEventArrayBuffer::read_events) to
+    /// keep track of them. The event is copied out of the buffer, and can be
+    /// larger than `E`, for example because of the padding added by the
+    /// kernel.
+    ///
+    /// # Errors
+    ///
+    /// [`PerfBufferError::EventTooShort`] is returned when the event is
+    /// smaller than `E`.
+    pub fn read_event<E: Pod>(&mut self) -> Result<Option<E>, PerfBufferError> {
+        self.buf.read_event()
+    }
+
+    /// Returns the total number of events lost on this buffer's CPU.
+    ///
+    /// The counter accumulates the `PERF_RECORD_LOST` records seen so far by
+    /// [`read_events`](PerfEventArrayBuffer::read_events) and
+    /// [`read_event`](PerfEventArrayBuffer::read_event).
+    pub fn lost_events(&self) -> u64 {
+        self.buf.lost_events()
+    }
 }
 
 impl<T: AsMut<MapData> + AsRef<MapData>> AsRawFd for PerfEventArrayBuffer<T> {
@@ -160,6 +187,13 @@ pub struct PerfEventArray<T> {
     page_size: usize,
 }
 
+impl<T: AsRef<MapData>> fmt::Debug for PerfEventArray<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let map_data: &MapData = self.map.deref().as_ref();
+        map_data.debug_struct(f, "PerfEventArray").finish()
+    }
+}
+
 impl<T: AsRef<MapData>> PerfEventArray<T> {
     pub(crate) fn new(map: T) -> Result<PerfEventArray<T>, MapError> {
         let _fd = map.as_ref().fd_or_err()?;
@@ -2,6 +2,7 @@
 use std::{
     borrow::Borrow,
     convert::{AsMut, AsRef},
+    fmt,
     marker::PhantomData,
 };
 
@@ -34,6 +35,12 @@ pub struct Queue<T, V: Pod> {
     _v: PhantomData<V>,
 }
 
+impl<T: AsRef<MapData>, V: Pod> fmt::Debug for Queue<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "Queue").finish()
+    }
+}
+
 impl<T: AsRef<MapData>, V: Pod> Queue<T, V> {
     pub(crate) fn new(map: T) -> Result<Queue<T, V>, MapError> {
         let data = map.as_ref();
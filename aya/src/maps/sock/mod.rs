@@ -1,7 +1,9 @@
 //! Socket maps.
+mod reuseport_sock_array;
 mod sock_hash;
 mod sock_map;
 
+pub use reuseport_sock_array::ReusePortSockArray;
 pub use sock_hash::SockHash;
 pub use sock_map::SockMap;
 
@@ -9,7 +11,7 @@ use std::os::unix::io::{AsRawFd, RawFd};
 
 /// A socket map file descriptor.
 #[derive(Copy, Clone)]
-pub struct SockMapFd(RawFd);
+pub struct SockMapFd(pub(crate) RawFd);
 
 impl AsRawFd for SockMapFd {
     fn as_raw_fd(&self) -> RawFd {
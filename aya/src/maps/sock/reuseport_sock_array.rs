@@ -0,0 +1,264 @@
+//! An array of sockets of a `SO_REUSEPORT` group.
+
+use std::{
+    convert::{AsMut, AsRef},
+    fmt, mem,
+    os::unix::{io::AsRawFd, prelude::RawFd},
+};
+
+use crate::{
+    maps::{check_bounds, check_kv_size, MapData, MapError, MapKeys},
+    sys::{bpf_map_delete_elem, bpf_map_update_elem},
+};
+
+/// An array of sockets belonging to a `SO_REUSEPORT` group.
+///
+/// A `ReusePortSockArray` holds the candidate sockets of a `SO_REUSEPORT`
+/// group. [`SkReuseport`](crate::programs::SkReuseport) programs can then pick
+/// the socket handling an incoming packet or connection with
+/// `bpf_sk_select_reuseport()`.
+///
+/// The values of the map can be declared as `u32` or `u64`. In both cases
+/// they're set from socket file descriptors, the kernel only uses the width of
+/// `u64` values to return socket cookies to lookups.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.19.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use std::net::UdpSocket;
+/// use aya::maps::ReusePortSockArray;
+///
+/// let mut sockets = ReusePortSockArray::try_from(bpf.map_mut("SOCKETS").unwrap())?;
+/// let socket = UdpSocket::bind("127.0.0.1:1234")?;
+/// sockets.set(0, &socket, 0)?;
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_REUSEPORT_SOCKARRAY")]
+pub struct ReusePortSockArray<T> {
+    pub(crate) inner: T,
+}
+
+impl<T: AsRef<MapData>> fmt::Debug for ReusePortSockArray<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner
+            .as_ref()
+            .debug_struct(f, "ReusePortSockArray")
+            .finish()
+    }
+}
+
+impl<T: AsRef<MapData>> ReusePortSockArray<T> {
+    pub(crate) fn new(map: T) -> Result<ReusePortSockArray<T>, MapError> {
+        let data = map.as_ref();
+        if data.obj.value_size() as usize == mem::size_of::<u64>() {
+            check_kv_size::<u32, u64>(data)?;
+        } else {
+            check_kv_size::<u32, RawFd>(data)?;
+        }
+
+        let _fd = data.fd_or_err()?;
+
+        Ok(ReusePortSockArray { inner: map })
+    }
+
+    /// An iterator over the indices of the array that point to a socket. The iterator item type
+    /// is `Result<u32, MapError>`.
+    pub fn indices(&self) -> MapKeys<'_, u32> {
+        MapKeys::new(self.inner.as_ref())
+    }
+}
+
+impl<T: AsMut<MapData>> ReusePortSockArray<T> {
+    /// Stores a socket into the map.
+    ///
+    /// The socket must have `SO_REUSEPORT` set and be bound, and all the
+    /// sockets in the map must belong to the same reuseport group.
+    pub fn set<I: AsRawFd>(&mut self, index: u32, socket: &I, flags: u64) -> Result<(), MapError> {
+        let data = self.inner.as_mut();
+        let fd = data.fd_or_err()?;
+        check_bounds(data, index)?;
+        let socket = socket.as_raw_fd();
+        let ret = if data.obj.value_size() as usize == mem::size_of::<u64>() {
+            bpf_map_update_elem(fd, Some(&index), &(socket as u64), flags)
+        } else {
+            bpf_map_update_elem(fd, Some(&index), &socket, flags)
+        };
+        ret.map_err(|(_, io_error)| MapError::SyscallError {
+            call: "bpf_map_update_elem".to_owned(),
+            io_error,
+        })?;
+        Ok(())
+    }
+
+    /// Removes the socket stored at `index` from the map.
+    pub fn clear_index(&mut self, index: &u32) -> Result<(), MapError> {
+        let data = self.inner.as_mut();
+        let fd = data.fd_or_err()?;
+        check_bounds(data, *index)?;
+        bpf_map_delete_elem(fd, index)
+            .map(|_| ())
+            .map_err(|(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_delete_elem".to_owned(),
+                io_error,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::EFAULT;
+
+    use super::*;
+    use crate::{
+        bpf_map_def,
+        generated::{
+            bpf_cmd,
+            bpf_map_type::{BPF_MAP_TYPE_REUSEPORT_SOCKARRAY, BPF_MAP_TYPE_SOCKMAP},
+        },
+        maps::Map,
+        obj::{
+            self,
+            maps::{LegacyMap, MapKind},
+        },
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    fn new_obj_map(value_size: u32) -> obj::Map {
+        obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: BPF_MAP_TYPE_REUSEPORT_SOCKARRAY as u32,
+                key_size: 4,
+                value_size,
+                max_entries: 4,
+                ..Default::default()
+            },
+            section_index: 0,
+            symbol_index: 0,
+            data: Vec::new(),
+            kind: MapKind::Other,
+        })
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    #[test]
+    fn test_wrong_value_size() {
+        let map = MapData {
+            obj: new_obj_map(2),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        assert!(matches!(
+            ReusePortSockArray::new(&map),
+            Err(MapError::InvalidValueSize {
+                size: 4,
+                expected: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_try_from_wrong_map() {
+        let mut obj = new_obj_map(4);
+        if let obj::Map::Legacy(LegacyMap { def, .. }) = &mut obj {
+            def.map_type = BPF_MAP_TYPE_SOCKMAP as u32;
+        }
+        let map = Map::SockMap(MapData {
+            obj,
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        });
+
+        assert!(matches!(
+            ReusePortSockArray::try_from(&map),
+            Err(MapError::InvalidMapType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_new_ok() {
+        for value_size in [4, 8] {
+            let map = Map::ReusePortSockArray(MapData {
+                obj: new_obj_map(value_size),
+                fd: Some(42),
+                pinned: false,
+                name: None,
+                btf_fd: None,
+            });
+
+            assert!(ReusePortSockArray::try_from(&map).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_set() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                ..
+            } => Ok(1),
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = MapData {
+            obj: new_obj_map(4),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let mut sockets = ReusePortSockArray::new(&mut map).unwrap();
+        // the fd is never looked at, the syscall is mocked
+        let socket = io::stdin();
+
+        assert!(sockets.set(0, &socket, 0).is_ok());
+        assert!(matches!(
+            sockets.set(4, &socket, 0),
+            Err(MapError::OutOfBounds {
+                index: 4,
+                max_entries: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn test_set_u64_value() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                assert_eq!(unsafe { *(u.key as *const u32) }, 1);
+                assert_eq!(unsafe { *(u.__bindgen_anon_1.value as *const u64) }, 0);
+                Ok(1)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = MapData {
+            obj: new_obj_map(8),
+            fd: Some(42),
+            pinned: false,
+            name: None,
+            btf_fd: None,
+        };
+        let mut sockets = ReusePortSockArray::new(&mut map).unwrap();
+        // the fd of stdin is passed as a u64
+        assert!(sockets.set(1, &io::stdin(), 0).is_ok());
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     borrow::Borrow,
     convert::{AsMut, AsRef},
+    fmt,
     marker::PhantomData,
     os::unix::io::{AsRawFd, RawFd},
 };
@@ -69,6 +70,12 @@ pub struct SockHash<T, K> {
     _k: PhantomData<K>,
 }
 
+impl<T: AsRef<MapData>, K> fmt::Debug for SockHash<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "SockHash").finish()
+    }
+}
+
 impl<T: AsRef<MapData>, K: Pod> SockHash<T, K> {
     pub(crate) fn new(map: T) -> Result<SockHash<T, K>, MapError> {
         let data = map.as_ref();
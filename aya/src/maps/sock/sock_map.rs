@@ -2,6 +2,7 @@
 
 use std::{
     convert::{AsMut, AsRef},
+    fmt,
     os::unix::{io::AsRawFd, prelude::RawFd},
 };
 
@@ -44,6 +45,12 @@ pub struct SockMap<T> {
     pub(crate) inner: T,
 }
 
+impl<T: AsRef<MapData>> fmt::Debug for SockMap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "SockMap").finish()
+    }
+}
+
 impl<T: AsRef<MapData>> SockMap<T> {
     pub(crate) fn new(map: T) -> Result<SockMap<T>, MapError> {
         let data = map.as_ref();
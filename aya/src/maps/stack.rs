@@ -2,6 +2,7 @@
 use std::{
     borrow::Borrow,
     convert::{AsMut, AsRef},
+    fmt,
     marker::PhantomData,
 };
 
@@ -34,6 +35,12 @@ pub struct Stack<T, V: Pod> {
     _v: PhantomData<V>,
 }
 
+impl<T: AsRef<MapData>, V: Pod> fmt::Debug for Stack<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.as_ref().debug_struct(f, "Stack").finish()
+    }
+}
+
 impl<T: AsRef<MapData>, V: Pod> Stack<T, V> {
     pub(crate) fn new(map: T) -> Result<Stack<T, V>, MapError> {
         let data = map.as_ref();
@@ -1,7 +1,7 @@
 //! A hash map of kernel or user space stack traces.
 //!
 //! See [`StackTraceMap`] for documentation and examples.
-use std::{collections::BTreeMap, convert::AsRef, fs, io, mem, path::Path, str::FromStr};
+use std::{collections::BTreeMap, convert::AsRef, fmt, fs, io, mem, path::Path, str::FromStr};
 
 use crate::{
     maps::{IterableMap, MapData, MapError, MapIter, MapKeys},
@@ -60,13 +60,22 @@ use crate::{
 /// # Ok::<(), Error>(())
 /// ```
 ///
-#[derive(Debug)]
 #[doc(alias = "BPF_MAP_TYPE_STACK_TRACE")]
 pub struct StackTraceMap<T> {
     inner: T,
     max_stack_depth: usize,
 }
 
+impl<T: AsRef<MapData>> fmt::Debug for StackTraceMap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner
+            .as_ref()
+            .debug_struct(f, "StackTraceMap")
+            .field("max_stack_depth", &self.max_stack_depth)
+            .finish()
+    }
+}
+
 impl<T: AsRef<MapData>> StackTraceMap<T> {
     pub(crate) fn new(map: T) -> Result<StackTraceMap<T>, MapError> {
         let data = map.as_ref();
@@ -4,9 +4,11 @@ use std::os::unix::prelude::{AsRawFd, RawFd};
 use crate::{
     generated::{bpf_attach_type::BPF_CGROUP_DEVICE, bpf_prog_type::BPF_PROG_TYPE_CGROUP_DEVICE},
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        cgroup_query::{attach_to_cgroup, detach_by_id, CgroupAttachment},
+        define_link_wrapper, impl_updatable_link,
+        links::UpdatableLink,
+        load_program, CgroupAttachMethod, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
     },
-    sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
 
 /// A program used to watch or prevent device interaction from a cgroup.
@@ -57,37 +59,29 @@ impl CgroupDevice {
 
     /// Attaches the program to the given cgroup.
     ///
+    /// A bpf link is used on kernels that support it, see
+    /// [CgroupDevice::attach_with_method] to choose how the program is attached.
+    ///
     /// The returned value can be used to detach, see [CgroupDevice::detach]
     pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<CgroupDeviceLinkId, ProgramError> {
+        self.attach_with_method(cgroup, CgroupAttachMethod::Auto)
+    }
+
+    /// Attaches the program to the given cgroup using the given method.
+    ///
+    /// The returned value can be used to detach, see [CgroupDevice::detach]
+    pub fn attach_with_method<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+        method: CgroupAttachMethod,
+    ) -> Result<CgroupDeviceLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
         let cgroup_fd = cgroup.as_raw_fd();
-
-        let k_ver = kernel_version().unwrap();
-        if k_ver >= (5, 7, 0) {
-            let link_fd = bpf_link_create(prog_fd, cgroup_fd, BPF_CGROUP_DEVICE, None, 0).map_err(
-                |(_, io_error)| ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                },
-            )? as RawFd;
-            self.data
-                .links
-                .insert(CgroupDeviceLink::new(CgroupDeviceLinkInner::Fd(
-                    FdLink::new(link_fd),
-                )))
-        } else {
-            bpf_prog_attach(prog_fd, cgroup_fd, BPF_CGROUP_DEVICE).map_err(|(_, io_error)| {
-                ProgramError::SyscallError {
-                    call: "bpf_prog_attach".to_owned(),
-                    io_error,
-                }
-            })?;
-            self.data
-                .links
-                .insert(CgroupDeviceLink::new(CgroupDeviceLinkInner::ProgAttach(
-                    ProgAttachLink::new(prog_fd, cgroup_fd, BPF_CGROUP_DEVICE),
-                )))
-        }
+        let link = match attach_to_cgroup(prog_fd, cgroup_fd, BPF_CGROUP_DEVICE, method)? {
+            CgroupAttachment::Fd(link) => CgroupDeviceLinkInner::Fd(link),
+            CgroupAttachment::ProgAttach(link) => CgroupDeviceLinkInner::ProgAttach(link),
+        };
+        self.data.links.insert(CgroupDeviceLink::new(link))
     }
 
     /// Takes ownership of the link referenced by the provided link_id.
@@ -107,6 +101,17 @@ impl CgroupDevice {
     pub fn detach(&mut self, link_id: CgroupDeviceLinkId) -> Result<(), ProgramError> {
         self.data.links.remove(link_id)
     }
+
+    /// Detaches the program with the given id from `cgroup`.
+    ///
+    /// This can be used to detach programs attached by other processes, for
+    /// example after listing them with [`CgroupProgramQuery`](crate::programs::CgroupProgramQuery).
+    /// Only programs attached with `BPF_PROG_ATTACH` can be detached this
+    /// way, programs attached through a bpf link stay attached until the link
+    /// is closed.
+    pub fn detach_by_id<T: AsRawFd>(cgroup: T, prog_id: u32) -> Result<(), ProgramError> {
+        detach_by_id(cgroup, prog_id, BPF_CGROUP_DEVICE)
+    }
 }
 
 #[derive(Debug, Hash, Eq, PartialEq)]
@@ -139,6 +144,19 @@ impl Link for CgroupDeviceLinkInner {
     }
 }
 
+impl UpdatableLink for CgroupDeviceLinkInner {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        match self {
+            CgroupDeviceLinkInner::Fd(fd) => fd.update_program(new_prog_fd, old_prog_fd),
+            CgroupDeviceLinkInner::ProgAttach(p) => p.update_program(new_prog_fd, old_prog_fd),
+        }
+    }
+}
+
 define_link_wrapper!(
     /// The link used by [CgroupDevice] programs.
     CgroupDeviceLink,
@@ -147,3 +165,5 @@ define_link_wrapper!(
     CgroupDeviceLinkInner,
     CgroupDeviceLinkIdInner
 );
+
+impl_updatable_link!(CgroupDeviceLink);
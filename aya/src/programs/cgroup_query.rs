@@ -0,0 +1,464 @@
+//! Attaching programs to cgroups and querying the programs attached to them.
+use std::os::unix::prelude::{AsRawFd, RawFd};
+
+use crate::{
+    generated::bpf_attach_type::{self, *},
+    programs::{query, FdLink, ProgAttachLink, ProgramError},
+    sys::{
+        bpf_link_create, bpf_prog_attach, bpf_prog_detach, bpf_prog_get_fd_by_id, kernel_version,
+    },
+};
+
+/// The method used to attach a program to a cgroup.
+///
+/// Both methods return a link that detaches the program when dropped. The
+/// difference is what happens when the process exits without dropping the
+/// link: programs attached with a bpf link are detached when the last
+/// reference to the link goes away, while programs attached with
+/// `BPF_PROG_ATTACH` stay attached until they're explicitly detached.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CgroupAttachMethod {
+    /// Use a bpf link if the kernel supports it (5.7 and newer), and fall
+    /// back to `BPF_PROG_ATTACH` otherwise.
+    #[default]
+    Auto,
+    /// Always attach with a bpf link (`BPF_LINK_CREATE`).
+    Link,
+    /// Always attach with `BPF_PROG_ATTACH`.
+    ProgAttach,
+}
+
+/// The cgroup hooks programs can be attached to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CgroupAttachType {
+    /// [`CgroupSkb`](crate::programs::CgroupSkb) programs attached to ingress.
+    InetIngress,
+    /// [`CgroupSkb`](crate::programs::CgroupSkb) programs attached to egress.
+    InetEgress,
+    /// [`CgroupSock`](crate::programs::CgroupSock) programs called on socket creation.
+    InetSockCreate,
+    /// [`CgroupSock`](crate::programs::CgroupSock) programs called on socket release.
+    InetSockRelease,
+    /// [`CgroupSock`](crate::programs::CgroupSock) programs called after an IPv4 bind.
+    Inet4PostBind,
+    /// [`CgroupSock`](crate::programs::CgroupSock) programs called after an IPv6 bind.
+    Inet6PostBind,
+    /// [`SockOps`](crate::programs::SockOps) programs.
+    SockOps,
+    /// [`CgroupDevice`](crate::programs::CgroupDevice) programs.
+    Device,
+    /// [`CgroupSysctl`](crate::programs::CgroupSysctl) programs.
+    Sysctl,
+    /// [`CgroupSockopt`](crate::programs::CgroupSockopt) programs called on `getsockopt`.
+    Getsockopt,
+    /// [`CgroupSockopt`](crate::programs::CgroupSockopt) programs called on `setsockopt`.
+    Setsockopt,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on IPv4 bind.
+    Inet4Bind,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on IPv6 bind.
+    Inet6Bind,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on IPv4 connect.
+    Inet4Connect,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on IPv6 connect.
+    Inet6Connect,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on IPv4 getpeername.
+    Inet4Getpeername,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on IPv6 getpeername.
+    Inet6Getpeername,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on IPv4 getsockname.
+    Inet4Getsockname,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on IPv6 getsockname.
+    Inet6Getsockname,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on UDPv4 sendmsg.
+    Udp4Sendmsg,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on UDPv6 sendmsg.
+    Udp6Sendmsg,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on UDPv4 recvmsg.
+    Udp4Recvmsg,
+    /// [`CgroupSockAddr`](crate::programs::CgroupSockAddr) programs called on UDPv6 recvmsg.
+    Udp6Recvmsg,
+}
+
+impl From<CgroupAttachType> for bpf_attach_type {
+    fn from(s: CgroupAttachType) -> bpf_attach_type {
+        match s {
+            CgroupAttachType::InetIngress => BPF_CGROUP_INET_INGRESS,
+            CgroupAttachType::InetEgress => BPF_CGROUP_INET_EGRESS,
+            CgroupAttachType::InetSockCreate => BPF_CGROUP_INET_SOCK_CREATE,
+            CgroupAttachType::InetSockRelease => BPF_CGROUP_INET_SOCK_RELEASE,
+            CgroupAttachType::Inet4PostBind => BPF_CGROUP_INET4_POST_BIND,
+            CgroupAttachType::Inet6PostBind => BPF_CGROUP_INET6_POST_BIND,
+            CgroupAttachType::SockOps => BPF_CGROUP_SOCK_OPS,
+            CgroupAttachType::Device => BPF_CGROUP_DEVICE,
+            CgroupAttachType::Sysctl => BPF_CGROUP_SYSCTL,
+            CgroupAttachType::Getsockopt => BPF_CGROUP_GETSOCKOPT,
+            CgroupAttachType::Setsockopt => BPF_CGROUP_SETSOCKOPT,
+            CgroupAttachType::Inet4Bind => BPF_CGROUP_INET4_BIND,
+            CgroupAttachType::Inet6Bind => BPF_CGROUP_INET6_BIND,
+            CgroupAttachType::Inet4Connect => BPF_CGROUP_INET4_CONNECT,
+            CgroupAttachType::Inet6Connect => BPF_CGROUP_INET6_CONNECT,
+            CgroupAttachType::Inet4Getpeername => BPF_CGROUP_INET4_GETPEERNAME,
+            CgroupAttachType::Inet6Getpeername => BPF_CGROUP_INET6_GETPEERNAME,
+            CgroupAttachType::Inet4Getsockname => BPF_CGROUP_INET4_GETSOCKNAME,
+            CgroupAttachType::Inet6Getsockname => BPF_CGROUP_INET6_GETSOCKNAME,
+            CgroupAttachType::Udp4Sendmsg => BPF_CGROUP_UDP4_SENDMSG,
+            CgroupAttachType::Udp6Sendmsg => BPF_CGROUP_UDP6_SENDMSG,
+            CgroupAttachType::Udp4Recvmsg => BPF_CGROUP_UDP4_RECVMSG,
+            CgroupAttachType::Udp6Recvmsg => BPF_CGROUP_UDP6_RECVMSG,
+        }
+    }
+}
+
+/// The programs attached to a cgroup at a given [`CgroupAttachType`].
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.15.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use aya::programs::{CgroupAttachType, CgroupProgramQuery};
+///
+/// let cgroup = File::open("/sys/fs/cgroup/unified")?;
+/// let query = CgroupProgramQuery::query(cgroup, CgroupAttachType::InetIngress)?;
+/// for id in query.program_ids() {
+///     println!("program {id} is attached");
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct CgroupProgramQuery {
+    prog_ids: Vec<u32>,
+    attach_flags: u32,
+}
+
+impl CgroupProgramQuery {
+    /// Queries the programs attached to `cgroup` at `attach_type`.
+    ///
+    /// Only the programs attached directly to the cgroup are returned, not
+    /// the ones inherited from its ancestors.
+    pub fn query<T: AsRawFd>(
+        cgroup: T,
+        attach_type: CgroupAttachType,
+    ) -> Result<CgroupProgramQuery, ProgramError> {
+        let mut attach_flags = Some(0);
+        let prog_ids = query(cgroup, attach_type.into(), 0, &mut attach_flags)?;
+
+        Ok(CgroupProgramQuery {
+            prog_ids,
+            attach_flags: attach_flags.unwrap_or_default(),
+        })
+    }
+
+    /// Returns the ids of the attached programs, in the order they run.
+    pub fn program_ids(&self) -> &[u32] {
+        &self.prog_ids
+    }
+
+    /// Returns the flags the programs were attached with.
+    ///
+    /// This is a combination of `BPF_F_ALLOW_OVERRIDE` (1) and
+    /// `BPF_F_ALLOW_MULTI` (2), or 0 if only one program can be attached.
+    pub fn attach_flags(&self) -> u32 {
+        self.attach_flags
+    }
+}
+
+// The attachment created by attach_to_cgroup. Each program type wraps it into
+// its own link type.
+pub(crate) enum CgroupAttachment {
+    Fd(FdLink),
+    ProgAttach(ProgAttachLink),
+}
+
+pub(crate) fn attach_to_cgroup(
+    prog_fd: RawFd,
+    cgroup_fd: RawFd,
+    attach_type: bpf_attach_type,
+    method: CgroupAttachMethod,
+) -> Result<CgroupAttachment, ProgramError> {
+    let use_link = match method {
+        CgroupAttachMethod::Auto => kernel_version().unwrap() >= (5, 7, 0),
+        CgroupAttachMethod::Link => true,
+        CgroupAttachMethod::ProgAttach => false,
+    };
+
+    if use_link {
+        let link_fd =
+            bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0).map_err(|(_, io_error)| {
+                ProgramError::SyscallError {
+                    call: "bpf_link_create".to_owned(),
+                    io_error,
+                }
+            })? as RawFd;
+        Ok(CgroupAttachment::Fd(FdLink::new(link_fd)))
+    } else {
+        bpf_prog_attach(prog_fd, cgroup_fd, attach_type).map_err(|(_, io_error)| {
+            ProgramError::SyscallError {
+                call: "bpf_prog_attach".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(CgroupAttachment::ProgAttach(ProgAttachLink::new(
+            prog_fd,
+            cgroup_fd,
+            attach_type,
+        )))
+    }
+}
+
+// Detaches the program with the given id from `cgroup`. The program must
+// have been attached with BPF_PROG_ATTACH, programs attached through a bpf
+// link can only be detached by closing or detaching the link.
+pub(crate) fn detach_by_id<T: AsRawFd>(
+    cgroup: T,
+    prog_id: u32,
+    attach_type: bpf_attach_type,
+) -> Result<(), ProgramError> {
+    let prog_fd = bpf_prog_get_fd_by_id(prog_id).map_err(|io_error| ProgramError::SyscallError {
+        call: "bpf_prog_get_fd_by_id".to_owned(),
+        io_error,
+    })? as RawFd;
+
+    let ret = bpf_prog_detach(prog_fd, cgroup.as_raw_fd(), attach_type);
+    unsafe { libc::close(prog_fd) };
+    ret.map_err(|(_, io_error)| ProgramError::SyscallError {
+        call: "bpf_prog_detach".to_owned(),
+        io_error,
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, io, ptr, slice};
+
+    use libc::ENOSPC;
+
+    use super::*;
+    use crate::{
+        generated::{bpf_attr, bpf_cmd},
+        programs::{tests::fake_program_data, CgroupSkb, CgroupSkbAttachType, SockOps},
+        sys::{override_syscall, Syscall},
+    };
+
+    thread_local! {
+        static LINK_FD: Cell<RawFd> = Cell::new(-1);
+        static DETACH_CALLS: Cell<u32> = Cell::new(0);
+    }
+
+    fn fake_cgroup_skb() -> CgroupSkb {
+        let mut prog = CgroupSkb {
+            data: fake_program_data(false),
+            expected_attach_type: None,
+        };
+        prog.data.fd = Some(99999);
+        prog
+    }
+
+    // The kernel writes the program count and the attach flags back into the
+    // attributes.
+    unsafe fn set_query_result(attr: &bpf_attr, prog_cnt: u32, attach_flags: u32) {
+        ptr::write(ptr::addr_of!(attr.query.prog_cnt) as *mut u32, prog_cnt);
+        ptr::write(
+            ptr::addr_of!(attr.query.attach_flags) as *mut u32,
+            attach_flags,
+        );
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_query() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_QUERY,
+                attr,
+            } => {
+                let u = unsafe { &attr.query };
+                assert_eq!(u.target_fd, 0);
+                assert_eq!(u.attach_type, BPF_CGROUP_INET_EGRESS as u32);
+                let ids = unsafe {
+                    slice::from_raw_parts_mut(u.prog_ids as *mut u32, u.prog_cnt as usize)
+                };
+                ids[..2].copy_from_slice(&[7, 3]);
+                unsafe { set_query_result(attr, 2, 2) };
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let query = CgroupProgramQuery::query(io::stdin(), CgroupAttachType::InetEgress).unwrap();
+        assert_eq!(query.program_ids(), &[7, 3]);
+        assert_eq!(query.attach_flags(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_query_grows_buffer() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_QUERY,
+                attr,
+            } => {
+                let u = unsafe { &attr.query };
+                if u.prog_cnt < 100 {
+                    // not enough room, report how many programs are attached
+                    unsafe { set_query_result(attr, 100, 2) };
+                    return Err((-1, io::Error::from_raw_os_error(ENOSPC)));
+                }
+                let ids = unsafe {
+                    slice::from_raw_parts_mut(u.prog_ids as *mut u32, u.prog_cnt as usize)
+                };
+                for (i, id) in ids.iter_mut().enumerate() {
+                    *id = i as u32;
+                }
+                unsafe { set_query_result(attr, 100, 2) };
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let query = CgroupProgramQuery::query(io::stdin(), CgroupAttachType::Device).unwrap();
+        assert_eq!(query.program_ids().len(), 100);
+        assert_eq!(query.program_ids()[99], 99);
+    }
+
+    #[test]
+    fn test_detach_by_id() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_GET_FD_BY_ID,
+                attr,
+            } => {
+                assert_eq!(
+                    unsafe { attr.__bindgen_anon_6.__bindgen_anon_1.prog_id },
+                    42
+                );
+                Ok(99999)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_DETACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.attach_bpf_fd, 99999);
+                assert_eq!(u.target_fd, 0);
+                assert_eq!(u.attach_type, BPF_CGROUP_INET_INGRESS as u32);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        CgroupSkb::detach_by_id(io::stdin(), 42, CgroupSkbAttachType::Ingress).unwrap();
+    }
+
+    #[test]
+    fn test_detach_by_id_unknown_program() {
+        override_syscall(|_| Err((-1, io::Error::from_raw_os_error(libc::ENOENT))));
+
+        assert!(matches!(
+            SockOps::detach_by_id(io::stdin(), 42),
+            Err(ProgramError::SyscallError { call, .. }) if call == "bpf_prog_get_fd_by_id"
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_attach_with_link() {
+        // use the write end of a pipe as the link fd, so that we can check
+        // that dropping the link closes it
+        let mut fds = [0; 2];
+        assert_eq!(
+            unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK) },
+            0
+        );
+        LINK_FD.with(|fd| fd.set(fds[1]));
+
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let u = unsafe { &attr.link_create };
+                assert_eq!(u.prog_fd, 99999);
+                assert_eq!(unsafe { u.__bindgen_anon_1.target_fd }, 0);
+                assert_eq!(u.attach_type, BPF_CGROUP_INET_EGRESS as u32);
+                Ok(LINK_FD.with(|fd| fd.get()) as _)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let mut prog = fake_cgroup_skb();
+        let link_id = prog
+            .attach_with_method(
+                io::stdin(),
+                CgroupSkbAttachType::Egress,
+                CgroupAttachMethod::Link,
+            )
+            .unwrap();
+        let link = prog.take_link(link_id).unwrap();
+        prog.data.fd = None;
+
+        let mut buf = [0u8; 1];
+        // the link is still open, there's nothing to read yet
+        assert_eq!(
+            unsafe { libc::read(fds[0], buf.as_mut_ptr() as *mut _, 1) },
+            -1
+        );
+        drop(link);
+        // the link has been closed, the pipe reports EOF
+        assert_eq!(
+            unsafe { libc::read(fds[0], buf.as_mut_ptr() as *mut _, 1) },
+            0
+        );
+        unsafe { libc::close(fds[0]) };
+    }
+
+    #[test]
+    fn test_attach_with_prog_attach() {
+        DETACH_CALLS.with(|calls| calls.set(0));
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_ATTACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.attach_bpf_fd, 99999);
+                assert_eq!(u.target_fd, 0);
+                assert_eq!(u.attach_type, BPF_CGROUP_INET_INGRESS as u32);
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_DETACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.attach_bpf_fd, 99999);
+                assert_eq!(u.attach_type, BPF_CGROUP_INET_INGRESS as u32);
+                DETACH_CALLS.with(|calls| calls.set(calls.get() + 1));
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let mut prog = fake_cgroup_skb();
+        let link_id = prog
+            .attach_with_method(
+                io::stdin(),
+                CgroupSkbAttachType::Ingress,
+                CgroupAttachMethod::ProgAttach,
+            )
+            .unwrap();
+        let link = prog.take_link(link_id).unwrap();
+        prog.data.fd = None;
+
+        assert_eq!(DETACH_CALLS.with(|calls| calls.get()), 0);
+        drop(link);
+        assert_eq!(DETACH_CALLS.with(|calls| calls.get()), 1);
+    }
+}
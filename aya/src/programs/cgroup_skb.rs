@@ -10,9 +10,11 @@ use crate::{
         bpf_prog_type::BPF_PROG_TYPE_CGROUP_SKB,
     },
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        cgroup_query::{attach_to_cgroup, detach_by_id, CgroupAttachment},
+        define_link_wrapper, impl_updatable_link,
+        links::UpdatableLink,
+        load_program, CgroupAttachMethod, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
     },
-    sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
 
 /// A program used to inspect or filter network activity for a given cgroup.
@@ -82,11 +84,26 @@ impl CgroupSkb {
 
     /// Attaches the program to the given cgroup.
     ///
+    /// A bpf link is used on kernels that support it, see
+    /// [CgroupSkb::attach_with_method] to choose how the program is attached.
+    ///
     /// The returned value can be used to detach, see [CgroupSkb::detach].
     pub fn attach<T: AsRawFd>(
         &mut self,
         cgroup: T,
         attach_type: CgroupSkbAttachType,
+    ) -> Result<CgroupSkbLinkId, ProgramError> {
+        self.attach_with_method(cgroup, attach_type, CgroupAttachMethod::Auto)
+    }
+
+    /// Attaches the program to the given cgroup using the given method.
+    ///
+    /// The returned value can be used to detach, see [CgroupSkb::detach].
+    pub fn attach_with_method<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+        attach_type: CgroupSkbAttachType,
+        method: CgroupAttachMethod,
     ) -> Result<CgroupSkbLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
         let cgroup_fd = cgroup.as_raw_fd();
@@ -95,33 +112,11 @@ impl CgroupSkb {
             CgroupSkbAttachType::Ingress => BPF_CGROUP_INET_INGRESS,
             CgroupSkbAttachType::Egress => BPF_CGROUP_INET_EGRESS,
         };
-        let k_ver = kernel_version().unwrap();
-        if k_ver >= (5, 7, 0) {
-            let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0).map_err(
-                |(_, io_error)| ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                },
-            )? as RawFd;
-            self.data
-                .links
-                .insert(CgroupSkbLink::new(CgroupSkbLinkInner::Fd(FdLink::new(
-                    link_fd,
-                ))))
-        } else {
-            bpf_prog_attach(prog_fd, cgroup_fd, attach_type).map_err(|(_, io_error)| {
-                ProgramError::SyscallError {
-                    call: "bpf_prog_attach".to_owned(),
-                    io_error,
-                }
-            })?;
-
-            self.data
-                .links
-                .insert(CgroupSkbLink::new(CgroupSkbLinkInner::ProgAttach(
-                    ProgAttachLink::new(prog_fd, cgroup_fd, attach_type),
-                )))
-        }
+        let link = match attach_to_cgroup(prog_fd, cgroup_fd, attach_type, method)? {
+            CgroupAttachment::Fd(link) => CgroupSkbLinkInner::Fd(link),
+            CgroupAttachment::ProgAttach(link) => CgroupSkbLinkInner::ProgAttach(link),
+        };
+        self.data.links.insert(CgroupSkbLink::new(link))
     }
 
     /// Takes ownership of the link referenced by the provided link_id.
@@ -138,6 +133,25 @@ impl CgroupSkb {
     pub fn detach(&mut self, link_id: CgroupSkbLinkId) -> Result<(), ProgramError> {
         self.data.links.remove(link_id)
     }
+
+    /// Detaches the program with the given id from `cgroup`.
+    ///
+    /// This can be used to detach programs attached by other processes, for
+    /// example after listing them with [`CgroupProgramQuery`](crate::programs::CgroupProgramQuery).
+    /// Only programs attached with `BPF_PROG_ATTACH` can be detached this
+    /// way, programs attached through a bpf link stay attached until the link
+    /// is closed.
+    pub fn detach_by_id<T: AsRawFd>(
+        cgroup: T,
+        prog_id: u32,
+        attach_type: CgroupSkbAttachType,
+    ) -> Result<(), ProgramError> {
+        let attach_type = match attach_type {
+            CgroupSkbAttachType::Ingress => BPF_CGROUP_INET_INGRESS,
+            CgroupSkbAttachType::Egress => BPF_CGROUP_INET_EGRESS,
+        };
+        detach_by_id(cgroup, prog_id, attach_type)
+    }
 }
 
 #[derive(Debug, Hash, Eq, PartialEq)]
@@ -170,6 +184,19 @@ impl Link for CgroupSkbLinkInner {
     }
 }
 
+impl UpdatableLink for CgroupSkbLinkInner {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        match self {
+            CgroupSkbLinkInner::Fd(fd) => fd.update_program(new_prog_fd, old_prog_fd),
+            CgroupSkbLinkInner::ProgAttach(p) => p.update_program(new_prog_fd, old_prog_fd),
+        }
+    }
+}
+
 define_link_wrapper!(
     /// The link used by [CgroupSkb] programs.
     CgroupSkbLink,
@@ -179,6 +206,8 @@ define_link_wrapper!(
     CgroupSkbLinkIdInner
 );
 
+impl_updatable_link!(CgroupSkbLink);
+
 /// Defines where to attach a [`CgroupSkb`] program.
 #[derive(Copy, Clone, Debug)]
 pub enum CgroupSkbAttachType {
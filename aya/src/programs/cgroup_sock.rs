@@ -9,9 +9,11 @@ use std::{
 use crate::{
     generated::bpf_prog_type::BPF_PROG_TYPE_CGROUP_SOCK,
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        cgroup_query::{attach_to_cgroup, detach_by_id, CgroupAttachment},
+        define_link_wrapper, impl_updatable_link,
+        links::UpdatableLink,
+        load_program, CgroupAttachMethod, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
     },
-    sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
 
 /// A program that is called on socket creation, bind and release.
@@ -65,38 +67,30 @@ impl CgroupSock {
 
     /// Attaches the program to the given cgroup.
     ///
+    /// A bpf link is used on kernels that support it, see
+    /// [CgroupSock::attach_with_method] to choose how the program is attached.
+    ///
     /// The returned value can be used to detach, see [CgroupSock::detach].
     pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<CgroupSockLinkId, ProgramError> {
+        self.attach_with_method(cgroup, CgroupAttachMethod::Auto)
+    }
+
+    /// Attaches the program to the given cgroup using the given method.
+    ///
+    /// The returned value can be used to detach, see [CgroupSock::detach].
+    pub fn attach_with_method<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+        method: CgroupAttachMethod,
+    ) -> Result<CgroupSockLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
         let cgroup_fd = cgroup.as_raw_fd();
         let attach_type = self.data.expected_attach_type.unwrap();
-        let k_ver = kernel_version().unwrap();
-        if k_ver >= (5, 7, 0) {
-            let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0).map_err(
-                |(_, io_error)| ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                },
-            )? as RawFd;
-            self.data
-                .links
-                .insert(CgroupSockLink::new(CgroupSockLinkInner::Fd(FdLink::new(
-                    link_fd,
-                ))))
-        } else {
-            bpf_prog_attach(prog_fd, cgroup_fd, attach_type).map_err(|(_, io_error)| {
-                ProgramError::SyscallError {
-                    call: "bpf_prog_attach".to_owned(),
-                    io_error,
-                }
-            })?;
-
-            self.data
-                .links
-                .insert(CgroupSockLink::new(CgroupSockLinkInner::ProgAttach(
-                    ProgAttachLink::new(prog_fd, cgroup_fd, attach_type),
-                )))
-        }
+        let link = match attach_to_cgroup(prog_fd, cgroup_fd, attach_type, method)? {
+            CgroupAttachment::Fd(link) => CgroupSockLinkInner::Fd(link),
+            CgroupAttachment::ProgAttach(link) => CgroupSockLinkInner::ProgAttach(link),
+        };
+        self.data.links.insert(CgroupSockLink::new(link))
     }
 
     /// Takes ownership of the link referenced by the provided link_id.
@@ -113,6 +107,21 @@ impl CgroupSock {
     pub fn detach(&mut self, link_id: CgroupSockLinkId) -> Result<(), ProgramError> {
         self.data.links.remove(link_id)
     }
+
+    /// Detaches the program with the given id from `cgroup`.
+    ///
+    /// This can be used to detach programs attached by other processes, for
+    /// example after listing them with [`CgroupProgramQuery`](crate::programs::CgroupProgramQuery).
+    /// Only programs attached with `BPF_PROG_ATTACH` can be detached this
+    /// way, programs attached through a bpf link stay attached until the link
+    /// is closed.
+    pub fn detach_by_id<T: AsRawFd>(
+        cgroup: T,
+        prog_id: u32,
+        attach_type: CgroupSockAttachType,
+    ) -> Result<(), ProgramError> {
+        detach_by_id(cgroup, prog_id, attach_type.into())
+    }
 }
 
 #[derive(Debug, Hash, Eq, PartialEq)]
@@ -145,6 +154,19 @@ impl Link for CgroupSockLinkInner {
     }
 }
 
+impl UpdatableLink for CgroupSockLinkInner {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        match self {
+            CgroupSockLinkInner::Fd(fd) => fd.update_program(new_prog_fd, old_prog_fd),
+            CgroupSockLinkInner::ProgAttach(p) => p.update_program(new_prog_fd, old_prog_fd),
+        }
+    }
+}
+
 define_link_wrapper!(
     /// The link used by [CgroupSock] programs.
     CgroupSockLink,
@@ -153,3 +175,5 @@ define_link_wrapper!(
     CgroupSockLinkInner,
     CgroupSockLinkIdInner
 );
+
+impl_updatable_link!(CgroupSockLink);
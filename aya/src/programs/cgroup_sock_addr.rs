@@ -9,9 +9,11 @@ use std::{
 use crate::{
     generated::bpf_prog_type::BPF_PROG_TYPE_CGROUP_SOCK_ADDR,
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        cgroup_query::{attach_to_cgroup, detach_by_id, CgroupAttachment},
+        define_link_wrapper, impl_updatable_link,
+        links::UpdatableLink,
+        load_program, CgroupAttachMethod, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
     },
-    sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
 
 /// A program that can be used to inspect or modify socket addresses (`struct sockaddr`).
@@ -66,40 +68,30 @@ impl CgroupSockAddr {
 
     /// Attaches the program to the given cgroup.
     ///
+    /// A bpf link is used on kernels that support it, see
+    /// [CgroupSockAddr::attach_with_method] to choose how the program is attached.
+    ///
     /// The returned value can be used to detach, see [CgroupSockAddr::detach].
     pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<CgroupSockAddrLinkId, ProgramError> {
+        self.attach_with_method(cgroup, CgroupAttachMethod::Auto)
+    }
+
+    /// Attaches the program to the given cgroup using the given method.
+    ///
+    /// The returned value can be used to detach, see [CgroupSockAddr::detach].
+    pub fn attach_with_method<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+        method: CgroupAttachMethod,
+    ) -> Result<CgroupSockAddrLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
         let cgroup_fd = cgroup.as_raw_fd();
         let attach_type = self.data.expected_attach_type.unwrap();
-        let k_ver = kernel_version().unwrap();
-        if k_ver >= (5, 7, 0) {
-            let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0).map_err(
-                |(_, io_error)| ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                },
-            )? as RawFd;
-            self.data
-                .links
-                .insert(CgroupSockAddrLink::new(CgroupSockAddrLinkInner::Fd(
-                    FdLink::new(link_fd),
-                )))
-        } else {
-            bpf_prog_attach(prog_fd, cgroup_fd, attach_type).map_err(|(_, io_error)| {
-                ProgramError::SyscallError {
-                    call: "bpf_prog_attach".to_owned(),
-                    io_error,
-                }
-            })?;
-
-            self.data.links.insert(CgroupSockAddrLink::new(
-                CgroupSockAddrLinkInner::ProgAttach(ProgAttachLink::new(
-                    prog_fd,
-                    cgroup_fd,
-                    attach_type,
-                )),
-            ))
-        }
+        let link = match attach_to_cgroup(prog_fd, cgroup_fd, attach_type, method)? {
+            CgroupAttachment::Fd(link) => CgroupSockAddrLinkInner::Fd(link),
+            CgroupAttachment::ProgAttach(link) => CgroupSockAddrLinkInner::ProgAttach(link),
+        };
+        self.data.links.insert(CgroupSockAddrLink::new(link))
     }
 
     /// Takes ownership of the link referenced by the provided link_id.
@@ -119,6 +111,21 @@ impl CgroupSockAddr {
     pub fn detach(&mut self, link_id: CgroupSockAddrLinkId) -> Result<(), ProgramError> {
         self.data.links.remove(link_id)
     }
+
+    /// Detaches the program with the given id from `cgroup`.
+    ///
+    /// This can be used to detach programs attached by other processes, for
+    /// example after listing them with [`CgroupProgramQuery`](crate::programs::CgroupProgramQuery).
+    /// Only programs attached with `BPF_PROG_ATTACH` can be detached this
+    /// way, programs attached through a bpf link stay attached until the link
+    /// is closed.
+    pub fn detach_by_id<T: AsRawFd>(
+        cgroup: T,
+        prog_id: u32,
+        attach_type: CgroupSockAddrAttachType,
+    ) -> Result<(), ProgramError> {
+        detach_by_id(cgroup, prog_id, attach_type.into())
+    }
 }
 
 #[derive(Debug, Hash, Eq, PartialEq)]
@@ -151,6 +158,19 @@ impl Link for CgroupSockAddrLinkInner {
     }
 }
 
+impl UpdatableLink for CgroupSockAddrLinkInner {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        match self {
+            CgroupSockAddrLinkInner::Fd(fd) => fd.update_program(new_prog_fd, old_prog_fd),
+            CgroupSockAddrLinkInner::ProgAttach(p) => p.update_program(new_prog_fd, old_prog_fd),
+        }
+    }
+}
+
 define_link_wrapper!(
     /// The link used by [CgroupSockAddr] programs.
     CgroupSockAddrLink,
@@ -159,3 +179,5 @@ define_link_wrapper!(
     CgroupSockAddrLinkInner,
     CgroupSockAddrLinkIdInner
 );
+
+impl_updatable_link!(CgroupSockAddrLink);
@@ -9,9 +9,11 @@ use std::{
 use crate::{
     generated::bpf_prog_type::BPF_PROG_TYPE_CGROUP_SOCKOPT,
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        cgroup_query::{attach_to_cgroup, detach_by_id, CgroupAttachment},
+        define_link_wrapper, impl_updatable_link,
+        links::UpdatableLink,
+        load_program, CgroupAttachMethod, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
     },
-    sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
 
 /// A program that can be used to get or set options on sockets.
@@ -63,38 +65,29 @@ impl CgroupSockopt {
 
     /// Attaches the program to the given cgroup.
     ///
+    /// A bpf link is used on kernels that support it, see
+    /// [CgroupSockopt::attach_with_method] to choose how the program is attached.
+    ///
     /// The returned value can be used to detach, see [CgroupSockopt::detach].
     pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<CgroupSockoptLinkId, ProgramError> {
+        self.attach_with_method(cgroup, CgroupAttachMethod::Auto)
+    }
+
+    /// Attaches the program to the given cgroup using the given method.
+    ///
+    /// The returned value can be used to detach, see [CgroupSockopt::detach].
+    pub fn attach_with_method<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+        method: CgroupAttachMethod,
+    ) -> Result<CgroupSockoptLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
         let cgroup_fd = cgroup.as_raw_fd();
-        let attach_type = self.data.expected_attach_type.unwrap();
-        let k_ver = kernel_version().unwrap();
-        if k_ver >= (5, 7, 0) {
-            let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0).map_err(
-                |(_, io_error)| ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                },
-            )? as RawFd;
-            self.data
-                .links
-                .insert(CgroupSockoptLink::new(CgroupSockoptLinkInner::Fd(
-                    FdLink::new(link_fd),
-                )))
-        } else {
-            bpf_prog_attach(prog_fd, cgroup_fd, attach_type).map_err(|(_, io_error)| {
-                ProgramError::SyscallError {
-                    call: "bpf_prog_attach".to_owned(),
-                    io_error,
-                }
-            })?;
-
-            self.data
-                .links
-                .insert(CgroupSockoptLink::new(CgroupSockoptLinkInner::ProgAttach(
-                    ProgAttachLink::new(prog_fd, cgroup_fd, attach_type),
-                )))
-        }
+        let link = match attach_to_cgroup(prog_fd, cgroup_fd, self.attach_type.into(), method)? {
+            CgroupAttachment::Fd(link) => CgroupSockoptLinkInner::Fd(link),
+            CgroupAttachment::ProgAttach(link) => CgroupSockoptLinkInner::ProgAttach(link),
+        };
+        self.data.links.insert(CgroupSockoptLink::new(link))
     }
 
     /// Takes ownership of the link referenced by the provided link_id.
@@ -114,6 +107,21 @@ impl CgroupSockopt {
     pub fn detach(&mut self, link_id: CgroupSockoptLinkId) -> Result<(), ProgramError> {
         self.data.links.remove(link_id)
     }
+
+    /// Detaches the program with the given id from `cgroup`.
+    ///
+    /// This can be used to detach programs attached by other processes, for
+    /// example after listing them with [`CgroupProgramQuery`](crate::programs::CgroupProgramQuery).
+    /// Only programs attached with `BPF_PROG_ATTACH` can be detached this
+    /// way, programs attached through a bpf link stay attached until the link
+    /// is closed.
+    pub fn detach_by_id<T: AsRawFd>(
+        cgroup: T,
+        prog_id: u32,
+        attach_type: CgroupSockoptAttachType,
+    ) -> Result<(), ProgramError> {
+        detach_by_id(cgroup, prog_id, attach_type.into())
+    }
 }
 
 #[derive(Debug, Hash, Eq, PartialEq)]
@@ -146,6 +154,19 @@ impl Link for CgroupSockoptLinkInner {
     }
 }
 
+impl UpdatableLink for CgroupSockoptLinkInner {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        match self {
+            CgroupSockoptLinkInner::Fd(fd) => fd.update_program(new_prog_fd, old_prog_fd),
+            CgroupSockoptLinkInner::ProgAttach(p) => p.update_program(new_prog_fd, old_prog_fd),
+        }
+    }
+}
+
 define_link_wrapper!(
     /// The link used by [CgroupSockopt] programs.
     CgroupSockoptLink,
@@ -154,3 +175,95 @@ define_link_wrapper!(
     CgroupSockoptLinkInner,
     CgroupSockoptLinkIdInner
 );
+
+impl_updatable_link!(CgroupSockoptLink);
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, io};
+
+    use super::*;
+    use crate::{
+        generated::{bpf_attach_type::BPF_CGROUP_SETSOCKOPT, bpf_cmd},
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    #[test]
+    fn test_load_and_attach_setsockopt() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                assert_eq!(u.prog_type, BPF_PROG_TYPE_CGROUP_SOCKOPT as u32);
+                assert_eq!(u.expected_attach_type, BPF_CGROUP_SETSOCKOPT as u32);
+                Ok(99999)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_ATTACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.attach_bpf_fd, 99999);
+                assert_eq!(u.attach_type, BPF_CGROUP_SETSOCKOPT as u32);
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = CgroupSockopt {
+            data: fake_program_data(false),
+            attach_type: CgroupSockoptAttachType::Set,
+        };
+        prog.load().unwrap();
+        assert_eq!(prog.data.fd, Some(99999));
+
+        let link_id = prog
+            .attach_with_method(io::stdin(), CgroupAttachMethod::ProgAttach)
+            .unwrap();
+        prog.detach(link_id).unwrap();
+        prog.data.fd = None;
+    }
+
+    #[test]
+    fn test_take_link_drop_and_forget() {
+        thread_local! {
+            static DETACH_CALLS: Cell<u32> = Cell::new(0);
+        }
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_DETACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.attach_bpf_fd, 99999);
+                DETACH_CALLS.with(|calls| calls.set(calls.get() + 1));
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = CgroupSockopt {
+            data: fake_program_data(false),
+            attach_type: CgroupSockoptAttachType::Set,
+        };
+        prog.data.fd = Some(99999);
+
+        // the owned link detaches the program when it's dropped
+        let link_id = prog
+            .attach_with_method(io::stdin(), CgroupAttachMethod::ProgAttach)
+            .unwrap();
+        drop(prog.take_link(link_id).unwrap());
+        assert_eq!(DETACH_CALLS.with(|calls| calls.get()), 1);
+
+        // unless it's forgotten
+        let link_id = prog
+            .attach_with_method(io::stdin(), CgroupAttachMethod::ProgAttach)
+            .unwrap();
+        prog.take_link(link_id).unwrap().forget();
+        assert_eq!(DETACH_CALLS.with(|calls| calls.get()), 1);
+        prog.data.fd = None;
+    }
+}
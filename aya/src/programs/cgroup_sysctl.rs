@@ -7,9 +7,11 @@ use std::{
 use crate::{
     generated::{bpf_attach_type::BPF_CGROUP_SYSCTL, bpf_prog_type::BPF_PROG_TYPE_CGROUP_SYSCTL},
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        cgroup_query::{attach_to_cgroup, detach_by_id, CgroupAttachment},
+        define_link_wrapper, impl_updatable_link,
+        links::UpdatableLink,
+        load_program, CgroupAttachMethod, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
     },
-    sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
 
 /// A program used to watch for sysctl changes.
@@ -59,38 +61,29 @@ impl CgroupSysctl {
 
     /// Attaches the program to the given cgroup.
     ///
+    /// A bpf link is used on kernels that support it, see
+    /// [CgroupSysctl::attach_with_method] to choose how the program is attached.
+    ///
     /// The returned value can be used to detach, see [CgroupSysctl::detach].
     pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<CgroupSysctlLinkId, ProgramError> {
+        self.attach_with_method(cgroup, CgroupAttachMethod::Auto)
+    }
+
+    /// Attaches the program to the given cgroup using the given method.
+    ///
+    /// The returned value can be used to detach, see [CgroupSysctl::detach].
+    pub fn attach_with_method<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+        method: CgroupAttachMethod,
+    ) -> Result<CgroupSysctlLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
         let cgroup_fd = cgroup.as_raw_fd();
-
-        let k_ver = kernel_version().unwrap();
-        if k_ver >= (5, 7, 0) {
-            let link_fd = bpf_link_create(prog_fd, cgroup_fd, BPF_CGROUP_SYSCTL, None, 0).map_err(
-                |(_, io_error)| ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                },
-            )? as RawFd;
-            self.data
-                .links
-                .insert(CgroupSysctlLink::new(CgroupSysctlLinkInner::Fd(
-                    FdLink::new(link_fd),
-                )))
-        } else {
-            bpf_prog_attach(prog_fd, cgroup_fd, BPF_CGROUP_SYSCTL).map_err(|(_, io_error)| {
-                ProgramError::SyscallError {
-                    call: "bpf_prog_attach".to_owned(),
-                    io_error,
-                }
-            })?;
-
-            self.data
-                .links
-                .insert(CgroupSysctlLink::new(CgroupSysctlLinkInner::ProgAttach(
-                    ProgAttachLink::new(prog_fd, cgroup_fd, BPF_CGROUP_SYSCTL),
-                )))
-        }
+        let link = match attach_to_cgroup(prog_fd, cgroup_fd, BPF_CGROUP_SYSCTL, method)? {
+            CgroupAttachment::Fd(link) => CgroupSysctlLinkInner::Fd(link),
+            CgroupAttachment::ProgAttach(link) => CgroupSysctlLinkInner::ProgAttach(link),
+        };
+        self.data.links.insert(CgroupSysctlLink::new(link))
     }
 
     /// Takes ownership of the link referenced by the provided link_id.
@@ -110,6 +103,17 @@ impl CgroupSysctl {
     pub fn detach(&mut self, link_id: CgroupSysctlLinkId) -> Result<(), ProgramError> {
         self.data.links.remove(link_id)
     }
+
+    /// Detaches the program with the given id from `cgroup`.
+    ///
+    /// This can be used to detach programs attached by other processes, for
+    /// example after listing them with [`CgroupProgramQuery`](crate::programs::CgroupProgramQuery).
+    /// Only programs attached with `BPF_PROG_ATTACH` can be detached this
+    /// way, programs attached through a bpf link stay attached until the link
+    /// is closed.
+    pub fn detach_by_id<T: AsRawFd>(cgroup: T, prog_id: u32) -> Result<(), ProgramError> {
+        detach_by_id(cgroup, prog_id, BPF_CGROUP_SYSCTL)
+    }
 }
 
 #[derive(Debug, Hash, Eq, PartialEq)]
@@ -142,6 +146,19 @@ impl Link for CgroupSysctlLinkInner {
     }
 }
 
+impl UpdatableLink for CgroupSysctlLinkInner {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        match self {
+            CgroupSysctlLinkInner::Fd(fd) => fd.update_program(new_prog_fd, old_prog_fd),
+            CgroupSysctlLinkInner::ProgAttach(p) => p.update_program(new_prog_fd, old_prog_fd),
+        }
+    }
+}
+
 define_link_wrapper!(
     /// The link used by [CgroupSysctl] programs.
     CgroupSysctlLink,
@@ -150,3 +167,5 @@ define_link_wrapper!(
     CgroupSysctlLinkInner,
     CgroupSysctlLinkIdInner
 );
+
+impl_updatable_link!(CgroupSysctlLink);
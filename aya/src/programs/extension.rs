@@ -68,13 +68,11 @@ impl Extension {
     /// The extension code will be loaded but inactive until it's attached.
     /// There are no restrictions on what functions may be replaced, so you could replace
     /// the main entry point of your program with an extension.
+    ///
+    /// The target `program` can be obtained from a loaded [`Program`](crate::programs::Program)
+    /// with [`Program::fd`](crate::programs::Program::fd).
     pub fn load(&mut self, program: ProgramFd, func_name: &str) -> Result<(), ProgramError> {
-        let target_prog_fd = program.as_raw_fd();
-        let (btf_fd, btf_id) = get_btf_info(target_prog_fd, func_name)?;
-
-        self.data.attach_btf_obj_fd = Some(btf_fd as u32);
-        self.data.attach_prog_fd = Some(target_prog_fd);
-        self.data.attach_btf_id = Some(btf_id);
+        self.data.set_attach_target(program, func_name)?;
         load_program(BPF_PROG_TYPE_EXT, &mut self.data)
     }
 
@@ -149,7 +147,7 @@ impl Extension {
 
 /// Retrieves the FD of the BTF object for the provided `prog_fd` and the BTF ID of the function
 /// with the name `func_name` within that BTF object.
-fn get_btf_info(prog_fd: i32, func_name: &str) -> Result<(RawFd, u32), ProgramError> {
+pub(crate) fn get_btf_info(prog_fd: i32, func_name: &str) -> Result<(RawFd, u32), ProgramError> {
     // retrieve program information
     let info =
         sys::bpf_prog_get_info_by_fd(prog_fd).map_err(|io_error| ProgramError::SyscallError {
@@ -212,3 +210,85 @@ define_link_wrapper!(
     FdLink,
     FdLinkId
 );
+
+#[cfg(test)]
+mod tests {
+    use std::{mem, slice};
+
+    use super::*;
+    use crate::{
+        generated::{bpf_btf_info, bpf_cmd, bpf_prog_info},
+        obj::{
+            btf::{BtfType, Func, FuncLinkage, FuncProto},
+            ProgramSection,
+        },
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    fn fake_program() -> Extension {
+        let mut data = fake_program_data(false);
+        data.obj.section = ProgramSection::Extension {
+            name: "test".to_owned(),
+        };
+        Extension { data }
+    }
+
+    // BTF of the target program, with `func` as type 2
+    fn fake_target_btf() -> Vec<u8> {
+        let mut btf = Btf::new();
+        let proto_id = btf.add_type(BtfType::FuncProto(FuncProto::new(Vec::new(), 0)));
+        let name_offset = btf.add_string("func".to_owned());
+        btf.add_type(BtfType::Func(Func::new(
+            name_offset,
+            proto_id,
+            FuncLinkage::Global,
+        )));
+        btf.to_bytes()
+    }
+
+    #[test]
+    fn test_load_sets_attach_prog_fd() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info_len = unsafe { attr.info.info_len } as usize;
+                if info_len == mem::size_of::<bpf_prog_info>() {
+                    let info = unsafe { &mut *(attr.info.info as *mut bpf_prog_info) };
+                    info.btf_id = 1;
+                } else {
+                    assert_eq!(info_len, mem::size_of::<bpf_btf_info>());
+                    let info = unsafe { &mut *(attr.info.info as *mut bpf_btf_info) };
+                    let btf = fake_target_btf();
+                    let buf = unsafe {
+                        slice::from_raw_parts_mut(info.btf as *mut u8, info.btf_size as usize)
+                    };
+                    buf[..btf.len()].copy_from_slice(&btf);
+                    info.btf_size = btf.len() as u32;
+                }
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_BTF_GET_FD_BY_ID,
+                ..
+            } => Ok(5),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                assert_eq!(unsafe { u.__bindgen_anon_1.attach_prog_fd }, 42);
+                assert_eq!(u.attach_btf_id, 2);
+                Ok(7)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program();
+        prog.load(ProgramFd(42), "func").unwrap();
+        assert_eq!(prog.data.attach_prog_fd, Some(42));
+        assert_eq!(prog.data.fd, Some(7));
+    }
+}
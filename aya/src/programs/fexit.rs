@@ -5,7 +5,7 @@ use crate::{
     obj::btf::{Btf, BtfKind},
     programs::{
         define_link_wrapper, load_program, utils::attach_raw_tracepoint, FdLink, FdLinkId,
-        ProgramData, ProgramError,
+        ProgramData, ProgramError, ProgramFd,
     },
 };
 
@@ -61,6 +61,22 @@ impl FExit {
         load_program(BPF_PROG_TYPE_TRACING, &mut self.data)
     }
 
+    /// Loads the program inside the kernel, targeting a function of another
+    /// eBPF program.
+    ///
+    /// Loads the program so it's executed when the function `fn_name` of the
+    /// already loaded `program` is exited. The target program must have been
+    /// loaded with BTF.
+    pub fn load_for_program(
+        &mut self,
+        program: ProgramFd,
+        fn_name: &str,
+    ) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(BPF_TRACE_FEXIT);
+        self.data.set_attach_target(program, fn_name)?;
+        load_program(BPF_PROG_TYPE_TRACING, &mut self.data)
+    }
+
     /// Attaches the program.
     ///
     /// The returned value can be used to detach, see [FExit::detach].
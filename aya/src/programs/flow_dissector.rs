@@ -0,0 +1,162 @@
+//! Flow dissector programs.
+
+use std::os::unix::io::AsRawFd;
+
+use crate::{
+    generated::{bpf_attach_type::BPF_FLOW_DISSECTOR, bpf_prog_type::BPF_PROG_TYPE_FLOW_DISSECTOR},
+    programs::{
+        define_link_wrapper, impl_updatable_link, load_program, ProgAttachLink, ProgAttachLinkId,
+        ProgramData, ProgramError,
+    },
+    sys::bpf_prog_attach,
+};
+
+/// A program used to dissect packets into flow keys.
+///
+/// [`FlowDissector`] programs are attached to network namespaces, and replace
+/// the kernel's built-in flow dissector for the packets received in the
+/// namespace. They can be used to parse custom protocols and to control how
+/// packets are hashed to flows.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.20.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(Debug, thiserror::Error)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     IO(#[from] std::io::Error),
+/// #     #[error(transparent)]
+/// #     Map(#[from] aya::maps::MapError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError)
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use std::fs::File;
+/// use aya::programs::FlowDissector;
+///
+/// let netns = File::open("/proc/self/ns/net")?;
+/// let program: &mut FlowDissector = bpf.program_mut("dissect").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach(netns)?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_FLOW_DISSECTOR")]
+pub struct FlowDissector {
+    pub(crate) data: ProgramData<FlowDissectorLink>,
+}
+
+impl FlowDissector {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(BPF_FLOW_DISSECTOR);
+        load_program(BPF_PROG_TYPE_FLOW_DISSECTOR, &mut self.data)
+    }
+
+    /// Attaches the program to the given network namespace.
+    ///
+    /// `netns` is usually a file opened from `/proc/<pid>/ns/net` or
+    /// `/var/run/netns/<name>`.
+    ///
+    /// The returned value can be used to detach, see [FlowDissector::detach].
+    pub fn attach<T: AsRawFd>(&mut self, netns: T) -> Result<FlowDissectorLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let netns_fd = netns.as_raw_fd();
+
+        bpf_prog_attach(prog_fd, netns_fd, BPF_FLOW_DISSECTOR).map_err(|(_, io_error)| {
+            ProgramError::SyscallError {
+                call: "bpf_prog_attach".to_owned(),
+                io_error,
+            }
+        })?;
+        self.data
+            .links
+            .insert(FlowDissectorLink::new(ProgAttachLink::new(
+                prog_fd,
+                netns_fd,
+                BPF_FLOW_DISSECTOR,
+            )))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [FlowDissector::attach].
+    pub fn detach(&mut self, link_id: FlowDissectorLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(
+        &mut self,
+        link_id: FlowDissectorLinkId,
+    ) -> Result<FlowDissectorLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [FlowDissector] programs.
+    FlowDissectorLink,
+    /// The type returned by [FlowDissector::attach]. Can be passed to [FlowDissector::detach].
+    FlowDissectorLinkId,
+    ProgAttachLink,
+    ProgAttachLinkId
+);
+
+impl_updatable_link!(FlowDissectorLink);
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    #[test]
+    fn test_attach_not_loaded() {
+        let mut prog = FlowDissector {
+            data: fake_program_data(false),
+        };
+        assert!(matches!(
+            prog.attach(io::stdin()),
+            Err(ProgramError::NotLoaded)
+        ));
+    }
+
+    #[test]
+    fn test_attach() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_ATTACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.attach_bpf_fd, 1);
+                assert_eq!(u.target_fd, 0);
+                assert_eq!(u.attach_type, BPF_FLOW_DISSECTOR as u32);
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = FlowDissector {
+            data: fake_program_data(false),
+        };
+        prog.data.fd = Some(1);
+        // the netns fd is never looked at, the syscall is mocked
+        assert!(prog.attach(io::stdin()).is_ok());
+    }
+}
@@ -0,0 +1,153 @@
+//! Iterator programs.
+use std::{
+    fs::File,
+    os::unix::io::{FromRawFd, RawFd},
+};
+
+use crate::{
+    generated::{bpf_attach_type::BPF_TRACE_ITER, bpf_prog_type::BPF_PROG_TYPE_TRACING},
+    obj::{
+        btf::{Btf, BtfKind},
+        ProgramSection,
+    },
+    programs::{
+        define_link_wrapper, impl_updatable_link, load_program, FdLink, FdLinkId, LinkError,
+        ProgramData, ProgramError,
+    },
+    sys::{bpf_create_iter, bpf_link_create},
+};
+
+/// A BPF iterator which allows to dump data from the kernel-space into the
+/// user-space.
+///
+/// It can be seen as an alternative to `/proc` filesystem as it offers more
+/// flexibility about what information should be retrieved and how it should be
+/// formatted.
+///
+/// The iterator target (eg `task` or `bpf_map`) is parsed from the section
+/// name, ie `iter/task`.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.8.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(thiserror::Error, Debug)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     BtfError(#[from] aya::BtfError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Link(#[from] aya::programs::links::LinkError),
+/// #     #[error(transparent)]
+/// #     Io(#[from] std::io::Error),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError),
+/// # }
+/// # let mut bpf = aya::Bpf::load_file("ebpf_programs.o")?;
+/// use std::io::{BufRead, BufReader};
+/// use aya::{programs::Iter, Btf};
+///
+/// let btf = Btf::from_sys_fs()?;
+/// let program: &mut Iter = bpf.program_mut("task").unwrap().try_into()?;
+/// program.load(&btf)?;
+///
+/// let link_id = program.attach()?;
+/// let link = program.take_link(link_id)?;
+/// let file = link.into_file()?;
+/// let reader = BufReader::new(file);
+///
+/// for line in reader.lines() {
+///     let line = line?;
+///     println!("{line}");
+/// }
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_TRACE_ITER")]
+#[doc(alias = "BPF_PROG_TYPE_TRACING")]
+pub struct Iter {
+    pub(crate) data: ProgramData<IterLink>,
+}
+
+impl Iter {
+    /// Loads the program inside the kernel.
+    ///
+    /// The `btf` argument must contain the BTF info for the running kernel,
+    /// which is used to resolve the `bpf_iter_<target>` function of the
+    /// iterator target.
+    pub fn load(&mut self, btf: &Btf) -> Result<(), ProgramError> {
+        let target = match &self.data.obj.section {
+            ProgramSection::Iter { target, .. } => target,
+            _ => return Err(ProgramError::UnexpectedProgramType),
+        };
+        let type_name = format!("bpf_iter_{target}");
+        self.data.expected_attach_type = Some(BPF_TRACE_ITER);
+        self.data.attach_btf_id =
+            Some(btf.id_by_type_name_kind(type_name.as_str(), BtfKind::Func)?);
+        load_program(BPF_PROG_TYPE_TRACING, &mut self.data)
+    }
+
+    /// Attaches the program.
+    ///
+    /// The returned value can be used to detach, see [Iter::detach]. To read
+    /// the output of the iterator, take ownership of the link with
+    /// [Iter::take_link] and call [IterLink::into_file].
+    pub fn attach(&mut self) -> Result<IterLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let link_fd =
+            bpf_link_create(prog_fd, 0, BPF_TRACE_ITER, None, 0).map_err(|(_, io_error)| {
+                ProgramError::SyscallError {
+                    call: "bpf_link_create".to_owned(),
+                    io_error,
+                }
+            })? as RawFd;
+
+        self.data.links.insert(IterLink::new(FdLink::new(link_fd)))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [Iter::attach].
+    pub fn detach(&mut self, link_id: IterLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(&mut self, link_id: IterLinkId) -> Result<IterLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+impl IterLink {
+    /// Converts the link into a [`File`] from which the output of the iterator
+    /// can be read.
+    pub fn into_file(self) -> Result<File, LinkError> {
+        let link_fd = self.inner().fd;
+        let fd = bpf_create_iter(link_fd).map_err(|(code, io_error)| LinkError::SyscallError {
+            call: "bpf_iter_create".to_owned(),
+            code,
+            io_error,
+        })? as RawFd;
+        // the iterator holds a reference to the link, so it's safe to drop the
+        // link once the iterator has been created
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [Iter] programs.
+    IterLink,
+    /// The type returned by [Iter::attach]. Can be passed to [Iter::detach].
+    IterLinkId,
+    FdLink,
+    FdLinkId
+);
+
+impl_updatable_link!(IterLink);
@@ -1,15 +1,20 @@
 //! Kernel space probes.
-use std::io;
+use std::{ffi::CString, io, os::unix::io::RawFd};
 use thiserror::Error;
 
 use crate::{
-    generated::bpf_prog_type::BPF_PROG_TYPE_KPROBE,
+    generated::{
+        bpf_attach_type::BPF_TRACE_KPROBE_MULTI, bpf_link_type,
+        bpf_prog_type::BPF_PROG_TYPE_KPROBE, BPF_F_KPROBE_MULTI_RETURN,
+    },
+    obj::ProgramSection,
     programs::{
         define_link_wrapper, load_program,
-        perf_attach::{PerfLink, PerfLinkId},
+        perf_attach::PerfLink,
         probe::{attach, ProbeKind},
-        ProgramData, ProgramError,
+        FdLink, Link, LinkError, ProgramData, ProgramError,
     },
+    sys::{bpf_link_create_kprobe_multi, bpf_link_get_info_by_fd},
 };
 
 /// A kernel probe.
@@ -45,9 +50,19 @@ pub struct KProbe {
 impl KProbe {
     /// Loads the program inside the kernel.
     pub fn load(&mut self) -> Result<(), ProgramError> {
+        if self.is_multi() {
+            self.data.expected_attach_type = Some(BPF_TRACE_KPROBE_MULTI);
+        }
         load_program(BPF_PROG_TYPE_KPROBE, &mut self.data)
     }
 
+    fn is_multi(&self) -> bool {
+        matches!(
+            self.data.obj.section,
+            ProgramSection::KProbeMulti { .. } | ProgramSection::KRetProbeMulti { .. }
+        )
+    }
+
     /// Returns `KProbe` if the program is a `kprobe`, or `KRetProbe` if the
     /// program is a `kretprobe`.
     pub fn kind(&self) -> ProbeKind {
@@ -65,10 +80,90 @@ impl KProbe {
     /// target function.
     ///
     /// The returned value can be used to detach from the given function, see [KProbe::detach].
+    ///
+    /// On kernels older than 4.17, or when the kprobe PMU isn't available,
+    /// the probe is created through the `kprobe_events` file of tracefs, and
+    /// removed from it on detach.
+    ///
+    /// # Errors
+    ///
+    /// `kretprobe`s can only be attached to the start of a function, so
+    /// [`KProbeError::InvalidOffset`] is returned if `offset` is non-zero.
     pub fn attach(&mut self, fn_name: &str, offset: u64) -> Result<KProbeLinkId, ProgramError> {
+        if let ProbeKind::KRetProbe = self.kind {
+            if offset != 0 {
+                return Err(KProbeError::InvalidOffset {
+                    fn_name: fn_name.to_owned(),
+                    offset,
+                }
+                .into());
+            }
+        }
         attach(&mut self.data, self.kind, fn_name, offset, None)
     }
 
+    /// Attaches the program to the given kernel address.
+    ///
+    /// This can be used to probe functions that aren't exported in
+    /// `/proc/kallsyms`, or arbitrary instructions inside a function. The
+    /// address must be the address of an instruction, or for `kretprobe`s the
+    /// start address of a function.
+    ///
+    /// The returned value can be used to detach, see [KProbe::detach].
+    pub fn attach_addr(&mut self, addr: u64) -> Result<KProbeLinkId, ProgramError> {
+        attach(&mut self.data, self.kind, "", addr, None)
+    }
+
+    /// Attaches the program to all the given kernel functions with a single
+    /// link.
+    ///
+    /// This is much faster than attaching to each function with
+    /// [KProbe::attach], and is only supported by programs defined in
+    /// `kprobe.multi` and `kretprobe.multi` sections. All the functions are
+    /// detached together.
+    ///
+    /// The returned value can be used to detach, see [KProbe::detach].
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.18.
+    ///
+    /// # Errors
+    ///
+    /// [`KProbeError::NotMulti`] is returned if the program isn't a
+    /// `kprobe.multi` or `kretprobe.multi` program.
+    pub fn attach_multi(&mut self, symbols: &[&str]) -> Result<KProbeLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        if !self.is_multi() {
+            return Err(KProbeError::NotMulti.into());
+        }
+        let symbols = symbols
+            .iter()
+            .map(|symbol| {
+                CString::new(*symbol).map_err(|_| ProgramError::InvalidName {
+                    name: symbol.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let flags = match self.kind {
+            ProbeKind::KRetProbe => BPF_F_KPROBE_MULTI_RETURN,
+            _ => 0,
+        };
+
+        let link_fd =
+            bpf_link_create_kprobe_multi(prog_fd, &symbols, flags).map_err(|(_, io_error)| {
+                ProgramError::SyscallError {
+                    call: "bpf_link_create".to_owned(),
+                    io_error,
+                }
+            })? as RawFd;
+        self.data
+            .links
+            .insert(KProbeLink::new(KProbeLinkInner::FdLink(FdLink::new(
+                link_fd,
+            ))))
+    }
+
     /// Detaches the program.
     ///
     /// See [KProbe::attach].
@@ -85,13 +180,90 @@ impl KProbe {
     }
 }
 
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub(crate) enum KProbeLinkIdInner {
+    PerfLinkId(<PerfLink as Link>::Id),
+    FdLinkId(<FdLink as Link>::Id),
+}
+
+#[derive(Debug)]
+pub(crate) enum KProbeLinkInner {
+    PerfLink(PerfLink),
+    FdLink(FdLink),
+}
+
+impl Link for KProbeLinkInner {
+    type Id = KProbeLinkIdInner;
+
+    fn id(&self) -> Self::Id {
+        match self {
+            KProbeLinkInner::PerfLink(link) => KProbeLinkIdInner::PerfLinkId(link.id()),
+            KProbeLinkInner::FdLink(link) => KProbeLinkIdInner::FdLinkId(link.id()),
+        }
+    }
+
+    fn detach(self) -> Result<(), ProgramError> {
+        match self {
+            KProbeLinkInner::PerfLink(link) => link.detach(),
+            KProbeLinkInner::FdLink(link) => link.detach(),
+        }
+    }
+}
+
+impl From<PerfLink> for KProbeLink {
+    fn from(link: PerfLink) -> KProbeLink {
+        KProbeLink::new(KProbeLinkInner::PerfLink(link))
+    }
+}
+
+impl TryFrom<KProbeLink> for PerfLink {
+    type Error = LinkError;
+
+    fn try_from(value: KProbeLink) -> Result<Self, Self::Error> {
+        if let KProbeLinkInner::PerfLink(link) = value.into_inner() {
+            Ok(link)
+        } else {
+            Err(LinkError::InvalidLink)
+        }
+    }
+}
+
+impl TryFrom<KProbeLink> for FdLink {
+    type Error = LinkError;
+
+    fn try_from(value: KProbeLink) -> Result<Self, Self::Error> {
+        if let KProbeLinkInner::FdLink(link) = value.into_inner() {
+            Ok(link)
+        } else {
+            Err(LinkError::InvalidLink)
+        }
+    }
+}
+
+impl TryFrom<FdLink> for KProbeLink {
+    type Error = LinkError;
+
+    fn try_from(fd_link: FdLink) -> Result<Self, Self::Error> {
+        let info =
+            bpf_link_get_info_by_fd(fd_link.fd).map_err(|io_error| LinkError::SyscallError {
+                call: "BPF_OBJ_GET_INFO_BY_FD".to_string(),
+                code: 0,
+                io_error,
+            })?;
+        if info.type_ == (bpf_link_type::BPF_LINK_TYPE_KPROBE_MULTI as u32) {
+            return Ok(KProbeLink::new(KProbeLinkInner::FdLink(fd_link)));
+        }
+        Err(LinkError::InvalidLink)
+    }
+}
+
 define_link_wrapper!(
     /// The link used by [KProbe] programs.
     KProbeLink,
     /// The type returned by [KProbe::attach]. Can be passed to [KProbe::detach].
     KProbeLinkId,
-    PerfLink,
-    PerfLinkId
+    KProbeLinkInner,
+    KProbeLinkIdInner
 );
 
 /// The type returned when attaching a [`KProbe`] fails.
@@ -106,4 +278,105 @@ pub enum KProbeError {
         #[source]
         io_error: io::Error,
     },
+
+    /// The program wasn't defined in a `kprobe.multi` or `kretprobe.multi`
+    /// section, so it can't be attached to multiple functions
+    #[error(
+        "only `kprobe.multi` and `kretprobe.multi` programs can be attached to multiple functions"
+    )]
+    NotMulti,
+
+    /// The offset can't be used with this kind of probe
+    #[error("`kretprobe`s can't be attached at offset {offset:#x} of `{fn_name}`")]
+    InvalidOffset {
+        /// The function name
+        fn_name: String,
+        /// The offset
+        offset: u64,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    fn fake_program(kind: ProbeKind) -> KProbe {
+        fake_program_with_section(
+            kind,
+            ProgramSection::KRetProbe {
+                name: "test".to_owned(),
+            },
+        )
+    }
+
+    fn fake_program_with_section(kind: ProbeKind, section: ProgramSection) -> KProbe {
+        let mut data = fake_program_data(false);
+        data.obj.section = section;
+        KProbe { data, kind }
+    }
+
+    #[test]
+    fn test_kretprobe_offset() {
+        let mut prog = fake_program(ProbeKind::KRetProbe);
+        assert!(matches!(
+            prog.attach("try_to_wake_up", 4),
+            Err(ProgramError::KProbeError(KProbeError::InvalidOffset {
+                offset: 4,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_attach_multi() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let link_create = unsafe { attr.link_create };
+                assert_eq!(link_create.attach_type, BPF_TRACE_KPROBE_MULTI as u32);
+                let kprobe_multi = unsafe { link_create.__bindgen_anon_2.kprobe_multi };
+                assert_eq!(kprobe_multi.flags, BPF_F_KPROBE_MULTI_RETURN);
+                assert_eq!(kprobe_multi.cnt, 2);
+                let syms = unsafe {
+                    std::slice::from_raw_parts(
+                        kprobe_multi.syms as *const *const libc::c_char,
+                        kprobe_multi.cnt as usize,
+                    )
+                };
+                let syms = syms
+                    .iter()
+                    .map(|sym| unsafe { std::ffi::CStr::from_ptr(*sym) }.to_str().unwrap())
+                    .collect::<Vec<_>>();
+                assert_eq!(syms, ["vfs_read", "vfs_write"]);
+                Ok(-1)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program_with_section(
+            ProbeKind::KRetProbe,
+            ProgramSection::KRetProbeMulti {
+                name: "test".to_owned(),
+            },
+        );
+        prog.data.fd = Some(1);
+        assert!(prog.attach_multi(&["vfs_read", "vfs_write"]).is_ok());
+    }
+
+    #[test]
+    fn test_attach_multi_not_multi() {
+        let mut prog = fake_program(ProbeKind::KProbe);
+        prog.data.fd = Some(1);
+        assert!(matches!(
+            prog.attach_multi(&["vfs_read"]),
+            Err(ProgramError::KProbeError(KProbeError::NotMulti))
+        ));
+    }
 }
@@ -1,4 +1,27 @@
 //! Program links.
+//!
+//! Attaching a program returns a link id and the link itself is owned by the
+//! program, so the program gets detached when it's dropped. Calling
+//! `take_link` with the id transfers ownership of the link to the caller: the
+//! returned link detaches the program when it's dropped, unless it's consumed
+//! with [`Link::forget`]. This holds for every attach method, including the
+//! netlink based XDP and tc attachments and the `bpf_prog_attach` based cgroup
+//! attachments, which stay in place after the process exits once forgotten.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # let mut bpf = aya::Bpf::load(&[])?;
+//! use aya::programs::{links::Link, Xdp, XdpFlags};
+//!
+//! let program: &mut Xdp = bpf.program_mut("prog").unwrap().try_into()?;
+//! program.load()?;
+//! let link_id = program.attach("eth0", XdpFlags::SKB_MODE)?;
+//!
+//! // keep the program attached once this process is done with it
+//! program.take_link(link_id)?.forget();
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
 use libc::{close, dup};
 use thiserror::Error;
 
@@ -14,7 +37,7 @@ use crate::{
     generated::bpf_attach_type,
     pin::PinError,
     programs::ProgramError,
-    sys::{bpf_get_object, bpf_pin_object, bpf_prog_detach},
+    sys::{bpf_get_object, bpf_link_update, bpf_pin_object, bpf_prog_attach, bpf_prog_detach},
 };
 
 /// A Link.
@@ -25,8 +48,33 @@ pub trait Link: std::fmt::Debug + 'static {
     /// Returns the link id
     fn id(&self) -> Self::Id;
 
-    /// Detaches the LinkOwnedLink is gone... but this doesn't work :(
+    /// Detaches the link.
     fn detach(self) -> Result<(), ProgramError>;
+
+    /// Consumes the link without detaching it.
+    ///
+    /// The program stays attached after the link is gone, and can't be
+    /// detached through this link anymore. Depending on the attach method, the
+    /// attachment ends when the process exits (perf events and `bpf_link`
+    /// based links, see [`FdLink::pin`] to keep those alive) or persists until
+    /// it's explicitly removed (netlink and `bpf_prog_attach` based links).
+    fn forget(self)
+    where
+        Self: Sized,
+    {
+        std::mem::forget(self);
+    }
+}
+
+/// A link whose program can be replaced without detaching it.
+pub(crate) trait UpdatableLink: Link {
+    /// Makes the link run the program `new_prog_fd` in place of
+    /// `old_prog_fd`, without a window in which no program is attached.
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError>;
 }
 
 #[derive(Debug)]
@@ -52,6 +100,10 @@ impl<T: Link> LinkMap<T> {
         Ok(id)
     }
 
+    pub(crate) fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
     pub(crate) fn remove(&mut self, link_id: T::Id) -> Result<(), ProgramError> {
         self.links
             .remove(&link_id)
@@ -71,6 +123,33 @@ impl<T: Link> LinkMap<T> {
     }
 }
 
+impl<T: UpdatableLink> LinkMap<T> {
+    // Moves all the links from `old_prog_fd` to `new_prog_fd`. The links keep
+    // their ids. If a link can't be updated, the links already moved are moved
+    // back to `old_prog_fd`.
+    pub(crate) fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        let mut updated = 0;
+        let mut result = Ok(());
+        for link in self.links.values_mut() {
+            result = link.update_program(new_prog_fd, old_prog_fd);
+            if result.is_err() {
+                break;
+            }
+            updated += 1;
+        }
+        if result.is_err() {
+            for link in self.links.values_mut().take(updated) {
+                let _ = link.update_program(old_prog_fd, new_prog_fd);
+            }
+        }
+        result
+    }
+}
+
 impl<T: Link> Drop for LinkMap<T> {
     fn drop(&mut self) {
         let _ = self.remove_all();
@@ -153,6 +232,22 @@ impl Link for FdLink {
     }
 }
 
+impl UpdatableLink for FdLink {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        bpf_link_update(self.fd, new_prog_fd, Some(old_prog_fd), 0).map_err(|(_, io_error)| {
+            ProgramError::SyscallError {
+                call: "bpf_link_update".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(())
+    }
+}
+
 impl From<PinnedLink> for FdLink {
     fn from(p: PinnedLink) -> Self {
         p.inner
@@ -182,14 +277,20 @@ impl PinnedLink {
     }
 
     /// Creates a [`PinnedLink`] from a valid path on bpffs.
-    pub fn from_pin<P: AsRef<Path>>(path: P) -> Result<Self, LinkError> {
-        let path_string = CString::new(path.as_ref().to_string_lossy().to_string()).unwrap();
-        let fd =
-            bpf_get_object(&path_string).map_err(|(code, io_error)| LinkError::SyscallError {
-                call: "BPF_OBJ_GET".to_string(),
-                code,
-                io_error,
-            })? as RawFd;
+    ///
+    /// This can be used to get back a link pinned with [`FdLink::pin`], for
+    /// example by another process or by a previous run of the program.
+    pub fn from_pin<P: AsRef<Path>>(path: P) -> Result<Self, PinError> {
+        let path_string =
+            CString::new(path.as_ref().to_string_lossy().into_owned()).map_err(|e| {
+                PinError::InvalidPinPath {
+                    error: e.to_string(),
+                }
+            })?;
+        let fd = bpf_get_object(&path_string).map_err(|(_, io_error)| PinError::SyscallError {
+            name: "BPF_OBJ_GET".to_string(),
+            io_error,
+        })? as RawFd;
         Ok(PinnedLink::new(
             path.as_ref().to_path_buf(),
             FdLink::new(fd),
@@ -203,6 +304,26 @@ impl PinnedLink {
     }
 }
 
+// Attaching a program where a program is already attached with
+// `bpf_prog_attach` replaces it for cgroups (without BPF_F_ALLOW_MULTI, which
+// aya doesn't use), sock maps and network namespaces.
+impl UpdatableLink for ProgAttachLink {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        _old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        bpf_prog_attach(new_prog_fd, self.target_fd, self.attach_type).map_err(
+            |(_, io_error)| ProgramError::SyscallError {
+                call: "bpf_prog_attach".to_owned(),
+                io_error,
+            },
+        )?;
+        self.prog_fd = new_prog_fd;
+        Ok(())
+    }
+}
+
 /// The identifier of a `ProgAttachLink`.
 #[derive(Debug, Hash, Eq, PartialEq)]
 pub struct ProgAttachLinkId(RawFd, RawFd, bpf_attach_type);
@@ -254,7 +375,7 @@ macro_rules! define_link_wrapper {
         pub struct $wrapper(Option<$base>);
 
         #[allow(dead_code)]
-        // allow dead code since currently XDP is the only consumer of inner and
+        // allow dead code since only some links use inner, inner_mut and
         // into_inner
         impl $wrapper {
             fn new(base: $base) -> $wrapper {
@@ -265,6 +386,10 @@ macro_rules! define_link_wrapper {
                 self.0.as_ref().unwrap()
             }
 
+            fn inner_mut(&mut self) -> &mut $base {
+                self.0.as_mut().unwrap()
+            }
+
             fn into_inner(mut self) -> $base {
                 self.0.take().unwrap()
             }
@@ -290,6 +415,10 @@ macro_rules! define_link_wrapper {
             fn detach(mut self) -> Result<(), ProgramError> {
                 self.0.take().unwrap().detach()
             }
+
+            fn forget(mut self) {
+                self.0.take().unwrap().forget()
+            }
         }
 
         impl From<$base> for $wrapper {
@@ -308,6 +437,27 @@ macro_rules! define_link_wrapper {
 
 pub(crate) use define_link_wrapper;
 
+// Implements UpdatableLink for a link wrapper whose base link implements it.
+macro_rules! impl_updatable_link {
+    ($wrapper:ident) => {
+        impl crate::programs::links::UpdatableLink for $wrapper {
+            fn update_program(
+                &mut self,
+                new_prog_fd: std::os::unix::io::RawFd,
+                old_prog_fd: std::os::unix::io::RawFd,
+            ) -> Result<(), ProgramError> {
+                crate::programs::links::UpdatableLink::update_program(
+                    self.inner_mut(),
+                    new_prog_fd,
+                    old_prog_fd,
+                )
+            }
+        }
+    };
+}
+
+pub(crate) use impl_updatable_link;
+
 #[derive(Error, Debug)]
 /// Errors from operations on links.
 pub enum LinkError {
@@ -329,11 +479,30 @@ pub enum LinkError {
 
 #[cfg(test)]
 mod tests {
-    use std::{cell::RefCell, env, fs::File, mem, os::unix::io::AsRawFd, rc::Rc};
+    use std::{
+        cell::{Cell, RefCell},
+        env,
+        ffi::CStr,
+        fs::{self, File},
+        io, mem,
+        os::unix::io::{AsRawFd, IntoRawFd, RawFd},
+        process,
+        rc::Rc,
+    };
 
-    use crate::{programs::ProgramError, sys::override_syscall};
+    use crate::{
+        generated::{bpf_attach_type, bpf_cmd},
+        pin::PinError,
+        programs::{sk_msg::SkMsgLink, ProgramError},
+        sys::{override_syscall, Syscall},
+    };
 
-    use super::{FdLink, Link, LinkMap};
+    use super::{FdLink, Link, LinkMap, PinnedLink, ProgAttachLink};
+
+    thread_local! {
+        static LINK_FD: Cell<RawFd> = Cell::new(-1);
+        static DETACH_CALLS: Cell<u32> = Cell::new(0);
+    }
 
     #[derive(Debug, Hash, Eq, PartialEq)]
     struct TestLinkId(u8, u8);
@@ -464,6 +633,39 @@ mod tests {
         assert!(*l2_detached.borrow() == 1);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_owned_link_drop_and_forget() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_DETACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.attach_bpf_fd, 42);
+                DETACH_CALLS.with(|calls| calls.set(calls.get() + 1));
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+        let target = File::open(env::temp_dir()).unwrap();
+        let new_link = || {
+            SkMsgLink::from(ProgAttachLink::new(
+                42,
+                target.as_raw_fd(),
+                bpf_attach_type::BPF_SK_MSG_VERDICT,
+            ))
+        };
+
+        // dropping an owned link detaches the program
+        drop(new_link());
+        assert_eq!(DETACH_CALLS.with(|calls| calls.get()), 1);
+
+        // forgetting it leaves the program attached
+        new_link().forget();
+        assert_eq!(DETACH_CALLS.with(|calls| calls.get()), 1);
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_pin() {
@@ -484,4 +686,64 @@ mod tests {
         pinned_link.unpin().expect("unpin failed");
         assert!(!dir.join("f1-pin").exists());
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_pin_and_from_pin() {
+        let dir = env::temp_dir().join(format!("aya-link-pin-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("link");
+        // the file that a real pin operation would create
+        let fd = File::create(&path)
+            .expect("unable to create file in tmpdir")
+            .into_raw_fd();
+        LINK_FD.with(|link_fd| link_fd.set(fd));
+
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_PIN,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_4 };
+                assert_eq!(u.bpf_fd as RawFd, LINK_FD.with(|fd| fd.get()));
+                let path = unsafe { CStr::from_ptr(u.pathname as *const _) };
+                let pin = format!("aya-link-pin-{}/link", process::id());
+                assert!(path.to_str().unwrap().ends_with(&pin));
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_4 };
+                let path = unsafe { CStr::from_ptr(u.pathname as *const _) };
+                match File::open(path.to_str().unwrap()) {
+                    Ok(file) => Ok(file.into_raw_fd() as _),
+                    Err(_) => Err((-1, io::Error::from_raw_os_error(libc::ENOENT))),
+                }
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        // dropping the pinned link closes the fd but leaves the pin
+        let pinned_link = FdLink::new(fd).pin(&path).expect("pin failed");
+        drop(pinned_link);
+        assert!(path.exists());
+
+        let pinned_link = PinnedLink::from_pin(&path).expect("from_pin failed");
+        let link = pinned_link.unpin().expect("unpin failed");
+        assert!(!path.exists());
+        drop(link);
+
+        assert!(matches!(
+            PinnedLink::from_pin(&path),
+            Err(PinError::SyscallError { name, .. }) if name == "BPF_OBJ_GET"
+        ));
+        assert!(matches!(
+            PinnedLink::from_pin("/sys/fs/bpf/foo\0bar"),
+            Err(PinError::InvalidPinPath { .. })
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
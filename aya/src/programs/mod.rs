@@ -36,6 +36,7 @@
 //! [`Bpf::program_mut`]: crate::Bpf::program_mut
 //! [`maps`]: crate::maps
 pub mod cgroup_device;
+mod cgroup_query;
 pub mod cgroup_skb;
 pub mod cgroup_sock;
 pub mod cgroup_sock_addr;
@@ -44,6 +45,8 @@ pub mod cgroup_sysctl;
 pub mod extension;
 pub mod fentry;
 pub mod fexit;
+mod flow_dissector;
+pub mod iter;
 pub mod kprobe;
 pub mod links;
 pub mod lirc_mode2;
@@ -54,26 +57,33 @@ mod probe;
 mod raw_trace_point;
 mod sk_lookup;
 mod sk_msg;
+mod sk_reuseport;
 mod sk_skb;
 mod sock_ops;
 mod socket_filter;
+pub mod syscall;
 pub mod tc;
+pub mod tcx;
 pub mod tp_btf;
 pub mod trace_point;
 pub mod uprobe;
+pub mod usdt;
 mod utils;
 pub mod xdp;
 
 use libc::ENOSPC;
 use std::{
+    cell::Cell,
     ffi::CString,
-    io,
+    io, mem,
     os::unix::io::{AsRawFd, RawFd},
     path::Path,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
 pub use cgroup_device::CgroupDevice;
+pub use cgroup_query::{CgroupAttachMethod, CgroupAttachType, CgroupProgramQuery};
 pub use cgroup_skb::{CgroupSkb, CgroupSkbAttachType};
 pub use cgroup_sock::{CgroupSock, CgroupSockAttachType};
 pub use cgroup_sock_addr::{CgroupSockAddr, CgroupSockAddrAttachType};
@@ -82,6 +92,8 @@ pub use cgroup_sysctl::CgroupSysctl;
 pub use extension::{Extension, ExtensionError};
 pub use fentry::FEntry;
 pub use fexit::FExit;
+pub use flow_dissector::FlowDissector;
+pub use iter::Iter;
 pub use kprobe::{KProbe, KProbeError};
 pub use links::Link;
 use links::*;
@@ -93,25 +105,37 @@ pub use probe::ProbeKind;
 pub use raw_trace_point::RawTracePoint;
 pub use sk_lookup::SkLookup;
 pub use sk_msg::SkMsg;
+pub use sk_reuseport::{SkReuseport, SkReuseportError};
 pub use sk_skb::{SkSkb, SkSkbKind};
 pub use sock_ops::SockOps;
 pub use socket_filter::{SocketFilter, SocketFilterError};
+pub use syscall::Syscall;
 pub use tc::{SchedClassifier, TcAttachType, TcError};
+pub use tcx::{Tcx, TcxAttachType, TcxError, TcxLinkOrder};
 pub use tp_btf::BtfTracePoint;
-pub use trace_point::{TracePoint, TracePointError};
+pub use trace_point::{TraceEventField, TraceEventFormat, TracePoint, TracePointError};
 pub use uprobe::{UProbe, UProbeError};
+pub use usdt::{Usdt, UsdtArgument, UsdtArgumentLocation, UsdtError, UsdtProbe};
 pub use xdp::{Xdp, XdpError, XdpFlags};
 
 use crate::{
-    generated::{bpf_attach_type, bpf_prog_info, bpf_prog_type},
+    generated::{bpf_attach_type, bpf_insn, bpf_prog_info, bpf_prog_type, BPF_F_SLEEPABLE},
     maps::MapError,
-    obj::{self, btf::BtfError, Function, KernelVersion},
+    obj::{
+        self,
+        btf::{Btf, BtfError},
+        relocation::relocate_maps_to_fd_array,
+        Function, KernelVersion,
+    },
     pin::PinError,
     sys::{
-        bpf_get_object, bpf_load_program, bpf_pin_object, bpf_prog_get_fd_by_id,
-        bpf_prog_get_info_by_fd, bpf_prog_query, retry_with_verifier_logs, BpfLoadProgramAttrs,
+        bpf_enable_stats, bpf_get_object, bpf_load_program, bpf_pin_object, bpf_prog_get_fd_by_id,
+        bpf_prog_get_info_by_fd, bpf_prog_get_jited_func_lens, bpf_prog_get_jited_ksyms,
+        bpf_prog_get_xlated_insns, bpf_prog_query, bpf_prog_test_run, bpf_prog_test_run_data,
+        retry_with_verifier_logs, BpfLoadProgramAttrs, BPF_STATS_RUN_TIME,
     },
     util::VerifierLog,
+    VerifierLogLevel,
 };
 
 /// Error type returned when working with programs.
@@ -153,6 +177,14 @@ pub enum ProgramError {
         io_error: io::Error,
     },
 
+    /// Some of the programs attached to a network interface couldn't be
+    /// detached, see [`Bpf::detach_all`](crate::Bpf::detach_all).
+    #[error("failed to detach {} programs", .errors.len())]
+    DetachErrors {
+        /// The errors, in the order in which they happened.
+        errors: Vec<ProgramError>,
+    },
+
     /// The network interface does not exist.
     #[error("unknown network interface {name}")]
     UnknownInterface {
@@ -176,6 +208,10 @@ pub enum ProgramError {
     #[error(transparent)]
     UProbeError(#[from] UProbeError),
 
+    /// An error occurred while working with a [`Usdt`] program.
+    #[error(transparent)]
+    UsdtError(#[from] UsdtError),
+
     /// An error occurred while working with a [`TracePoint`].
     #[error(transparent)]
     TracePointError(#[from] TracePointError),
@@ -184,6 +220,10 @@ pub enum ProgramError {
     #[error(transparent)]
     SocketFilterError(#[from] SocketFilterError),
 
+    /// An error occurred while working with a [`SkReuseport`] program.
+    #[error(transparent)]
+    SkReuseportError(#[from] SkReuseportError),
+
     /// An error occurred while working with an [`Xdp`] program.
     #[error(transparent)]
     XdpError(#[from] XdpError),
@@ -192,6 +232,10 @@ pub enum ProgramError {
     #[error(transparent)]
     TcError(#[from] TcError),
 
+    /// An error occurred while working with a [`Tcx`] program.
+    #[error(transparent)]
+    TcxError(#[from] TcxError),
+
     /// An error occurred while working with an [`Extension`] program.
     #[error(transparent)]
     ExtensionError(#[from] ExtensionError),
@@ -269,6 +313,18 @@ pub enum Program {
     CgroupSock(CgroupSock),
     /// A [`CgroupDevice`] program
     CgroupDevice(CgroupDevice),
+    /// An [`Iter`] program
+    Iter(Iter),
+    /// A [`Syscall`] program
+    Syscall(Syscall),
+    /// A [`Tcx`] program
+    Tcx(Tcx),
+    /// A [`SkReuseport`] program
+    SkReuseport(SkReuseport),
+    /// A [`FlowDissector`] program
+    FlowDissector(FlowDissector),
+    /// A [`Usdt`] program
+    Usdt(Usdt),
 }
 
 impl Program {
@@ -300,6 +356,12 @@ impl Program {
             Program::SkLookup(_) => BPF_PROG_TYPE_SK_LOOKUP,
             Program::CgroupSock(_) => BPF_PROG_TYPE_CGROUP_SOCK,
             Program::CgroupDevice(_) => BPF_PROG_TYPE_CGROUP_DEVICE,
+            Program::Iter(_) => BPF_PROG_TYPE_TRACING,
+            Program::Syscall(_) => BPF_PROG_TYPE_SYSCALL,
+            Program::Tcx(_) => BPF_PROG_TYPE_SCHED_CLS,
+            Program::SkReuseport(_) => BPF_PROG_TYPE_SK_REUSEPORT,
+            Program::FlowDissector(_) => BPF_PROG_TYPE_FLOW_DISSECTOR,
+            Program::Usdt(_) => BPF_PROG_TYPE_KPROBE,
         }
     }
 
@@ -330,6 +392,12 @@ impl Program {
             Program::SkLookup(p) => p.pin(path),
             Program::CgroupSock(p) => p.pin(path),
             Program::CgroupDevice(p) => p.pin(path),
+            Program::Iter(p) => p.pin(path),
+            Program::Syscall(p) => p.pin(path),
+            Program::Tcx(p) => p.pin(path),
+            Program::SkReuseport(p) => p.pin(path),
+            Program::FlowDissector(p) => p.pin(path),
+            Program::Usdt(p) => p.pin(path),
         }
     }
 
@@ -360,6 +428,50 @@ impl Program {
             Program::SkLookup(p) => p.unload(),
             Program::CgroupSock(p) => p.unload(),
             Program::CgroupDevice(p) => p.unload(),
+            Program::Iter(p) => p.unload(),
+            Program::Syscall(p) => p.unload(),
+            Program::Tcx(p) => p.unload(),
+            Program::SkReuseport(p) => p.unload(),
+            Program::FlowDissector(p) => p.unload(),
+            Program::Usdt(p) => p.unload(),
+        }
+    }
+
+    /// Replaces the code of the program with `obj`, see
+    /// [`Bpf::reload_program`](crate::Bpf::reload_program).
+    pub(crate) fn reload(&mut self, obj: obj::Program) -> Result<(), ProgramError> {
+        let prog_type = self.prog_type();
+        match self {
+            Program::KProbe(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::UProbe(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::TracePoint(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::SocketFilter(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::Xdp(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::SkMsg(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::SkSkb(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::SockOps(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::SchedClassifier(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::CgroupSkb(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::CgroupSysctl(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::CgroupSockopt(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::LircMode2(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::PerfEvent(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::RawTracePoint(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::Lsm(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::BtfTracePoint(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::FEntry(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::FExit(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::Extension(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::CgroupSockAddr(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::SkLookup(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::CgroupSock(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::CgroupDevice(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::Iter(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::Syscall(p) => reload_program(prog_type, &mut p.data, obj),
+            Program::Tcx(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::SkReuseport(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::FlowDissector(p) => reload_attached_program(prog_type, &mut p.data, obj),
+            Program::Usdt(p) => reload_program(prog_type, &mut p.data, obj),
         }
     }
 
@@ -393,8 +505,165 @@ impl Program {
             Program::SkLookup(p) => p.fd(),
             Program::CgroupSock(p) => p.fd(),
             Program::CgroupDevice(p) => p.fd(),
+            Program::Iter(p) => p.fd(),
+            Program::Syscall(p) => p.fd(),
+            Program::Tcx(p) => p.fd(),
+            Program::SkReuseport(p) => p.fd(),
+            Program::FlowDissector(p) => p.fd(),
+            Program::Usdt(p) => p.fd(),
+        }
+    }
+
+    /// Returns the instructions of the program as rewritten by the kernel.
+    ///
+    /// The kernel rewrites programs at load time, for example replacing map
+    /// file descriptors with map addresses and inlining helper calls. The
+    /// returned instructions are the ones run by the kernel after
+    /// verification. The kernel only exposes them to privileged users, an
+    /// empty vector is returned otherwise.
+    pub fn xlated_instructions(&self) -> Result<Vec<bpf_insn>, ProgramError> {
+        let fd = self.fd().ok_or(ProgramError::NotLoaded)?;
+        bpf_prog_get_xlated_insns(fd.as_raw_fd()).map_err(|io_error| ProgramError::SyscallError {
+            call: "bpf_prog_get_info_by_fd".to_owned(),
+            io_error,
+        })
+    }
+
+    /// Returns the kernel addresses of the jited functions of the program.
+    ///
+    /// The first address is the one of the main function, the following ones
+    /// the ones of the BPF to BPF functions it calls, so that samples of
+    /// kernel stacks can be attributed to the program. An empty vector is
+    /// returned if the program is run by the interpreter, or if the kernel
+    /// doesn't expose the addresses to the current user.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 4.18.
+    pub fn jited_ksyms(&self) -> Result<Vec<u64>, ProgramError> {
+        let fd = self.fd().ok_or(ProgramError::NotLoaded)?;
+        bpf_prog_get_jited_ksyms(fd.as_raw_fd()).map_err(|io_error| ProgramError::SyscallError {
+            call: "bpf_prog_get_info_by_fd".to_owned(),
+            io_error,
+        })
+    }
+
+    /// Returns the sizes in bytes of the jited functions of the program.
+    ///
+    /// The sizes are in the same order as the addresses returned by
+    /// [`Program::jited_ksyms`]. An empty vector is returned if the program is
+    /// run by the interpreter.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 4.18.
+    pub fn jited_func_lens(&self) -> Result<Vec<u32>, ProgramError> {
+        let fd = self.fd().ok_or(ProgramError::NotLoaded)?;
+        bpf_prog_get_jited_func_lens(fd.as_raw_fd()).map_err(|io_error| {
+            ProgramError::SyscallError {
+                call: "bpf_prog_get_info_by_fd".to_owned(),
+                io_error,
+            }
+        })
+    }
+
+    /// Returns the run time stats of the program.
+    ///
+    /// The kernel only collects the stats while they're enabled, see
+    /// [`enable_stats`]. The stats of a program that never ran while they
+    /// were enabled are zero.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.1.
+    pub fn stats(&self) -> Result<ProgramStats, ProgramError> {
+        Ok(self.info()?.stats())
+    }
+
+    /// Returns the information the kernel has about the loaded program.
+    pub fn info(&self) -> Result<ProgramInfo, ProgramError> {
+        let fd = self.fd().ok_or(ProgramError::NotLoaded)?;
+        let info = bpf_prog_get_info_by_fd(fd.as_raw_fd()).map_err(|io_error| {
+            ProgramError::SyscallError {
+                call: "bpf_prog_get_info_by_fd".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(ProgramInfo(info))
+    }
+
+    /// Runs the program once with the given context.
+    ///
+    /// Returns the value returned by the program and the context as left by
+    /// the program. This is the only way to run [`Syscall`] programs, other
+    /// program types may or may not support it depending on the kernel.
+    pub fn test_run(&self, ctx: &[u8]) -> Result<(u32, Vec<u8>), ProgramError> {
+        let fd = self.fd().ok_or(ProgramError::NotLoaded)?;
+        let mut ctx_in = ctx.to_vec();
+        let map_err = |(_, io_error)| ProgramError::SyscallError {
+            call: "bpf_prog_test_run".to_owned(),
+            io_error,
+        };
+        match self {
+            // syscall programs write the context back to ctx_in
+            Program::Syscall(_) => {
+                let (retval, _) =
+                    bpf_prog_test_run(fd.as_raw_fd(), &mut ctx_in, None).map_err(map_err)?;
+                Ok((retval, ctx_in))
+            }
+            _ => {
+                let mut ctx_out = vec![0u8; ctx.len()];
+                let (retval, ctx_size_out) =
+                    bpf_prog_test_run(fd.as_raw_fd(), &mut ctx_in, Some(&mut ctx_out))
+                        .map_err(map_err)?;
+                ctx_out.truncate(ctx_size_out);
+                Ok((retval, ctx_out))
+            }
         }
     }
+
+    /// Runs the program `repeat` times with the given packet.
+    ///
+    /// This can be used to test packet programs like [`Xdp`] and
+    /// [`SchedClassifier`] without attaching them to an interface. The packet
+    /// as modified by the last run is returned along with the verdict and the
+    /// average duration of a run. A `repeat` of 0 is treated as 1 by the
+    /// kernel.
+    pub fn test_run_packet(&self, data: &[u8], repeat: u32) -> Result<TestRunResult, ProgramError> {
+        let fd = self.fd().ok_or(ProgramError::NotLoaded)?;
+        // programs can grow the packet, eg with bpf_xdp_adjust_head() or
+        // bpf_skb_change_tail(), so leave some room for that
+        let mut data_out = vec![0u8; data.len() + TEST_RUN_HEADROOM];
+        let (retval, data_size_out, duration) =
+            bpf_prog_test_run_data(fd.as_raw_fd(), data, &mut data_out, repeat).map_err(
+                |(_, io_error)| ProgramError::SyscallError {
+                    call: "bpf_prog_test_run".to_owned(),
+                    io_error,
+                },
+            )?;
+        data_out.truncate(data_size_out);
+
+        Ok(TestRunResult {
+            retval,
+            data: data_out,
+            duration: Duration::from_nanos(duration as u64),
+        })
+    }
+}
+
+// The extra space allocated for the output packet of test runs. A program can't
+// grow a packet by more than a page.
+const TEST_RUN_HEADROOM: usize = 4096;
+
+/// The result of running a program with [`Program::test_run_packet`].
+#[derive(Debug, Clone)]
+pub struct TestRunResult {
+    /// The value returned by the program, eg the XDP action or TC verdict.
+    pub retval: u32,
+    /// The packet as modified by the program.
+    pub data: Vec<u8>,
+    /// The average duration of a run.
+    pub duration: Duration,
 }
 
 impl Drop for Program {
@@ -415,6 +684,10 @@ pub(crate) struct ProgramData<T: Link> {
     pub(crate) attach_prog_fd: Option<RawFd>,
     pub(crate) btf_fd: Option<RawFd>,
     pub(crate) verifier_log_level: u32,
+    pub(crate) capture_load_stats: bool,
+    pub(crate) load_stats: Option<LoadStats>,
+    pub(crate) use_fd_array: bool,
+    pub(crate) flags: u32,
 }
 
 impl<T: Link> ProgramData<T> {
@@ -423,7 +696,13 @@ impl<T: Link> ProgramData<T> {
         obj: obj::Program,
         btf_fd: Option<RawFd>,
         verifier_log_level: u32,
+        use_fd_array: bool,
     ) -> ProgramData<T> {
+        let flags = if obj.section.sleepable() {
+            BPF_F_SLEEPABLE
+        } else {
+            0
+        };
         ProgramData {
             name,
             obj,
@@ -435,6 +714,10 @@ impl<T: Link> ProgramData<T> {
             attach_prog_fd: None,
             btf_fd,
             verifier_log_level,
+            capture_load_stats: false,
+            load_stats: None,
+            use_fd_array,
+            flags,
         }
     }
 }
@@ -447,6 +730,33 @@ impl<T: Link> ProgramData<T> {
     pub(crate) fn take_link(&mut self, link_id: T::Id) -> Result<T, ProgramError> {
         self.links.forget(link_id)
     }
+
+    // Returns the id the kernel assigned to the program when it was loaded.
+    pub(crate) fn prog_id(&self) -> Result<u32, ProgramError> {
+        let fd = self.fd_or_err()?;
+        let info = bpf_prog_get_info_by_fd(fd).map_err(|io_error| ProgramError::SyscallError {
+            call: "bpf_prog_get_info_by_fd".to_owned(),
+            io_error,
+        })?;
+        Ok(info.id)
+    }
+
+    // Makes the function `func_name` of the already loaded program `target` the
+    // attach target, for programs that replace or trace functions of other eBPF
+    // programs.
+    pub(crate) fn set_attach_target(
+        &mut self,
+        target: ProgramFd,
+        func_name: &str,
+    ) -> Result<(), ProgramError> {
+        let target_prog_fd = target.as_raw_fd();
+        let (btf_fd, btf_id) = extension::get_btf_info(target_prog_fd, func_name)?;
+
+        self.attach_btf_obj_fd = Some(btf_fd as u32);
+        self.attach_prog_fd = Some(target_prog_fd);
+        self.attach_btf_id = Some(btf_id);
+        Ok(())
+    }
 }
 
 fn unload_program<T: Link>(data: &mut ProgramData<T>) -> Result<(), ProgramError> {
@@ -458,6 +768,74 @@ fn unload_program<T: Link>(data: &mut ProgramData<T>) -> Result<(), ProgramError
     Ok(())
 }
 
+// Replaces the code of a program whose links can't be moved to another
+// program, see Bpf::reload_program.
+fn reload_program<T: Link>(
+    prog_type: bpf_prog_type,
+    data: &mut ProgramData<T>,
+    obj: obj::Program,
+) -> Result<(), ProgramError> {
+    // the links would keep running the old code
+    if !data.links.is_empty() {
+        return Err(ProgramError::AlreadyAttached);
+    }
+    replace_program(prog_type, data, obj, |_, _, _| Ok(()))
+}
+
+// Replaces the code of a program and atomically moves its links to the new
+// code.
+fn reload_attached_program<T: UpdatableLink>(
+    prog_type: bpf_prog_type,
+    data: &mut ProgramData<T>,
+    obj: obj::Program,
+) -> Result<(), ProgramError> {
+    replace_program(prog_type, data, obj, LinkMap::update_program)
+}
+
+fn replace_program<T: Link>(
+    prog_type: bpf_prog_type,
+    data: &mut ProgramData<T>,
+    mut obj: obj::Program,
+    update_links: impl FnOnce(&mut LinkMap<T>, RawFd, RawFd) -> Result<(), ProgramError>,
+) -> Result<(), ProgramError> {
+    if mem::discriminant(&data.obj.section) != mem::discriminant(&obj.section) {
+        return Err(ProgramError::UnexpectedProgramType);
+    }
+    // the func and line info of the new code reference the BTF of the new
+    // object, which isn't loaded
+    let function = &mut obj.function;
+    function.func_info = Default::default();
+    function.func_info_rec_size = 0;
+    function.line_info = Default::default();
+    function.line_info_rec_size = 0;
+
+    let old_obj = mem::replace(&mut data.obj, obj);
+    let old_fd = match data.fd.take() {
+        Some(fd) => fd,
+        // the new code is used the next time the program is loaded
+        None => return Ok(()),
+    };
+    let old_btf_fd = data.btf_fd.take();
+    let result = load_program(prog_type, data).and_then(|()| {
+        let new_fd = data.fd.unwrap();
+        let result = update_links(&mut data.links, new_fd, old_fd);
+        if result.is_err() {
+            unsafe { libc::close(new_fd) };
+        }
+        result
+    });
+    if let Err(e) = result {
+        data.obj = old_obj;
+        data.fd = Some(old_fd);
+        data.btf_fd = old_btf_fd;
+        return Err(e);
+    }
+    unsafe {
+        libc::close(old_fd);
+    }
+    Ok(())
+}
+
 fn pin_program<T: Link, P: AsRef<Path>>(
     data: &mut ProgramData<T>,
     path: P,
@@ -505,14 +883,21 @@ fn load_program<T: Link>(
     } = obj;
 
     let target_kernel_version = match *kernel_version {
-        KernelVersion::Any => {
-            let (major, minor, patch) = crate::sys::kernel_version().unwrap();
-            (major << 16) + (minor << 8) + patch
-        }
+        KernelVersion::Any => crate::util::current_kernel_version()
+            .map_err(|io_error| ProgramError::SyscallError {
+                call: "uname".to_owned(),
+                io_error,
+            })?
+            .code(),
         _ => (*kernel_version).into(),
     };
 
     let mut logger = VerifierLog::new();
+    if data.capture_load_stats {
+        // the verifier only writes its log, and so the stats line, when it's
+        // given a buffer, which normally only happens after a failed load
+        logger.grow();
+    }
 
     let prog_name = if let Some(name) = &data.name {
         let mut name = name.clone();
@@ -526,10 +911,22 @@ fn load_program<T: Link>(
         None
     };
 
+    // the instructions are rewritten on a copy so that the program can be
+    // loaded again after being unloaded
+    let mut fd_array = None;
+    let mut relocated;
+    let insns = if data.use_fd_array {
+        relocated = instructions.clone();
+        fd_array = Some(relocate_maps_to_fd_array(&mut relocated));
+        relocated.as_slice()
+    } else {
+        instructions.as_slice()
+    };
+
     let attr = BpfLoadProgramAttrs {
         name: prog_name,
         ty: prog_type,
-        insns: instructions,
+        insns,
         license,
         kernel_version: target_kernel_version,
         expected_attach_type: data.expected_attach_type,
@@ -541,16 +938,31 @@ fn load_program<T: Link>(
         func_info: func_info.clone(),
         line_info_rec_size: *line_info_rec_size,
         line_info: line_info.clone(),
+        fd_array: fd_array.as_deref(),
+        flags: data.flags,
     };
 
     let verifier_log_level = data.verifier_log_level;
+    let duration = Cell::new(Duration::ZERO);
     let ret = retry_with_verifier_logs(10, &mut logger, |logger| {
-        bpf_load_program(&attr, logger, verifier_log_level)
+        let start = Instant::now();
+        let ret = bpf_load_program(&attr, logger, verifier_log_level);
+        duration.set(start.elapsed());
+        ret
     });
 
     match ret {
         Ok(prog_fd) => {
             *fd = Some(prog_fd as RawFd);
+            data.load_stats = data.capture_load_stats.then(|| {
+                logger.truncate();
+                LoadStats {
+                    duration: duration.get(),
+                    verifier_stats: logger
+                        .as_c_str()
+                        .and_then(|log| VerifierStats::parse(&log.to_string_lossy())),
+                }
+            });
             Ok(())
         }
         Err((_, io_error)) => {
@@ -646,6 +1058,12 @@ impl_program_unload!(
     SockOps,
     CgroupSock,
     CgroupDevice,
+    Iter,
+    Syscall,
+    Tcx,
+    SkReuseport,
+    FlowDissector,
+    Usdt,
 );
 
 macro_rules! impl_fd {
@@ -686,6 +1104,75 @@ impl_fd!(
     SockOps,
     CgroupSock,
     CgroupDevice,
+    Iter,
+    Syscall,
+    Tcx,
+    SkReuseport,
+    FlowDissector,
+    Usdt,
+);
+
+macro_rules! impl_load_stats {
+    ($($struct_name:ident),+ $(,)?) => {
+        $(
+            impl $struct_name {
+                /// Sets the verifier log level used when loading this program
+                /// and enables the collection of [`LoadStats`].
+                ///
+                /// This overrides the level set with
+                /// [`BpfLoader::verifier_log_level`](crate::BpfLoader::verifier_log_level)
+                /// and must be called before the program is loaded. Include
+                /// [`VerifierLogLevel::STATS`] to have the verifier report
+                /// [`VerifierStats`].
+                pub fn set_verifier_log_level(&mut self, level: VerifierLogLevel) {
+                    self.data.verifier_log_level = level.bits();
+                    self.data.capture_load_stats = true;
+                }
+
+                /// Returns the stats collected when the program was loaded.
+                ///
+                /// Stats are only collected after
+                /// [`set_verifier_log_level`](Self::set_verifier_log_level) has
+                /// been called.
+                pub fn load_stats(&self) -> Option<&LoadStats> {
+                    self.data.load_stats.as_ref()
+                }
+            }
+        )+
+    }
+}
+
+impl_load_stats!(
+    KProbe,
+    UProbe,
+    TracePoint,
+    SocketFilter,
+    Xdp,
+    SkMsg,
+    SkSkb,
+    SchedClassifier,
+    CgroupSkb,
+    CgroupSysctl,
+    CgroupSockopt,
+    LircMode2,
+    PerfEvent,
+    Lsm,
+    RawTracePoint,
+    BtfTracePoint,
+    FEntry,
+    FExit,
+    Extension,
+    CgroupSockAddr,
+    SkLookup,
+    SockOps,
+    CgroupSock,
+    CgroupDevice,
+    Iter,
+    Syscall,
+    Tcx,
+    SkReuseport,
+    FlowDissector,
+    Usdt,
 );
 
 macro_rules! impl_program_pin{
@@ -731,6 +1218,12 @@ impl_program_pin!(
     SockOps,
     CgroupSock,
     CgroupDevice,
+    Iter,
+    Syscall,
+    Tcx,
+    SkReuseport,
+    FlowDissector,
+    Usdt,
 );
 
 macro_rules! impl_try_from_program {
@@ -786,6 +1279,12 @@ impl_try_from_program!(
     SkLookup,
     CgroupSock,
     CgroupDevice,
+    Iter,
+    Syscall,
+    Tcx,
+    SkReuseport,
+    FlowDissector,
+    Usdt,
 );
 
 /// Provides information about a loaded program, like name, id and statistics
@@ -829,6 +1328,77 @@ impl ProgramInfo {
         Ok(fd as RawFd)
     }
 
+    /// The number of times the program ran while stats were enabled, see
+    /// [`enable_stats`].
+    pub fn run_cnt(&self) -> u64 {
+        self.0.run_cnt
+    }
+
+    /// The total time in nanoseconds the program ran while stats were
+    /// enabled, see [`enable_stats`].
+    pub fn run_time_ns(&self) -> u64 {
+        self.0.run_time_ns
+    }
+
+    /// The id of the BTF object of the program, or 0 if the program was
+    /// loaded without BTF.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.0.
+    pub fn btf_id(&self) -> u32 {
+        self.0.btf_id
+    }
+
+    /// The id of the BTF type the program is attached to, for example the
+    /// function of an [`FEntry`] or an [`Lsm`] program, or 0 if the program
+    /// isn't attached through BTF.
+    ///
+    /// Use [`ProgramInfo::attach_btf_name`] to resolve it to a name.
+    pub fn attach_btf_id(&self) -> u32 {
+        self.0.attach_btf_id
+    }
+
+    /// The id of the BTF object [`ProgramInfo::attach_btf_id`] belongs to,
+    /// either the kernel BTF or the BTF of a kernel module.
+    pub fn attach_btf_obj_id(&self) -> u32 {
+        self.0.attach_btf_obj_id
+    }
+
+    /// Resolves [`ProgramInfo::attach_btf_id`] to the name of the type the
+    /// program is attached to, for example `vfs_read`.
+    ///
+    /// `btf` must be the BTF object [`ProgramInfo::attach_btf_id`] belongs
+    /// to, usually the kernel BTF returned by [`Btf::from_sys_fs`]. Returns
+    /// `None` if the program isn't attached through BTF.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// use aya::Btf;
+    ///
+    /// let info = bpf.program("fentry_vfs_read").unwrap().info()?;
+    /// if let Some(name) = info.attach_btf_name(&Btf::from_sys_fs()?)? {
+    ///     println!("attached to {name}");
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn attach_btf_name(&self, btf: &Btf) -> Result<Option<String>, ProgramError> {
+        match self.0.attach_btf_id {
+            0 => Ok(None),
+            type_id => Ok(Some(btf.type_name_by_id(type_id)?.into_owned())),
+        }
+    }
+
+    /// The run time stats of the program.
+    pub fn stats(&self) -> ProgramStats {
+        ProgramStats {
+            run_count: self.0.run_cnt,
+            run_time: Duration::from_nanos(self.0.run_time_ns),
+        }
+    }
+
     /// Loads a program from a pinned path in bpffs.
     pub fn from_pin<P: AsRef<Path>>(path: P) -> Result<ProgramInfo, ProgramError> {
         let path_string = CString::new(path.as_ref().to_str().unwrap()).unwrap();
@@ -849,3 +1419,881 @@ impl ProgramInfo {
         Ok(ProgramInfo(info))
     }
 }
+
+/// The run time stats of a program, see [`Program::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProgramStats {
+    /// The number of times the program ran.
+    pub run_count: u64,
+    /// The total time the program ran.
+    pub run_time: Duration,
+}
+
+impl ProgramStats {
+    /// The average time the program took to run, or `None` if it didn't run.
+    pub fn average_run_time(&self) -> Option<Duration> {
+        if self.run_count == 0 {
+            return None;
+        }
+        Some(Duration::from_nanos(
+            (self.run_time.as_nanos() / self.run_count as u128) as u64,
+        ))
+    }
+}
+
+/// Keeps the collection of run time stats enabled, see [`enable_stats`].
+///
+/// The stats stop being collected when the last [`StatsFd`] of all the
+/// processes is dropped, unless they're enabled with the
+/// `kernel.bpf_stats_enabled` sysctl.
+#[derive(Debug)]
+pub struct StatsFd(RawFd);
+
+impl AsRawFd for StatsFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for StatsFd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+/// Enables the collection of the run time stats of all the programs.
+///
+/// Collecting the stats slows down every program a bit, so the kernel only
+/// does it as long as the returned [`StatsFd`] is alive. The stats can then be
+/// read with [`Program::stats`] or [`ProgramInfo::stats`].
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.8.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let bpf = aya::Bpf::load(&[])?;
+/// use aya::programs::enable_stats;
+///
+/// let _stats = enable_stats()?;
+/// // ... let the programs run ...
+/// let stats = bpf.program("prog").unwrap().stats()?;
+/// println!("ran {} times in {:?}", stats.run_count, stats.run_time);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn enable_stats() -> Result<StatsFd, ProgramError> {
+    let fd =
+        bpf_enable_stats(BPF_STATS_RUN_TIME).map_err(|io_error| ProgramError::SyscallError {
+            call: "bpf_enable_stats".to_owned(),
+            io_error,
+        })?;
+    Ok(StatsFd(fd))
+}
+
+/// Stats collected while loading a program.
+///
+/// See [`KProbe::set_verifier_log_level`] (and the equivalent methods of the
+/// other program types) for how to enable them.
+#[derive(Debug, Clone)]
+pub struct LoadStats {
+    /// How long the `BPF_PROG_LOAD` syscall took.
+    pub duration: Duration,
+    /// The stats reported by the verifier, if any.
+    pub verifier_stats: Option<VerifierStats>,
+}
+
+/// The stats the verifier reports at the end of its log when
+/// [`VerifierLogLevel::STATS`] is set.
+///
+/// Fields that the running kernel doesn't report are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifierStats {
+    /// The number of instructions the verifier processed.
+    pub insns_processed: u32,
+    /// The maximum number of instructions the verifier is allowed to process.
+    pub insns_limit: Option<u32>,
+    /// The maximum number of states kept for a single instruction.
+    pub max_states_per_insn: Option<u32>,
+    /// The total number of states the verifier created.
+    pub total_states: Option<u32>,
+    /// The maximum number of states alive at the same time.
+    pub peak_states: Option<u32>,
+    /// The time spent in the verifier.
+    pub verification_time: Option<Duration>,
+}
+
+impl VerifierStats {
+    /// Parses the stats out of a verifier log.
+    ///
+    /// Returns `None` if the log doesn't contain a `processed N insns` line.
+    pub fn parse(log: &str) -> Option<VerifierStats> {
+        let mut stats = None;
+        let mut verification_time = None;
+        for line in log.lines() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                // verification time 123 usec
+                Some("verification") if words.next() == Some("time") => {
+                    verification_time = words
+                        .next()
+                        .and_then(|usec| usec.parse().ok())
+                        .map(Duration::from_micros);
+                }
+                // processed 12 insns (limit 1000000) max_states_per_insn 0 total_states 1 peak_states 1 mark_read 0
+                Some("processed") => {
+                    let insns_processed = match words.next().map(str::parse) {
+                        Some(Ok(insns)) => insns,
+                        _ => continue,
+                    };
+                    let mut s = VerifierStats {
+                        insns_processed,
+                        ..Default::default()
+                    };
+                    while let Some(word) = words.next() {
+                        let field = match word {
+                            "(limit" => &mut s.insns_limit,
+                            "max_states_per_insn" => &mut s.max_states_per_insn,
+                            "total_states" => &mut s.total_states,
+                            "peak_states" => &mut s.peak_states,
+                            _ => continue,
+                        };
+                        *field = words
+                            .next()
+                            .and_then(|value| value.trim_end_matches([')', ',']).parse().ok());
+                    }
+                    stats = Some(s);
+                }
+                _ => {}
+            }
+        }
+
+        stats.map(|stats| VerifierStats {
+            verification_time,
+            ..stats
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{fs::File, mem, os::unix::io::IntoRawFd, slice, str::FromStr};
+
+    use object::SectionIndex;
+
+    use super::*;
+    use crate::{
+        generated::{
+            bpf_cmd, bpf_insn,
+            bpf_prog_type::{BPF_PROG_TYPE_SOCKET_FILTER, BPF_PROG_TYPE_TRACING},
+            BPF_F_REPLACE, BPF_PSEUDO_MAP_FD, BPF_PSEUDO_MAP_IDX,
+        },
+        obj::ProgramSection,
+        sys::{override_syscall, Syscall},
+    };
+
+    pub(crate) fn fake_program_data<T: Link>(use_fd_array: bool) -> ProgramData<T> {
+        let mut lddw: bpf_insn = unsafe { mem::zeroed() };
+        lddw.code = 0x18;
+        lddw.set_dst_reg(1);
+        lddw.set_src_reg(BPF_PSEUDO_MAP_FD as u8);
+        lddw.imm = 42;
+        let mut exit: bpf_insn = unsafe { mem::zeroed() };
+        exit.code = 0x95;
+
+        ProgramData::new(
+            None,
+            obj::Program {
+                license: CString::new("GPL").unwrap(),
+                kernel_version: KernelVersion::Version(0),
+                section: ProgramSection::SocketFilter {
+                    name: "test".to_owned(),
+                },
+                function: Function {
+                    address: 0,
+                    name: "test".to_owned(),
+                    section_index: SectionIndex(0),
+                    section_offset: 0,
+                    instructions: vec![lddw, unsafe { mem::zeroed() }, exit],
+                    func_info: Default::default(),
+                    line_info: Default::default(),
+                    func_info_rec_size: 0,
+                    line_info_rec_size: 0,
+                },
+            },
+            None,
+            0,
+            use_fd_array,
+        )
+    }
+
+    #[test]
+    fn test_load_program_fd_array() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                let insns = unsafe {
+                    slice::from_raw_parts(u.insns as *const bpf_insn, u.insn_cnt as usize)
+                };
+                assert_eq!(insns[0].src_reg(), BPF_PSEUDO_MAP_IDX as u8);
+                assert_eq!(insns[0].imm, 0);
+                assert_ne!(u.fd_array, 0);
+                assert_eq!(unsafe { *(u.fd_array as *const RawFd) }, 42);
+                Ok(1)
+            }
+            _ => Ok(0),
+        });
+
+        let mut data = fake_program_data::<FdLink>(true);
+        load_program(BPF_PROG_TYPE_SOCKET_FILTER, &mut data).unwrap();
+        assert_eq!(data.fd, Some(1));
+
+        // the instructions of the program are left untouched
+        let ins = &data.obj.function.instructions[0];
+        assert_eq!(ins.src_reg(), BPF_PSEUDO_MAP_FD as u8);
+        assert_eq!(ins.imm, 42);
+    }
+
+    #[test]
+    fn test_load_sleepable_program() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                assert_eq!(unsafe { attr.__bindgen_anon_3.prog_flags }, BPF_F_SLEEPABLE);
+                Ok(1)
+            }
+            _ => Ok(0),
+        });
+
+        let mut obj = fake_program_data::<FdLink>(false).obj;
+        obj.section = ProgramSection::from_str("fentry.s/vfs_read").unwrap();
+        let mut data = ProgramData::<FdLink>::new(None, obj, None, 0, false);
+        assert_eq!(data.flags, BPF_F_SLEEPABLE);
+        load_program(BPF_PROG_TYPE_TRACING, &mut data).unwrap();
+        assert_eq!(data.fd, Some(1));
+    }
+
+    #[test]
+    fn test_load_program_name() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let name = unsafe { attr.__bindgen_anon_3.prog_name };
+                let name = name.map(|c| c as u8);
+                assert_eq!(&name, b"a_very_long_pro\0");
+                Ok(1)
+            }
+            _ => Ok(0),
+        });
+
+        let mut data = fake_program_data::<FdLink>(false);
+        data.name = Some("a_very_long_program_name".to_owned());
+        load_program(BPF_PROG_TYPE_SOCKET_FILTER, &mut data).unwrap();
+        assert_eq!(data.fd, Some(1));
+    }
+
+    #[test]
+    fn test_load_program_inline_fds() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                let insns = unsafe {
+                    slice::from_raw_parts(u.insns as *const bpf_insn, u.insn_cnt as usize)
+                };
+                assert_eq!(insns[0].src_reg(), BPF_PSEUDO_MAP_FD as u8);
+                assert_eq!(insns[0].imm, 42);
+                assert_eq!(u.fd_array, 0);
+                Ok(1)
+            }
+            _ => Ok(0),
+        });
+
+        let mut data = fake_program_data::<FdLink>(false);
+        load_program(BPF_PROG_TYPE_SOCKET_FILTER, &mut data).unwrap();
+        assert_eq!(data.fd, Some(1));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_reload_program() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                let insns = unsafe {
+                    slice::from_raw_parts(u.insns as *const bpf_insn, u.insn_cnt as usize)
+                };
+                // the exit instruction of the new code, without BTF
+                assert_eq!(insns.len(), 1);
+                assert_eq!(u.prog_btf_fd, 0);
+                Ok(File::open("/dev/null").unwrap().into_raw_fd() as _)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let old_fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let mut data = fake_program_data::<FdLink>(false);
+        data.fd = Some(old_fd);
+        data.btf_fd = Some(42);
+
+        let mut obj = data.obj.clone();
+        obj.function.instructions.drain(..2);
+        reload_program(BPF_PROG_TYPE_SOCKET_FILTER, &mut data, obj).unwrap();
+        assert!(matches!(data.fd, Some(fd) if fd >= 0 && fd != old_fd));
+        assert_eq!(data.obj.function.instructions.len(), 1);
+        assert_eq!(data.btf_fd, None);
+
+        // the code of a program of another type is rejected
+        let mut obj = data.obj.clone();
+        obj.section = ProgramSection::Xdp {
+            name: "test".to_owned(),
+        };
+        assert!(matches!(
+            reload_program(BPF_PROG_TYPE_SOCKET_FILTER, &mut data, obj),
+            Err(ProgramError::UnexpectedProgramType)
+        ));
+    }
+
+    #[test]
+    fn test_reload_program_failure() {
+        override_syscall(|_| Err((-1, io::Error::from_raw_os_error(libc::EINVAL))));
+
+        let mut data = fake_program_data::<FdLink>(false);
+        data.fd = Some(99999);
+        data.btf_fd = Some(42);
+
+        let mut obj = data.obj.clone();
+        obj.function.instructions.drain(..2);
+        assert!(matches!(
+            reload_program(BPF_PROG_TYPE_SOCKET_FILTER, &mut data, obj),
+            Err(ProgramError::LoadError { .. })
+        ));
+        // the old program is kept
+        assert_eq!(data.fd, Some(99999));
+        assert_eq!(data.btf_fd, Some(42));
+        assert_eq!(data.obj.function.instructions.len(), 3);
+
+        // the links of the program would keep running the old code
+        let link_fd = File::open("/dev/null").unwrap().into_raw_fd();
+        data.links.insert(FdLink::new(link_fd)).unwrap();
+        let obj = data.obj.clone();
+        assert!(matches!(
+            reload_program(BPF_PROG_TYPE_SOCKET_FILTER, &mut data, obj),
+            Err(ProgramError::AlreadyAttached)
+        ));
+    }
+
+    #[test]
+    fn test_reload_unloaded_program() {
+        override_syscall(|_| panic!("unexpected syscall"));
+
+        let mut data = fake_program_data::<FdLink>(false);
+        data.btf_fd = Some(42);
+
+        let mut obj = data.obj.clone();
+        obj.function.instructions.drain(..2);
+        reload_program(BPF_PROG_TYPE_SOCKET_FILTER, &mut data, obj).unwrap();
+        assert_eq!(data.fd, None);
+        assert_eq!(data.obj.function.instructions.len(), 1);
+        // the BTF is still loaded with the program
+        assert_eq!(data.btf_fd, Some(42));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_reload_attached_program() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                ..
+            } => Ok(99998),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_UPDATE,
+                attr,
+            } => {
+                let u = unsafe { &attr.link_update };
+                assert_eq!(u.link_fd, 99997);
+                assert_eq!(u.new_prog_fd, 99998);
+                assert_eq!(u.old_prog_fd, 99999);
+                assert_eq!(u.flags, BPF_F_REPLACE);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let mut data = fake_program_data::<FdLink>(false);
+        data.fd = Some(99999);
+        let link_id = data.links.insert(FdLink::new(99997)).unwrap();
+
+        let mut obj = data.obj.clone();
+        obj.function.instructions.drain(..2);
+        reload_attached_program(BPF_PROG_TYPE_SOCKET_FILTER, &mut data, obj).unwrap();
+        assert_eq!(data.fd, Some(99998));
+        // the link id is still valid and the link now runs the new code
+        let link = data.links.forget(link_id).unwrap();
+        assert_eq!(link.fd, 99997);
+        link.forget();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_reload_attached_program_failure() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                ..
+            } => Ok(File::open("/dev/null").unwrap().into_raw_fd() as _),
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let old_fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let link_fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let mut data = fake_program_data::<FdLink>(false);
+        data.fd = Some(old_fd);
+        data.links.insert(FdLink::new(link_fd)).unwrap();
+
+        let mut obj = data.obj.clone();
+        obj.function.instructions.drain(..2);
+        assert!(matches!(
+            reload_attached_program(BPF_PROG_TYPE_SOCKET_FILTER, &mut data, obj),
+            Err(ProgramError::SyscallError { call, .. }) if call == "bpf_link_update"
+        ));
+        // the link keeps running the old code
+        assert_eq!(data.fd, Some(old_fd));
+        assert_eq!(data.obj.function.instructions.len(), 3);
+    }
+
+    #[test]
+    fn test_xlated_instructions() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_prog_info) };
+                if info.xlated_prog_insns == 0 {
+                    info.xlated_prog_len = 2 * mem::size_of::<bpf_insn>() as u32;
+                } else {
+                    assert_eq!(info.xlated_prog_len, 2 * mem::size_of::<bpf_insn>() as u32);
+                    let insns = unsafe {
+                        slice::from_raw_parts_mut(info.xlated_prog_insns as *mut bpf_insn, 2)
+                    };
+                    // r0 = 0; exit
+                    insns[0].code = 0xb7;
+                    insns[1].code = 0x95;
+                }
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut data = fake_program_data(false);
+        data.fd = Some(42);
+        let program = Program::SocketFilter(SocketFilter { data });
+
+        let insns = program.xlated_instructions().unwrap();
+        assert_eq!(insns.len(), 2);
+        assert_eq!(insns[0].code, 0xb7);
+        assert_eq!(insns[1].code, 0x95);
+
+        // don't close the fake fd
+        mem::forget(program);
+    }
+
+    #[test]
+    fn test_jited_ksyms() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_prog_info) };
+                if info.jited_ksyms == 0 && info.jited_func_lens == 0 {
+                    info.nr_jited_ksyms = 2;
+                    info.nr_jited_func_lens = 2;
+                } else if info.jited_ksyms != 0 {
+                    assert_eq!(info.nr_jited_ksyms, 2);
+                    let ksyms =
+                        unsafe { slice::from_raw_parts_mut(info.jited_ksyms as *mut u64, 2) };
+                    ksyms.copy_from_slice(&[0xffffffffc0001000, 0xffffffffc0002000]);
+                } else {
+                    assert_eq!(info.nr_jited_func_lens, 2);
+                    let lens =
+                        unsafe { slice::from_raw_parts_mut(info.jited_func_lens as *mut u32, 2) };
+                    lens.copy_from_slice(&[128, 64]);
+                }
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut data = fake_program_data(false);
+        data.fd = Some(42);
+        let program = Program::SocketFilter(SocketFilter { data });
+
+        assert_eq!(
+            program.jited_ksyms().unwrap(),
+            [0xffffffffc0001000, 0xffffffffc0002000]
+        );
+        assert_eq!(program.jited_func_lens().unwrap(), [128, 64]);
+
+        // don't close the fake fd
+        mem::forget(program);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_stats() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_ENABLE_STATS,
+                attr,
+            } => {
+                assert_eq!(unsafe { attr.enable_stats.type_ }, BPF_STATS_RUN_TIME);
+                Ok(File::open("/dev/null").unwrap().into_raw_fd() as _)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_prog_info) };
+                info.run_cnt = 4;
+                info.run_time_ns = 10_000;
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let stats_fd = enable_stats().unwrap();
+        assert!(stats_fd.as_raw_fd() >= 0);
+
+        let mut data = fake_program_data(false);
+        data.fd = Some(42);
+        let program = Program::SocketFilter(SocketFilter { data });
+
+        let stats = program.stats().unwrap();
+        assert_eq!(stats.run_count, 4);
+        assert_eq!(stats.run_time, Duration::from_micros(10));
+        assert_eq!(stats.average_run_time(), Some(Duration::from_nanos(2500)));
+        assert_eq!(ProgramStats::default().average_run_time(), None);
+
+        // don't close the fake fd
+        mem::forget(program);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_info_attach_btf_name() {
+        use crate::obj::btf::{BtfType, Func, FuncLinkage};
+
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_prog_info) };
+                info.btf_id = 7;
+                info.attach_btf_obj_id = 1;
+                info.attach_btf_id = 2;
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("vfs_write".to_string());
+        btf.add_type(BtfType::Func(Func::new(
+            name_offset,
+            0,
+            FuncLinkage::Global,
+        )));
+        let name_offset = btf.add_string("vfs_read".to_string());
+        btf.add_type(BtfType::Func(Func::new(
+            name_offset,
+            0,
+            FuncLinkage::Global,
+        )));
+
+        let mut data = fake_program_data(false);
+        data.fd = Some(42);
+        let program = Program::SocketFilter(SocketFilter { data });
+
+        let info = program.info().unwrap();
+        assert_eq!(info.btf_id(), 7);
+        assert_eq!(info.attach_btf_obj_id(), 1);
+        assert_eq!(info.attach_btf_id(), 2);
+        assert_eq!(
+            info.attach_btf_name(&btf).unwrap().as_deref(),
+            Some("vfs_read")
+        );
+
+        // don't close the fake fd
+        mem::forget(program);
+    }
+
+    #[test]
+    fn test_info_not_attached_through_btf() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                ..
+            } => Ok(0),
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let mut data = fake_program_data(false);
+        data.fd = Some(42);
+        let program = Program::SocketFilter(SocketFilter { data });
+
+        let info = program.info().unwrap();
+        assert_eq!(info.attach_btf_id(), 0);
+        assert!(matches!(info.attach_btf_name(&Btf::new()), Ok(None)));
+
+        // don't close the fake fd
+        mem::forget(program);
+    }
+
+    #[test]
+    fn test_enable_stats_unsupported() {
+        override_syscall(|_| Err((-1, io::Error::from_raw_os_error(libc::EINVAL))));
+        assert!(matches!(
+            enable_stats(),
+            Err(ProgramError::SyscallError { call, .. }) if call == "bpf_enable_stats"
+        ));
+    }
+
+    #[test]
+    fn test_jited_ksyms_interpreter() {
+        // programs run by the interpreter have no jited functions
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                ..
+            } => Ok(0),
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let mut data = fake_program_data(false);
+        data.fd = Some(42);
+        let program = Program::SocketFilter(SocketFilter { data });
+
+        assert!(program.jited_ksyms().unwrap().is_empty());
+        assert!(program.jited_func_lens().unwrap().is_empty());
+
+        mem::forget(program);
+    }
+
+    #[test]
+    fn test_xlated_instructions_not_loaded() {
+        let program = Program::SocketFilter(SocketFilter {
+            data: fake_program_data(false),
+        });
+        assert!(matches!(
+            program.xlated_instructions(),
+            Err(ProgramError::NotLoaded)
+        ));
+    }
+
+    #[test]
+    fn test_syscall_test_run() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_TEST_RUN,
+                attr,
+            } => {
+                let u = unsafe { &attr.test };
+                assert_eq!(u.prog_fd, 42);
+                assert_eq!(u.ctx_out, 0);
+                assert_eq!(u.ctx_size_in, 4);
+                let ctx = unsafe {
+                    slice::from_raw_parts_mut(u.ctx_in as *mut u8, u.ctx_size_in as usize)
+                };
+                assert_eq!(ctx, &[1, 2, 3, 4]);
+                ctx.reverse();
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut data = fake_program_data(false);
+        data.fd = Some(42);
+        let program = Program::Syscall(super::Syscall { data });
+
+        let (retval, ctx) = program.test_run(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(retval, 0);
+        assert_eq!(ctx, vec![4, 3, 2, 1]);
+
+        // don't close the fake fd
+        mem::forget(program);
+    }
+
+    #[test]
+    fn test_test_run_ctx_out() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_TEST_RUN,
+                attr,
+            } => {
+                let u = unsafe { &attr.test };
+                assert_ne!(u.ctx_out, 0);
+                assert_eq!(u.ctx_size_out, 2);
+                let ctx_out = unsafe { slice::from_raw_parts_mut(u.ctx_out as *mut u8, 2) };
+                ctx_out.copy_from_slice(&[0xaa, 0xbb]);
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut data = fake_program_data(false);
+        data.fd = Some(42);
+        let program = Program::SocketFilter(SocketFilter { data });
+
+        let (_, ctx) = program.test_run(&[1, 2]).unwrap();
+        assert_eq!(ctx, vec![0xaa, 0xbb]);
+
+        // don't close the fake fd
+        mem::forget(program);
+    }
+
+    #[test]
+    fn test_test_run_not_loaded() {
+        let program = Program::Syscall(super::Syscall {
+            data: fake_program_data(false),
+        });
+        assert!(matches!(
+            program.test_run(&[]),
+            Err(ProgramError::NotLoaded)
+        ));
+    }
+
+    #[test]
+    fn test_test_run_packet() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_TEST_RUN,
+                attr,
+            } => {
+                let u = unsafe { &attr.test };
+                assert_eq!(u.prog_fd, 42);
+                assert_eq!(u.repeat, 10);
+                let data_in = unsafe {
+                    slice::from_raw_parts(u.data_in as *const u8, u.data_size_in as usize)
+                };
+                assert_eq!(data_in, &[1, 2, 3, 4]);
+                // the output buffer has room for the program to grow the packet
+                assert_eq!(u.data_size_out as usize, 4 + TEST_RUN_HEADROOM);
+                let data_out = unsafe {
+                    slice::from_raw_parts_mut(u.data_out as *mut u8, u.data_size_out as usize)
+                };
+                data_out[..6].copy_from_slice(&[0, 0, 1, 2, 3, 4]);
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut data = fake_program_data(false);
+        data.fd = Some(42);
+        let program = Program::Xdp(Xdp { data });
+
+        let result = program.test_run_packet(&[1, 2, 3, 4], 10).unwrap();
+        assert!(result.data.starts_with(&[0, 0, 1, 2, 3, 4]));
+
+        // don't close the fake fd
+        mem::forget(program);
+    }
+
+    #[test]
+    fn test_test_run_packet_not_loaded() {
+        let program = Program::Xdp(Xdp {
+            data: fake_program_data(false),
+        });
+        assert!(matches!(
+            program.test_run_packet(&[], 1),
+            Err(ProgramError::NotLoaded)
+        ));
+    }
+
+    #[test]
+    fn test_verifier_stats_parse() {
+        let log = "func#0 @0\n\
+                   0: R1=ctx(off=0,imm=0) R10=fp0\n\
+                   0: (b7) r0 = 0\n\
+                   1: (95) exit\n\
+                   verification time 123 usec\n\
+                   stack depth 0\n\
+                   processed 2 insns (limit 1000000) max_states_per_insn 0 total_states 1 peak_states 1 mark_read 0\n";
+        assert_eq!(
+            VerifierStats::parse(log),
+            Some(VerifierStats {
+                insns_processed: 2,
+                insns_limit: Some(1000000),
+                max_states_per_insn: Some(0),
+                total_states: Some(1),
+                peak_states: Some(1),
+                verification_time: Some(Duration::from_micros(123)),
+            })
+        );
+
+        // older kernels only report the processed insns
+        assert_eq!(
+            VerifierStats::parse("processed 42 insns (limit 131072), stack depth 8\n"),
+            Some(VerifierStats {
+                insns_processed: 42,
+                insns_limit: Some(131072),
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(VerifierStats::parse("0: (95) exit\n"), None);
+    }
+
+    #[test]
+    fn test_load_stats() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                assert_eq!(u.log_level, VerifierLogLevel::STATS.bits());
+                assert_ne!(u.log_buf, 0);
+                let log = b"processed 3 insns (limit 1000000) max_states_per_insn 0 total_states 2 peak_states 2 mark_read 0\n\0";
+                let buf =
+                    unsafe { slice::from_raw_parts_mut(u.log_buf as *mut u8, u.log_size as usize) };
+                buf[..log.len()].copy_from_slice(log);
+                Ok(99999)
+            }
+            _ => Ok(0),
+        });
+
+        let mut program = SocketFilter {
+            data: fake_program_data(false),
+        };
+        assert!(program.load_stats().is_none());
+        program.set_verifier_log_level(VerifierLogLevel::STATS);
+        program.load().unwrap();
+
+        let stats = program.load_stats().unwrap();
+        let verifier_stats = stats.verifier_stats.as_ref().unwrap();
+        assert_eq!(verifier_stats.insns_processed, 3);
+        assert_eq!(verifier_stats.peak_states, Some(2));
+
+        // don't close the fake fd
+        mem::forget(program);
+    }
+}
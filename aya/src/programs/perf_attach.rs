@@ -64,6 +64,18 @@ fn perf_attach_either<T: Link + From<PerfLink>>(
     event_alias: Option<String>,
 ) -> Result<T::Id, ProgramError> {
     let prog_fd = data.fd_or_err()?;
+    let link = perf_link(prog_fd, fd, probe_kind, event_alias)?;
+    data.links.insert(link.into())
+}
+
+// Attaches the program to the perf event `fd` and returns the link, which owns
+// `fd` from then on.
+pub(crate) fn perf_link(
+    prog_fd: RawFd,
+    fd: RawFd,
+    probe_kind: Option<ProbeKind>,
+    event_alias: Option<String>,
+) -> Result<PerfLink, ProgramError> {
     perf_event_ioctl(fd, PERF_EVENT_IOC_SET_BPF, prog_fd).map_err(|(_, io_error)| {
         ProgramError::SyscallError {
             call: "PERF_EVENT_IOC_SET_BPF".to_owned(),
@@ -77,12 +89,9 @@ fn perf_attach_either<T: Link + From<PerfLink>>(
         }
     })?;
 
-    data.links.insert(
-        PerfLink {
-            perf_fd: fd,
-            probe_kind,
-            event_alias,
-        }
-        .into(),
-    )
+    Ok(PerfLink {
+        perf_fd: fd,
+        probe_kind,
+        event_alias,
+    })
 }
@@ -16,7 +16,7 @@ use crate::{
         perf_attach::{PerfLink, PerfLinkId},
         ProgramData, ProgramError,
     },
-    sys::perf_event_open,
+    sys::{perf_event_attr_new, perf_event_open_attr},
 };
 
 /// The type of perf event
@@ -76,6 +76,48 @@ pub enum PerfEventScope {
     },
 }
 
+/// The perf event a [PerfEvent] program is attached to.
+///
+/// Besides the event itself, the config controls the sample policy, which
+/// processes and cpus are monitored and which privilege levels are counted.
+#[derive(Debug, Clone)]
+pub struct PerfEventConfig {
+    /// The type of perf event
+    pub perf_type: PerfTypeId,
+    /// The event id, its meaning depends on `perf_type`
+    pub config: u64,
+    /// The scope of the perf event
+    pub scope: PerfEventScope,
+    /// The sample policy
+    pub sample_policy: SamplePolicy,
+    /// Don't count events happening in user space
+    pub exclude_user: bool,
+    /// Don't count events happening in the kernel
+    pub exclude_kernel: bool,
+    /// Don't count events happening in the hypervisor
+    pub exclude_hv: bool,
+}
+
+impl PerfEventConfig {
+    /// Creates a new config, counting events at all privilege levels.
+    pub fn new(
+        perf_type: PerfTypeId,
+        config: u64,
+        scope: PerfEventScope,
+        sample_policy: SamplePolicy,
+    ) -> PerfEventConfig {
+        PerfEventConfig {
+            perf_type,
+            config,
+            scope,
+            sample_policy,
+            exclude_user: false,
+            exclude_kernel: false,
+            exclude_hv: false,
+        }
+    }
+}
+
 /// A program that can be attached at a perf event.
 ///
 /// # Minimum kernel version
@@ -141,30 +183,51 @@ impl PerfEvent {
         scope: PerfEventScope,
         sample_policy: SamplePolicy,
     ) -> Result<PerfLinkId, ProgramError> {
-        let (sample_period, sample_frequency) = match sample_policy {
+        self.attach_with_config(PerfEventConfig::new(
+            perf_type,
+            config,
+            scope,
+            sample_policy,
+        ))
+    }
+
+    /// Attaches to the perf event described by `config`.
+    ///
+    /// The perf event is opened with `perf_event_open()` and the program is
+    /// attached to it with `PERF_EVENT_IOC_SET_BPF`.
+    ///
+    /// The returned value can be used to detach, see [PerfEvent::detach].
+    pub fn attach_with_config(
+        &mut self,
+        config: PerfEventConfig,
+    ) -> Result<PerfLinkId, ProgramError> {
+        let (sample_period, sample_frequency) = match config.sample_policy {
             SamplePolicy::Period(period) => (period, None),
             SamplePolicy::Frequency(frequency) => (0, Some(frequency)),
         };
-        let (pid, cpu) = match scope {
+        let (pid, cpu) = match config.scope {
             PerfEventScope::CallingProcessAnyCpu => (0, -1),
             PerfEventScope::CallingProcessOneCpu { cpu } => (0, cpu as i32),
             PerfEventScope::OneProcessAnyCpu { pid } => (pid as i32, -1),
             PerfEventScope::OneProcessOneCpu { cpu, pid } => (pid as i32, cpu as i32),
             PerfEventScope::AllProcessesOneCpu { cpu } => (-1, cpu as i32),
         };
-        let fd = perf_event_open(
-            perf_type as u32,
-            config,
-            pid,
-            cpu,
+        let mut attr = perf_event_attr_new(
+            config.perf_type as u32,
+            config.config,
             sample_period,
             sample_frequency,
             false,
-            0,
-        )
-        .map_err(|(_code, io_error)| ProgramError::SyscallError {
-            call: "perf_event_open".to_owned(),
-            io_error,
+        );
+        attr.set_exclude_user(config.exclude_user as u64);
+        attr.set_exclude_kernel(config.exclude_kernel as u64);
+        attr.set_exclude_hv(config.exclude_hv as u64);
+
+        let fd = perf_event_open_attr(attr, pid, cpu, 0).map_err(|(_code, io_error)| {
+            ProgramError::SyscallError {
+                call: "perf_event_open".to_owned(),
+                io_error,
+            }
         })? as i32;
 
         perf_attach(&mut self.data, fd)
@@ -185,3 +248,62 @@ impl PerfEvent {
         self.data.take_link(link_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generated::perf_hw_id::PERF_COUNT_HW_CPU_CYCLES,
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+        PERF_EVENT_IOC_SET_BPF,
+    };
+
+    // the link closes the perf fd when dropped, so use one that can't be open
+    const PERF_FD: i32 = 99999;
+
+    #[test]
+    fn test_attach_with_config() {
+        override_syscall(|call| match call {
+            Syscall::PerfEventOpen {
+                attr,
+                pid,
+                cpu,
+                group,
+                flags,
+            } => {
+                assert_eq!(attr.type_, PERF_TYPE_HARDWARE as u32);
+                assert_eq!(attr.config, PERF_COUNT_HW_CPU_CYCLES as u64);
+                assert_eq!(attr.freq(), 1);
+                assert_eq!(unsafe { attr.__bindgen_anon_1.sample_freq }, 99);
+                assert_eq!(attr.exclude_user(), 0);
+                assert_eq!(attr.exclude_kernel(), 1);
+                assert_eq!(attr.exclude_hv(), 1);
+                assert_eq!((pid, cpu, group, flags), (-1, 3, -1, 0));
+                Ok(PERF_FD.into())
+            }
+            Syscall::PerfEventIoctl { fd, request, arg } => {
+                assert_eq!(fd, PERF_FD);
+                if request == PERF_EVENT_IOC_SET_BPF {
+                    assert_eq!(arg, 1);
+                }
+                Ok(0)
+            }
+            _ => panic!("unexpected syscall"),
+        });
+
+        let mut prog = PerfEvent {
+            data: fake_program_data(false),
+        };
+        prog.data.fd = Some(1);
+        let mut config = PerfEventConfig::new(
+            PerfTypeId::Hardware,
+            PERF_COUNT_HW_CPU_CYCLES as u64,
+            PerfEventScope::AllProcessesOneCpu { cpu: 3 },
+            SamplePolicy::Frequency(99),
+        );
+        config.exclude_kernel = true;
+        config.exclude_hv = true;
+        prog.attach_with_config(config).unwrap();
+    }
+}
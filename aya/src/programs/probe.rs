@@ -1,15 +1,21 @@
-use libc::pid_t;
+use libc::{pid_t, ENOENT, EOPNOTSUPP};
+use log::debug;
 use std::{
     fs::{self, OpenOptions},
     io::{self, Write},
+    os::unix::io::RawFd,
     process,
 };
 
 use crate::{
     programs::{
-        kprobe::KProbeError, perf_attach, perf_attach::PerfLink, perf_attach_debugfs,
-        trace_point::read_sys_fs_trace_point_id, uprobe::UProbeError, Link, ProgramData,
-        ProgramError,
+        kprobe::KProbeError,
+        perf_attach,
+        perf_attach::{perf_link, PerfLink},
+        perf_attach_debugfs,
+        trace_point::{read_sys_fs_trace_point_id, tracefs_path},
+        uprobe::UProbeError,
+        Link, ProgramData, ProgramError,
     },
     sys::{kernel_version, perf_event_open_probe, perf_event_open_trace_point},
 };
@@ -43,18 +49,73 @@ pub(crate) fn attach<T: Link + From<PerfLink>>(
     offset: u64,
     pid: Option<pid_t>,
 ) -> Result<T::Id, ProgramError> {
+    match create_probe(kind, fn_name, offset, pid)? {
+        (fd, Some(event_alias)) => perf_attach_debugfs(program_data, fd, kind, event_alias),
+        (fd, None) => perf_attach(program_data, fd),
+    }
+}
+
+// Like attach(), but returns the link instead of adding it to the program's
+// links. Used by programs that group several probes into a single link.
+pub(crate) fn probe_link(
+    prog_fd: RawFd,
+    kind: ProbeKind,
+    fn_name: &str,
+    offset: u64,
+    pid: Option<pid_t>,
+) -> Result<PerfLink, ProgramError> {
+    match create_probe(kind, fn_name, offset, pid)? {
+        (fd, Some(event_alias)) => perf_link(prog_fd, fd, Some(kind), Some(event_alias)),
+        (fd, None) => perf_link(prog_fd, fd, None, None),
+    }
+}
+
+// Opens the perf event of the probe. The probe is created with the kprobe or
+// uprobe PMU, or as a trace point through tracefs when the PMU isn't
+// available. In the latter case the alias of the tracefs event is returned
+// too, and the event must be deleted on detach.
+fn create_probe(
+    kind: ProbeKind,
+    fn_name: &str,
+    offset: u64,
+    pid: Option<pid_t>,
+) -> Result<(i32, Option<String>), ProgramError> {
     // https://github.com/torvalds/linux/commit/e12f03d7031a977356e3d7b75a68c2185ff8d155
     // Use debugfs to create probe
     let k_ver = kernel_version().unwrap();
     if k_ver < (4, 17, 0) {
         let (fd, event_alias) = create_as_trace_point(kind, fn_name, offset, pid)?;
+        return Ok((fd, Some(event_alias)));
+    }
 
-        return perf_attach_debugfs(program_data, fd, kind, event_alias);
-    };
-
-    let fd = create_as_probe(kind, fn_name, offset, pid)?;
+    match create_as_probe(kind, fn_name, offset, pid) {
+        Ok(fd) => Ok((fd, None)),
+        Err(e) if is_pmu_unsupported(&e) => {
+            debug!(
+                "{} PMU not supported ({}), creating the probe through tracefs",
+                kind.pmu(),
+                e
+            );
+            let (fd, event_alias) = create_as_trace_point(kind, fn_name, offset, pid)?;
+            Ok((fd, Some(event_alias)))
+        }
+        Err(e) => Err(e),
+    }
+}
 
-    perf_attach(program_data, fd)
+// Whether create_as_probe() failed because the kernel has no kprobe or uprobe
+// PMU, or doesn't let it be used.
+fn is_pmu_unsupported(error: &ProgramError) -> bool {
+    match error {
+        ProgramError::SyscallError { call, io_error } if call == "perf_event_open" => {
+            io_error.raw_os_error() == Some(EOPNOTSUPP)
+        }
+        ProgramError::KProbeError(KProbeError::FileError { io_error, .. })
+        | ProgramError::UProbeError(UProbeError::FileError { io_error, .. }) => {
+            io_error.raw_os_error() == Some(ENOENT)
+        }
+        _ => false,
+    }
 }
 
 pub(crate) fn detach_debug_fs(kind: ProbeKind, event_alias: &str) -> Result<(), ProgramError> {
@@ -141,7 +202,7 @@ fn create_probe_event(
 ) -> Result<String, (String, io::Error)> {
     use ProbeKind::*;
 
-    let events_file_name = format!("/sys/kernel/debug/tracing/{}_events", kind.pmu());
+    let events_file_name = format!("{}/{}_events", tracefs_path().display(), kind.pmu());
     let probe_type_prefix = match kind {
         KProbe | UProbe => 'p',
         KRetProbe | URetProbe => 'r',
@@ -154,6 +215,8 @@ fn create_probe_event(
         offset
     );
     let offset_suffix = match kind {
+        // without a function name, `offset` is the absolute address of the probe
+        KProbe | KRetProbe if fn_name.is_empty() => format!("{offset:#x}"),
         KProbe => format!("+{offset}"),
         UProbe => format!(":{offset:#x}"),
         _ => "".to_string(),
@@ -180,7 +243,7 @@ fn create_probe_event(
 }
 
 fn delete_probe_event(kind: ProbeKind, event_alias: &str) -> Result<(), (String, io::Error)> {
-    let events_file_name = format!("/sys/kernel/debug/tracing/{}_events", kind.pmu());
+    let events_file_name = format!("{}/{}_events", tracefs_path().display(), kind.pmu());
 
     let events =
         fs::read_to_string(&events_file_name).map_err(|e| (events_file_name.clone(), e))?;
@@ -232,3 +295,53 @@ fn read_sys_fs_perf_ret_probe(pmu: &str) -> Result<u32, (String, io::Error)> {
         .parse::<u32>()
         .map_err(|e| (file, io::Error::new(io::ErrorKind::Other, e)))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs::File, os::unix::io::IntoRawFd};
+
+    use crate::{
+        generated::perf_type_id::PERF_TYPE_TRACEPOINT,
+        programs::trace_point::override_tracefs,
+        sys::{override_syscall, Syscall},
+    };
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_kprobe_tracefs_fallback() {
+        let tracefs = env::temp_dir().join(format!("aya-tracefs-{}", process::id()));
+        let event_alias = format!("aya_{}_p_try_to_wake_up_0x0", process::id());
+        let event_dir = tracefs.join("events/kprobes").join(&event_alias);
+        fs::create_dir_all(&event_dir).unwrap();
+        fs::write(event_dir.join("id"), "1234\n").unwrap();
+        fs::write(tracefs.join("kprobe_events"), "").unwrap();
+        override_tracefs(&tracefs);
+
+        override_syscall(|call| match call {
+            Syscall::PerfEventOpen { attr, .. } if attr.type_ == PERF_TYPE_TRACEPOINT as u32 => {
+                assert_eq!(attr.config, 1234);
+                Ok(File::open("/dev/null").unwrap().into_raw_fd() as _)
+            }
+            // the kprobe PMU isn't supported
+            Syscall::PerfEventOpen { .. } => Err((-1, io::Error::from_raw_os_error(EOPNOTSUPP))),
+            Syscall::PerfEventIoctl { .. } => Ok(0),
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let link = probe_link(42, ProbeKind::KProbe, "try_to_wake_up", 0, None).unwrap();
+        let events = fs::read_to_string(tracefs.join("kprobe_events")).unwrap();
+        assert_eq!(
+            events,
+            format!("p:kprobes/{event_alias} try_to_wake_up+0\n")
+        );
+
+        // detaching deletes the tracefs event
+        link.detach().unwrap();
+        let events = fs::read_to_string(tracefs.join("kprobe_events")).unwrap();
+        assert!(events.ends_with(&format!("-:{event_alias}\n")));
+
+        fs::remove_dir_all(&tracefs).unwrap();
+    }
+}
@@ -48,7 +48,9 @@ impl RawTracePoint {
     ///
     /// The returned value can be used to detach, see [RawTracePoint::detach].
     pub fn attach(&mut self, tp_name: &str) -> Result<RawTracePointLinkId, ProgramError> {
-        let tp_name_c = CString::new(tp_name).unwrap();
+        let tp_name_c = CString::new(tp_name).map_err(|_| ProgramError::InvalidName {
+            name: tp_name.to_owned(),
+        })?;
         attach_raw_tracepoint(&mut self.data, Some(&tp_name_c))
     }
 
@@ -79,3 +81,46 @@ define_link_wrapper!(
     FdLink,
     FdLinkId
 );
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    // the link closes the fd when dropped, so use one that can't be open
+    const LINK_FD: i32 = 99999;
+
+    #[test]
+    fn test_attach() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_RAW_TRACEPOINT_OPEN,
+                attr,
+            } => {
+                let u = unsafe { &attr.raw_tracepoint };
+                let name = unsafe { CStr::from_ptr(u.name as *const _) };
+                assert_eq!(name.to_str().unwrap(), "sched_switch");
+                assert_eq!(u.prog_fd, 1);
+                Ok(LINK_FD.into())
+            }
+            _ => panic!("unexpected syscall"),
+        });
+
+        let mut prog = RawTracePoint {
+            data: fake_program_data(false),
+        };
+        prog.data.fd = Some(1);
+        prog.attach("sched_switch").unwrap();
+
+        assert!(matches!(
+            prog.attach("sched\0switch"),
+            Err(ProgramError::InvalidName { .. })
+        ));
+    }
+}
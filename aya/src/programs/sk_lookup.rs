@@ -2,7 +2,9 @@ use std::os::unix::prelude::{AsRawFd, RawFd};
 
 use crate::{
     generated::{bpf_attach_type::BPF_SK_LOOKUP, bpf_prog_type::BPF_PROG_TYPE_SK_LOOKUP},
-    programs::{define_link_wrapper, load_program, FdLinkId, ProgramData, ProgramError},
+    programs::{
+        define_link_wrapper, impl_updatable_link, load_program, FdLinkId, ProgramData, ProgramError,
+    },
     sys::bpf_link_create,
 };
 
@@ -99,3 +101,78 @@ define_link_wrapper!(
     FdLink,
     FdLinkId
 );
+
+impl_updatable_link!(SkLookupLink);
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    #[test]
+    fn test_load_sets_expected_attach_type() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                assert_eq!(u.prog_type, BPF_PROG_TYPE_SK_LOOKUP as u32);
+                assert_eq!(u.expected_attach_type, BPF_SK_LOOKUP as u32);
+                Ok(99999)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = SkLookup {
+            data: fake_program_data(false),
+        };
+        prog.load().unwrap();
+        // don't close the fake fd
+        prog.data.fd = None;
+    }
+
+    #[test]
+    fn test_attach_not_loaded() {
+        let mut prog = SkLookup {
+            data: fake_program_data(false),
+        };
+        assert!(matches!(
+            prog.attach(io::stdin()),
+            Err(ProgramError::NotLoaded)
+        ));
+    }
+
+    #[test]
+    fn test_attach() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let u = unsafe { &attr.link_create };
+                assert_eq!(u.prog_fd, 1);
+                assert_eq!(unsafe { u.__bindgen_anon_1.target_fd }, 0);
+                assert_eq!(u.attach_type, BPF_SK_LOOKUP as u32);
+                Ok(99999)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = SkLookup {
+            data: fake_program_data(false),
+        };
+        prog.data.fd = Some(1);
+        // the netns fd is never looked at, the syscall is mocked
+        let link_id = prog.attach(io::stdin()).unwrap();
+        assert!(prog.take_link(link_id).is_ok());
+        // don't close the fake program fd
+        prog.data.fd = None;
+    }
+}
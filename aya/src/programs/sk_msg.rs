@@ -6,8 +6,8 @@ use crate::{
     generated::{bpf_attach_type::BPF_SK_MSG_VERDICT, bpf_prog_type::BPF_PROG_TYPE_SK_MSG},
     maps::sock::SockMapFd,
     programs::{
-        define_link_wrapper, load_program, ProgAttachLink, ProgAttachLinkId, ProgramData,
-        ProgramError,
+        define_link_wrapper, impl_updatable_link, load_program, ProgAttachLink, ProgAttachLinkId,
+        ProgramData, ProgramError,
     },
     sys::bpf_prog_attach,
 };
@@ -119,3 +119,54 @@ define_link_wrapper!(
     ProgAttachLink,
     ProgAttachLinkId
 );
+
+impl_updatable_link!(SkMsgLink);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        obj::ProgramSection,
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    fn fake_program() -> SkMsg {
+        let mut data = fake_program_data(false);
+        data.obj.section = ProgramSection::SkMsg {
+            name: "test".to_owned(),
+        };
+        SkMsg { data }
+    }
+
+    #[test]
+    fn test_attach_not_loaded() {
+        let mut prog = fake_program();
+        assert!(matches!(
+            prog.attach(SockMapFd(42)),
+            Err(ProgramError::NotLoaded)
+        ));
+    }
+
+    #[test]
+    fn test_attach() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_ATTACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.attach_bpf_fd, 1);
+                assert_eq!(u.target_fd, 42);
+                assert_eq!(u.attach_type, BPF_SK_MSG_VERDICT as u32);
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program();
+        prog.data.fd = Some(1);
+        assert!(prog.attach(SockMapFd(42)).is_ok());
+    }
+}
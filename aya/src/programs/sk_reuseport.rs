@@ -0,0 +1,189 @@
+//! Programs selecting a socket in a `SO_REUSEPORT` group.
+use libc::{setsockopt, SOL_SOCKET};
+use std::{
+    io, mem,
+    os::unix::prelude::{AsRawFd, RawFd},
+};
+use thiserror::Error;
+
+use crate::{
+    generated::{
+        bpf_attach_type::{BPF_SK_REUSEPORT_SELECT, BPF_SK_REUSEPORT_SELECT_OR_MIGRATE},
+        bpf_prog_type::BPF_PROG_TYPE_SK_REUSEPORT,
+        SO_ATTACH_REUSEPORT_EBPF, SO_DETACH_REUSEPORT_BPF,
+    },
+    programs::{links::UpdatableLink, load_program, Link, ProgramData, ProgramError},
+};
+
+/// The type returned when attaching a [`SkReuseport`] fails.
+#[derive(Debug, Error)]
+pub enum SkReuseportError {
+    /// Setting the `SO_ATTACH_REUSEPORT_EBPF` socket option failed.
+    #[error("setsockopt SO_ATTACH_REUSEPORT_EBPF failed")]
+    SoAttachReuseportEbpfError {
+        /// original [`io::Error`]
+        #[source]
+        io_error: io::Error,
+    },
+}
+
+/// A program used to select a socket within a `SO_REUSEPORT` group.
+///
+/// [`SkReuseport`] programs are attached to a socket that is part of a
+/// `SO_REUSEPORT` group, and are run to pick which socket of the group
+/// receives an incoming packet or connection. The candidate sockets are
+/// usually stored in a [`ReusePortSockArray`](crate::maps::ReusePortSockArray)
+/// and selected with `bpf_sk_select_reuseport()`.
+///
+/// Programs in the `sk_reuseport/migrate` section are also run when a
+/// listener is closed, to migrate its pending requests to another socket.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.19, and 5.14
+/// for `sk_reuseport/migrate` programs.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(Debug, thiserror::Error)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     IO(#[from] std::io::Error),
+/// #     #[error(transparent)]
+/// #     Map(#[from] aya::maps::MapError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError)
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use std::net::TcpListener;
+/// use std::os::unix::io::AsRawFd;
+/// use aya::maps::ReusePortSockArray;
+/// use aya::programs::SkReuseport;
+///
+/// // the listener must have SO_REUSEPORT set before binding
+/// # let listener = TcpListener::bind("127.0.0.1:1234")?;
+/// let mut sockets = ReusePortSockArray::try_from(bpf.map_mut("SOCKETS").unwrap())?;
+/// sockets.set(0, &listener, 0)?;
+///
+/// let prog: &mut SkReuseport = bpf.program_mut("select_socket").unwrap().try_into()?;
+/// prog.load()?;
+/// prog.attach(listener.as_raw_fd())?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_SK_REUSEPORT")]
+pub struct SkReuseport {
+    pub(crate) data: ProgramData<SkReuseportLink>,
+    pub(crate) migrate: bool,
+}
+
+impl SkReuseport {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(if self.migrate {
+            BPF_SK_REUSEPORT_SELECT_OR_MIGRATE
+        } else {
+            BPF_SK_REUSEPORT_SELECT
+        });
+        load_program(BPF_PROG_TYPE_SK_REUSEPORT, &mut self.data)
+    }
+
+    /// Attaches the program to the `SO_REUSEPORT` group of the given socket.
+    ///
+    /// The returned value can be used to detach from the socket, see [SkReuseport::detach].
+    pub fn attach<T: AsRawFd>(&mut self, socket: T) -> Result<SkReuseportLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let socket = socket.as_raw_fd();
+
+        attach_socket(socket, prog_fd)?;
+        self.data.links.insert(SkReuseportLink { socket, prog_fd })
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [SkReuseport::attach].
+    pub fn detach(&mut self, link_id: SkReuseportLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(
+        &mut self,
+        link_id: SkReuseportLinkId,
+    ) -> Result<SkReuseportLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+/// The type returned by [SkReuseport::attach]. Can be passed to [SkReuseport::detach].
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub struct SkReuseportLinkId(RawFd, RawFd);
+
+/// A SkReuseport Link
+#[derive(Debug)]
+pub struct SkReuseportLink {
+    socket: RawFd,
+    prog_fd: RawFd,
+}
+
+impl Link for SkReuseportLink {
+    type Id = SkReuseportLinkId;
+
+    fn id(&self) -> Self::Id {
+        SkReuseportLinkId(self.socket, self.prog_fd)
+    }
+
+    fn detach(self) -> Result<(), ProgramError> {
+        // the option value is ignored, the program is detached from the whole
+        // reuseport group
+        let unused: i32 = 0;
+        unsafe {
+            setsockopt(
+                self.socket,
+                SOL_SOCKET,
+                SO_DETACH_REUSEPORT_BPF as i32,
+                &unused as *const _ as *const _,
+                mem::size_of::<i32>() as u32,
+            );
+        }
+        Ok(())
+    }
+}
+
+impl UpdatableLink for SkReuseportLink {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        _old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        // attaching a program replaces the one already attached
+        attach_socket(self.socket, new_prog_fd)?;
+        self.prog_fd = new_prog_fd;
+        Ok(())
+    }
+}
+
+fn attach_socket(socket: RawFd, prog_fd: RawFd) -> Result<(), ProgramError> {
+    let ret = unsafe {
+        setsockopt(
+            socket,
+            SOL_SOCKET,
+            SO_ATTACH_REUSEPORT_EBPF as i32,
+            &prog_fd as *const _ as *const _,
+            mem::size_of::<RawFd>() as u32,
+        )
+    };
+    if ret < 0 {
+        return Err(SkReuseportError::SoAttachReuseportEbpfError {
+            io_error: io::Error::last_os_error(),
+        }
+        .into());
+    }
+    Ok(())
+}
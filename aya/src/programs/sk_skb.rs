@@ -9,8 +9,8 @@ use crate::{
     },
     maps::sock::SockMapFd,
     programs::{
-        define_link_wrapper, load_program, ProgAttachLink, ProgAttachLinkId, ProgramData,
-        ProgramError,
+        define_link_wrapper, impl_updatable_link, load_program, ProgAttachLink, ProgAttachLinkId,
+        ProgramData, ProgramError,
     },
     sys::bpf_prog_attach,
 };
@@ -115,3 +115,71 @@ define_link_wrapper!(
     ProgAttachLink,
     ProgAttachLinkId
 );
+
+impl_updatable_link!(SkSkbLink);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        obj::ProgramSection,
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    fn fake_program(section: ProgramSection, kind: SkSkbKind) -> SkSkb {
+        let mut data = fake_program_data(false);
+        data.obj.section = section;
+        data.fd = Some(1);
+        SkSkb { data, kind }
+    }
+
+    #[test]
+    fn test_attach_stream_parser() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_ATTACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.target_fd, 42);
+                assert_eq!(u.attach_type, BPF_SK_SKB_STREAM_PARSER as u32);
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program(
+            ProgramSection::SkSkbStreamParser {
+                name: "test".to_owned(),
+            },
+            SkSkbKind::StreamParser,
+        );
+        assert!(prog.attach(SockMapFd(42)).is_ok());
+    }
+
+    #[test]
+    fn test_attach_stream_verdict() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_ATTACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.target_fd, 42);
+                assert_eq!(u.attach_type, BPF_SK_SKB_STREAM_VERDICT as u32);
+                Ok(0)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program(
+            ProgramSection::SkSkbStreamVerdict {
+                name: "test".to_owned(),
+            },
+            SkSkbKind::StreamVerdict,
+        );
+        assert!(prog.attach(SockMapFd(42)).is_ok());
+    }
+}
@@ -1,13 +1,17 @@
 //! Socket option programs.
-use std::os::unix::io::AsRawFd;
+use std::{
+    hash::Hash,
+    os::unix::io::{AsRawFd, RawFd},
+};
 
 use crate::{
     generated::{bpf_attach_type::BPF_CGROUP_SOCK_OPS, bpf_prog_type::BPF_PROG_TYPE_SOCK_OPS},
     programs::{
-        define_link_wrapper, load_program, ProgAttachLink, ProgAttachLinkId, ProgramData,
-        ProgramError,
+        cgroup_query::{attach_to_cgroup, detach_by_id, CgroupAttachment},
+        define_link_wrapper, impl_updatable_link,
+        links::UpdatableLink,
+        load_program, CgroupAttachMethod, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
     },
-    sys::bpf_prog_attach,
 };
 
 /// A program used to work with sockets.
@@ -57,22 +61,29 @@ impl SockOps {
 
     /// Attaches the program to the given cgroup.
     ///
+    /// A bpf link is used on kernels that support it, see
+    /// [SockOps::attach_with_method] to choose how the program is attached.
+    ///
     /// The returned value can be used to detach, see [SockOps::detach].
     pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<SockOpsLinkId, ProgramError> {
+        self.attach_with_method(cgroup, CgroupAttachMethod::Auto)
+    }
+
+    /// Attaches the program to the given cgroup using the given method.
+    ///
+    /// The returned value can be used to detach, see [SockOps::detach].
+    pub fn attach_with_method<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+        method: CgroupAttachMethod,
+    ) -> Result<SockOpsLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
         let cgroup_fd = cgroup.as_raw_fd();
-
-        bpf_prog_attach(prog_fd, cgroup_fd, BPF_CGROUP_SOCK_OPS).map_err(|(_, io_error)| {
-            ProgramError::SyscallError {
-                call: "bpf_prog_attach".to_owned(),
-                io_error,
-            }
-        })?;
-        self.data.links.insert(SockOpsLink::new(ProgAttachLink::new(
-            prog_fd,
-            cgroup_fd,
-            BPF_CGROUP_SOCK_OPS,
-        )))
+        let link = match attach_to_cgroup(prog_fd, cgroup_fd, BPF_CGROUP_SOCK_OPS, method)? {
+            CgroupAttachment::Fd(link) => SockOpsLinkInner::Fd(link),
+            CgroupAttachment::ProgAttach(link) => SockOpsLinkInner::ProgAttach(link),
+        };
+        self.data.links.insert(SockOpsLink::new(link))
     }
 
     /// Detaches the program.
@@ -89,6 +100,60 @@ impl SockOps {
     pub fn take_link(&mut self, link_id: SockOpsLinkId) -> Result<SockOpsLink, ProgramError> {
         self.data.take_link(link_id)
     }
+
+    /// Detaches the program with the given id from `cgroup`.
+    ///
+    /// This can be used to detach programs attached by other processes, for
+    /// example after listing them with [`CgroupProgramQuery`](crate::programs::CgroupProgramQuery).
+    /// Only programs attached with `BPF_PROG_ATTACH` can be detached this
+    /// way, programs attached through a bpf link stay attached until the link
+    /// is closed.
+    pub fn detach_by_id<T: AsRawFd>(cgroup: T, prog_id: u32) -> Result<(), ProgramError> {
+        detach_by_id(cgroup, prog_id, BPF_CGROUP_SOCK_OPS)
+    }
+}
+
+#[derive(Debug, Hash, Eq, PartialEq)]
+enum SockOpsLinkIdInner {
+    Fd(<FdLink as Link>::Id),
+    ProgAttach(<ProgAttachLink as Link>::Id),
+}
+
+#[derive(Debug)]
+enum SockOpsLinkInner {
+    Fd(FdLink),
+    ProgAttach(ProgAttachLink),
+}
+
+impl Link for SockOpsLinkInner {
+    type Id = SockOpsLinkIdInner;
+
+    fn id(&self) -> Self::Id {
+        match self {
+            SockOpsLinkInner::Fd(fd) => SockOpsLinkIdInner::Fd(fd.id()),
+            SockOpsLinkInner::ProgAttach(p) => SockOpsLinkIdInner::ProgAttach(p.id()),
+        }
+    }
+
+    fn detach(self) -> Result<(), ProgramError> {
+        match self {
+            SockOpsLinkInner::Fd(fd) => fd.detach(),
+            SockOpsLinkInner::ProgAttach(p) => p.detach(),
+        }
+    }
+}
+
+impl UpdatableLink for SockOpsLinkInner {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        match self {
+            SockOpsLinkInner::Fd(fd) => fd.update_program(new_prog_fd, old_prog_fd),
+            SockOpsLinkInner::ProgAttach(p) => p.update_program(new_prog_fd, old_prog_fd),
+        }
+    }
 }
 
 define_link_wrapper!(
@@ -96,6 +161,79 @@ define_link_wrapper!(
     SockOpsLink,
     /// The type returned by [SockOps::attach]. Can be passed to [SockOps::detach].
     SockOpsLinkId,
-    ProgAttachLink,
-    ProgAttachLinkId
+    SockOpsLinkInner,
+    SockOpsLinkIdInner
 );
+
+impl_updatable_link!(SockOpsLink);
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    fn fake_sock_ops() -> SockOps {
+        let mut prog = SockOps {
+            data: fake_program_data(false),
+        };
+        prog.data.fd = Some(99999);
+        prog
+    }
+
+    #[test]
+    fn test_attach() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_ATTACH,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(u.attach_bpf_fd, 99999);
+                assert_eq!(u.target_fd, 0);
+                assert_eq!(u.attach_type, BPF_CGROUP_SOCK_OPS as u32);
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_DETACH,
+                ..
+            } => Ok(0),
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let mut prog = fake_sock_ops();
+        let link_id = prog
+            .attach_with_method(io::stdin(), CgroupAttachMethod::ProgAttach)
+            .unwrap();
+        prog.detach(link_id).unwrap();
+        prog.data.fd = None;
+    }
+
+    #[test]
+    fn test_attach_with_link() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let u = unsafe { &attr.link_create };
+                assert_eq!(u.prog_fd, 99999);
+                assert_eq!(u.attach_type, BPF_CGROUP_SOCK_OPS as u32);
+                Err((-1, io::Error::from_raw_os_error(libc::EPERM)))
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        let mut prog = fake_sock_ops();
+        assert!(matches!(
+            prog.attach_with_method(io::stdin(), CgroupAttachMethod::Link),
+            Err(ProgramError::SyscallError { call, .. }) if call == "bpf_link_create"
+        ));
+        prog.data.fd = None;
+    }
+}
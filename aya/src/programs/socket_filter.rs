@@ -8,7 +8,7 @@ use thiserror::Error;
 
 use crate::{
     generated::{bpf_prog_type::BPF_PROG_TYPE_SOCKET_FILTER, SO_ATTACH_BPF, SO_DETACH_BPF},
-    programs::{load_program, Link, ProgramData, ProgramError},
+    programs::{links::UpdatableLink, load_program, Link, ProgramData, ProgramError},
 };
 
 /// The type returned when attaching a [`SocketFilter`] fails.
@@ -76,22 +76,7 @@ impl SocketFilter {
         let prog_fd = self.data.fd_or_err()?;
         let socket = socket.as_raw_fd();
 
-        let ret = unsafe {
-            setsockopt(
-                socket,
-                SOL_SOCKET,
-                SO_ATTACH_BPF as i32,
-                &prog_fd as *const _ as *const _,
-                mem::size_of::<RawFd>() as u32,
-            )
-        };
-        if ret < 0 {
-            return Err(SocketFilterError::SoAttachBpfError {
-                io_error: io::Error::last_os_error(),
-            }
-            .into());
-        }
-
+        attach_socket(socket, prog_fd)?;
         self.data.links.insert(SocketFilterLink { socket, prog_fd })
     }
 
@@ -145,3 +130,35 @@ impl Link for SocketFilterLink {
         Ok(())
     }
 }
+
+impl UpdatableLink for SocketFilterLink {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        _old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        // attaching a program replaces the one already attached
+        attach_socket(self.socket, new_prog_fd)?;
+        self.prog_fd = new_prog_fd;
+        Ok(())
+    }
+}
+
+fn attach_socket(socket: RawFd, prog_fd: RawFd) -> Result<(), ProgramError> {
+    let ret = unsafe {
+        setsockopt(
+            socket,
+            SOL_SOCKET,
+            SO_ATTACH_BPF as i32,
+            &prog_fd as *const _ as *const _,
+            mem::size_of::<RawFd>() as u32,
+        )
+    };
+    if ret < 0 {
+        return Err(SocketFilterError::SoAttachBpfError {
+            io_error: io::Error::last_os_error(),
+        }
+        .into());
+    }
+    Ok(())
+}
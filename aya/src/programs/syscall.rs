@@ -0,0 +1,49 @@
+//! Syscall programs.
+use crate::{
+    generated::{bpf_prog_type::BPF_PROG_TYPE_SYSCALL, BPF_F_SLEEPABLE},
+    programs::{load_program, FdLink, ProgramData, ProgramError},
+};
+
+/// A program that can be invoked from userspace to issue BPF syscalls.
+///
+/// [`Syscall`] programs aren't attached to any hook, they are executed on
+/// demand with [`Program::test_run`](crate::programs::Program::test_run). The
+/// context passed to the program is copied back to userspace once the program
+/// returns, so it can be used to exchange data with the program.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.14.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(thiserror::Error, Debug)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError)
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::programs::Syscall;
+///
+/// let program: &mut Syscall = bpf.program_mut("create_map").unwrap().try_into()?;
+/// program.load()?;
+///
+/// let (retval, ctx) = bpf.program("create_map").unwrap().test_run(&[0u8; 16])?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_SYSCALL")]
+pub struct Syscall {
+    pub(crate) data: ProgramData<FdLink>,
+}
+
+impl Syscall {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.flags = BPF_F_SLEEPABLE;
+        load_program(BPF_PROG_TYPE_SYSCALL, &mut self.data)
+    }
+}
@@ -4,16 +4,20 @@ use thiserror::Error;
 use std::{
     ffi::{CStr, CString},
     io,
+    os::unix::io::RawFd,
 };
 
 use crate::{
     generated::{
         bpf_prog_type::BPF_PROG_TYPE_SCHED_CLS, TC_H_CLSACT, TC_H_MIN_EGRESS, TC_H_MIN_INGRESS,
     },
-    programs::{define_link_wrapper, load_program, Link, ProgramData, ProgramError},
+    programs::{
+        define_link_wrapper, impl_updatable_link, links::UpdatableLink, load_program, Link,
+        ProgramData, ProgramError,
+    },
     sys::{
-        netlink_find_filter_with_name, netlink_qdisc_add_clsact, netlink_qdisc_attach,
-        netlink_qdisc_detach,
+        netlink_find_bpf_filters, netlink_find_filter_with_name, netlink_qdisc_add_clsact,
+        netlink_qdisc_attach, netlink_qdisc_detach,
     },
     util::{ifindex_from_ifname, tc_handler_make},
 };
@@ -161,6 +165,7 @@ impl SchedClassifier {
                 &self.name,
                 options.priority,
                 options.handle,
+                false,
             )
         }
         .map_err(|io_error| TcError::NetlinkError { io_error })?;
@@ -170,6 +175,7 @@ impl SchedClassifier {
             attach_type,
             priority,
             handle,
+            prog_name: self.name.clone(),
         }))
     }
 
@@ -180,6 +186,40 @@ impl SchedClassifier {
         self.data.links.remove(link_id)
     }
 
+    /// Detaches this program from the interface with the given `if_index`.
+    ///
+    /// Unlike [SchedClassifier::detach], this doesn't need the link returned
+    /// when attaching, so it can be used to clean up after a process that
+    /// exited without detaching. When `force` is true, all the bpf filters
+    /// attached at `attach_type` are detached, including the ones attached by
+    /// other processes.
+    ///
+    /// Returns the number of filters detached. If detaching a filter fails,
+    /// the other filters are still detached and the first error is returned.
+    pub fn detach_interface(
+        &mut self,
+        if_index: u32,
+        attach_type: TcAttachType,
+        force: bool,
+    ) -> Result<usize, ProgramError> {
+        let prog_id = if force {
+            None
+        } else {
+            Some(self.data.prog_id()?)
+        };
+        let mut errors = Vec::new();
+        let detached = detach_bpf_filters(
+            if_index,
+            attach_type,
+            |id| prog_id.is_none() || prog_id == Some(id),
+            &mut errors,
+        );
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(detached),
+        }
+    }
+
     /// Takes ownership of the link referenced by the provided link_id.
     ///
     /// The link will be detached on `Drop` and the caller is now responsible
@@ -201,6 +241,7 @@ struct TcLink {
     attach_type: TcAttachType,
     priority: u16,
     handle: u32,
+    prog_name: Box<CStr>,
 }
 
 impl Link for TcLink {
@@ -219,6 +260,28 @@ impl Link for TcLink {
     }
 }
 
+impl UpdatableLink for TcLink {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        _old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        unsafe {
+            netlink_qdisc_attach(
+                self.if_index,
+                &self.attach_type,
+                new_prog_fd,
+                &self.prog_name,
+                self.priority,
+                self.handle,
+                true,
+            )
+        }
+        .map_err(|io_error| TcError::NetlinkError { io_error })?;
+        Ok(())
+    }
+}
+
 define_link_wrapper!(
     /// The link used by [SchedClassifier] programs.
     SchedClassifierLink,
@@ -228,6 +291,41 @@ define_link_wrapper!(
     TcLinkId
 );
 
+impl_updatable_link!(SchedClassifierLink);
+
+// Detaches the bpf filters attached to the interface with the given `if_index`
+// whose program id satisfies `owned`. Failures are pushed to `errors` and don't
+// stop the other filters from being detached. Returns the number of filters
+// detached.
+pub(crate) fn detach_bpf_filters(
+    if_index: u32,
+    attach_type: TcAttachType,
+    owned: impl Fn(u32) -> bool,
+    errors: &mut Vec<ProgramError>,
+) -> usize {
+    let if_index = if_index as i32;
+    let filters = match unsafe { netlink_find_bpf_filters(if_index, attach_type) } {
+        Ok(filters) => filters,
+        Err(io_error) => {
+            errors.push(TcError::NetlinkError { io_error }.into());
+            return 0;
+        }
+    };
+
+    let mut detached = 0;
+    for (priority, handle, prog_id) in filters {
+        if !owned(prog_id) {
+            continue;
+        }
+        match unsafe { netlink_qdisc_detach(if_index, &attach_type, priority, handle) } {
+            Ok(()) => detached += 1,
+            Err(io_error) => errors.push(TcError::NetlinkError { io_error }.into()),
+        }
+    }
+
+    detached
+}
+
 /// Add the `clasct` qdisc to the given interface.
 ///
 /// The `clsact` qdisc must be added to an interface before [`SchedClassifier`]
@@ -0,0 +1,224 @@
+//! TCX (tc express) programs.
+pub use aya_obj::programs::TcxAttachType;
+
+use std::os::unix::io::RawFd;
+
+use thiserror::Error;
+
+use crate::{
+    generated::{bpf_prog_type::BPF_PROG_TYPE_SCHED_CLS, BPF_F_AFTER, BPF_F_BEFORE, BPF_F_ID},
+    programs::{
+        define_link_wrapper, impl_updatable_link, load_program, FdLink, FdLinkId, ProgramData,
+        ProgramError,
+    },
+    sys::{bpf_link_create_tcx, kernel_version},
+};
+
+/// A network traffic control classifier attached with a TCX link.
+///
+/// [`Tcx`] programs are the link based equivalent of
+/// [`SchedClassifier`](crate::programs::SchedClassifier) programs. They don't
+/// need a `clsact` qdisc, and multiple programs attached to the same interface
+/// are run in an order that can be controlled with [`TcxLinkOrder`].
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 6.6.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(Debug, thiserror::Error)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError)
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::programs::{Tcx, TcxLinkOrder};
+///
+/// let prog: &mut Tcx = bpf.program_mut("ingress").unwrap().try_into()?;
+/// prog.load()?;
+/// prog.attach(2, TcxLinkOrder::First)?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_SCHED_CLS")]
+pub struct Tcx {
+    pub(crate) data: ProgramData<TcxLink>,
+    pub(crate) attach_type: TcxAttachType,
+}
+
+/// Errors from TCX programs
+#[derive(Debug, Error)]
+pub enum TcxError {
+    /// the running kernel doesn't support TCX
+    #[error("TCX links are not supported by the running kernel, 6.6 or later is required")]
+    NotSupported,
+}
+
+/// Where a [`Tcx`] program is placed among the programs attached to the same
+/// interface and direction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TcxLinkOrder {
+    /// Run after all the programs currently attached.
+    #[default]
+    Last,
+    /// Run before all the programs currently attached.
+    First,
+    /// Run before the program with the given id.
+    Before(u32),
+    /// Run after the program with the given id.
+    After(u32),
+}
+
+impl TcxLinkOrder {
+    // Returns the link_create flags and relative program id
+    fn flags(&self) -> (u32, u32) {
+        match self {
+            TcxLinkOrder::Last => (0, 0),
+            TcxLinkOrder::First => (BPF_F_BEFORE, 0),
+            TcxLinkOrder::Before(id) => (BPF_F_BEFORE | BPF_F_ID, *id),
+            TcxLinkOrder::After(id) => (BPF_F_AFTER | BPF_F_ID, *id),
+        }
+    }
+}
+
+impl Tcx {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(self.attach_type.into());
+        load_program(BPF_PROG_TYPE_SCHED_CLS, &mut self.data)
+    }
+
+    /// Attaches the program to the interface with the given `if_index`.
+    ///
+    /// The direction is the one given in the program section, either
+    /// `tcx/ingress` or `tcx/egress`.
+    ///
+    /// The returned value can be used to detach, see [Tcx::detach].
+    ///
+    /// # Errors
+    ///
+    /// [`TcxError::NotSupported`] is returned on kernels older than 6.6.
+    pub fn attach(
+        &mut self,
+        if_index: u32,
+        order: TcxLinkOrder,
+    ) -> Result<TcxLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+
+        let k_ver = kernel_version().unwrap();
+        if k_ver < (6, 6, 0) {
+            return Err(TcxError::NotSupported.into());
+        }
+
+        let (flags, relative_id) = order.flags();
+        let link_fd = bpf_link_create_tcx(
+            prog_fd,
+            if_index,
+            self.attach_type.into(),
+            flags,
+            relative_id,
+        )
+        .map_err(|(_, io_error)| ProgramError::SyscallError {
+            call: "bpf_link_create".to_owned(),
+            io_error,
+        })? as RawFd;
+        self.data.links.insert(TcxLink::new(FdLink::new(link_fd)))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [Tcx::attach].
+    pub fn detach(&mut self, link_id: TcxLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(&mut self, link_id: TcxLinkId) -> Result<TcxLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [Tcx] programs.
+    TcxLink,
+    /// The type returned by [Tcx::attach]. Can be passed to [Tcx::detach].
+    TcxLinkId,
+    FdLink,
+    FdLinkId
+);
+
+impl_updatable_link!(TcxLink);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generated::{bpf_attach_type::BPF_TCX_EGRESS, bpf_cmd},
+        obj::ProgramSection,
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    fn fake_program(attach_type: TcxAttachType) -> Tcx {
+        let mut data = fake_program_data(false);
+        data.obj.section = ProgramSection::Tcx {
+            name: "test".to_owned(),
+            attach_type,
+        };
+        Tcx { data, attach_type }
+    }
+
+    #[test]
+    fn test_attach_before() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let link_create = unsafe { attr.link_create };
+                assert_eq!(link_create.attach_type, BPF_TCX_EGRESS as u32);
+                assert_eq!(unsafe { link_create.__bindgen_anon_1.target_ifindex }, 7);
+                assert_eq!(link_create.flags, BPF_F_BEFORE | BPF_F_ID);
+                assert_eq!(
+                    unsafe {
+                        link_create
+                            .__bindgen_anon_2
+                            .tcx
+                            .__bindgen_anon_1
+                            .relative_id
+                    },
+                    42
+                );
+                Ok(-1)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program(TcxAttachType::Egress);
+        prog.data.fd = Some(1);
+        assert!(prog.attach(7, TcxLinkOrder::Before(42)).is_ok());
+    }
+
+    #[test]
+    fn test_link_order_flags() {
+        assert_eq!(TcxLinkOrder::default().flags(), (0, 0));
+        assert_eq!(TcxLinkOrder::First.flags(), (BPF_F_BEFORE, 0));
+        assert_eq!(TcxLinkOrder::After(3).flags(), (BPF_F_AFTER | BPF_F_ID, 3));
+    }
+
+    #[test]
+    fn test_attach_not_loaded() {
+        let mut prog = fake_program(TcxAttachType::Ingress);
+        assert!(matches!(
+            prog.attach(7, TcxLinkOrder::Last),
+            Err(ProgramError::NotLoaded)
+        ));
+    }
+}
@@ -0,0 +1,17 @@
+// Target of the uprobe symbol resolution tests.
+//
+// Built with:
+//   gcc -shared -fPIC -nostdlib -Os -Wl,--build-id=none -Wl,-z,noseparate-code \
+//       -o libuprobe_target.so uprobe_target.c
+
+extern int external_function(int);
+
+int uprobe_target_function(int x)
+{
+    return x * 2 + 1;
+}
+
+int uprobe_target_calls_plt(int x)
+{
+    return external_function(x);
+}
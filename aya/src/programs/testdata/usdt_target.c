@@ -0,0 +1,56 @@
+// Target of the USDT note parsing tests.
+//
+// Built with:
+//   gcc -shared -fPIC -nostdlib -Os -Wl,--build-id=none -Wl,-z,noseparate-code \
+//       -o libusdt_target.so usdt_target.c
+//
+// The probes are defined like sys/sdt.h does, without depending on it.
+
+#define USDT_BASE                                                              \
+    ".ifndef _.stapsdt.base\n"                                                 \
+    ".pushsection .stapsdt.base,\"aG\",\"progbits\",.stapsdt.base,comdat\n"    \
+    ".weak _.stapsdt.base\n"                                                   \
+    ".hidden _.stapsdt.base\n"                                                 \
+    "_.stapsdt.base: .space 1\n"                                               \
+    ".size _.stapsdt.base, 1\n"                                                \
+    ".popsection\n"                                                            \
+    ".endif\n"
+
+#define USDT_NOTE(provider, name, args)                                        \
+    "990: nop\n"                                                               \
+    ".pushsection .note.stapsdt,\"?\",\"note\"\n"                              \
+    ".balign 4\n"                                                              \
+    ".4byte 992f-991f, 994f-993f, 3\n"                                         \
+    "991: .asciz \"stapsdt\"\n"                                                \
+    "992: .balign 4\n"                                                         \
+    "993: .8byte 990b\n"                                                       \
+    ".8byte _.stapsdt.base\n"                                                  \
+    ".8byte 0\n"                                                               \
+    ".asciz \"" provider "\"\n"                                                \
+    ".asciz \"" name "\"\n"                                                    \
+    ".asciz \"" args "\"\n"                                                    \
+    "994: .balign 4\n"                                                         \
+    ".popsection\n" USDT_BASE
+
+int usdt_target_function(int x, long y)
+{
+    __asm__ __volatile__(USDT_NOTE("aya", "args", "-4@%0 8@%1 -4@$42")
+                         :
+                         : "nor"(x), "nor"(y));
+    return x * 2 + 1;
+}
+
+int usdt_target_deref(long *p)
+{
+    __asm__ __volatile__(USDT_NOTE("aya", "deref", "8@8(%%rdi) 2@-4(%%rbp)")
+                         :
+                         :);
+    return p[1];
+}
+
+void usdt_target_noargs(void)
+{
+    __asm__ __volatile__(USDT_NOTE("aya", "noargs", "")
+                         :
+                         :);
+}
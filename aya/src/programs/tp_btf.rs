@@ -98,3 +98,69 @@ define_link_wrapper!(
     FdLink,
     FdLinkId
 );
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        obj::btf::{BtfError, BtfType, FuncProto, Ptr, Typedef},
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    // kernel BTF with `btf_trace_sched_switch` as type 3 and
+    // `btf_trace_sched_wakeup` as type 4
+    fn fake_kernel_btf() -> Btf {
+        let mut btf = Btf::new();
+        let proto_id = btf.add_type(BtfType::FuncProto(FuncProto::new(Vec::new(), 0)));
+        let ptr_id = btf.add_type(BtfType::Ptr(Ptr::new(0, proto_id)));
+        for name in ["btf_trace_sched_switch", "btf_trace_sched_wakeup"] {
+            let name_offset = btf.add_string(name.to_owned());
+            btf.add_type(BtfType::Typedef(Typedef::new(name_offset, ptr_id)));
+        }
+        btf
+    }
+
+    #[test]
+    fn test_load_resolves_attach_btf_id() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                assert_eq!(u.prog_type, BPF_PROG_TYPE_TRACING as u32);
+                assert_eq!(u.expected_attach_type, BPF_TRACE_RAW_TP as u32);
+                assert_eq!(u.attach_btf_id, 3);
+                Ok(7)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = BtfTracePoint {
+            data: fake_program_data(false),
+        };
+        prog.load("sched_switch", &fake_kernel_btf()).unwrap();
+        assert_eq!(prog.data.attach_btf_id, Some(3));
+        assert_eq!(prog.data.fd, Some(7));
+        prog.data.fd = None;
+    }
+
+    #[test]
+    fn test_load_unknown_tracepoint() {
+        override_syscall(|_| panic!("the program must not be loaded"));
+
+        let mut prog = BtfTracePoint {
+            data: fake_program_data(false),
+        };
+        assert_matches!(
+            prog.load("sched_foo", &fake_kernel_btf()),
+            Err(ProgramError::Btf(BtfError::UnknownBtfTypeName { type_name }))
+                if type_name == "btf_trace_sched_foo"
+        );
+        assert_eq!(prog.data.fd, None);
+    }
+}
@@ -1,5 +1,8 @@
 //! Tracepoint programs.
-use std::{fs, io};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
 
 use crate::{
@@ -24,6 +27,15 @@ pub enum TracePointError {
         #[source]
         io_error: io::Error,
     },
+
+    /// The trace point doesn't exist on the running kernel
+    #[error("trace point `{category}:{name}` not found, see `TracePoint::list_available`")]
+    TracePointNotFound {
+        /// The category of the trace point
+        category: String,
+        /// The name of the trace point
+        name: String,
+    },
 }
 
 /// A program that can be attached at a pre-defined kernel trace point.
@@ -73,7 +85,8 @@ impl TracePoint {
     /// Attaches to a given trace point.
     ///
     /// For a list of the available event categories and names, see
-    /// `/sys/kernel/debug/tracing/events`.
+    /// [TracePoint::list_available]. If the trace point doesn't exist on the
+    /// running kernel, [TracePointError::TracePointNotFound] is returned.
     ///
     /// The returned value can be used to detach, see [TracePoint::detach].
     pub fn attach(&mut self, category: &str, name: &str) -> Result<TracePointLinkId, ProgramError> {
@@ -88,6 +101,78 @@ impl TracePoint {
         perf_attach(&mut self.data, fd)
     }
 
+    /// Returns the category and name of the trace points of the running
+    /// kernel.
+    ///
+    /// The trace points are read from the `available_events` file of tracefs,
+    /// mounted at `/sys/kernel/tracing` or `/sys/kernel/debug/tracing`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aya::programs::TracePoint;
+    ///
+    /// for (category, name) in TracePoint::list_available()? {
+    ///     println!("{category}:{name}");
+    /// }
+    /// # Ok::<(), aya::programs::TracePointError>(())
+    /// ```
+    pub fn list_available() -> Result<Vec<(String, String)>, TracePointError> {
+        let (file, events) = TRACEFS_PATHS
+            .iter()
+            .map(|path| format!("{path}/available_events"))
+            .find_map(|file| fs::read_to_string(&file).ok().map(|events| (file, events)))
+            .ok_or_else(|| TracePointError::FileError {
+                filename: format!("{}/available_events", TRACEFS_PATHS[0]),
+                io_error: io::Error::from(io::ErrorKind::NotFound),
+            })?;
+        parse_available_events(&events).map_err(|io_error| TracePointError::FileError {
+            filename: file,
+            io_error,
+        })
+    }
+
+    /// Returns the layout of the record passed to programs attached to the
+    /// given trace point.
+    ///
+    /// The layout is read from the `events/<category>/<name>/format` file of
+    /// tracefs. If the trace point doesn't exist on the running kernel,
+    /// [TracePointError::TracePointNotFound] is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aya::programs::TracePoint;
+    ///
+    /// let format = TracePoint::format("sched", "sched_switch")?;
+    /// let next_pid = format.field("next_pid").unwrap();
+    /// println!("next_pid: offset {} size {}", next_pid.offset, next_pid.size);
+    /// # Ok::<(), aya::programs::TracePointError>(())
+    /// ```
+    pub fn format(category: &str, name: &str) -> Result<TraceEventFormat, TracePointError> {
+        let file = tracefs_path()
+            .join("events")
+            .join(category)
+            .join(name)
+            .join("format");
+        let filename = file.display().to_string();
+
+        let format = fs::read_to_string(&file).map_err(|io_error| {
+            if io_error.kind() == io::ErrorKind::NotFound {
+                TracePointError::TracePointNotFound {
+                    category: category.to_owned(),
+                    name: name.to_owned(),
+                }
+            } else {
+                TracePointError::FileError {
+                    filename: filename.clone(),
+                    io_error,
+                }
+            }
+        })?;
+        parse_format(&format).map_err(|io_error| TracePointError::FileError { filename, io_error })
+    }
+
     /// Detaches from a trace point.
     ///
     /// See [TracePoint::attach].
@@ -104,6 +189,39 @@ impl TracePoint {
     }
 }
 
+/// The layout of a trace point record, as returned by [TracePoint::format].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEventFormat {
+    fields: Vec<TraceEventField>,
+}
+
+impl TraceEventFormat {
+    /// Returns the fields of the record, in the order they're declared.
+    ///
+    /// The first fields are the `common_*` fields shared by all trace points.
+    pub fn fields(&self) -> &[TraceEventField] {
+        &self.fields
+    }
+
+    /// Returns the field with the given name, if any.
+    pub fn field(&self, name: &str) -> Option<&TraceEventField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+/// A field of a trace point record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEventField {
+    /// The name of the field, without any array suffix
+    pub name: String,
+    /// The offset of the field from the start of the record, in bytes
+    pub offset: usize,
+    /// The size of the field, in bytes
+    pub size: usize,
+    /// Whether the field holds a signed value
+    pub signed: bool,
+}
+
 define_link_wrapper!(
     /// The link used by [TracePoint] programs.
     TracePointLink,
@@ -113,15 +231,127 @@ define_link_wrapper!(
     PerfLinkId
 );
 
+// the mount points of tracefs, tried in order
+const TRACEFS_PATHS: [&str; 2] = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+#[cfg(test)]
+thread_local! {
+    static TEST_TRACEFS: std::cell::RefCell<Option<PathBuf>> = Default::default();
+}
+
+/// Makes [`tracefs_path`] return `path` on the current thread.
+#[cfg(test)]
+pub(crate) fn override_tracefs(path: &Path) {
+    TEST_TRACEFS.with(|tracefs| *tracefs.borrow_mut() = Some(path.to_owned()));
+}
+
+// Returns the mount point of tracefs.
+pub(crate) fn tracefs_path() -> PathBuf {
+    #[cfg(test)]
+    if let Some(path) = TEST_TRACEFS.with(|tracefs| tracefs.borrow().clone()) {
+        return path;
+    }
+
+    let path = TRACEFS_PATHS
+        .iter()
+        .copied()
+        .find(|path| Path::new(path).join("events").exists())
+        .unwrap_or(TRACEFS_PATHS[1]);
+    PathBuf::from(path)
+}
+
+fn parse_available_events(events: &str) -> Result<Vec<(String, String)>, io::Error> {
+    events
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| match line.trim().split_once(':') {
+            Some((category, name)) if !category.is_empty() && !name.is_empty() => {
+                Ok((category.to_owned(), name.to_owned()))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid event `{line}`"),
+            )),
+        })
+        .collect()
+}
+
+fn parse_format(format: &str) -> Result<TraceEventFormat, io::Error> {
+    let invalid = |line: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid field `{}`", line.trim()),
+        )
+    };
+
+    let mut fields = Vec::new();
+    for line in format.lines() {
+        let line_trimmed = line.trim();
+        if !line_trimmed.starts_with("field:") {
+            continue;
+        }
+
+        // each line looks like:
+        // field:unsigned short common_type; offset:0; size:2; signed:0;
+        let (mut decl, mut offset, mut size, mut signed) = (None, None, None, None);
+        for property in line_trimmed.split(';').map(str::trim) {
+            let (key, value) = match property.split_once(':') {
+                Some(property) => property,
+                None => continue,
+            };
+            match key {
+                "field" => decl = Some(value),
+                "offset" => offset = value.parse::<usize>().ok(),
+                "size" => size = value.parse::<usize>().ok(),
+                "signed" => signed = value.parse::<u8>().ok().map(|signed| signed != 0),
+                _ => {}
+            }
+        }
+
+        // the name is the last word of the declaration, eg `char prev_comm[16]`
+        let name = decl
+            .and_then(|decl| decl.split_whitespace().last())
+            .map(|name| name.split('[').next().unwrap_or(name))
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| invalid(line))?;
+        let (offset, size, signed) = match (offset, size, signed) {
+            (Some(offset), Some(size), Some(signed)) => (offset, size, signed),
+            _ => return Err(invalid(line)),
+        };
+
+        fields.push(TraceEventField {
+            name: name.to_owned(),
+            offset,
+            size,
+            signed,
+        });
+    }
+
+    Ok(TraceEventFormat { fields })
+}
+
 pub(crate) fn read_sys_fs_trace_point_id(
     category: &str,
     name: &str,
 ) -> Result<u32, TracePointError> {
-    let file = format!("/sys/kernel/debug/tracing/events/{category}/{name}/id");
+    let file = format!("{}/events/{category}/{name}/id", tracefs_path().display());
 
-    let id = fs::read_to_string(&file).map_err(|io_error| TracePointError::FileError {
-        filename: file.clone(),
-        io_error,
+    let id = fs::read_to_string(&file).map_err(|io_error| {
+        // tell a missing trace point apart from a tracefs that can't be read
+        if io_error.kind() == io::ErrorKind::NotFound {
+            if let Ok(available) = TracePoint::list_available() {
+                if !available.iter().any(|(c, n)| c == category && n == name) {
+                    return TracePointError::TracePointNotFound {
+                        category: category.to_owned(),
+                        name: name.to_owned(),
+                    };
+                }
+            }
+        }
+        TracePointError::FileError {
+            filename: file.clone(),
+            io_error,
+        }
     })?;
     let id = id
         .trim()
@@ -133,3 +363,89 @@ pub(crate) fn read_sys_fs_trace_point_id(
 
     Ok(id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_available_events() {
+        let events = include_str!("testdata/available_events");
+        let events = parse_available_events(events).unwrap();
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[0], ("sched".to_owned(), "sched_switch".to_owned()));
+        assert!(events.contains(&("raw_syscalls".to_owned(), "sys_enter".to_owned())));
+        assert!(events.contains(&("kprobes".to_owned(), "aya_12345_do_sys_open".to_owned())));
+
+        assert!(parse_available_events("").unwrap().is_empty());
+        assert!(parse_available_events("sched:sched_switch\n\n").is_ok());
+        assert!(parse_available_events("sched_switch\n").is_err());
+        assert!(parse_available_events(":sched_switch\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_format() {
+        let format = parse_format(include_str!("testdata/sched_switch_format")).unwrap();
+        assert_eq!(format.fields().len(), 11);
+        assert_eq!(
+            format.fields()[0],
+            TraceEventField {
+                name: "common_type".to_owned(),
+                offset: 0,
+                size: 2,
+                signed: false,
+            }
+        );
+        assert_eq!(
+            format.field("prev_comm"),
+            Some(&TraceEventField {
+                name: "prev_comm".to_owned(),
+                offset: 8,
+                size: 16,
+                signed: false,
+            })
+        );
+        assert_eq!(
+            format.field("next_pid"),
+            Some(&TraceEventField {
+                name: "next_pid".to_owned(),
+                offset: 56,
+                size: 4,
+                signed: true,
+            })
+        );
+        assert!(format.field("missing").is_none());
+
+        let data_loc =
+            parse_format("\tfield:__data_loc char[] name;\toffset:8;\tsize:4;\tsigned:1;\n")
+                .unwrap();
+        assert_eq!(data_loc.fields()[0].name, "name");
+
+        assert!(parse_format("").unwrap().fields().is_empty());
+        assert!(parse_format("\tfield:int pid;\toffset:4;\n").is_err());
+        assert!(parse_format("\tfield:int pid;\toffset:x;\tsize:4;\tsigned:1;\n").is_err());
+        assert!(parse_format("\tfield:;\toffset:4;\tsize:4;\tsigned:1;\n").is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let dir = std::env::temp_dir().join(format!("aya-test-tracefs-{}", std::process::id()));
+        let event_dir = dir.join("events/sched/sched_switch");
+        fs::create_dir_all(&event_dir).unwrap();
+        fs::write(
+            event_dir.join("format"),
+            include_str!("testdata/sched_switch_format"),
+        )
+        .unwrap();
+        override_tracefs(&dir);
+
+        let format = TracePoint::format("sched", "sched_switch").unwrap();
+        assert_eq!(format.field("prev_pid").unwrap().offset, 24);
+        assert!(matches!(
+            TracePoint::format("sched", "missing"),
+            Err(TracePointError::TracePointNotFound { .. })
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
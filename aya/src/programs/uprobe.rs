@@ -79,36 +79,7 @@ impl UProbe {
         target: T,
         pid: Option<pid_t>,
     ) -> Result<UProbeLinkId, ProgramError> {
-        let target = target.as_ref();
-        let target_str = &*target.as_os_str().to_string_lossy();
-
-        let mut path = if let Some(pid) = pid {
-            find_lib_in_proc_maps(pid, target_str).map_err(|io_error| UProbeError::FileError {
-                filename: format!("/proc/{pid}/maps"),
-                io_error,
-            })?
-        } else {
-            None
-        };
-
-        if path.is_none() {
-            path = if target.is_absolute() {
-                Some(target_str)
-            } else {
-                let cache =
-                    LD_SO_CACHE
-                        .as_ref()
-                        .map_err(|error| UProbeError::InvalidLdSoCache {
-                            io_error: error.clone(),
-                        })?;
-                cache.resolve(target_str)
-            }
-            .map(String::from)
-        };
-
-        let path = path.ok_or(UProbeError::InvalidTarget {
-            path: target.to_owned(),
-        })?;
+        let path = resolve_target(target.as_ref(), pid)?;
 
         let sym_offset = if let Some(fn_name) = fn_name {
             resolve_symbol(&path, fn_name).map_err(|error| UProbeError::SymbolError {
@@ -122,6 +93,25 @@ impl UProbe {
         attach(&mut self.data, self.kind, &path, sym_offset + offset, pid)
     }
 
+    /// Attaches the program to the function `symbol` defined in the binary or
+    /// library at `path`.
+    ///
+    /// The symbol is looked up in the symbol table and the dynamic symbol
+    /// table of `path`, and its address is translated to a file offset, so
+    /// position independent executables and shared libraries are supported.
+    /// Attaching fails with [`UProbeError::SymbolError`] if the symbol can't be
+    /// found, or if it's not defined in `path` but imported through the PLT.
+    ///
+    /// This is equivalent to calling [UProbe::attach] with no offset and no
+    /// `pid`.
+    pub fn attach_symbol(
+        &mut self,
+        path: &Path,
+        symbol: &str,
+    ) -> Result<UProbeLinkId, ProgramError> {
+        self.attach(Some(symbol), 0, path, None)
+    }
+
     /// Detaches the program.
     ///
     /// See [UProbe::attach].
@@ -186,6 +176,39 @@ pub enum UProbeError {
     },
 }
 
+// Resolves `target`, a path to a binary or a library name, to the path of the
+// file to probe.
+pub(crate) fn resolve_target(target: &Path, pid: Option<pid_t>) -> Result<String, UProbeError> {
+    let target_str = &*target.as_os_str().to_string_lossy();
+
+    let mut path = if let Some(pid) = pid {
+        find_lib_in_proc_maps(pid, target_str).map_err(|io_error| UProbeError::FileError {
+            filename: format!("/proc/{pid}/maps"),
+            io_error,
+        })?
+    } else {
+        None
+    };
+
+    if path.is_none() {
+        path = if target.is_absolute() {
+            Some(target_str)
+        } else {
+            let cache = LD_SO_CACHE
+                .as_ref()
+                .map_err(|error| UProbeError::InvalidLdSoCache {
+                    io_error: error.clone(),
+                })?;
+            cache.resolve(target_str)
+        }
+        .map(String::from)
+    };
+
+    path.ok_or(UProbeError::InvalidTarget {
+        path: target.to_owned(),
+    })
+}
+
 fn proc_maps_libs(pid: pid_t) -> Result<Vec<(String, String)>, io::Error> {
     let maps_file = format!("/proc/{pid}/maps");
     let data = fs::read_to_string(maps_file)?;
@@ -320,6 +343,9 @@ enum ResolveSymbolError {
     #[error("unknown symbol `{0}`")]
     Unknown(String),
 
+    #[error("symbol `{0}` is not defined in the target, it resolves to a PLT stub")]
+    PltStub(String),
+
     #[error("symbol `{0}` does not appear in section")]
     NotInSection(String),
 
@@ -331,11 +357,19 @@ fn resolve_symbol(path: &str, symbol: &str) -> Result<u64, ResolveSymbolError> {
     let data = fs::read(path)?;
     let obj = object::read::File::parse(&*data)?;
 
-    let sym = obj
+    let mut syms = obj
         .dynamic_symbols()
         .chain(obj.symbols())
-        .find(|sym| sym.name().map(|name| name == symbol).unwrap_or(false))
-        .ok_or_else(|| ResolveSymbolError::Unknown(symbol.to_string()))?;
+        .filter(|sym| sym.name().map(|name| name == symbol).unwrap_or(false))
+        .peekable();
+    if syms.peek().is_none() {
+        return Err(ResolveSymbolError::Unknown(symbol.to_string()));
+    }
+    // functions imported from other objects are undefined and only called
+    // through the PLT, probing the stub would not probe the function
+    let sym = syms
+        .find(|sym| !sym.is_undefined())
+        .ok_or_else(|| ResolveSymbolError::PltStub(symbol.to_string()))?;
 
     let needs_addr_translation = matches!(
         obj.kind(),
@@ -357,3 +391,42 @@ fn resolve_symbol(path: &str, symbol: &str) -> Result<u64, ResolveSymbolError> {
         Ok(sym.address() - section.address() + offset)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // built from testdata/uprobe_target.c
+    const TARGET: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/programs/testdata/libuprobe_target.so"
+    );
+
+    #[test]
+    fn test_resolve_symbol() {
+        assert_eq!(
+            resolve_symbol(TARGET, "uprobe_target_function").unwrap(),
+            0x2a0
+        );
+        assert_eq!(
+            resolve_symbol(TARGET, "uprobe_target_calls_plt").unwrap(),
+            0x2a5
+        );
+    }
+
+    #[test]
+    fn test_resolve_symbol_unknown() {
+        assert!(matches!(
+            resolve_symbol(TARGET, "missing"),
+            Err(ResolveSymbolError::Unknown(name)) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn test_resolve_symbol_plt_stub() {
+        assert!(matches!(
+            resolve_symbol(TARGET, "external_function"),
+            Err(ResolveSymbolError::PltStub(name)) if name == "external_function"
+        ));
+    }
+}
@@ -0,0 +1,975 @@
+//! User statically-defined tracepoints.
+use libc::{close, pid_t};
+use object::{Architecture, Object, ObjectKind, ObjectSection};
+use std::{collections::HashSet, fs, io, os::unix::io::RawFd, path::Path};
+use thiserror::Error;
+
+use crate::{
+    generated::bpf_prog_type::BPF_PROG_TYPE_KPROBE,
+    maps::MapError,
+    programs::{
+        define_link_wrapper, load_program,
+        perf_attach::{PerfLink, PerfLinkId},
+        probe::{probe_link, ProbeKind},
+        uprobe::resolve_target,
+        Link, ProgramData, ProgramError,
+    },
+    sys::{bpf_map_delete_elem, bpf_map_get_next_key, bpf_map_lookup_elem, bpf_map_update_elem},
+    Pod,
+};
+
+/// The name of the map holding the argument specs, indexed by spec id.
+pub(crate) const USDT_SPECS_MAP: &str = "__bpf_usdt_specs";
+/// The name of the map associating probe addresses with spec ids.
+pub(crate) const USDT_IP_TO_SPEC_ID_MAP: &str = "__bpf_usdt_ip_to_spec_id";
+
+const USDT_NOTE_TYPE: u32 = 3;
+const USDT_NOTE_NAME: &[u8] = b"stapsdt\0";
+const USDT_MAX_ARG_COUNT: usize = 12;
+
+/// A user statically-defined tracepoint program.
+///
+/// USDT probes are tracepoints compiled into user space binaries, for example
+/// with the `DTRACE_PROBE` macros of `sys/sdt.h`. Their location and the
+/// location of their arguments are described by `stapsdt` ELF notes.
+///
+/// [`Usdt`] programs are uprobes attached to the location of a probe. The
+/// location of the arguments of each probe is written to the
+/// `__bpf_usdt_specs` map, and the address of the probe to the
+/// `__bpf_usdt_ip_to_spec_id` map, using the same layout as libbpf's
+/// `usdt.bpf.h` so that its `bpf_usdt_arg()` helpers can be used to read the
+/// arguments. Both maps must be defined by the eBPF object.
+///
+/// Only x86_64 binaries are supported, and probes guarded by a semaphore
+/// can't be attached to.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.3.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::programs::Usdt;
+///
+/// let program: &mut Usdt = bpf.program_mut("query_start").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach("/usr/sbin/mysqld", "mysql", "query__start", None)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_KPROBE")]
+pub struct Usdt {
+    pub(crate) data: ProgramData<UsdtLink>,
+    // duplicates of the fds of the USDT maps, if the object defines them
+    pub(crate) maps: Option<(RawFd, RawFd)>,
+}
+
+impl Usdt {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        load_program(BPF_PROG_TYPE_KPROBE, &mut self.data)
+    }
+
+    /// Returns the USDT probes defined in the binary or library at `path`.
+    pub fn probes<P: AsRef<Path>>(path: P) -> Result<Vec<UsdtProbe>, UsdtError> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|io_error| UsdtError::FileError {
+            filename: path.display().to_string(),
+            io_error,
+        })?;
+        parse_probes(&data)
+    }
+
+    /// Attaches the program to the USDT probe `provider:name` of `target`.
+    ///
+    /// The `target` argument can be an absolute path to a binary or library, or
+    /// a library name (eg: `"libc"`). If the probe appears several times in
+    /// the target, the program is attached to all the locations.
+    ///
+    /// If `pid` is not `None`, the program executes only when the probe is
+    /// hit by the given `pid`. The `pid` is required for shared libraries and
+    /// position independent executables, as the addresses of their probes
+    /// depend on where they're loaded.
+    ///
+    /// Probes guarded by a semaphore aren't supported: the semaphore would
+    /// have to be incremented in the memory of every traced process for the
+    /// probe to fire. Attaching to a probe with a non-zero
+    /// [`UsdtProbe::semaphore`] returns [`UsdtError::SemaphoreNotSupported`],
+    /// and nothing gets attached.
+    ///
+    /// The returned value can be used to detach, see [Usdt::detach].
+    pub fn attach<T: AsRef<Path>>(
+        &mut self,
+        target: T,
+        provider: &str,
+        name: &str,
+        pid: Option<pid_t>,
+    ) -> Result<UsdtLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let (specs_fd, ip_to_spec_id_fd) = self.maps.ok_or(UsdtError::MissingMaps)?;
+
+        let path = resolve_target(target.as_ref(), pid)?;
+        let probes = Usdt::probes(&path)?
+            .into_iter()
+            .filter(|probe| probe.provider == provider && probe.name == name)
+            .collect::<Vec<_>>();
+        if probes.is_empty() {
+            return Err(UsdtError::ProbeNotFound {
+                provider: provider.to_owned(),
+                name: name.to_owned(),
+            }
+            .into());
+        }
+
+        let maps = match pid {
+            Some(pid) => Some(fs::read_to_string(format!("/proc/{pid}/maps")).map_err(
+                |io_error| UsdtError::FileError {
+                    filename: format!("/proc/{pid}/maps"),
+                    io_error,
+                },
+            )?),
+            None => None,
+        };
+
+        // compute everything before touching the maps
+        let mut sites = Vec::new();
+        for probe in &probes {
+            if probe.semaphore != 0 {
+                return Err(UsdtError::SemaphoreNotSupported {
+                    provider: provider.to_owned(),
+                    name: name.to_owned(),
+                }
+                .into());
+            }
+            let spec = UsdtSpec::new(&probe.arguments()?)?;
+            let ip = if probe.position_independent {
+                let maps = maps
+                    .as_deref()
+                    .ok_or_else(|| UsdtError::PidRequired { path: path.clone() })?;
+                ip_from_proc_maps(maps, &path, probe.offset).ok_or_else(|| {
+                    UsdtError::ProbeNotMapped {
+                        path: path.clone(),
+                        offset: probe.offset,
+                    }
+                })?
+            } else {
+                probe.address
+            };
+            sites.push((probe.offset, ip, spec));
+        }
+
+        let mut used_ids = used_spec_ids(ip_to_spec_id_fd)?;
+        let mut link = UsdtLinkInner {
+            perf_links: Vec::new(),
+            ip_to_spec_id_fd: dup_fd(ip_to_spec_id_fd)?,
+            ips: Vec::new(),
+        };
+        let mut spec_id = 0;
+        let result = sites.into_iter().try_for_each(|(offset, ip, spec)| {
+            while used_ids.contains(&spec_id) {
+                spec_id += 1;
+            }
+            used_ids.insert(spec_id);
+
+            bpf_map_update_elem(specs_fd, Some(&spec_id), &spec, 0).map_err(|(_, io_error)| {
+                MapError::SyscallError {
+                    call: "bpf_map_update_elem".to_owned(),
+                    io_error,
+                }
+            })?;
+            bpf_map_update_elem(link.ip_to_spec_id_fd, Some(&ip), &spec_id, 0).map_err(
+                |(_, io_error)| MapError::SyscallError {
+                    call: "bpf_map_update_elem".to_owned(),
+                    io_error,
+                },
+            )?;
+            link.ips.push(ip);
+
+            let perf_link = probe_link(prog_fd, ProbeKind::UProbe, &path, offset, pid)?;
+            link.perf_links.push(perf_link);
+            Ok::<_, ProgramError>(())
+        });
+        // the link isn't detached on drop, clean up what has been attached so
+        // far
+        if let Err(e) = result {
+            let _ = link.detach();
+            return Err(e);
+        }
+
+        self.data.links.insert(UsdtLink::new(link))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [Usdt::attach].
+    pub fn detach(&mut self, link_id: UsdtLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(&mut self, link_id: UsdtLinkId) -> Result<UsdtLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+impl Drop for Usdt {
+    fn drop(&mut self) {
+        if let Some((specs_fd, ip_to_spec_id_fd)) = self.maps.take() {
+            unsafe {
+                close(specs_fd);
+                close(ip_to_spec_id_fd);
+            }
+        }
+    }
+}
+
+// Duplicates the fds of the USDT maps so that they remain valid for as long as
+// the program exists, even if the maps are dropped.
+pub(crate) fn dup_maps(specs_fd: RawFd, ip_to_spec_id_fd: RawFd) -> Option<(RawFd, RawFd)> {
+    let specs_fd = dup_fd(specs_fd).ok()?;
+    match dup_fd(ip_to_spec_id_fd) {
+        Ok(ip_to_spec_id_fd) => Some((specs_fd, ip_to_spec_id_fd)),
+        Err(_) => {
+            unsafe { close(specs_fd) };
+            None
+        }
+    }
+}
+
+fn dup_fd(fd: RawFd) -> Result<RawFd, ProgramError> {
+    match unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) } {
+        fd if fd < 0 => Err(ProgramError::SyscallError {
+            call: "fcntl".to_owned(),
+            io_error: io::Error::last_os_error(),
+        }),
+        fd => Ok(fd),
+    }
+}
+
+// Returns the spec ids referenced by the ip to spec id map, which are used by
+// the probes already attached.
+fn used_spec_ids(ip_to_spec_id_fd: RawFd) -> Result<HashSet<u32>, MapError> {
+    let map_error = |(_, io_error)| MapError::SyscallError {
+        call: "bpf_map_get_next_key".to_owned(),
+        io_error,
+    };
+    let mut ids = HashSet::new();
+    let mut key = bpf_map_get_next_key::<u64>(ip_to_spec_id_fd, None).map_err(map_error)?;
+    while let Some(ip) = key {
+        if let Some(id) =
+            bpf_map_lookup_elem::<u64, u32>(ip_to_spec_id_fd, &ip, 0).map_err(|(_, io_error)| {
+                MapError::SyscallError {
+                    call: "bpf_map_lookup_elem".to_owned(),
+                    io_error,
+                }
+            })?
+        {
+            ids.insert(id);
+        }
+        key = bpf_map_get_next_key(ip_to_spec_id_fd, Some(&ip)).map_err(map_error)?;
+    }
+    Ok(ids)
+}
+
+// Translates the file offset of a probe to its address in the process, using
+// the content of /proc/<pid>/maps.
+fn ip_from_proc_maps(maps: &str, path: &str, offset: u64) -> Option<u64> {
+    maps.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let range = parts.next()?;
+        let _perms = parts.next()?;
+        let map_offset = u64::from_str_radix(parts.next()?, 16).ok()?;
+        let _dev = parts.next()?;
+        let _inode = parts.next()?;
+        if parts.next()? != path {
+            return None;
+        }
+        let (start, end) = range.split_once('-')?;
+        let start = u64::from_str_radix(start, 16).ok()?;
+        let end = u64::from_str_radix(end, 16).ok()?;
+        if offset >= map_offset && offset - map_offset < end - start {
+            Some(start + offset - map_offset)
+        } else {
+            None
+        }
+    })
+}
+
+/// A USDT probe defined in a binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsdtProbe {
+    /// The provider, the namespace of the probe
+    pub provider: String,
+    /// The name of the probe
+    pub name: String,
+    /// The virtual address of the probe
+    pub address: u64,
+    /// The file offset of the probe, where the uprobe is attached
+    pub offset: u64,
+    /// The virtual address of the semaphore guarding the probe, or 0
+    pub semaphore: u64,
+    /// The argument spec, eg `-4@%edi 8@8(%rax)`
+    pub args_spec: String,
+    arch: Architecture,
+    position_independent: bool,
+}
+
+impl UsdtProbe {
+    /// Returns the location of the arguments of the probe.
+    pub fn arguments(&self) -> Result<Vec<UsdtArgument>, UsdtError> {
+        if self.arch != Architecture::X86_64 {
+            return Err(UsdtError::UnsupportedArchitecture {
+                arch: format!("{:?}", self.arch),
+            });
+        }
+        self.args_spec
+            .split_whitespace()
+            .map(UsdtArgument::parse)
+            .collect()
+    }
+}
+
+/// The location of an argument of a [UsdtProbe].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsdtArgumentLocation {
+    /// The argument is a constant
+    Constant(i64),
+    /// The argument is held in a register
+    Register {
+        /// The register name, eg `rdi`
+        register: String,
+    },
+    /// The argument is in memory, at `offset` bytes from the address held in
+    /// a register
+    Memory {
+        /// The register name, eg `rbp`
+        register: String,
+        /// The offset from the address in the register
+        offset: i64,
+    },
+}
+
+/// An argument of a [UsdtProbe].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsdtArgument {
+    /// The size of the argument in bytes
+    pub size: u8,
+    /// Whether the argument is signed
+    pub signed: bool,
+    /// The location of the argument
+    pub location: UsdtArgumentLocation,
+}
+
+impl UsdtArgument {
+    // parses an x86_64 argument spec, eg `-4@%edi`, `8@$42` or `8@-8(%rbp)`
+    fn parse(spec: &str) -> Result<UsdtArgument, UsdtError> {
+        let invalid = || UsdtError::InvalidArgument {
+            spec: spec.to_owned(),
+        };
+
+        let (size, location) = spec.split_once('@').ok_or_else(invalid)?;
+        let (signed, size) = match size.strip_prefix('-') {
+            Some(size) => (true, size),
+            None => (false, size),
+        };
+        let size = match size.parse::<u8>() {
+            Ok(size @ (1 | 2 | 4 | 8)) => size,
+            _ => return Err(invalid()),
+        };
+
+        let location = if let Some(value) = location.strip_prefix('$') {
+            UsdtArgumentLocation::Constant(parse_int(value).ok_or_else(invalid)?)
+        } else if let Some(register) = location.strip_prefix('%') {
+            UsdtArgumentLocation::Register {
+                register: x86_64_register(register).ok_or_else(invalid)?.to_owned(),
+            }
+        } else if let Some((offset, register)) = location.split_once('(') {
+            let register = register
+                .strip_prefix('%')
+                .and_then(|r| r.strip_suffix(')'))
+                .and_then(x86_64_register)
+                .ok_or_else(invalid)?;
+            let offset = if offset.is_empty() {
+                0
+            } else {
+                parse_int(offset).ok_or_else(invalid)?
+            };
+            UsdtArgumentLocation::Memory {
+                register: register.to_owned(),
+                offset,
+            }
+        } else {
+            return Err(invalid());
+        };
+
+        Ok(UsdtArgument {
+            size,
+            signed,
+            location,
+        })
+    }
+}
+
+fn parse_int(s: &str) -> Option<i64> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let value = match s.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => s.parse::<i64>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+// x86_64 registers and their aliases, with their offset in struct pt_regs
+const X86_64_REGISTERS: &[(&[&str], &str, i16)] = &[
+    (&["rip"], "rip", 128),
+    (&["rax", "eax", "ax", "al"], "rax", 80),
+    (&["rbx", "ebx", "bx", "bl"], "rbx", 40),
+    (&["rcx", "ecx", "cx", "cl"], "rcx", 88),
+    (&["rdx", "edx", "dx", "dl"], "rdx", 96),
+    (&["rsi", "esi", "si", "sil"], "rsi", 104),
+    (&["rdi", "edi", "di", "dil"], "rdi", 112),
+    (&["rbp", "ebp", "bp", "bpl"], "rbp", 32),
+    (&["rsp", "esp", "sp", "spl"], "rsp", 152),
+    (&["r8", "r8d", "r8w", "r8b"], "r8", 72),
+    (&["r9", "r9d", "r9w", "r9b"], "r9", 64),
+    (&["r10", "r10d", "r10w", "r10b"], "r10", 56),
+    (&["r11", "r11d", "r11w", "r11b"], "r11", 48),
+    (&["r12", "r12d", "r12w", "r12b"], "r12", 24),
+    (&["r13", "r13d", "r13w", "r13b"], "r13", 16),
+    (&["r14", "r14d", "r14w", "r14b"], "r14", 8),
+    (&["r15", "r15d", "r15w", "r15b"], "r15", 0),
+];
+
+// returns the name of the full register `name` is part of
+fn x86_64_register(name: &str) -> Option<&'static str> {
+    X86_64_REGISTERS
+        .iter()
+        .find(|(aliases, _, _)| aliases.contains(&name))
+        .map(|(_, register, _)| *register)
+}
+
+fn x86_64_register_offset(register: &str) -> Option<i16> {
+    X86_64_REGISTERS
+        .iter()
+        .find(|(_, name, _)| *name == register)
+        .map(|(_, _, offset)| *offset)
+}
+
+// The layout of struct __bpf_usdt_arg_spec in libbpf's usdt.bpf.h.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct UsdtArgSpec {
+    val_off: u64,
+    arg_type: u32,
+    reg_off: i16,
+    arg_signed: bool,
+    arg_bitshift: i8,
+}
+
+const BPF_USDT_ARG_CONST: u32 = 0;
+const BPF_USDT_ARG_REG: u32 = 1;
+const BPF_USDT_ARG_REG_DEREF: u32 = 2;
+
+// The layout of struct __bpf_usdt_spec in libbpf's usdt.bpf.h.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct UsdtSpec {
+    args: [UsdtArgSpec; USDT_MAX_ARG_COUNT],
+    usdt_cookie: u64,
+    arg_cnt: i16,
+}
+
+unsafe impl Pod for UsdtSpec {}
+
+impl UsdtSpec {
+    fn new(args: &[UsdtArgument]) -> Result<UsdtSpec, UsdtError> {
+        if args.len() > USDT_MAX_ARG_COUNT {
+            return Err(UsdtError::TooManyArguments { count: args.len() });
+        }
+
+        let mut spec = UsdtSpec {
+            arg_cnt: args.len() as i16,
+            ..Default::default()
+        };
+        for (arg, arg_spec) in args.iter().zip(spec.args.iter_mut()) {
+            let register_offset = |register: &str| {
+                x86_64_register_offset(register).ok_or_else(|| UsdtError::InvalidArgument {
+                    spec: register.to_owned(),
+                })
+            };
+            *arg_spec = match &arg.location {
+                UsdtArgumentLocation::Constant(value) => UsdtArgSpec {
+                    val_off: *value as u64,
+                    arg_type: BPF_USDT_ARG_CONST,
+                    ..Default::default()
+                },
+                UsdtArgumentLocation::Register { register } => UsdtArgSpec {
+                    arg_type: BPF_USDT_ARG_REG,
+                    reg_off: register_offset(register)?,
+                    ..Default::default()
+                },
+                UsdtArgumentLocation::Memory { register, offset } => UsdtArgSpec {
+                    val_off: *offset as u64,
+                    arg_type: BPF_USDT_ARG_REG_DEREF,
+                    reg_off: register_offset(register)?,
+                    ..Default::default()
+                },
+            };
+            arg_spec.arg_signed = arg.signed;
+            // the value is shifted left then right to truncate and extend it
+            arg_spec.arg_bitshift = (64 - arg.size as i32 * 8) as i8;
+        }
+
+        Ok(spec)
+    }
+}
+
+// Parses the stapsdt notes of the ELF file `data`.
+fn parse_probes(data: &[u8]) -> Result<Vec<UsdtProbe>, UsdtError> {
+    let obj = object::read::File::parse(data)?;
+    let section = match obj.section_by_name(".note.stapsdt") {
+        Some(section) => section,
+        None => return Ok(Vec::new()),
+    };
+    let notes = section.data()?;
+    // the address of .stapsdt.base at link time, used to adjust the addresses
+    // of binaries that have been prelinked since
+    let base = obj.section_by_name(".stapsdt.base").map(|s| s.address());
+    let position_independent = obj.kind() == ObjectKind::Dynamic;
+    let address_size = if obj.is_64() { 8 } else { 4 };
+    let little_endian = obj.is_little_endian();
+
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes = notes.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+    let read_address = |offset: usize| -> Option<u64> {
+        let bytes = notes.get(offset..offset + address_size)?;
+        let mut buf = [0u8; 8];
+        if little_endian {
+            buf[..address_size].copy_from_slice(bytes);
+            Some(u64::from_le_bytes(buf))
+        } else {
+            buf[8 - address_size..].copy_from_slice(bytes);
+            Some(u64::from_be_bytes(buf))
+        }
+    };
+    let align4 = |n: usize| (n + 3) & !3;
+
+    let mut probes = Vec::new();
+    let mut offset = 0;
+    while offset < notes.len() {
+        let invalid = || UsdtError::InvalidNote { offset };
+        let name_size = read_u32(offset).ok_or_else(invalid)? as usize;
+        let desc_size = read_u32(offset + 4).ok_or_else(invalid)? as usize;
+        let note_type = read_u32(offset + 8).ok_or_else(invalid)?;
+        let name_start = offset + 12;
+        let desc_start = name_start + align4(name_size);
+        let desc = notes
+            .get(desc_start..desc_start + desc_size)
+            .ok_or_else(invalid)?;
+        let next = desc_start + align4(desc_size);
+
+        if note_type != USDT_NOTE_TYPE
+            || notes.get(name_start..name_start + name_size) != Some(USDT_NOTE_NAME)
+        {
+            offset = next;
+            continue;
+        }
+
+        let mut address = read_address(desc_start).ok_or_else(invalid)?;
+        let note_base = read_address(desc_start + address_size).ok_or_else(invalid)?;
+        let mut semaphore = read_address(desc_start + 2 * address_size).ok_or_else(invalid)?;
+        if let Some(base) = base {
+            if note_base != 0 {
+                address = address.wrapping_add(base).wrapping_sub(note_base);
+                if semaphore != 0 {
+                    semaphore = semaphore.wrapping_add(base).wrapping_sub(note_base);
+                }
+            }
+        }
+
+        let mut strings = desc
+            .get(3 * address_size..)
+            .ok_or_else(invalid)?
+            .split(|b| *b == 0)
+            .map(|s| String::from_utf8_lossy(s).into_owned());
+        let provider = strings.next().ok_or_else(invalid)?;
+        let name = strings.next().ok_or_else(invalid)?;
+        let args_spec = strings.next().unwrap_or_default();
+
+        probes.push(UsdtProbe {
+            provider,
+            name,
+            address,
+            offset: file_offset(&obj, address).ok_or_else(invalid)?,
+            semaphore,
+            args_spec,
+            arch: obj.architecture(),
+            position_independent,
+        });
+
+        offset = next;
+    }
+
+    Ok(probes)
+}
+
+// translates a virtual address to a file offset
+fn file_offset(obj: &object::read::File, address: u64) -> Option<u64> {
+    if !matches!(obj.kind(), ObjectKind::Dynamic | ObjectKind::Executable) {
+        return Some(address);
+    }
+    obj.sections().find_map(|section| {
+        // sections that aren't loaded have no address
+        if section.address() == 0 {
+            return None;
+        }
+        let (offset, size) = section.file_range()?;
+        if address >= section.address() && address - section.address() < size {
+            Some(address - section.address() + offset)
+        } else {
+            None
+        }
+    })
+}
+
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub(crate) struct UsdtLinkIdInner(Vec<PerfLinkId>);
+
+#[derive(Debug)]
+pub(crate) struct UsdtLinkInner {
+    perf_links: Vec<PerfLink>,
+    ip_to_spec_id_fd: RawFd,
+    ips: Vec<u64>,
+}
+
+impl Link for UsdtLinkInner {
+    type Id = UsdtLinkIdInner;
+
+    fn id(&self) -> Self::Id {
+        UsdtLinkIdInner(self.perf_links.iter().map(|link| link.id()).collect())
+    }
+
+    fn detach(self) -> Result<(), ProgramError> {
+        let UsdtLinkInner {
+            perf_links,
+            ip_to_spec_id_fd,
+            ips,
+        } = self;
+        for link in perf_links {
+            let _ = link.detach();
+        }
+        for ip in ips {
+            let _ = bpf_map_delete_elem(ip_to_spec_id_fd, &ip);
+        }
+        unsafe { close(ip_to_spec_id_fd) };
+        Ok(())
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [Usdt] programs.
+    UsdtLink,
+    /// The type returned by [Usdt::attach]. Can be passed to [Usdt::detach].
+    UsdtLinkId,
+    UsdtLinkInner,
+    UsdtLinkIdInner
+);
+
+/// The type returned when working with a [`Usdt`] program fails.
+#[derive(Debug, Error)]
+pub enum UsdtError {
+    /// There was an error accessing `filename`.
+    #[error("`{filename}`")]
+    FileError {
+        /// The file name
+        filename: String,
+        /// The [`io::Error`] returned from the file operation
+        #[source]
+        io_error: io::Error,
+    },
+
+    /// The target isn't a valid ELF file.
+    #[error("error parsing ELF")]
+    ElfError(#[from] object::Error),
+
+    /// A `stapsdt` note is malformed.
+    #[error("invalid stapsdt note at offset {offset}")]
+    InvalidNote {
+        /// The offset of the note in the notes section
+        offset: usize,
+    },
+
+    /// The probe isn't defined by the target.
+    #[error("USDT probe `{provider}:{name}` not found")]
+    ProbeNotFound {
+        /// The provider
+        provider: String,
+        /// The probe name
+        name: String,
+    },
+
+    /// The argument specs of the target's architecture can't be parsed.
+    #[error("USDT arguments are not supported on {arch}")]
+    UnsupportedArchitecture {
+        /// The architecture of the target
+        arch: String,
+    },
+
+    /// An argument spec is invalid or not supported.
+    #[error("invalid or unsupported USDT argument `{spec}`")]
+    InvalidArgument {
+        /// The argument spec
+        spec: String,
+    },
+
+    /// The probe has more arguments than can be described in the specs map.
+    #[error("USDT probe has {count} arguments, at most 12 are supported")]
+    TooManyArguments {
+        /// The number of arguments
+        count: usize,
+    },
+
+    /// The probe is guarded by a semaphore.
+    #[error("USDT probe `{provider}:{name}` uses a semaphore, which is not supported")]
+    SemaphoreNotSupported {
+        /// The provider
+        provider: String,
+        /// The probe name
+        name: String,
+    },
+
+    /// The address of the probe depends on where the target is loaded.
+    #[error("`{path}` is position independent, a pid is required to attach to its USDT probes")]
+    PidRequired {
+        /// The path of the target
+        path: String,
+    },
+
+    /// The target isn't mapped in the process at the probe location.
+    #[error("`{path}` is not mapped at offset {offset:#x} in the process")]
+    ProbeNotMapped {
+        /// The path of the target
+        path: String,
+        /// The file offset of the probe
+        offset: u64,
+    },
+
+    /// The eBPF object doesn't define the USDT maps.
+    #[error("the `__bpf_usdt_specs` and `__bpf_usdt_ip_to_spec_id` maps are required")]
+    MissingMaps,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::*;
+
+    // built from testdata/usdt_target.c
+    const TARGET: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/programs/testdata/libusdt_target.so"
+    );
+
+    fn reg(size: u8, signed: bool, register: &str) -> UsdtArgument {
+        UsdtArgument {
+            size,
+            signed,
+            location: UsdtArgumentLocation::Register {
+                register: register.to_owned(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_probes() {
+        let probes = Usdt::probes(TARGET).unwrap();
+        let names = probes
+            .iter()
+            .map(|p| (p.provider.as_str(), p.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [("aya", "args"), ("aya", "deref"), ("aya", "noargs")]
+        );
+
+        let args = &probes[0];
+        assert_eq!(args.address, 0x25b);
+        assert_eq!(args.offset, 0x25b);
+        assert_eq!(args.semaphore, 0);
+        assert!(args.position_independent);
+        assert_eq!(args.args_spec, "-4@%edi 8@%rsi -4@$42");
+        assert_eq!(
+            args.arguments().unwrap(),
+            [
+                reg(4, true, "rdi"),
+                reg(8, false, "rsi"),
+                UsdtArgument {
+                    size: 4,
+                    signed: true,
+                    location: UsdtArgumentLocation::Constant(42),
+                },
+            ]
+        );
+
+        let deref = &probes[1];
+        assert_eq!(deref.offset, 0x261);
+        assert_eq!(
+            deref.arguments().unwrap(),
+            [
+                UsdtArgument {
+                    size: 8,
+                    signed: false,
+                    location: UsdtArgumentLocation::Memory {
+                        register: "rdi".to_owned(),
+                        offset: 8,
+                    },
+                },
+                UsdtArgument {
+                    size: 2,
+                    signed: false,
+                    location: UsdtArgumentLocation::Memory {
+                        register: "rbp".to_owned(),
+                        offset: -4,
+                    },
+                },
+            ]
+        );
+
+        assert!(probes[2].arguments().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_probes_none() {
+        // the uprobe target has no stapsdt notes
+        let path = TARGET.replace("libusdt_target.so", "libuprobe_target.so");
+        assert!(Usdt::probes(path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        for spec in [
+            "8@foo(%rip)",
+            "3@%rax",
+            "4@%xmm0",
+            "4@(%rax,%rbx,2)",
+            "4@$abc",
+            "%rax",
+        ] {
+            assert!(
+                matches!(
+                    UsdtArgument::parse(spec),
+                    Err(UsdtError::InvalidArgument { spec: s }) if s == spec
+                ),
+                "{spec}"
+            );
+        }
+        assert_eq!(
+            UsdtArgument::parse("-8@-0x10(%r12)").unwrap(),
+            UsdtArgument {
+                size: 8,
+                signed: true,
+                location: UsdtArgumentLocation::Memory {
+                    register: "r12".to_owned(),
+                    offset: -16,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_spec() {
+        // must match the layout of struct __bpf_usdt_spec
+        assert_eq!(mem::size_of::<UsdtArgSpec>(), 16);
+        assert_eq!(mem::size_of::<UsdtSpec>(), 208);
+
+        let args = [
+            reg(4, true, "rdi"),
+            UsdtArgument {
+                size: 8,
+                signed: false,
+                location: UsdtArgumentLocation::Constant(42),
+            },
+            UsdtArgument {
+                size: 2,
+                signed: false,
+                location: UsdtArgumentLocation::Memory {
+                    register: "rbp".to_owned(),
+                    offset: -4,
+                },
+            },
+        ];
+        let spec = UsdtSpec::new(&args).unwrap();
+        assert_eq!(spec.arg_cnt, 3);
+        assert_eq!(
+            spec.args[0],
+            UsdtArgSpec {
+                val_off: 0,
+                arg_type: BPF_USDT_ARG_REG,
+                reg_off: 112,
+                arg_signed: true,
+                arg_bitshift: 32,
+            }
+        );
+        assert_eq!(
+            spec.args[1],
+            UsdtArgSpec {
+                val_off: 42,
+                arg_type: BPF_USDT_ARG_CONST,
+                reg_off: 0,
+                arg_signed: false,
+                arg_bitshift: 0,
+            }
+        );
+        assert_eq!(
+            spec.args[2],
+            UsdtArgSpec {
+                val_off: -4i64 as u64,
+                arg_type: BPF_USDT_ARG_REG_DEREF,
+                reg_off: 32,
+                arg_signed: false,
+                arg_bitshift: 48,
+            }
+        );
+        assert_eq!(spec.args[3], UsdtArgSpec::default());
+
+        assert!(matches!(
+            UsdtSpec::new(&vec![reg(8, false, "rax"); 13]),
+            Err(UsdtError::TooManyArguments { count: 13 })
+        ));
+    }
+
+    #[test]
+    fn test_ip_from_proc_maps() {
+        let maps = "\
+55d0b5a00000-55d0b5a01000 r--p 00000000 fd:01 1234 /usr/bin/foo
+7f1c2a000000-7f1c2a001000 r--p 00000000 fd:01 5678 /usr/lib/libfoo.so
+7f1c2a001000-7f1c2a003000 r-xp 00001000 fd:01 5678 /usr/lib/libfoo.so
+7ffd4e5f0000-7ffd4e611000 rw-p 00000000 00:00 0 [stack]
+";
+        assert_eq!(
+            ip_from_proc_maps(maps, "/usr/lib/libfoo.so", 0x1234),
+            Some(0x7f1c2a001234)
+        );
+        assert_eq!(
+            ip_from_proc_maps(maps, "/usr/lib/libfoo.so", 0x25b),
+            Some(0x7f1c2a00025b)
+        );
+        assert_eq!(ip_from_proc_maps(maps, "/usr/lib/libfoo.so", 0x3000), None);
+        assert_eq!(ip_from_proc_maps(maps, "/usr/lib/libbar.so", 0x25b), None);
+    }
+}
@@ -1,7 +1,7 @@
 //! eXpress Data Path (XDP) programs.
 use bitflags;
 use libc::if_nametoindex;
-use std::{convert::TryFrom, ffi::CString, hash::Hash, io, mem, os::unix::io::RawFd};
+use std::{convert::TryFrom, ffi::CString, hash::Hash, io, os::unix::io::RawFd};
 use thiserror::Error;
 
 use crate::{
@@ -13,11 +13,12 @@ use crate::{
         XDP_FLAGS_UPDATE_IF_NOEXIST,
     },
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, LinkError, ProgramData, ProgramError,
+        define_link_wrapper, impl_updatable_link, links::UpdatableLink, load_program, FdLink, Link,
+        LinkError, ProgramData, ProgramError,
     },
     sys::{
-        bpf_link_create, bpf_link_get_info_by_fd, bpf_link_update, kernel_version,
-        netlink_set_xdp_fd,
+        bpf_link_create, bpf_link_get_info_by_fd, bpf_link_update, bpf_prog_get_fd_by_id,
+        kernel_version, netlink_set_xdp_fd, netlink_xdp_attached,
     },
 };
 
@@ -31,10 +32,17 @@ pub enum XdpError {
         #[source]
         io_error: io::Error,
     },
+
+    /// the program attached to the interface is not the one that was expected
+    #[error("the XDP program attached to the interface doesn't have id {expected_id}")]
+    UnexpectedProgram {
+        /// the id of the program that was expected to be attached
+        expected_id: u32,
+    },
 }
 
 bitflags! {
-    /// Flags passed to [`Xdp::attach()`].
+    /// Flags passed to [`Xdp::attach()`] and [`Xdp::attach_with_flags()`].
     #[derive(Default)]
     pub struct XdpFlags: u32 {
         /// Skb mode.
@@ -98,14 +106,38 @@ impl Xdp {
     /// [`XdpError::NetlinkError`] is returned for older
     /// kernels.
     pub fn attach(&mut self, interface: &str, flags: XdpFlags) -> Result<XdpLinkId, ProgramError> {
-        let prog_fd = self.data.fd_or_err()?;
         let c_interface = CString::new(interface).unwrap();
-        let if_index = unsafe { if_nametoindex(c_interface.as_ptr()) } as RawFd;
+        let if_index = unsafe { if_nametoindex(c_interface.as_ptr()) };
         if if_index == 0 {
             return Err(ProgramError::UnknownInterface {
                 name: interface.to_string(),
             });
         }
+        self.attach_with_flags(if_index, flags)
+    }
+
+    /// Attaches the program to the interface with the given `if_index`.
+    ///
+    /// The attach mode is selected with [`XdpFlags::SKB_MODE`],
+    /// [`XdpFlags::DRV_MODE`] or [`XdpFlags::HW_MODE`]. When no mode is given
+    /// the kernel picks native mode if the driver supports it, and generic
+    /// mode otherwise.
+    ///
+    /// The returned value can be used to detach, see [Xdp::detach].
+    ///
+    /// # Errors
+    ///
+    /// When attaching fails, [`ProgramError::SyscallError`] is returned for
+    /// kernels `>= 5.9.0`, and instead
+    /// [`XdpError::NetlinkError`] is returned for older
+    /// kernels.
+    pub fn attach_with_flags(
+        &mut self,
+        if_index: u32,
+        flags: XdpFlags,
+    ) -> Result<XdpLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let if_index = if_index as RawFd;
 
         let k_ver = kernel_version().unwrap();
         if k_ver >= (5, 9, 0) {
@@ -132,6 +164,110 @@ impl Xdp {
         }
     }
 
+    /// Attaches the program to the interface with the given `if_index`,
+    /// trying hardware offload first, then driver mode and finally generic
+    /// (skb) mode.
+    ///
+    /// A mode is skipped when the kernel reports that the device or driver
+    /// doesn't support it: `EOPNOTSUPP` or `EINVAL` for hardware mode, and
+    /// `EOPNOTSUPP` for driver mode. Any other error is returned immediately.
+    ///
+    /// On success, returns the link id together with the mode the program
+    /// was attached in.
+    pub fn attach_with_fallback(
+        &mut self,
+        if_index: u32,
+    ) -> Result<(XdpLinkId, XdpFlags), ProgramError> {
+        let modes: [(XdpFlags, &[i32]); 3] = [
+            (XdpFlags::HW_MODE, &[libc::EOPNOTSUPP, libc::EINVAL]),
+            (XdpFlags::DRV_MODE, &[libc::EOPNOTSUPP]),
+            (XdpFlags::SKB_MODE, &[]),
+        ];
+
+        let mut last_error = None;
+        for (mode, unsupported) in modes {
+            match self.attach_with_flags(if_index, mode) {
+                Ok(link_id) => return Ok((link_id, mode)),
+                Err(e) => {
+                    let errno = match &e {
+                        ProgramError::SyscallError { io_error, .. }
+                        | ProgramError::XdpError(XdpError::NetlinkError { io_error }) => {
+                            io_error.raw_os_error()
+                        }
+                        _ => None,
+                    };
+                    match errno {
+                        Some(errno) if unsupported.contains(&errno) => last_error = Some(e),
+                        _ => return Err(e),
+                    }
+                }
+            }
+        }
+
+        // skb mode never falls back, so the loop always returns
+        Err(last_error.unwrap())
+    }
+
+    /// Atomically replaces the program with id `old_prog_id` attached to the
+    /// interface with the given `if_index`.
+    ///
+    /// The new program is only attached if the program currently attached to
+    /// the interface is `old_prog_id`, which makes it safe to upgrade a
+    /// program while other processes might be changing it concurrently.
+    /// `flags` select the attach mode, which must be the same mode
+    /// `old_prog_id` was attached in.
+    ///
+    /// The returned value can be used to detach, see [Xdp::detach].
+    ///
+    /// # Errors
+    ///
+    /// If a different program is attached to the interface,
+    /// [`XdpError::UnexpectedProgram`] is returned.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.7.
+    pub fn replace(
+        &mut self,
+        if_index: u32,
+        old_prog_id: u32,
+        flags: XdpFlags,
+    ) -> Result<XdpLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let if_index = if_index as RawFd;
+        let old_prog_fd =
+            bpf_prog_get_fd_by_id(old_prog_id).map_err(|io_error| ProgramError::SyscallError {
+                call: "bpf_prog_get_fd_by_id".to_owned(),
+                io_error,
+            })?;
+
+        let flags = flags - XdpFlags::REPLACE;
+        let ret = unsafe {
+            netlink_set_xdp_fd(
+                if_index,
+                prog_fd,
+                Some(old_prog_fd),
+                (flags | XdpFlags::REPLACE).bits(),
+            )
+        };
+        unsafe { libc::close(old_prog_fd) };
+        ret.map_err(|io_error| match io_error.raw_os_error() {
+            // the kernel returns EEXIST if the attached program isn't the expected one
+            Some(libc::EEXIST) => XdpError::UnexpectedProgram {
+                expected_id: old_prog_id,
+            },
+            _ => XdpError::NetlinkError { io_error },
+        })?;
+
+        self.data
+            .links
+            .insert(XdpLink::new(XdpLinkInner::NlLink(NlLink {
+                if_index,
+                prog_fd,
+                flags,
+            })))
+    }
+
     /// Detaches the program.
     ///
     /// See [Xdp::attach].
@@ -139,6 +275,37 @@ impl Xdp {
         self.data.links.remove(link_id)
     }
 
+    /// Detaches this program from the interface with the given `if_index`.
+    ///
+    /// Unlike [Xdp::detach], this doesn't need the link returned when
+    /// attaching, so it can be used to clean up after a process that exited
+    /// without detaching. When `force` is true, whatever XDP program is
+    /// attached to the interface is detached, including programs attached by
+    /// other processes.
+    ///
+    /// Returns whether a program was detached. If programs are attached in
+    /// several modes and detaching one of them fails, the others are still
+    /// detached and the first error is returned. Programs attached with a
+    /// `bpf_link` (kernels `>= 5.9.0`) can't be detached this way, see
+    /// [Xdp::detach].
+    pub fn detach_interface(&mut self, if_index: u32, force: bool) -> Result<bool, ProgramError> {
+        let prog_id = if force {
+            None
+        } else {
+            Some(self.data.prog_id()?)
+        };
+        let mut errors = Vec::new();
+        let detached = detach_interface_programs(
+            if_index,
+            |id| prog_id.is_none() || prog_id == Some(id),
+            &mut errors,
+        );
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(detached),
+        }
+    }
+
     /// Takes ownership of the link referenced by the provided link_id.
     ///
     /// The link will be detached on `Drop` and the caller is now responsible
@@ -162,7 +329,7 @@ impl Xdp {
                     }
                 })?;
                 // dispose of link and avoid detach on drop
-                mem::forget(link);
+                link.forget();
                 self.data
                     .links
                     .insert(XdpLink::new(XdpLinkInner::FdLink(FdLink::new(link_fd))))
@@ -177,7 +344,7 @@ impl Xdp {
                         .map_err(|io_error| XdpError::NetlinkError { io_error })?;
                 }
                 // dispose of link and avoid detach on drop
-                mem::forget(link);
+                link.forget();
                 self.data
                     .links
                     .insert(XdpLink::new(XdpLinkInner::NlLink(NlLink {
@@ -190,6 +357,36 @@ impl Xdp {
     }
 }
 
+// Detaches the XDP programs attached to the interface with the given `if_index`
+// whose id satisfies `owned`. Failures are pushed to `errors` and don't stop the
+// other programs from being detached. Returns whether any program was detached.
+pub(crate) fn detach_interface_programs(
+    if_index: u32,
+    owned: impl Fn(u32) -> bool,
+    errors: &mut Vec<ProgramError>,
+) -> bool {
+    let attached = match unsafe { netlink_xdp_attached(if_index as i32) } {
+        Ok(attached) => attached,
+        Err(io_error) => {
+            errors.push(XdpError::NetlinkError { io_error }.into());
+            return false;
+        }
+    };
+
+    let mut detached = false;
+    for (prog_id, mode) in attached {
+        if !owned(prog_id) {
+            continue;
+        }
+        match unsafe { netlink_set_xdp_fd(if_index as i32, -1, None, mode) } {
+            Ok(()) => detached = true,
+            Err(io_error) => errors.push(XdpError::NetlinkError { io_error }.into()),
+        }
+    }
+
+    detached
+}
+
 #[derive(Debug)]
 pub(crate) struct NlLink {
     if_index: i32,
@@ -216,6 +413,20 @@ impl Link for NlLink {
     }
 }
 
+impl UpdatableLink for NlLink {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        let flags = self.flags | XdpFlags::REPLACE;
+        unsafe { netlink_set_xdp_fd(self.if_index, new_prog_fd, Some(old_prog_fd), flags.bits()) }
+            .map_err(|io_error| XdpError::NetlinkError { io_error })?;
+        self.prog_fd = new_prog_fd;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Hash, Eq, PartialEq)]
 pub(crate) enum XdpLinkIdInner {
     FdLinkId(<FdLink as Link>::Id),
@@ -246,6 +457,19 @@ impl Link for XdpLinkInner {
     }
 }
 
+impl UpdatableLink for XdpLinkInner {
+    fn update_program(
+        &mut self,
+        new_prog_fd: RawFd,
+        old_prog_fd: RawFd,
+    ) -> Result<(), ProgramError> {
+        match self {
+            XdpLinkInner::FdLink(link) => link.update_program(new_prog_fd, old_prog_fd),
+            XdpLinkInner::NlLink(link) => link.update_program(new_prog_fd, old_prog_fd),
+        }
+    }
+}
+
 impl TryFrom<XdpLink> for FdLink {
     type Error = LinkError;
 
@@ -284,3 +508,163 @@ define_link_wrapper!(
     XdpLinkInner,
     XdpLinkIdInner
 );
+
+impl_updatable_link!(XdpLink);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        obj::ProgramSection,
+        programs::tests::fake_program_data,
+        sys::{override_syscall, Syscall},
+    };
+
+    fn fake_program() -> Xdp {
+        let mut data = fake_program_data(false);
+        data.obj.section = ProgramSection::Xdp {
+            name: "test".to_owned(),
+        };
+        Xdp { data }
+    }
+
+    fn link_create_flags(attr: &crate::generated::bpf_attr) -> XdpFlags {
+        XdpFlags::from_bits_truncate(unsafe { attr.link_create.flags })
+    }
+
+    #[test]
+    fn test_attach_with_flags() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                assert_eq!(link_create_flags(attr), XdpFlags::DRV_MODE);
+                assert_eq!(unsafe { attr.link_create.__bindgen_anon_1.target_fd }, 7);
+                Ok(-1)
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program();
+        prog.data.fd = Some(1);
+        assert!(prog.attach_with_flags(7, XdpFlags::DRV_MODE).is_ok());
+    }
+
+    #[test]
+    fn test_attach_with_fallback_to_skb() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let flags = link_create_flags(attr);
+                if flags == XdpFlags::HW_MODE {
+                    Err((-1, io::Error::from_raw_os_error(libc::EINVAL)))
+                } else if flags == XdpFlags::DRV_MODE {
+                    Err((-1, io::Error::from_raw_os_error(libc::EOPNOTSUPP)))
+                } else {
+                    assert_eq!(flags, XdpFlags::SKB_MODE);
+                    Ok(-1)
+                }
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program();
+        prog.data.fd = Some(1);
+        let (_, mode) = prog.attach_with_fallback(7).unwrap();
+        assert_eq!(mode, XdpFlags::SKB_MODE);
+    }
+
+    #[test]
+    fn test_attach_with_fallback_to_drv() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let flags = link_create_flags(attr);
+                if flags == XdpFlags::HW_MODE {
+                    Err((-1, io::Error::from_raw_os_error(libc::EOPNOTSUPP)))
+                } else {
+                    assert_eq!(flags, XdpFlags::DRV_MODE);
+                    Ok(-1)
+                }
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program();
+        prog.data.fd = Some(1);
+        let (_, mode) = prog.attach_with_fallback(7).unwrap();
+        assert_eq!(mode, XdpFlags::DRV_MODE);
+    }
+
+    #[test]
+    fn test_attach_with_fallback_stops_on_other_errors() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let flags = link_create_flags(attr);
+                if flags == XdpFlags::HW_MODE {
+                    Err((-1, io::Error::from_raw_os_error(libc::EOPNOTSUPP)))
+                } else {
+                    // an unsupported driver mode is EOPNOTSUPP, anything
+                    // else must not fall back to skb mode
+                    assert_eq!(flags, XdpFlags::DRV_MODE);
+                    Err((-1, io::Error::from_raw_os_error(libc::EBUSY)))
+                }
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program();
+        prog.data.fd = Some(1);
+        match prog.attach_with_fallback(7) {
+            Err(ProgramError::SyscallError { io_error, .. }) => {
+                assert_eq!(io_error.raw_os_error(), Some(libc::EBUSY))
+            }
+            _ => panic!("expected EBUSY"),
+        }
+    }
+
+    #[test]
+    fn test_replace_gets_old_program() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_GET_FD_BY_ID,
+                attr,
+            } => {
+                assert_eq!(
+                    unsafe { attr.__bindgen_anon_6.__bindgen_anon_1.prog_id },
+                    1234
+                );
+                Err((-1, io::Error::from_raw_os_error(libc::ENOENT)))
+            }
+            _ => Ok(0),
+        });
+
+        let mut prog = fake_program();
+        prog.data.fd = Some(1);
+        match prog.replace(7, 1234, XdpFlags::DRV_MODE) {
+            Err(ProgramError::SyscallError { call, io_error }) => {
+                assert_eq!(call, "bpf_prog_get_fd_by_id");
+                assert_eq!(io_error.raw_os_error(), Some(libc::ENOENT));
+            }
+            _ => panic!("expected bpf_prog_get_fd_by_id to fail"),
+        }
+    }
+
+    #[test]
+    fn test_replace_not_loaded() {
+        let mut prog = fake_program();
+        assert!(matches!(
+            prog.replace(7, 1234, XdpFlags::default()),
+            Err(ProgramError::NotLoaded)
+        ));
+    }
+}
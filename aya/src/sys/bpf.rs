@@ -4,7 +4,7 @@ use std::{
     io,
     mem::{self, MaybeUninit},
     os::unix::io::RawFd,
-    slice,
+    ptr, slice,
 };
 
 use libc::{c_char, c_long, close, ENOENT, ENOSPC};
@@ -28,7 +28,12 @@ use crate::{
     Btf, Pod, BPF_OBJ_NAME_LEN,
 };
 
-pub(crate) fn bpf_create_map(name: &CStr, def: &obj::Map, btf_fd: Option<RawFd>) -> SysResult {
+pub(crate) fn bpf_create_map(
+    name: &CStr,
+    def: &obj::Map,
+    btf_fd: Option<RawFd>,
+    inner_map_fd: Option<RawFd>,
+) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
 
     let u = unsafe { &mut attr.__bindgen_anon_1 };
@@ -37,6 +42,10 @@ pub(crate) fn bpf_create_map(name: &CStr, def: &obj::Map, btf_fd: Option<RawFd>)
     u.value_size = def.value_size();
     u.max_entries = def.max_entries();
     u.map_flags = def.map_flags();
+    if let Some(fd) = inner_map_fd {
+        // the template of the inner maps of a map of maps
+        u.inner_map_fd = fd as u32;
+    }
 
     if let obj::Map::Btf(m) = def {
         u.btf_key_type_id = m.def.btf_key_type_id;
@@ -49,15 +58,30 @@ pub(crate) fn bpf_create_map(name: &CStr, def: &obj::Map, btf_fd: Option<RawFd>)
     // older kernels for compatibility
     let k_ver = kernel_version().unwrap();
     if k_ver >= (4, 15, 0) {
-        // u.map_name is 16 bytes max and must be NULL terminated
-        let name_len = cmp::min(name.to_bytes().len(), BPF_OBJ_NAME_LEN - 1);
-        u.map_name[..name_len]
-            .copy_from_slice(unsafe { slice::from_raw_parts(name.as_ptr(), name_len) });
+        u.map_name = bpf_obj_name(name);
     }
 
     sys_bpf(bpf_cmd::BPF_MAP_CREATE, &attr)
 }
 
+// Map and program names are 16 bytes max, must be NULL terminated and can only
+// contain alphanumeric characters, '_' and '.', otherwise the kernel fails with
+// EINVAL. Names are truncated and invalid characters replaced with '_'.
+fn bpf_obj_name(name: &CStr) -> [c_char; BPF_OBJ_NAME_LEN] {
+    let mut obj_name = [0; BPF_OBJ_NAME_LEN];
+    for (dst, c) in obj_name[..BPF_OBJ_NAME_LEN - 1]
+        .iter_mut()
+        .zip(name.to_bytes())
+    {
+        *dst = if c.is_ascii_alphanumeric() || *c == b'_' || *c == b'.' {
+            *c
+        } else {
+            b'_'
+        } as c_char;
+    }
+    obj_name
+}
+
 pub(crate) fn bpf_pin_object(fd: RawFd, path: &CStr) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     let u = unsafe { &mut attr.__bindgen_anon_4 };
@@ -88,6 +112,8 @@ pub(crate) struct BpfLoadProgramAttrs<'a> {
     pub(crate) func_info: FuncSecInfo,
     pub(crate) line_info_rec_size: usize,
     pub(crate) line_info: LineSecInfo,
+    pub(crate) fd_array: Option<&'a [RawFd]>,
+    pub(crate) flags: u32,
 }
 
 pub(crate) fn bpf_load_program(
@@ -100,13 +126,7 @@ pub(crate) fn bpf_load_program(
     let u = unsafe { &mut attr.__bindgen_anon_3 };
 
     if let Some(prog_name) = &aya_attr.name {
-        let mut name: [c_char; 16] = [0; 16];
-        let name_bytes = prog_name.to_bytes();
-        let len = min(name.len(), name_bytes.len());
-        name[..len].copy_from_slice(unsafe {
-            slice::from_raw_parts(name_bytes.as_ptr() as *const c_char, len)
-        });
-        u.prog_name = name;
+        u.prog_name = bpf_obj_name(prog_name);
     }
 
     u.prog_type = aya_attr.ty as u32;
@@ -117,6 +137,7 @@ pub(crate) fn bpf_load_program(
     u.insn_cnt = aya_attr.insns.len() as u32;
     u.license = aya_attr.license.as_ptr() as u64;
     u.kern_version = aya_attr.kernel_version;
+    u.prog_flags = aya_attr.flags;
 
     // these must be allocated here to ensure the slice outlives the pointer
     // so .as_ptr below won't point to garbage
@@ -153,6 +174,10 @@ pub(crate) fn bpf_load_program(
         u.attach_btf_id = v;
     }
 
+    if let Some(fd_array) = aya_attr.fd_array {
+        u.fd_array = fd_array.as_ptr() as u64;
+    }
+
     sys_bpf(bpf_cmd::BPF_PROG_LOAD, &attr)
 }
 
@@ -188,6 +213,27 @@ pub(crate) fn bpf_map_lookup_elem<K: Pod, V: Pod>(
     lookup(fd, Some(key), flags, bpf_cmd::BPF_MAP_LOOKUP_ELEM)
 }
 
+/// Looks up the value associated with `key` in a heap allocated buffer of
+/// `value_size` bytes, the value size the map was created with.
+///
+/// The kernel copies `value_size` bytes, so sizing the buffer from the map
+/// definition rather than from `V` guarantees it can't write past it, and
+/// keeps large values off the stack.
+pub(crate) fn bpf_map_lookup_elem_sized<K: Pod, V: Pod>(
+    fd: RawFd,
+    key: &K,
+    value_size: usize,
+    flags: u64,
+) -> Result<Option<V>, (c_long, io::Error)> {
+    let mut value = vec![0u8; cmp::max(value_size, mem::size_of::<V>())];
+    match bpf_map_lookup_elem_ptr(fd, Some(key), value.as_mut_ptr(), flags)? {
+        Some(()) => Ok(Some(unsafe {
+            ptr::read_unaligned(value.as_ptr() as *const V)
+        })),
+        None => Ok(None),
+    }
+}
+
 pub(crate) fn bpf_map_lookup_and_delete_elem<K: Pod, V: Pod>(
     fd: RawFd,
     key: Option<&K>,
@@ -199,9 +245,10 @@ pub(crate) fn bpf_map_lookup_and_delete_elem<K: Pod, V: Pod>(
 pub(crate) fn bpf_map_lookup_elem_per_cpu<K: Pod, V: Pod>(
     fd: RawFd,
     key: &K,
+    nr_cpus: usize,
     flags: u64,
 ) -> Result<Option<PerCpuValues<V>>, (c_long, io::Error)> {
-    let mut mem = PerCpuValues::<V>::alloc_kernel_mem().map_err(|io_error| (-1, io_error))?;
+    let mut mem = PerCpuValues::<V>::alloc_kernel_mem(nr_cpus);
     match bpf_map_lookup_elem_ptr(fd, Some(key), mem.as_mut_ptr(), flags) {
         Ok(_) => Ok(Some(unsafe { PerCpuValues::from_kernel_mem(mem) })),
         Err((_, io_error)) if io_error.raw_os_error() == Some(ENOENT) => Ok(None),
@@ -285,7 +332,7 @@ pub(crate) fn bpf_map_update_elem_per_cpu<K: Pod, V: Pod>(
     values: &PerCpuValues<V>,
     flags: u64,
 ) -> SysResult {
-    let mut mem = values.build_kernel_mem().map_err(|e| (-1, e))?;
+    let mut mem = values.build_kernel_mem();
     bpf_map_update_elem_ptr(fd, key, mem.as_mut_ptr(), flags)
 }
 
@@ -320,6 +367,27 @@ pub(crate) fn bpf_map_get_next_key<K: Pod>(
     }
 }
 
+pub(crate) fn bpf_map_get_next_key_ptr(
+    fd: RawFd,
+    key: Option<*const u8>,
+    next_key: *mut u8,
+) -> Result<Option<()>, (c_long, io::Error)> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let u = unsafe { &mut attr.__bindgen_anon_2 };
+    u.map_fd = fd as u32;
+    if let Some(key) = key {
+        u.key = key as u64;
+    }
+    u.__bindgen_anon_1.next_key = next_key as u64;
+
+    match sys_bpf(bpf_cmd::BPF_MAP_GET_NEXT_KEY, &attr) {
+        Ok(_) => Ok(Some(())),
+        Err((_, io_error)) if io_error.raw_os_error() == Some(ENOENT) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
 // since kernel 5.2
 pub(crate) fn bpf_map_freeze(fd: RawFd) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
@@ -349,6 +417,48 @@ pub(crate) fn bpf_link_create(
     sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
 }
 
+// since kernel 5.18
+pub(crate) fn bpf_link_create_kprobe_multi(
+    prog_fd: RawFd,
+    symbols: &[CString],
+    flags: u32,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+    let syms = symbols.iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+
+    attr.link_create.prog_fd = prog_fd as u32;
+    attr.link_create.attach_type = bpf_attach_type::BPF_TRACE_KPROBE_MULTI as u32;
+    let kprobe_multi = unsafe { &mut attr.link_create.__bindgen_anon_2.kprobe_multi };
+    kprobe_multi.flags = flags;
+    kprobe_multi.cnt = syms.len() as u32;
+    kprobe_multi.syms = syms.as_ptr() as u64;
+
+    sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
+}
+
+// since kernel 6.6
+pub(crate) fn bpf_link_create_tcx(
+    prog_fd: RawFd,
+    if_index: u32,
+    attach_type: bpf_attach_type,
+    flags: u32,
+    relative_id: u32,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    attr.link_create.prog_fd = prog_fd as u32;
+    attr.link_create.__bindgen_anon_1.target_ifindex = if_index;
+    attr.link_create.attach_type = attach_type as u32;
+    attr.link_create.flags = flags;
+    attr.link_create
+        .__bindgen_anon_2
+        .tcx
+        .__bindgen_anon_1
+        .relative_id = relative_id;
+
+    sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
+}
+
 // since kernel 5.7
 pub(crate) fn bpf_link_update(
     link_fd: RawFd,
@@ -370,6 +480,15 @@ pub(crate) fn bpf_link_update(
     sys_bpf(bpf_cmd::BPF_LINK_UPDATE, &attr)
 }
 
+// since kernel 5.8
+pub(crate) fn bpf_create_iter(link_fd: RawFd) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    attr.iter_create.link_fd = link_fd as u32;
+
+    sys_bpf(bpf_cmd::BPF_ITER_CREATE, &attr)
+}
+
 pub(crate) fn bpf_prog_attach(
     prog_fd: RawFd,
     target_fd: RawFd,
@@ -425,6 +544,61 @@ pub(crate) fn bpf_prog_query(
     ret
 }
 
+/// Runs the program once with the given context.
+///
+/// Returns the return value of the program and the size of the context
+/// written to `ctx_out`. Some program types (eg syscall programs) don't
+/// support `ctx_out` and write the context back to `ctx_in` instead.
+pub(crate) fn bpf_prog_test_run(
+    prog_fd: RawFd,
+    ctx_in: &mut [u8],
+    ctx_out: Option<&mut [u8]>,
+) -> Result<(u32, usize), (c_long, io::Error)> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let u = unsafe { &mut attr.test };
+    u.prog_fd = prog_fd as u32;
+    if !ctx_in.is_empty() {
+        u.ctx_in = ctx_in.as_mut_ptr() as u64;
+        u.ctx_size_in = ctx_in.len() as u32;
+    }
+    if let Some(ctx_out) = ctx_out {
+        u.ctx_out = ctx_out.as_mut_ptr() as u64;
+        u.ctx_size_out = ctx_out.len() as u32;
+    }
+
+    sys_bpf(bpf_cmd::BPF_PROG_TEST_RUN, &attr)?;
+
+    let u = unsafe { &attr.test };
+    Ok((u.retval, u.ctx_size_out as usize))
+}
+
+/// Runs the program `repeat` times with the given packet data.
+///
+/// Returns the return value of the program, the size of the packet written to
+/// `data_out` and the average duration of a run in nanoseconds.
+pub(crate) fn bpf_prog_test_run_data(
+    prog_fd: RawFd,
+    data_in: &[u8],
+    data_out: &mut [u8],
+    repeat: u32,
+) -> Result<(u32, usize, u32), (c_long, io::Error)> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let u = unsafe { &mut attr.test };
+    u.prog_fd = prog_fd as u32;
+    u.data_in = data_in.as_ptr() as u64;
+    u.data_size_in = data_in.len() as u32;
+    u.data_out = data_out.as_mut_ptr() as u64;
+    u.data_size_out = data_out.len() as u32;
+    u.repeat = repeat;
+
+    sys_bpf(bpf_cmd::BPF_PROG_TEST_RUN, &attr)?;
+
+    let u = unsafe { &attr.test };
+    Ok((u.retval, u.data_size_out as usize, u.duration))
+}
+
 pub(crate) fn bpf_prog_get_fd_by_id(prog_id: u32) -> Result<RawFd, io::Error> {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
 
@@ -451,6 +625,98 @@ pub(crate) fn bpf_prog_get_info_by_fd(prog_fd: RawFd) -> Result<bpf_prog_info, i
     }
 }
 
+// BPF_STATS_RUN_TIME from enum bpf_stats_type
+pub(crate) const BPF_STATS_RUN_TIME: u32 = 0;
+
+pub(crate) fn bpf_enable_stats(stats_type: u32) -> Result<RawFd, io::Error> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+    attr.enable_stats.type_ = stats_type;
+    match sys_bpf(bpf_cmd::BPF_ENABLE_STATS, &attr) {
+        Ok(fd) => Ok(fd as RawFd),
+        Err((_, err)) => Err(err),
+    }
+}
+
+// Fills `info`, copying the arrays its pointers refer to.
+fn bpf_prog_fill_info(prog_fd: RawFd, info: &mut bpf_prog_info) -> Result<(), io::Error> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+    attr.info.bpf_fd = prog_fd as u32;
+    attr.info.info = info as *mut _ as u64;
+    attr.info.info_len = mem::size_of::<bpf_prog_info>() as u32;
+    sys_bpf(bpf_cmd::BPF_OBJ_GET_INFO_BY_FD, &attr)
+        .map(|_| ())
+        .map_err(|(_, err)| err)
+}
+
+pub(crate) fn bpf_prog_get_xlated_insns(prog_fd: RawFd) -> Result<Vec<bpf_insn>, io::Error> {
+    // the first call returns the size of the instructions, the second one
+    // copies them
+    let mut info = unsafe { mem::zeroed::<bpf_prog_info>() };
+    bpf_prog_fill_info(prog_fd, &mut info)?;
+    let len = info.xlated_prog_len as usize / mem::size_of::<bpf_insn>();
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut insns = vec![unsafe { mem::zeroed::<bpf_insn>() }; len];
+    let mut info = unsafe { mem::zeroed::<bpf_prog_info>() };
+    info.xlated_prog_len = (len * mem::size_of::<bpf_insn>()) as u32;
+    info.xlated_prog_insns = insns.as_mut_ptr() as u64;
+    bpf_prog_fill_info(prog_fd, &mut info)?;
+    insns.truncate(info.xlated_prog_len as usize / mem::size_of::<bpf_insn>());
+
+    Ok(insns)
+}
+
+pub(crate) fn bpf_prog_get_jited_ksyms(prog_fd: RawFd) -> Result<Vec<u64>, io::Error> {
+    let mut info = unsafe { mem::zeroed::<bpf_prog_info>() };
+    bpf_prog_fill_info(prog_fd, &mut info)?;
+    // 0 if the program isn't jited
+    let len = info.nr_jited_ksyms as usize;
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut ksyms = vec![0u64; len];
+    let mut info = unsafe { mem::zeroed::<bpf_prog_info>() };
+    info.nr_jited_ksyms = len as u32;
+    info.jited_ksyms = ksyms.as_mut_ptr() as u64;
+    bpf_prog_fill_info(prog_fd, &mut info)?;
+    ksyms.truncate(info.nr_jited_ksyms as usize);
+
+    Ok(ksyms)
+}
+
+pub(crate) fn bpf_prog_get_jited_func_lens(prog_fd: RawFd) -> Result<Vec<u32>, io::Error> {
+    let mut info = unsafe { mem::zeroed::<bpf_prog_info>() };
+    bpf_prog_fill_info(prog_fd, &mut info)?;
+    // 0 if the program isn't jited
+    let len = info.nr_jited_func_lens as usize;
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut lens = vec![0u32; len];
+    let mut info = unsafe { mem::zeroed::<bpf_prog_info>() };
+    info.nr_jited_func_lens = len as u32;
+    info.jited_func_lens = lens.as_mut_ptr() as u64;
+    bpf_prog_fill_info(prog_fd, &mut info)?;
+    lens.truncate(info.nr_jited_func_lens as usize);
+
+    Ok(lens)
+}
+
+pub(crate) fn bpf_map_get_fd_by_id(map_id: u32) -> Result<RawFd, io::Error> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    attr.__bindgen_anon_6.__bindgen_anon_1.map_id = map_id;
+
+    match sys_bpf(bpf_cmd::BPF_MAP_GET_FD_BY_ID, &attr) {
+        Ok(v) => Ok(v as RawFd),
+        Err((_, err)) => Err(err),
+    }
+}
+
 pub(crate) fn bpf_map_get_info_by_fd(prog_fd: RawFd) -> Result<bpf_map_info, io::Error> {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     // info gets entirely populated by the kernel
@@ -512,14 +778,19 @@ pub(crate) fn bpf_raw_tracepoint_open(name: Option<&CStr>, prog_fd: RawFd) -> Sy
     sys_bpf(bpf_cmd::BPF_RAW_TRACEPOINT_OPEN, &attr)
 }
 
-pub(crate) fn bpf_load_btf(raw_btf: &[u8], log: &mut VerifierLog) -> SysResult {
+pub(crate) fn bpf_load_btf(
+    raw_btf: &[u8],
+    log: &mut VerifierLog,
+    verifier_log_level: u32,
+) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     let u = unsafe { &mut attr.__bindgen_anon_7 };
     u.btf = raw_btf.as_ptr() as *const _ as u64;
     u.btf_size = mem::size_of_val(raw_btf) as u32;
     let log_buf = log.buf();
-    if log_buf.capacity() > 0 {
-        u.btf_log_level = 1;
+    // the kernel rejects a log buffer without a log level
+    if log_buf.capacity() > 0 && verifier_log_level > 0 {
+        u.btf_log_level = verifier_log_level;
         u.btf_log_buf = log_buf.as_mut_ptr() as u64;
         u.btf_log_size = log_buf.capacity() as u32;
     }
@@ -7,10 +7,8 @@ mod fake;
 
 use std::io;
 #[cfg(not(test))]
-use std::{ffi::CString, mem};
+use std::mem;
 
-#[cfg(not(test))]
-use libc::utsname;
 use libc::{c_int, c_long, pid_t};
 
 pub(crate) use bpf::*;
@@ -84,27 +82,10 @@ pub(crate) fn kernel_version() -> Result<(u32, u32, u32), ()> {
 
 #[cfg(not(test))]
 pub(crate) fn kernel_version() -> Result<(u32, u32, u32), ()> {
-    unsafe {
-        let mut v = mem::zeroed::<utsname>();
-        if libc::uname(&mut v as *mut _) != 0 {
-            return Err(());
-        }
-
-        let mut major = 0u32;
-        let mut minor = 0u32;
-        let mut patch = 0u32;
-        let format = CString::new("%u.%u.%u").unwrap();
-        if libc::sscanf(
-            v.release.as_ptr(),
-            format.as_ptr(),
-            &mut major as *mut u32,
-            &mut minor as *mut _,
-            &mut patch as *mut _,
-        ) != 3
-        {
-            return Err(());
-        }
-
-        Ok((major, minor, patch))
-    }
+    let version = crate::util::current_kernel_version().map_err(|_| ())?;
+    Ok((
+        version.major() as u32,
+        version.minor() as u32,
+        version.patch() as u32,
+    ))
 }
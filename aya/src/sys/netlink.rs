@@ -3,17 +3,20 @@ use thiserror::Error;
 
 use libc::{
     close, getsockname, nlattr, nlmsgerr, nlmsghdr, recv, send, setsockopt, sockaddr_nl, socket,
-    AF_NETLINK, AF_UNSPEC, ETH_P_ALL, IFLA_XDP, NETLINK_EXT_ACK, NETLINK_ROUTE, NLA_ALIGNTO,
-    NLA_F_NESTED, NLA_TYPE_MASK, NLMSG_DONE, NLMSG_ERROR, NLM_F_ACK, NLM_F_CREATE, NLM_F_DUMP,
-    NLM_F_ECHO, NLM_F_EXCL, NLM_F_MULTI, NLM_F_REQUEST, RTM_DELTFILTER, RTM_GETTFILTER,
-    RTM_NEWQDISC, RTM_NEWTFILTER, RTM_SETLINK, SOCK_RAW, SOL_NETLINK,
+    AF_NETLINK, AF_UNSPEC, ETH_P_ALL, IFLA_XDP, MSG_PEEK, MSG_TRUNC, NETLINK_EXT_ACK,
+    NETLINK_ROUTE, NLA_ALIGNTO, NLA_F_NESTED, NLA_TYPE_MASK, NLMSG_DONE, NLMSG_ERROR, NLM_F_ACK,
+    NLM_F_CREATE, NLM_F_DUMP, NLM_F_ECHO, NLM_F_EXCL, NLM_F_MULTI, NLM_F_REQUEST, RTM_DELTFILTER,
+    RTM_GETLINK, RTM_GETTFILTER, RTM_NEWLINK, RTM_NEWQDISC, RTM_NEWTFILTER, RTM_SETLINK, SOCK_RAW,
+    SOL_NETLINK,
 };
 
 use crate::{
     generated::{
-        ifinfomsg, tcmsg, IFLA_XDP_EXPECTED_FD, IFLA_XDP_FD, IFLA_XDP_FLAGS, NLMSG_ALIGNTO,
-        TCA_BPF_FD, TCA_BPF_FLAGS, TCA_BPF_FLAG_ACT_DIRECT, TCA_BPF_NAME, TCA_KIND, TCA_OPTIONS,
-        TC_H_CLSACT, TC_H_INGRESS, TC_H_MAJ_MASK, TC_H_UNSPEC, XDP_FLAGS_REPLACE,
+        ifinfomsg, tcmsg, IFLA_XDP_ATTACHED, IFLA_XDP_DRV_PROG_ID, IFLA_XDP_EXPECTED_FD,
+        IFLA_XDP_FD, IFLA_XDP_FLAGS, IFLA_XDP_HW_PROG_ID, IFLA_XDP_PROG_ID, IFLA_XDP_SKB_PROG_ID,
+        NLMSG_ALIGNTO, TCA_BPF_FD, TCA_BPF_FLAGS, TCA_BPF_FLAG_ACT_DIRECT, TCA_BPF_ID,
+        TCA_BPF_NAME, TCA_KIND, TCA_OPTIONS, TC_H_CLSACT, TC_H_INGRESS, TC_H_MAJ_MASK, TC_H_UNSPEC,
+        XDP_FLAGS_DRV_MODE, XDP_FLAGS_HW_MODE, XDP_FLAGS_REPLACE, XDP_FLAGS_SKB_MODE,
     },
     programs::TcAttachType,
     util::tc_handler_make,
@@ -31,6 +34,20 @@ pub(crate) unsafe fn netlink_set_xdp_fd(
 ) -> Result<(), io::Error> {
     let sock = NetlinkSocket::open()?;
 
+    let req = xdp_set_fd_request(if_index, fd, old_fd, flags)?;
+    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
+
+    sock.recv()?;
+
+    Ok(())
+}
+
+unsafe fn xdp_set_fd_request(
+    if_index: i32,
+    fd: RawFd,
+    old_fd: Option<RawFd>,
+    flags: u32,
+) -> Result<Request, io::Error> {
     // Safety: Request is POD so this is safe
     let mut req = mem::zeroed::<Request>();
 
@@ -61,11 +78,86 @@ pub(crate) unsafe fn netlink_set_xdp_fd(
     let nla_len = attrs.finish()?;
     req.header.nlmsg_len += align_to(nla_len, NLA_ALIGNTO as usize) as u32;
 
+    Ok(req)
+}
+
+// values of IFLA_XDP_ATTACHED, see enum in include/uapi/linux/if_link.h
+const XDP_ATTACHED_DRV: u8 = 1;
+const XDP_ATTACHED_SKB: u8 = 2;
+const XDP_ATTACHED_HW: u8 = 3;
+
+// Returns a vector of tuple (program id, attach mode flag) for the XDP programs
+// attached to the interface
+pub(crate) unsafe fn netlink_xdp_attached(if_index: i32) -> Result<Vec<(u32, u32)>, io::Error> {
+    let mut req = mem::zeroed::<Request>();
+
+    let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<ifinfomsg>();
+    req.header = nlmsghdr {
+        nlmsg_len: nlmsg_len as u32,
+        nlmsg_flags: NLM_F_REQUEST as u16,
+        nlmsg_type: RTM_GETLINK,
+        nlmsg_pid: 0,
+        nlmsg_seq: 1,
+    };
+    req.if_info.ifi_family = AF_UNSPEC as u8;
+    req.if_info.ifi_index = if_index;
+
+    let sock = NetlinkSocket::open()?;
     sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
 
-    sock.recv()?;
+    for msg in sock.recv()? {
+        if msg.header.nlmsg_type != RTM_NEWLINK {
+            continue;
+        }
+        let attrs = parse_attrs(&msg.data[mem::size_of::<ifinfomsg>()..])?;
+        return match attrs.get(&IFLA_XDP) {
+            Some(xdp) => parse_xdp_attached(xdp.data),
+            None => Ok(Vec::new()),
+        };
+    }
 
-    Ok(())
+    Ok(Vec::new())
+}
+
+fn parse_xdp_attached(buf: &[u8]) -> Result<Vec<(u32, u32)>, io::Error> {
+    let attrs = parse_attrs(buf)?;
+    let attr_u32 = |attr_type: u32| {
+        attrs
+            .get(&(attr_type as u16))
+            .and_then(|attr| attr.data.try_into().ok())
+            .map(u32::from_ne_bytes)
+            .filter(|id| *id != 0)
+    };
+
+    // since 4.19 the kernel reports the program attached in each mode
+    let mut progs = Vec::new();
+    for (attr_type, mode) in [
+        (IFLA_XDP_DRV_PROG_ID, XDP_FLAGS_DRV_MODE),
+        (IFLA_XDP_SKB_PROG_ID, XDP_FLAGS_SKB_MODE),
+        (IFLA_XDP_HW_PROG_ID, XDP_FLAGS_HW_MODE),
+    ] {
+        if let Some(id) = attr_u32(attr_type) {
+            progs.push((id, mode));
+        }
+    }
+    if !progs.is_empty() {
+        return Ok(progs);
+    }
+
+    let attached = attrs
+        .get(&(IFLA_XDP_ATTACHED as u16))
+        .and_then(|attr| attr.data.first().copied());
+    let mode = match attached {
+        Some(XDP_ATTACHED_DRV) => XDP_FLAGS_DRV_MODE,
+        Some(XDP_ATTACHED_SKB) => XDP_FLAGS_SKB_MODE,
+        Some(XDP_ATTACHED_HW) => XDP_FLAGS_HW_MODE,
+        _ => return Ok(progs),
+    };
+    if let Some(id) = attr_u32(IFLA_XDP_PROG_ID) {
+        progs.push((id, mode));
+    }
+
+    Ok(progs)
 }
 
 pub(crate) unsafe fn netlink_qdisc_add_clsact(if_index: i32) -> Result<(), io::Error> {
@@ -98,6 +190,8 @@ pub(crate) unsafe fn netlink_qdisc_add_clsact(if_index: i32) -> Result<(), io::E
     Ok(())
 }
 
+// Attaches a bpf filter, or replaces the program of the existing filter with
+// the given `priority` and `handle` if `replace` is true.
 pub(crate) unsafe fn netlink_qdisc_attach(
     if_index: i32,
     attach_type: &TcAttachType,
@@ -105,14 +199,20 @@ pub(crate) unsafe fn netlink_qdisc_attach(
     prog_name: &CStr,
     priority: u16,
     handle: u32,
+    replace: bool,
 ) -> Result<(u16, u32), io::Error> {
     let sock = NetlinkSocket::open()?;
     let mut req = mem::zeroed::<TcRequest>();
 
     let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<tcmsg>();
+    let flags = if replace {
+        NLM_F_REQUEST | NLM_F_ACK | NLM_F_ECHO
+    } else {
+        NLM_F_REQUEST | NLM_F_ACK | NLM_F_EXCL | NLM_F_CREATE | NLM_F_ECHO
+    };
     req.header = nlmsghdr {
         nlmsg_len: nlmsg_len as u32,
-        nlmsg_flags: (NLM_F_REQUEST | NLM_F_ACK | NLM_F_EXCL | NLM_F_CREATE | NLM_F_ECHO) as u16,
+        nlmsg_flags: flags as u16,
         nlmsg_type: RTM_NEWTFILTER,
         nlmsg_pid: 0,
         nlmsg_seq: 1,
@@ -168,6 +268,21 @@ pub(crate) unsafe fn netlink_qdisc_detach(
     handle: u32,
 ) -> Result<(), io::Error> {
     let sock = NetlinkSocket::open()?;
+    let req = qdisc_detach_request(if_index, attach_type, priority, handle);
+
+    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
+
+    sock.recv()?;
+
+    Ok(())
+}
+
+unsafe fn qdisc_detach_request(
+    if_index: i32,
+    attach_type: &TcAttachType,
+    priority: u16,
+    handle: u32,
+) -> TcRequest {
     let mut req = mem::zeroed::<TcRequest>();
 
     req.header = nlmsghdr {
@@ -184,11 +299,7 @@ pub(crate) unsafe fn netlink_qdisc_detach(
     req.tc_info.tcm_parent = attach_type.parent();
     req.tc_info.tcm_ifindex = if_index;
 
-    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
-
-    sock.recv()?;
-
-    Ok(())
+    req
 }
 
 // Returns a vector of tuple (priority, handle) for filters matching the provided parameters
@@ -197,6 +308,42 @@ pub(crate) unsafe fn netlink_find_filter_with_name(
     attach_type: TcAttachType,
     name: &CStr,
 ) -> Result<Vec<(u16, u32)>, io::Error> {
+    let mut filter_info = Vec::new();
+    for msg in netlink_dump_filters(if_index, attach_type)? {
+        let filter = match parse_bpf_filter(&msg.data)? {
+            Some(filter) => filter,
+            None => continue,
+        };
+        if filter.name == Some(name) {
+            filter_info.push((filter.priority, filter.handle));
+        }
+    }
+
+    Ok(filter_info)
+}
+
+// Returns a vector of tuple (priority, handle, program id) for the bpf filters
+// attached to the given parent
+pub(crate) unsafe fn netlink_find_bpf_filters(
+    if_index: i32,
+    attach_type: TcAttachType,
+) -> Result<Vec<(u16, u32, u32)>, io::Error> {
+    let mut filter_info = Vec::new();
+    for msg in netlink_dump_filters(if_index, attach_type)? {
+        if let Some(filter) = parse_bpf_filter(&msg.data)? {
+            if let Some(prog_id) = filter.prog_id {
+                filter_info.push((filter.priority, filter.handle, prog_id));
+            }
+        }
+    }
+
+    Ok(filter_info)
+}
+
+unsafe fn netlink_dump_filters(
+    if_index: i32,
+    attach_type: TcAttachType,
+) -> Result<Vec<NetlinkMessage>, io::Error> {
     let mut req = mem::zeroed::<TcRequest>();
 
     let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<tcmsg>();
@@ -215,29 +362,56 @@ pub(crate) unsafe fn netlink_find_filter_with_name(
     let sock = NetlinkSocket::open()?;
     sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
 
-    let mut filter_info = Vec::new();
-    for msg in sock.recv()? {
-        if msg.header.nlmsg_type != RTM_NEWTFILTER {
-            continue;
-        }
+    Ok(sock
+        .recv()?
+        .into_iter()
+        .filter(|msg| msg.header.nlmsg_type == RTM_NEWTFILTER)
+        .collect())
+}
+
+struct BpfFilter<'a> {
+    priority: u16,
+    handle: u32,
+    name: Option<&'a CStr>,
+    prog_id: Option<u32>,
+}
 
-        let tc_msg = ptr::read_unaligned(msg.data.as_ptr() as *const tcmsg);
-        let priority = (tc_msg.tcm_info >> 16) as u16;
-        let attrs = parse_attrs(&msg.data[mem::size_of::<tcmsg>()..])?;
+// Parses the payload of a RTM_NEWTFILTER message, returning None for filters
+// that aren't bpf filters
+fn parse_bpf_filter(data: &[u8]) -> Result<Option<BpfFilter<'_>>, io::Error> {
+    if data.len() < mem::size_of::<tcmsg>() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "buffer smaller than tcmsg",
+        ));
+    }
+    // Safety: tcmsg is POD so read is safe
+    let tc_msg = unsafe { ptr::read_unaligned(data.as_ptr() as *const tcmsg) };
+    let attrs = parse_attrs(&data[mem::size_of::<tcmsg>()..])?;
 
-        if let Some(opts) = attrs.get(&(TCA_OPTIONS as u16)) {
-            let opts = parse_attrs(opts.data)?;
-            if let Some(f_name) = opts.get(&(TCA_BPF_NAME as u16)) {
-                if let Ok(f_name) = CStr::from_bytes_with_nul(f_name.data) {
-                    if name == f_name {
-                        filter_info.push((priority, tc_msg.tcm_handle));
-                    }
-                }
-            }
-        }
+    match attrs.get(&(TCA_KIND as u16)) {
+        Some(kind) if kind.data == b"bpf\0" => {}
+        _ => return Ok(None),
     }
 
-    Ok(filter_info)
+    let mut filter = BpfFilter {
+        priority: (tc_msg.tcm_info >> 16) as u16,
+        handle: tc_msg.tcm_handle,
+        name: None,
+        prog_id: None,
+    };
+    if let Some(opts) = attrs.get(&(TCA_OPTIONS as u16)) {
+        let opts = parse_attrs(opts.data)?;
+        filter.name = opts
+            .get(&(TCA_BPF_NAME as u16))
+            .and_then(|name| CStr::from_bytes_with_nul(name.data).ok());
+        filter.prog_id = opts
+            .get(&(TCA_BPF_ID as u16))
+            .and_then(|id| id.data.try_into().ok())
+            .map(u32::from_ne_bytes);
+    }
+
+    Ok(Some(filter))
 }
 
 #[repr(C)]
@@ -303,11 +477,29 @@ impl NetlinkSocket {
     }
 
     fn recv(&self) -> Result<Vec<NetlinkMessage>, io::Error> {
-        let mut buf = [0u8; 4096];
+        let mut buf = vec![0u8; 4096];
         let mut messages = Vec::new();
         let mut multipart = true;
         'out: while multipart {
             multipart = false;
+            // peek at the size of the next datagram and grow the buffer so that
+            // large replies, eg the attributes of an interface with many VFs,
+            // aren't truncated
+            // Safety: libc wrapper
+            let len = unsafe {
+                recv(
+                    self.sock,
+                    buf.as_mut_ptr() as *mut _,
+                    buf.len(),
+                    MSG_PEEK | MSG_TRUNC,
+                )
+            };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if len as usize > buf.len() {
+                buf.resize(len as usize, 0);
+            }
             // Safety: libc wrapper
             let len = unsafe { recv(self.sock, buf.as_mut_ptr() as *mut _, buf.len(), 0) };
             if len < 0 {
@@ -635,6 +827,152 @@ mod tests {
         assert_eq!(fd, 24);
     }
 
+    #[test]
+    fn test_xdp_set_fd_request_replace() {
+        let req = unsafe { xdp_set_fd_request(3, 42, Some(24), XDP_FLAGS_REPLACE).unwrap() };
+        assert_eq!(req.if_info.ifi_index, 3);
+
+        let msg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<ifinfomsg>();
+        let buf = &bytes_of(&req)[msg_len..req.header.nlmsg_len as usize];
+        let attrs = parse_attrs(buf).unwrap();
+        let xdp = parse_attrs(attrs.get(&(IFLA_XDP as u16)).unwrap().data).unwrap();
+
+        let attr_u32 = |attr_type: u32| {
+            let data = xdp.get(&(attr_type as u16)).unwrap().data;
+            u32::from_ne_bytes(data.try_into().unwrap())
+        };
+        assert_eq!(attr_u32(IFLA_XDP_FD), 42);
+        assert_eq!(attr_u32(IFLA_XDP_FLAGS), XDP_FLAGS_REPLACE);
+        assert_eq!(attr_u32(IFLA_XDP_EXPECTED_FD), 24);
+    }
+
+    #[test]
+    fn test_xdp_set_fd_request_no_replace() {
+        let req = unsafe { xdp_set_fd_request(3, 42, None, 0).unwrap() };
+
+        let msg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<ifinfomsg>();
+        let buf = &bytes_of(&req)[msg_len..req.header.nlmsg_len as usize];
+        let attrs = parse_attrs(buf).unwrap();
+        let xdp = parse_attrs(attrs.get(&(IFLA_XDP as u16)).unwrap().data).unwrap();
+        assert!(xdp.contains_key(&(IFLA_XDP_FD as u16)));
+        assert!(!xdp.contains_key(&(IFLA_XDP_FLAGS as u16)));
+        assert!(!xdp.contains_key(&(IFLA_XDP_EXPECTED_FD as u16)));
+    }
+
+    #[test]
+    fn test_xdp_detach_request() {
+        let req = unsafe { xdp_set_fd_request(3, -1, None, XDP_FLAGS_SKB_MODE).unwrap() };
+        assert_eq!(req.header.nlmsg_type, RTM_SETLINK);
+        assert_eq!(req.if_info.ifi_index, 3);
+
+        let msg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<ifinfomsg>();
+        let buf = &bytes_of(&req)[msg_len..req.header.nlmsg_len as usize];
+        let attrs = parse_attrs(buf).unwrap();
+        let xdp = parse_attrs(attrs.get(&(IFLA_XDP as u16)).unwrap().data).unwrap();
+        let attr_i32 = |attr_type: u32| {
+            let data = xdp.get(&(attr_type as u16)).unwrap().data;
+            i32::from_ne_bytes(data.try_into().unwrap())
+        };
+        assert_eq!(attr_i32(IFLA_XDP_FD), -1);
+        assert_eq!(attr_i32(IFLA_XDP_FLAGS), XDP_FLAGS_SKB_MODE as i32);
+        assert!(!xdp.contains_key(&(IFLA_XDP_EXPECTED_FD as u16)));
+    }
+
+    #[test]
+    fn test_qdisc_detach_request() {
+        let req = unsafe { qdisc_detach_request(3, &TcAttachType::Egress, 49152, 1) };
+        assert_eq!(req.header.nlmsg_type, RTM_DELTFILTER);
+        assert_eq!(
+            req.header.nlmsg_len as usize,
+            mem::size_of::<nlmsghdr>() + mem::size_of::<tcmsg>()
+        );
+        assert_eq!(req.tc_info.tcm_ifindex, 3);
+        assert_eq!(req.tc_info.tcm_handle, 1);
+        assert_eq!(req.tc_info.tcm_parent, TcAttachType::Egress.parent());
+        assert_eq!(req.tc_info.tcm_info >> 16, 49152);
+    }
+
+    #[test]
+    fn test_parse_xdp_attached_per_mode() {
+        let mut buf = [0; 64];
+        let mut attrs = NestedAttrs::new(&mut buf, IFLA_XDP);
+        attrs.write_attr(IFLA_XDP_ATTACHED as u16, 4u8).unwrap();
+        attrs
+            .write_attr(IFLA_XDP_DRV_PROG_ID as u16, 12u32)
+            .unwrap();
+        attrs
+            .write_attr(IFLA_XDP_SKB_PROG_ID as u16, 34u32)
+            .unwrap();
+        let len = attrs.finish().unwrap();
+
+        let progs = parse_xdp_attached(&buf[NLA_HDR_LEN..len]).unwrap();
+        assert_eq!(
+            progs,
+            vec![(12, XDP_FLAGS_DRV_MODE), (34, XDP_FLAGS_SKB_MODE)]
+        );
+    }
+
+    #[test]
+    fn test_parse_xdp_attached_legacy() {
+        let mut buf = [0; 64];
+        let mut attrs = NestedAttrs::new(&mut buf, IFLA_XDP);
+        attrs
+            .write_attr(IFLA_XDP_ATTACHED as u16, XDP_ATTACHED_SKB)
+            .unwrap();
+        attrs.write_attr(IFLA_XDP_PROG_ID as u16, 42u32).unwrap();
+        let len = attrs.finish().unwrap();
+
+        let progs = parse_xdp_attached(&buf[NLA_HDR_LEN..len]).unwrap();
+        assert_eq!(progs, vec![(42, XDP_FLAGS_SKB_MODE)]);
+    }
+
+    #[test]
+    fn test_parse_xdp_attached_none() {
+        let mut buf = [0; 64];
+        let mut attrs = NestedAttrs::new(&mut buf, IFLA_XDP);
+        attrs.write_attr(IFLA_XDP_ATTACHED as u16, 0u8).unwrap();
+        let len = attrs.finish().unwrap();
+
+        assert!(parse_xdp_attached(&buf[NLA_HDR_LEN..len])
+            .unwrap()
+            .is_empty());
+    }
+
+    fn bpf_filter_payload(kind: &[u8], prog_id: u32) -> Vec<u8> {
+        let mut tc_msg = unsafe { mem::zeroed::<tcmsg>() };
+        tc_msg.tcm_handle = 1;
+        tc_msg.tcm_info = 49152 << 16;
+
+        let mut buf = [0; 128];
+        let kind_len = write_attr_bytes(&mut buf, 0, TCA_KIND as u16, kind).unwrap();
+        let mut options = NestedAttrs::new(&mut buf[kind_len..], TCA_OPTIONS as u16);
+        options
+            .write_attr_bytes(TCA_BPF_NAME as u16, b"foo\0")
+            .unwrap();
+        options.write_attr(TCA_BPF_ID as u16, prog_id).unwrap();
+        let options_len = options.finish().unwrap();
+
+        let mut data = bytes_of(&tc_msg).to_vec();
+        data.extend_from_slice(&buf[..kind_len + options_len]);
+        data
+    }
+
+    #[test]
+    fn test_parse_bpf_filter() {
+        let data = bpf_filter_payload(b"bpf\0", 42);
+        let filter = parse_bpf_filter(&data).unwrap().unwrap();
+        assert_eq!(filter.priority, 49152);
+        assert_eq!(filter.handle, 1);
+        assert_eq!(filter.name.unwrap().to_bytes(), b"foo");
+        assert_eq!(filter.prog_id, Some(42));
+    }
+
+    #[test]
+    fn test_parse_bpf_filter_other_kind() {
+        let data = bpf_filter_payload(b"u32\0", 42);
+        assert!(parse_bpf_filter(&data).unwrap().is_none());
+    }
+
     #[test]
     fn test_nlattr_iterator_empty() {
         let mut iter = NlAttrsIterator::new(&[]);
@@ -23,6 +23,17 @@ pub(crate) fn perf_event_open(
     wakeup: bool,
     flags: u32,
 ) -> SysResult {
+    let attr = perf_event_attr_new(perf_type, config, sample_period, sample_frequency, wakeup);
+    perf_event_open_attr(attr, pid, cpu, flags)
+}
+
+pub(crate) fn perf_event_attr_new(
+    perf_type: u32,
+    config: u64,
+    sample_period: u64,
+    sample_frequency: Option<u64>,
+    wakeup: bool,
+) -> perf_event_attr {
     let mut attr = unsafe { mem::zeroed::<perf_event_attr>() };
 
     attr.config = config;
@@ -39,6 +50,15 @@ pub(crate) fn perf_event_open(
         attr.__bindgen_anon_1.sample_period = sample_period;
     }
 
+    attr
+}
+
+pub(crate) fn perf_event_open_attr(
+    attr: perf_event_attr,
+    pid: pid_t,
+    cpu: c_int,
+    flags: u32,
+) -> SysResult {
     syscall(Syscall::PerfEventOpen {
         attr,
         pid,
@@ -78,7 +98,12 @@ pub(crate) fn perf_event_open_probe(
 
     attr.size = mem::size_of::<perf_event_attr>() as u32;
     attr.type_ = ty;
-    attr.__bindgen_anon_3.config1 = c_name.as_ptr() as u64;
+    // without a name, `offset` is the absolute address of the probe
+    attr.__bindgen_anon_3.config1 = if name.is_empty() {
+        0
+    } else {
+        c_name.as_ptr() as u64
+    };
     attr.__bindgen_anon_4.config2 = offset;
 
     let cpu = if pid.is_some() { -1 } else { 0 };
@@ -151,3 +176,47 @@ impl TryFrom<u32> for perf_event_type {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+    use crate::sys::override_syscall;
+
+    #[test]
+    fn test_perf_event_open_probe_symbol_offset() {
+        override_syscall(|call| match call {
+            Syscall::PerfEventOpen { attr, .. } => {
+                let name = unsafe { CStr::from_ptr(attr.__bindgen_anon_3.config1 as *const _) };
+                assert_eq!(name.to_str().unwrap(), "try_to_wake_up");
+                assert_eq!(unsafe { attr.__bindgen_anon_4.config2 }, 0x10);
+                assert_eq!(attr.type_, 6);
+                Ok(42)
+            }
+            _ => panic!("unexpected syscall"),
+        });
+
+        assert_eq!(
+            perf_event_open_probe(6, None, "try_to_wake_up", 0x10, None).unwrap(),
+            42
+        );
+    }
+
+    #[test]
+    fn test_perf_event_open_probe_addr() {
+        override_syscall(|call| match call {
+            Syscall::PerfEventOpen { attr, .. } => {
+                assert_eq!(unsafe { attr.__bindgen_anon_3.config1 }, 0);
+                assert_eq!(unsafe { attr.__bindgen_anon_4.config2 }, 0xffffffff81000000);
+                Ok(42)
+            }
+            _ => panic!("unexpected syscall"),
+        });
+
+        assert_eq!(
+            perf_event_open_probe(6, None, "", 0xffffffff81000000, None).unwrap(),
+            42
+        );
+    }
+}
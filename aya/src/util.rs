@@ -1,5 +1,6 @@
 //! Utility functions.
 use std::{
+    cmp,
     collections::BTreeMap,
     ffi::{CStr, CString},
     fs::{self, File},
@@ -10,27 +11,49 @@ use std::{
 
 use crate::generated::{TC_H_MAJ_MASK, TC_H_MIN_MASK};
 
-use libc::{if_nametoindex, sysconf, _SC_PAGESIZE};
+use libc::{if_nametoindex, sysconf, uname, utsname, _SC_PAGESIZE};
 
 use io::BufRead;
 
-const ONLINE_CPUS: &str = "/sys/devices/system/cpu/online";
+pub(crate) const ONLINE_CPUS: &str = "/sys/devices/system/cpu/online";
 pub(crate) const POSSIBLE_CPUS: &str = "/sys/devices/system/cpu/possible";
 
+#[cfg(test)]
+thread_local! {
+    static CPU_FILES: std::cell::RefCell<std::collections::HashMap<&'static str, String>> =
+        Default::default();
+}
+
+/// Makes [`online_cpus`] and [`possible_cpus`] read `data` instead of the
+/// sysfs file at `path` on the current thread.
+#[cfg(test)]
+pub(crate) fn override_cpu_file(path: &'static str, data: &str) {
+    CPU_FILES.with(|files| files.borrow_mut().insert(path, data.to_owned()));
+}
+
+fn read_cpu_file(path: &'static str) -> Result<Vec<u32>, io::Error> {
+    #[cfg(test)]
+    let data = match CPU_FILES.with(|files| files.borrow().get(path).cloned()) {
+        Some(data) => data,
+        None => fs::read_to_string(path)?,
+    };
+    #[cfg(not(test))]
+    let data = fs::read_to_string(path)?;
+
+    parse_cpu_ranges(data.trim())
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, format!("unexpected {path} format")))
+}
+
 /// Returns the numeric IDs of the CPUs currently online.
 pub fn online_cpus() -> Result<Vec<u32>, io::Error> {
-    let data = fs::read_to_string(ONLINE_CPUS)?;
-    parse_cpu_ranges(data.trim()).map_err(|_| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("unexpected {ONLINE_CPUS} format"),
-        )
-    })
+    read_cpu_file(ONLINE_CPUS)
 }
 
 /// Get the number of possible cpus.
 ///
-/// See `/sys/devices/system/cpu/possible`.
+/// See `/sys/devices/system/cpu/possible`. The kernel stores the values of
+/// per-cpu maps for every possible CPU, including the offline ones, so this
+/// is the number of values of [`PerCpuValues`](crate::maps::PerCpuValues).
 pub fn nr_cpus() -> Result<usize, io::Error> {
     Ok(possible_cpus()?.len())
 }
@@ -39,13 +62,7 @@ pub fn nr_cpus() -> Result<usize, io::Error> {
 ///
 /// See `/sys/devices/system/cpu/possible`.
 pub(crate) fn possible_cpus() -> Result<Vec<u32>, io::Error> {
-    let data = fs::read_to_string(POSSIBLE_CPUS)?;
-    parse_cpu_ranges(data.trim()).map_err(|_| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("unexpected {POSSIBLE_CPUS} format"),
-        )
-    })
+    read_cpu_file(POSSIBLE_CPUS)
 }
 
 fn parse_cpu_ranges(data: &str) -> Result<Vec<u32>, ()> {
@@ -92,6 +109,93 @@ fn parse_kernel_symbols(reader: impl BufRead) -> Result<BTreeMap<u64, String>, i
     Ok(syms)
 }
 
+/// A kernel version, as in `major.minor.patch`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KernelVersion {
+    major: u8,
+    minor: u8,
+    patch: u16,
+}
+
+impl KernelVersion {
+    /// Creates a new kernel version.
+    pub const fn new(major: u8, minor: u8, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Returns the major version.
+    pub fn major(&self) -> u8 {
+        self.major
+    }
+
+    /// Returns the minor version.
+    pub fn minor(&self) -> u8 {
+        self.minor
+    }
+
+    /// Returns the patch version.
+    pub fn patch(&self) -> u16 {
+        self.patch
+    }
+
+    /// Returns the version encoded as the kernel `KERNEL_VERSION(a, b, c)` macro
+    /// does, as expected in `LINUX_VERSION_CODE` and `bpf_attr.kern_version`.
+    ///
+    /// Like the kernel, the patch version is clamped to 255.
+    pub fn code(&self) -> u32 {
+        let patch = cmp::min(self.patch, 255) as u32;
+        ((self.major as u32) << 16) + ((self.minor as u32) << 8) + patch
+    }
+}
+
+/// Returns the version of the running kernel.
+///
+/// The version is parsed from the release field of `uname`, falling back to
+/// `/proc/version`.
+pub fn current_kernel_version() -> Result<KernelVersion, io::Error> {
+    let mut uts = unsafe { mem::zeroed::<utsname>() };
+    // Safety: libc
+    if unsafe { uname(&mut uts) } == 0 {
+        // Safety: uname() NUL terminates the fields
+        let release = unsafe { CStr::from_ptr(uts.release.as_ptr()) };
+        if let Some(version) = release.to_str().ok().and_then(parse_kernel_version) {
+            return Ok(version);
+        }
+    }
+
+    // /proc/version looks like "Linux version 6.1.0-13-amd64 (...) #1 SMP ..."
+    let data = fs::read_to_string("/proc/version")?;
+    data.split_whitespace()
+        .nth(2)
+        .and_then(parse_kernel_version)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected /proc/version format: {}", data.trim()),
+            )
+        })
+}
+
+// Parses a release string like "5.15.0-76-generic" or "6.6-rc1". A missing
+// patch version defaults to 0.
+fn parse_kernel_version(release: &str) -> Option<KernelVersion> {
+    let end = release
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(release.len());
+    let mut parts = release[..end].split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = match parts.next() {
+        Some(patch) => patch.parse().ok()?,
+        None => 0,
+    };
+    Some(KernelVersion::new(major, minor, patch))
+}
+
 pub(crate) fn ifindex_from_ifname(if_name: &str) -> Result<u32, io::Error> {
     let c_str_if_name = CString::new(if_name)?;
     let c_if_name = c_str_if_name.as_ptr();
@@ -232,6 +336,30 @@ mod tests {
         assert!(parse_cpu_ranges("foo").is_err());
     }
 
+    #[test]
+    fn test_parse_kernel_version() {
+        assert_eq!(
+            parse_kernel_version("5.15.0-76-generic"),
+            Some(KernelVersion::new(5, 15, 0))
+        );
+        assert_eq!(
+            parse_kernel_version("4.19.282"),
+            Some(KernelVersion::new(4, 19, 282))
+        );
+        assert_eq!(
+            parse_kernel_version("6.6-rc1"),
+            Some(KernelVersion::new(6, 6, 0))
+        );
+        assert_eq!(parse_kernel_version("6"), None);
+        assert_eq!(parse_kernel_version("foo"), None);
+    }
+
+    #[test]
+    fn test_kernel_version_code() {
+        assert_eq!(KernelVersion::new(5, 15, 0).code(), 0x050f00);
+        assert_eq!(KernelVersion::new(4, 19, 282).code(), 0x0413ff);
+    }
+
     #[test]
     fn test_parse_kernel_symbols() {
         let data = "0000000000002000 A irq_stack_backing_store\n\
@@ -1,13 +1,13 @@
-use std::{process::Command, thread, time};
+use std::{os::unix::io::AsRawFd, process::Command, thread, time};
 
 use aya::{
     include_bytes_aligned,
-    maps::Array,
+    maps::{Array, IterableMap},
     programs::{
         links::{FdLink, PinnedLink},
-        KProbe, TracePoint, Xdp, XdpFlags,
+        KProbe, ProgramError, TracePoint, Xdp, XdpFlags,
     },
-    Bpf,
+    Bpf, BpfError,
 };
 use log::warn;
 
@@ -58,6 +58,44 @@ fn multiple_btf_maps() {
     assert_eq!(val_2, 42);
 }
 
+#[integration_test]
+fn reload_program() {
+    let bytes =
+        include_bytes_aligned!("../../../../target/bpfel-unknown-none/debug/multimap-btf.bpf.o");
+    let mut bpf = Bpf::load(bytes).unwrap();
+
+    let map_fd = |bpf: &Bpf| {
+        let map: Array<_, u64> = bpf.map("map_1").unwrap().try_into().unwrap();
+        map.map().fd().unwrap().as_raw_fd()
+    };
+    let old_map_fd = map_fd(&bpf);
+
+    let prog: &mut TracePoint = bpf.program_mut("tracepoint").unwrap().try_into().unwrap();
+    prog.load().unwrap();
+    let old_prog_fd = prog.fd().unwrap().as_raw_fd();
+    let link_id = prog.attach("sched", "sched_switch").unwrap();
+
+    // tracepoint links can't be moved to the new program
+    assert!(matches!(
+        bpf.reload_program("tracepoint", bytes),
+        Err(BpfError::ProgramError(ProgramError::AlreadyAttached))
+    ));
+
+    let prog: &mut TracePoint = bpf.program_mut("tracepoint").unwrap().try_into().unwrap();
+    prog.detach(link_id).unwrap();
+    bpf.reload_program("tracepoint", bytes).unwrap();
+    assert_eq!(map_fd(&bpf), old_map_fd);
+
+    let prog: &mut TracePoint = bpf.program_mut("tracepoint").unwrap().try_into().unwrap();
+    assert_ne!(prog.fd().unwrap().as_raw_fd(), old_prog_fd);
+
+    // the new program writes to the maps created by the first load
+    prog.attach("sched", "sched_switch").unwrap();
+    thread::sleep(time::Duration::from_secs(3));
+    let map: Array<_, u64> = bpf.map("map_1").unwrap().try_into().unwrap();
+    assert_eq!(map.get(&0, 0).unwrap(), 24);
+}
+
 fn is_loaded(name: &str) -> bool {
     let output = Command::new("bpftool").args(["prog"]).output();
     let output = match output {
@@ -113,6 +151,27 @@ fn unload_xdp() {
     assert_loaded!("test_unload_xdp", false);
 }
 
+#[integration_test]
+fn reload_attached_program() {
+    let bytes = include_bytes_aligned!("../../../../target/bpfel-unknown-none/debug/pass");
+    let mut bpf = Bpf::load(bytes).unwrap();
+
+    let prog: &mut Xdp = bpf.program_mut("pass").unwrap().try_into().unwrap();
+    prog.load().unwrap();
+    let old_prog_fd = prog.fd().unwrap().as_raw_fd();
+    let link_id = prog.attach("lo", XdpFlags::default()).unwrap();
+
+    // the link is moved to the new program
+    bpf.reload_program("pass", bytes).unwrap();
+    let prog: &mut Xdp = bpf.program_mut("pass").unwrap().try_into().unwrap();
+    assert_ne!(prog.fd().unwrap().as_raw_fd(), old_prog_fd);
+    assert_loaded!("pass", true);
+
+    prog.detach(link_id).unwrap();
+    prog.unload().unwrap();
+    assert_loaded!("pass", false);
+}
+
 #[integration_test]
 fn unload_kprobe() {
     let bytes = include_bytes_aligned!("../../../../target/bpfel-unknown-none/debug/test");
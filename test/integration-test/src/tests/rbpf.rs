@@ -1,5 +1,5 @@
 use core::{mem::size_of, ptr::null_mut, slice::from_raw_parts};
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap};
 
 use aya::include_bytes_aligned;
 use aya_obj::{generated::bpf_insn, Object, ProgramSection};
@@ -112,3 +112,120 @@ fn bpf_map_update_elem_multimap(map: u64, key: u64, value: u64, _: u64, _: u64)
     }
     0
 }
+
+const BPF_FUNC_PROBE_READ: u32 = 4;
+const BPF_FUNC_PROBE_READ_KERNEL: u32 = 113;
+
+thread_local! {
+    static KERNEL_MEMORY: RefCell<Option<MockKernelMemory>> = RefCell::new(None);
+}
+
+/// A fake kernel address space for programs run with rbpf.
+///
+/// rbpf helpers are plain functions, so the memory is installed in a thread
+/// local and read by the `bpf_probe_read` and `bpf_probe_read_kernel` helpers
+/// registered by [`MockKernelMemory::register_helpers`].
+#[derive(Debug, Default)]
+pub struct MockKernelMemory {
+    regions: Vec<(u64, Vec<u8>)>,
+}
+
+impl MockKernelMemory {
+    pub fn new() -> MockKernelMemory {
+        MockKernelMemory::default()
+    }
+
+    /// Maps `bytes` at the address `addr`.
+    pub fn insert(&mut self, addr: u64, bytes: &[u8]) -> &mut MockKernelMemory {
+        self.regions.push((addr, bytes.to_vec()));
+        self
+    }
+
+    /// Returns the `len` bytes at `addr`, or `None` if any of them isn't mapped.
+    pub fn read(&self, addr: u64, len: usize) -> Option<&[u8]> {
+        self.regions.iter().find_map(|(start, bytes)| {
+            let offset = addr.checked_sub(*start)? as usize;
+            bytes.get(offset..offset.checked_add(len)?)
+        })
+    }
+
+    /// Makes the memory visible to the probe read helpers of the current thread.
+    pub fn install(self) {
+        KERNEL_MEMORY.with(|memory| *memory.borrow_mut() = Some(self));
+    }
+
+    /// Unmaps the memory installed on the current thread.
+    pub fn uninstall() {
+        KERNEL_MEMORY.with(|memory| *memory.borrow_mut() = None);
+    }
+
+    /// Registers the probe read helpers on `vm`.
+    pub fn register_helpers(vm: &mut rbpf::EbpfVmNoData) {
+        vm.register_helper(BPF_FUNC_PROBE_READ, bpf_probe_read_mock)
+            .expect("Helper failed");
+        vm.register_helper(BPF_FUNC_PROBE_READ_KERNEL, bpf_probe_read_mock)
+            .expect("Helper failed");
+    }
+}
+
+fn bpf_probe_read_mock(dst: u64, size: u64, unsafe_ptr: u64, _: u64, _: u64) -> u64 {
+    let dst = unsafe { std::slice::from_raw_parts_mut(dst as usize as *mut u8, size as usize) };
+    KERNEL_MEMORY.with(|memory| {
+        match memory
+            .borrow()
+            .as_ref()
+            .and_then(|memory| memory.read(unsafe_ptr, size as usize))
+        {
+            Some(bytes) => {
+                dst.copy_from_slice(bytes);
+                0
+            }
+            None => {
+                // like the kernel, zero the destination on faults
+                dst.fill(0);
+                -(libc::EFAULT as i64) as u64
+            }
+        }
+    })
+}
+
+#[integration_test]
+fn probe_read_with_rbpf() {
+    const ADDR: u64 = 0xffff_8880_0000_1000;
+
+    #[rustfmt::skip]
+    let prog: &[u8] = &[
+        // r1 = r10
+        0xbf, 0xa1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // r1 += -8
+        0x07, 0x01, 0x00, 0x00, 0xf8, 0xff, 0xff, 0xff,
+        // r2 = 8
+        0xb7, 0x02, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
+        // r3 = ADDR + 8 ll
+        0x18, 0x03, 0x00, 0x00, 0x08, 0x10, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x80, 0x88, 0xff, 0xff,
+        // call bpf_probe_read
+        0x85, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00,
+        // if r0 != 0 goto +1
+        0x55, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // r0 = *(u64 *)(r10 - 8)
+        0x79, 0xa0, 0xf8, 0xff, 0x00, 0x00, 0x00, 0x00,
+        // exit
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    let mut memory = MockKernelMemory::new();
+    memory.insert(ADDR, &[0u8; 8]);
+    memory.insert(ADDR + 8, &0xdead_beef_u64.to_ne_bytes());
+    memory.install();
+
+    let mut vm = rbpf::EbpfVmNoData::new(Some(prog)).unwrap();
+    MockKernelMemory::register_helpers(&mut vm);
+    assert_eq!(vm.execute_program().unwrap(), 0xdead_beef);
+
+    // reading unmapped memory fails
+    MockKernelMemory::new().install();
+    assert_eq!(vm.execute_program().unwrap(), -(libc::EFAULT as i64) as u64);
+
+    MockKernelMemory::uninstall();
+}
@@ -121,6 +121,8 @@ fn codegen_bindings(opts: &Options) -> Result<(), anyhow::Error> {
         "BPF_CALL",
         "SO_ATTACH_BPF",
         "SO_DETACH_BPF",
+        "SO_ATTACH_REUSEPORT_EBPF",
+        "SO_DETACH_REUSEPORT_BPF",
         // BTF
         "BTF_INT_.*",
         "BTF_KIND_.*",